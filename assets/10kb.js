@@ -20,7 +20,7 @@
  * SOFTWARE.
  */
 
-async function tryvote(site_id, vote) {
+async function tryvote(site_id, vote, retried=false) {
     let url = `/vote/`;
 
     let voter_id = await get_id(false);
@@ -46,17 +46,16 @@ async function tryvote(site_id, vote) {
     }
 
     if (json['code'] == 200) {
-        if (vote == 1) {
-            let elem = document.getElementById(`vote-${site_id}`);
-            elem.className = 'upvoted';
-            let closure = vote_closure(site_id, 0);
-            elem.addEventListener('click', (e) => closure());
-        } else if (vote == 0) {
-            let elem = document.getElementById(`vote-${site_id}`);
-            elem.className = 'unvoted';
-            let closure = vote_closure(site_id, 1);
-            elem.addEventListener('click', (e) => closure);
+        let elem = document.getElementById(`vote-${site_id}`);
+        elem.className = json['voted'] ? 'upvoted' : 'unvoted';
+        elem.onclick = vote_closure(site_id, json['voted'] ? 0 : 1);
+        for (count of document.querySelectorAll(`[data-votes="${site_id}"]`)) {
+            count.textContent = json['votes'];
         }
+    } else if (json['code'] == 404 && !retried) {
+        // The stored id is unknown to the server (e.g. after a restore); get a new one.
+        localStorage.removeItem('10kb_voter_id');
+        await tryvote(site_id, vote, true);
     } else {
         update_status(`Unable to vote: ${json['status']}`);
     }
@@ -92,6 +91,10 @@ async function get_id(force=false) {
 async function populate_votes() {
     let url = '/votes/';
 
+    if (document.body.dataset.voting === undefined) {
+        return;
+    }
+
     let ids = Array();
     for (elem of document.querySelectorAll("div")) {
         if (elem.id.match(/vote\-/)) {
@@ -103,8 +106,7 @@ async function populate_votes() {
     for (site_id of ids) {
         let elem = document.getElementById(`vote-${site_id}`);
         elem.className = 'unvoted';
-        let closure = vote_closure(site_id, 1);
-        elem.addEventListener('click', () => closure());
+        elem.onclick = vote_closure(site_id, 1);
         elem.innerHTML = '&#10145;';
     }
 
@@ -123,8 +125,7 @@ async function populate_votes() {
         for (site_id of json['site_ids']) {
             let elem = document.getElementById(`vote-${site_id}`);
             elem.className = 'upvoted';
-            let closure = vote_closure(site_id, 0);
-            elem.addEventListener('click', (e) => closure());
+            elem.onclick = vote_closure(site_id, 0);
         }
     } catch (error) {
         console.log(`Error getting vote data: ${error}`);
@@ -132,6 +133,64 @@ async function populate_votes() {
     }
 }
 
+// Comments are posted under the same id as votes, so fetch one before submitting.
+function comment_form() {
+    let form = document.getElementById('comment-form');
+    if (form === null) {
+        return;
+    }
+
+    form.addEventListener('submit', async (e) => {
+        e.preventDefault();
+        let voter_id = await get_id(false);
+        if (voter_id === null || voter_id.length == 0) {
+            return; // status text is handled by get_id
+        }
+        form.elements['voter_id'].value = voter_id;
+        form.submit();
+    });
+
+    own_comments(form.dataset.site);
+}
+
+// Shadowed comments are only served to their author, who sees them like any other.
+async function own_comments(slug) {
+    let voter_id = localStorage.getItem('10kb_voter_id');
+    if (!voter_id) {
+        return;
+    }
+
+    let json;
+    try {
+        let data = new URLSearchParams();
+        data.append('voter_id', voter_id);
+
+        let res = await fetch(`/site/${slug}/comments/mine/`, { method: 'POST', body: data });
+        json = await res.json();
+    } catch (error) {
+        console.log(`Error getting comments: ${error}`);
+        return;
+    }
+
+    let list = document.getElementById('comments');
+    for (comment of json['comments']) {
+        let elem = document.createElement('div');
+        elem.className = 'comment';
+
+        let meta = document.createElement('p');
+        meta.className = 'comment-meta';
+        meta.textContent = `#${comment['commenter']}, ${comment['posted']}`;
+
+        let body = document.createElement('p');
+        body.className = 'comment-body';
+        body.textContent = comment['body'];
+
+        elem.append(meta, body);
+        list.append(elem);
+        document.getElementById('no-comments')?.remove();
+    }
+}
+
 function vote_closure(id, vote) {
     return function() {
         tryvote(id, vote);
@@ -142,6 +201,34 @@ function update_status(msg) {
     alert(msg);
 }
 
+function live_updates() {
+    if (document.body.dataset.live === undefined) {
+        return;
+    }
+
+    let scheme = window.location.protocol == 'https:' ? 'wss:' : 'ws:';
+    let socket = new WebSocket(`${scheme}//${window.location.host}/ws/`);
+    let new_sites = 0;
+
+    socket.addEventListener('message', (e) => {
+        for (event of JSON.parse(e.data)) {
+            if (event['type'] == 'votes') {
+                for (elem of document.querySelectorAll(`[data-votes="${event['site_id']}"]`)) {
+                    elem.textContent = event['votes'];
+                }
+            } else if (event['type'] == 'new_site') {
+                new_sites += 1;
+                let elem = document.getElementById('live-status');
+                elem.innerHTML = `${new_sites} new site${new_sites == 1 ? '' : 's'} added. ` +
+                    '<a href="/?sortby=New">Show new sites</a>';
+                elem.hidden = false;
+            }
+        }
+    });
+}
+
 (function () {
     window.addEventListener('DOMContentLoaded', populate_votes, false);
+    window.addEventListener('DOMContentLoaded', live_updates, false);
+    window.addEventListener('DOMContentLoaded', comment_form, false);
 })();
@@ -0,0 +1,185 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The index's hot paths against a seeded in-memory database: `get_sites` under every
+//! sort, on the first and last pages; `get_page_links`; and rendering the whole index
+//! page.  `cargo bench --features bench --bench hot_paths`.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use minijinja::context;
+use tenkbclub::{
+    config::Config,
+    database::{get_featured_sites, get_sites, init_db_in_memory, Pool},
+    get_page_links,
+    i18n::Catalogs,
+    index_uri,
+    page::PageContext,
+    prefs::{Prefs, View},
+    seed::{seed, SeedOptions},
+    templates::Templates,
+    SiteFilter, SortOptions,
+};
+
+const SITES: u32 = 2_000;
+const VOTES: u32 = 40_000;
+const PAGINATE: usize = 25;
+
+const SORTS: [SortOptions; 6] = [
+    SortOptions::New,
+    SortOptions::Size,
+    SortOptions::Votes,
+    SortOptions::Discussed,
+    SortOptions::Week,
+    SortOptions::Month,
+];
+
+fn database() -> Pool {
+    let pool = init_db_in_memory();
+    seed(
+        &pool,
+        &SeedOptions {
+            sites: SITES,
+            votes: VOTES,
+            seed: 1,
+        },
+    )
+    .expect("unable to seed the database");
+    pool
+}
+
+fn config() -> Config {
+    serde_json::from_value(serde_json::json!({
+        "database_path": ":memory:",
+        "template_path": concat!(env!("CARGO_MANIFEST_DIR"), "/templates"),
+        "template_reload": false,
+        "cloudflare_account": "",
+        "cloudflare_api_token": "",
+    }))
+    .expect("invalid benchmark configuration")
+}
+
+fn bench_get_sites(c: &mut Criterion) {
+    let pool = database();
+    let filter = SiteFilter::default();
+    let last = SITES as usize - PAGINATE;
+
+    // Deep pages are slow enough that the default hundred samples take minutes each.
+    let mut group = c.benchmark_group("get_sites");
+    group.sample_size(10);
+    for sortby in SORTS {
+        for (page, skip) in [("first", 0), ("last", last)] {
+            group.bench_with_input(
+                BenchmarkId::new(sortby.to_string(), page),
+                &skip,
+                |b, &skip| b.iter(|| get_sites(&pool, sortby, &filter, skip, PAGINATE).unwrap()),
+            );
+        }
+    }
+    group.finish();
+}
+
+fn bench_page_links(c: &mut Criterion) {
+    let prefs = Prefs::site(&config());
+    let filter = SiteFilter::default();
+    let pages = SITES as usize / PAGINATE;
+
+    c.bench_function("get_page_links", |b| {
+        b.iter(|| {
+            get_page_links(
+                pages / 2,
+                SITES as f32,
+                PAGINATE as f32,
+                SortOptions::Votes,
+                View::Table,
+                &filter,
+                &prefs,
+            )
+        })
+    });
+}
+
+fn bench_render_index(c: &mut Criterion) {
+    let pool = database();
+    let config = config();
+    let catalogs = Arc::new(Catalogs::load(None, &config.default_locale).unwrap());
+    let templates = Templates::new(&config, catalogs);
+    let prefs = Prefs::site(&config);
+    let filter = SiteFilter::default();
+    let sortby = SortOptions::Votes;
+
+    let sites = get_sites(&pool, sortby, &filter, 0, PAGINATE).unwrap();
+    let top = get_sites(&pool, SortOptions::Week, &filter, 0, 10).unwrap();
+    let featured = get_featured_sites(&pool).unwrap();
+    let (page_links, prev_link, next_link) = get_page_links(
+        1,
+        SITES as f32,
+        PAGINATE as f32,
+        sortby,
+        View::Table,
+        &filter,
+        &prefs,
+    );
+
+    let mut group = c.benchmark_group("render_index");
+    for view in [View::Table, View::Compact, View::Gallery] {
+        let view_links = [View::Table, View::Compact, View::Gallery].map(|other| {
+            (
+                other,
+                index_uri(1, PAGINATE, sortby, other, &filter, &prefs),
+            )
+        });
+        group.bench_function(view.as_str(), |b| {
+            b.iter(|| {
+                PageContext::new(&config, "The 10KiB Club", "/")
+                    .sortby(sortby)
+                    .voting()
+                    .render(
+                        &templates,
+                        "index.html",
+                        context!(
+                            sites => sites,
+                            page_links => page_links,
+                            next_link => next_link,
+                            prev_link => prev_link,
+                            filter => filter,
+                            featured => featured,
+                            top => top,
+                            view => view,
+                            view_links => view_links,
+                        ),
+                    )
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_get_sites,
+    bench_page_links,
+    bench_render_index
+);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The index's queries against a scratch database, with and without the prepared
+//! statement cache: `cargo bench --bench statement_cache`.
+
+use std::time::{Duration, Instant};
+
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+use tenkbclub::{
+    database::{get_site_count, get_sites, init_db, Pool},
+    SiteFilter, SortOptions,
+};
+
+const SITES: u32 = 500;
+const ITERATIONS: u32 = 2000;
+
+fn main() {
+    let path = std::env::temp_dir().join(format!("tenkb-bench-{}.db", std::process::id()));
+    seed(&path);
+
+    let cached = init_db(&path, None);
+    let uncached = Pool::new(SqliteConnectionManager::file(&path).with_init(|conn| {
+        conn.set_prepared_statement_cache_capacity(0);
+        Ok(())
+    }))
+    .expect("unable to open database");
+
+    let uncached_time = run(&uncached);
+    let cached_time = run(&cached);
+    println!("uncached: {:?} per index page", uncached_time / ITERATIONS);
+    println!("cached:   {:?} per index page", cached_time / ITERATIONS);
+    println!(
+        "speedup:  {:.2}x",
+        uncached_time.as_secs_f64() / cached_time.as_secs_f64()
+    );
+
+    let _ = std::fs::remove_file(&path);
+}
+
+fn seed(path: &std::path::PathBuf) {
+    let conn = init_db(path, None)
+        .get()
+        .expect("unable to create database");
+    for id in 1..=SITES {
+        conn.execute(
+            "INSERT INTO site_ids (id, url, host) VALUES (?, ?, ?)",
+            params![
+                id,
+                format!("https://{id}.example/"),
+                format!("{id}.example")
+            ],
+        )
+        .expect("unable to add site");
+        conn.execute(
+            "INSERT INTO sites (id, size, date_added, valid) VALUES (?, ?, DATETIME(), true)",
+            params![id, 500 + id * 17 % 9000],
+        )
+        .expect("unable to add site");
+    }
+}
+
+fn run(pool: &Pool) -> Duration {
+    let filter = SiteFilter::default();
+    let start = Instant::now();
+    for page in 0..ITERATIONS {
+        let skip = (page as usize * 25) % SITES as usize;
+        get_site_count(pool, &filter).expect("count failed");
+        get_sites(pool, SortOptions::Votes, &filter, skip, 25).expect("listing failed");
+    }
+    start.elapsed()
+}
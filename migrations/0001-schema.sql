@@ -35,7 +35,7 @@ CREATE TABLE validation_log (id INT REFERENCES site_ids(id),
 );
 
 CREATE TABLE voter_ids(id INTEGER PRIMARY KEY AUTOINCREMENT,
-                       secret TEXT UNIQUE,
+                       secret TEXT UNIQUE
 );
 
 CREATE TABLE votes(id INTEGER NOT NULL REFERENCES site_ids(id),
@@ -0,0 +1,6 @@
+CREATE TABLE peer_sites(peer TEXT NOT NULL,
+                        url TEXT NOT NULL,
+                        size FLOAT,
+                        fetched DATETIME,
+                        PRIMARY KEY(peer, url)
+);
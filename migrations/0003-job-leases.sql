@@ -0,0 +1,4 @@
+CREATE TABLE job_leases(name TEXT PRIMARY KEY,
+                        holder TEXT,
+                        expires DATETIME
+);
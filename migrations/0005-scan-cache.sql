@@ -0,0 +1,5 @@
+CREATE TABLE scan_cache(url TEXT PRIMARY KEY,
+                        size FLOAT,
+                        acceptable BOOL,
+                        scanned DATETIME
+);
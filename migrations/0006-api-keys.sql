@@ -0,0 +1,7 @@
+CREATE TABLE api_keys(id INTEGER PRIMARY KEY AUTOINCREMENT,
+                      name TEXT,
+                      key_hash TEXT UNIQUE,
+                      quota INTEGER,
+                      created DATETIME,
+                      revoked BOOL
+);
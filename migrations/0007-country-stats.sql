@@ -0,0 +1,6 @@
+CREATE TABLE country_stats(day DATE,
+                           country TEXT,
+                           visits INTEGER,
+                           votes INTEGER,
+                           PRIMARY KEY(day, country)
+);
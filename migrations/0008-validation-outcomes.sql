@@ -0,0 +1,41 @@
+-- The queue's scan flag becomes an outcome.  Sites still flagged for scanning are pending;
+-- the rest passed if they were listed, failed on size if that was the last thing logged
+-- about them, and otherwise failed for some reason the old queue didn't keep.
+CREATE TABLE validation_queue_outcomes(id INTEGER REFERENCES site_ids(id),
+                    outcome TEXT NOT NULL DEFAULT 'pending',
+                    date_added DATETIME,
+                    last_checked DATETIME,
+                    site_live BOOL,
+                    urlscan_reportid TEXT,
+                    urlscan_check_timestamp DATETIME,
+                    urlscan_validated BOOL,
+                    priority INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO validation_queue_outcomes
+    SELECT validation_queue.id,
+           CASE
+               WHEN validation_queue.scan THEN 'pending'
+               WHEN (SELECT valid FROM sites WHERE sites.id = validation_queue.id) THEN 'passed'
+               WHEN (SELECT comment FROM validation_log WHERE validation_log.id = validation_queue.id
+                     ORDER BY timestamp DESC LIMIT 1) LIKE 'size validation failed%'
+                   THEN 'failed_size'
+               ELSE 'failed_error'
+           END,
+           date_added, last_checked, site_live, urlscan_reportid, urlscan_check_timestamp,
+           urlscan_validated, priority
+    FROM validation_queue;
+
+DROP TABLE validation_queue;
+
+ALTER TABLE validation_queue_outcomes RENAME TO validation_queue;
+
+ALTER TABLE scan_cache ADD COLUMN malicious BOOL NOT NULL DEFAULT false;
+
+CREATE TABLE validation_attempts(id INTEGER PRIMARY KEY AUTOINCREMENT,
+                                 site_id INTEGER REFERENCES site_ids(id),
+                                 outcome TEXT NOT NULL,
+                                 size FLOAT,
+                                 detail TEXT,
+                                 attempted DATETIME
+);
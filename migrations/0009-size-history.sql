@@ -0,0 +1,6 @@
+CREATE TABLE size_history(site_id INTEGER REFERENCES site_ids(id),
+                          size FLOAT,
+                          measured DATETIME,
+                          kind TEXT,
+                          passed BOOL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE sites ADD COLUMN featured BOOL NOT NULL DEFAULT false;
+
+ALTER TABLE sites ADD COLUMN featured_until DATETIME;
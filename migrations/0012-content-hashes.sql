@@ -0,0 +1,7 @@
+CREATE TABLE content_hashes(site_id INTEGER PRIMARY KEY REFERENCES site_ids(id),
+                            hash TEXT NOT NULL,
+                            duplicate_of INTEGER REFERENCES site_ids(id),
+                            hashed DATETIME
+);
+
+CREATE INDEX content_hashes_hash ON content_hashes(hash);
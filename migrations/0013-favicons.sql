@@ -0,0 +1,5 @@
+CREATE TABLE favicons(site_id INTEGER PRIMARY KEY REFERENCES site_ids(id),
+                      content_type TEXT NOT NULL,
+                      data BLOB NOT NULL,
+                      fetched DATETIME
+);
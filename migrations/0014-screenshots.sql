@@ -0,0 +1,7 @@
+ALTER TABLE scan_cache ADD COLUMN scan_id TEXT;
+
+CREATE TABLE screenshots(site_id INTEGER PRIMARY KEY REFERENCES site_ids(id),
+                         path TEXT NOT NULL,
+                         thumbnail_path TEXT NOT NULL,
+                         captured DATETIME
+);
@@ -0,0 +1,5 @@
+CREATE TABLE snapshots(site_id INTEGER REFERENCES site_ids(id),
+                       captured DATETIME NOT NULL,
+                       html TEXT NOT NULL,
+                       PRIMARY KEY (site_id, captured)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE content_changes(site_id INTEGER REFERENCES site_ids(id),
+                             captured DATETIME NOT NULL,
+                             previous DATETIME NOT NULL,
+                             bytes_added INTEGER,
+                             bytes_removed INTEGER,
+                             at_risk BOOL NOT NULL DEFAULT false,
+                             PRIMARY KEY (site_id, captured)
+);
@@ -0,0 +1,19 @@
+CREATE TABLE site_feeds(id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        site_id INTEGER REFERENCES site_ids(id),
+                        url TEXT NOT NULL,
+                        kind TEXT,
+                        etag TEXT,
+                        last_modified TEXT,
+                        fetched DATETIME,
+                        UNIQUE (site_id, url)
+);
+
+CREATE TABLE planet_posts(feed_id INTEGER REFERENCES site_feeds(id),
+                          guid TEXT NOT NULL,
+                          title TEXT,
+                          link TEXT,
+                          published DATETIME,
+                          PRIMARY KEY (feed_id, guid)
+);
+
+CREATE INDEX planet_posts_published ON planet_posts(published);
@@ -0,0 +1,6 @@
+CREATE TABLE webmentions(site_id INTEGER REFERENCES site_ids(id),
+                         source TEXT NOT NULL,
+                         title TEXT,
+                         verified DATETIME,
+                         PRIMARY KEY (site_id, source)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE webmentions_sent(id INTEGER PRIMARY KEY,
+                              site_id INTEGER REFERENCES site_ids(id),
+                              target TEXT NOT NULL,
+                              reason TEXT NOT NULL,
+                              endpoint TEXT,
+                              result TEXT NOT NULL,
+                              sent DATETIME
+);
+
+CREATE INDEX webmentions_sent_target ON webmentions_sent(target, sent);
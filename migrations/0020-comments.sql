@@ -0,0 +1,9 @@
+CREATE TABLE comments(id INTEGER PRIMARY KEY AUTOINCREMENT,
+                      site_id INTEGER NOT NULL REFERENCES site_ids(id),
+                      voter_id INTEGER NOT NULL REFERENCES voter_ids(id),
+                      body TEXT NOT NULL,
+                      status TEXT NOT NULL,
+                      posted DATETIME
+);
+
+CREATE INDEX comments_site ON comments(site_id, status, posted);
@@ -0,0 +1,8 @@
+ALTER TABLE votes ADD COLUMN shadowed BOOL NOT NULL DEFAULT false;
+
+CREATE TABLE shadow_flags(kind TEXT NOT NULL,
+                          value TEXT NOT NULL,
+                          reason TEXT,
+                          flagged DATETIME,
+                          PRIMARY KEY (kind, value)
+);
@@ -0,0 +1,16 @@
+CREATE TABLE spam_tokens(token TEXT PRIMARY KEY,
+                         spam INTEGER NOT NULL DEFAULT 0,
+                         ham INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE spam_training(label TEXT PRIMARY KEY,
+                           documents INTEGER NOT NULL
+);
+
+CREATE TABLE spam_scores(kind TEXT NOT NULL,
+                         subject TEXT NOT NULL,
+                         score REAL NOT NULL,
+                         scored DATETIME
+);
+
+CREATE INDEX spam_scores_scored ON spam_scores(scored);
@@ -0,0 +1,3 @@
+ALTER TABLE site_ids ADD COLUMN host TEXT;
+
+CREATE INDEX site_ids_host ON site_ids(host);
@@ -0,0 +1,5 @@
+CREATE TABLE homograph_flags(site_id INTEGER PRIMARY KEY REFERENCES site_ids(id),
+                             reason TEXT NOT NULL,
+                             lookalike_of INTEGER REFERENCES site_ids(id),
+                             flagged DATETIME
+);
@@ -0,0 +1,5 @@
+CREATE TABLE site_aliases(url TEXT PRIMARY KEY,
+                          site_id INTEGER NOT NULL REFERENCES site_ids(id),
+                          reason TEXT NOT NULL,
+                          added DATETIME
+);
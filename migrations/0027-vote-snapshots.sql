@@ -0,0 +1,5 @@
+CREATE TABLE vote_snapshots(site_id INTEGER REFERENCES site_ids(id),
+                            day DATE NOT NULL,
+                            votes INTEGER NOT NULL,
+                            PRIMARY KEY (site_id, day)
+);
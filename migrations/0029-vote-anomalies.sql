@@ -0,0 +1,22 @@
+ALTER TABLE voter_ids ADD COLUMN created DATETIME;
+
+ALTER TABLE votes ADD COLUMN voted DATETIME;
+
+CREATE INDEX votes_voted ON votes(voted);
+
+CREATE TABLE vote_anomalies(id INTEGER PRIMARY KEY AUTOINCREMENT,
+                            kind TEXT NOT NULL,
+                            site_id INTEGER REFERENCES site_ids(id),
+                            detail TEXT NOT NULL,
+                            votes INTEGER NOT NULL,
+                            detected DATETIME,
+                            invalidated DATETIME
+);
+
+CREATE TABLE vote_anomaly_votes(anomaly_id INTEGER NOT NULL REFERENCES vote_anomalies(id),
+                                site_id INTEGER NOT NULL REFERENCES site_ids(id),
+                                voter_id INTEGER NOT NULL REFERENCES voter_ids(id),
+                                PRIMARY KEY (anomaly_id, site_id, voter_id)
+);
+
+CREATE INDEX vote_anomaly_votes_vote ON vote_anomaly_votes(site_id, voter_id);
@@ -0,0 +1,10 @@
+CREATE TABLE related_suggestions(id INTEGER PRIMARY KEY AUTOINCREMENT,
+                                 site_id INTEGER NOT NULL REFERENCES site_ids(id),
+                                 discussion_url TEXT NOT NULL,
+                                 url TEXT NOT NULL,
+                                 title TEXT,
+                                 status TEXT NOT NULL,
+                                 client_ip TEXT,
+                                 suggested DATETIME,
+                                 UNIQUE(site_id, discussion_url)
+);
@@ -0,0 +1,4 @@
+CREATE TABLE related_refreshes(site_id INTEGER PRIMARY KEY REFERENCES site_ids(id),
+                               refreshed DATETIME,
+                               requested DATETIME
+);
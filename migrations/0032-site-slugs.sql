@@ -0,0 +1,7 @@
+CREATE TABLE site_slugs(slug TEXT PRIMARY KEY,
+                        site_id INTEGER NOT NULL REFERENCES site_ids(id),
+                        is_primary BOOL NOT NULL DEFAULT true,
+                        added DATETIME
+);
+
+CREATE INDEX site_slugs_site ON site_slugs(site_id);
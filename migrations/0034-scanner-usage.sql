@@ -0,0 +1,3 @@
+CREATE TABLE scanner_usage(day DATE PRIMARY KEY,
+                           scans INTEGER NOT NULL DEFAULT 0
+);
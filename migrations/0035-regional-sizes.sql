@@ -0,0 +1,6 @@
+CREATE TABLE regional_sizes(site_id INTEGER REFERENCES site_ids(id),
+                            country TEXT NOT NULL,
+                            size FLOAT,
+                            scanned DATETIME,
+                            PRIMARY KEY(site_id, country)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE uptime_checks(site_id INTEGER REFERENCES site_ids(id),
+                           checked DATETIME,
+                           up BOOL NOT NULL,
+                           detail TEXT
+);
+
+CREATE INDEX uptime_checks_site ON uptime_checks(site_id, checked);
@@ -0,0 +1,96 @@
+CREATE TABLE site_ids(id SERIAL PRIMARY KEY,
+                      url TEXT NOT NULL UNIQUE,
+                      host TEXT
+);
+
+CREATE INDEX site_ids_host ON site_ids(host);
+
+CREATE TABLE site_slugs(slug TEXT PRIMARY KEY,
+                        site_id INTEGER NOT NULL REFERENCES site_ids(id),
+                        is_primary BOOL NOT NULL DEFAULT true,
+                        added TIMESTAMP(0)
+);
+
+CREATE INDEX site_slugs_site ON site_slugs(site_id);
+
+CREATE TABLE site_aliases(url TEXT PRIMARY KEY,
+                          site_id INTEGER NOT NULL REFERENCES site_ids(id),
+                          reason TEXT NOT NULL,
+                          added TIMESTAMP(0)
+);
+
+CREATE TABLE sites(id INTEGER REFERENCES site_ids(id),
+                   size DOUBLE PRECISION,
+                   mobile_size DOUBLE PRECISION,
+                   date_added TIMESTAMP(0),
+                   valid BOOL,
+                   note TEXT
+);
+
+CREATE INDEX sites_id ON sites(id);
+
+CREATE TABLE blocked_site_patterns(id SERIAL PRIMARY KEY, pattern TEXT NOT NULL, notes TEXT);
+
+CREATE TABLE voter_ids(id SERIAL PRIMARY KEY,
+                       uuid TEXT NOT NULL UNIQUE,
+                       created TIMESTAMP(0)
+);
+
+CREATE TABLE votes(id INTEGER NOT NULL REFERENCES site_ids(id),
+                   voter_id INTEGER NOT NULL REFERENCES voter_ids(id),
+                   shadowed BOOL NOT NULL DEFAULT false,
+                   voted TIMESTAMP(0),
+                   UNIQUE (id, voter_id)
+);
+
+CREATE INDEX votes_voter ON votes(voter_id);
+
+CREATE TABLE vote_snapshots(site_id INTEGER REFERENCES site_ids(id),
+                            day DATE NOT NULL,
+                            votes INTEGER NOT NULL,
+                            PRIMARY KEY (site_id, day)
+);
+
+CREATE TABLE validation_queue(id INTEGER REFERENCES site_ids(id),
+                              outcome TEXT NOT NULL DEFAULT 'pending',
+                              priority INTEGER NOT NULL DEFAULT 0,
+                              date_added TIMESTAMP(0)
+);
+
+CREATE INDEX validation_queue_id ON validation_queue(id);
+
+CREATE TABLE validation_attempts(id SERIAL PRIMARY KEY,
+                                 site_id INTEGER REFERENCES site_ids(id),
+                                 outcome TEXT NOT NULL,
+                                 size DOUBLE PRECISION,
+                                 detail TEXT,
+                                 attempted TIMESTAMP(0)
+);
+
+CREATE TABLE validation_log(id INTEGER REFERENCES site_ids(id),
+                            timestamp TIMESTAMP(0),
+                            comment TEXT
+);
+
+CREATE TABLE size_history(site_id INTEGER REFERENCES site_ids(id),
+                          size DOUBLE PRECISION,
+                          measured TIMESTAMP(0),
+                          kind TEXT,
+                          passed BOOL
+);
+
+CREATE TABLE related(id INTEGER REFERENCES site_ids(id),
+                     url TEXT NOT NULL,
+                     discussion_url TEXT NOT NULL UNIQUE,
+                     date TEXT NOT NULL,
+                     title TEXT NOT NULL,
+                     score INTEGER NOT NULL,
+                     comments INTEGER NOT NULL
+);
+
+CREATE INDEX related_id ON related(id);
+
+CREATE TABLE related_refreshes(site_id INTEGER PRIMARY KEY REFERENCES site_ids(id),
+                               refreshed TIMESTAMP(0),
+                               requested TIMESTAMP(0)
+);
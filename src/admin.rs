@@ -0,0 +1,78 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! HTTP Basic authentication for the admin pages.
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tracing::warn;
+
+use crate::{config::Config, get_client_ip};
+
+fn authorized(req: &ServiceRequest, config: &Config) -> bool {
+    let Some(admin) = &config.admin else {
+        return false;
+    };
+
+    let Some(credentials) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+    else {
+        return false;
+    };
+
+    credentials.split_once(':') == Some((&admin.username[..], &admin.password[..]))
+}
+
+/// Middleware that requires the admin credentials from the config on every request.
+pub async fn admin_auth_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let allowed = req
+        .app_data::<web::Data<Config>>()
+        .is_some_and(|config| authorized(&req, config));
+
+    if allowed {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    if req.headers().contains_key(header::AUTHORIZATION) {
+        let client_ip = get_client_ip(req.request()).unwrap_or_default();
+        warn!("failed admin login from {client_ip}");
+    }
+
+    let res = HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, r#"Basic realm="10kb.club admin""#))
+        .finish();
+    Ok(req.into_response(res).map_into_right_body())
+}
@@ -0,0 +1,198 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Opt-in, first-party access log.  Requests are recorded (with truncated or hashed client
+//! IPs) in a separate SQLite database, written in batches by a background task.
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error,
+};
+use chrono::Utc;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config::{Config, IpPrivacy},
+    database::Pool,
+    error::TenKbError,
+    get_client_ip_with,
+};
+
+/// Records queued for the writer before new ones are dropped.
+const QUEUE_CAPACITY: usize = 10_000;
+
+const ANALYTICS_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS requests(timestamp DATETIME,
+                                    path TEXT,
+                                    status INTEGER,
+                                    latency_ms INTEGER,
+                                    ip TEXT,
+                                    referrer TEXT
+);
+CREATE INDEX IF NOT EXISTS requests_timestamp ON requests(timestamp);
+"#;
+
+#[derive(Debug)]
+pub struct RequestRecord {
+    pub timestamp: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: u64,
+    pub ip: String,
+    pub referrer: Option<String>,
+}
+
+pub type AnalyticsSender = mpsc::Sender<RequestRecord>;
+
+/// Open (creating if needed) the analytics database.
+pub fn open(path: &Path) -> Result<Pool, TenKbError> {
+    let pool = Pool::new(SqliteConnectionManager::file(path))?;
+    pool.get()?.execute_batch(ANALYTICS_SCHEMA)?;
+    Ok(pool)
+}
+
+/// Start the writer task, which inserts records `batch_size` at a time or every
+/// `flush_interval`, whichever comes first.
+pub fn spawn_writer(pool: Pool, batch_size: usize, flush_interval: Duration) -> AnalyticsSender {
+    let (tx, mut rx) = mpsc::channel(QUEUE_CAPACITY);
+
+    tokio::task::spawn(async move {
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut ticker = tokio::time::interval(flush_interval);
+
+        loop {
+            let (flush, closed) = tokio::select! {
+                record = rx.recv() => match record {
+                    Some(record) => {
+                        batch.push(record);
+                        (batch.len() >= batch_size, false)
+                    }
+                    None => (true, true),
+                },
+                _ = ticker.tick() => (true, false),
+            };
+
+            if flush && !batch.is_empty() {
+                let records = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+                let pool = pool.clone();
+                match tokio::task::spawn_blocking(move || insert_batch(&pool, &records)).await {
+                    Ok(Ok(n)) => debug!("wrote {n} access log records"),
+                    Ok(Err(e)) => error!("unable to write access log records: {e:?}"),
+                    Err(e) => error!("access log writer failed: {e}"),
+                }
+            }
+
+            if closed {
+                return;
+            }
+        }
+    });
+
+    tx
+}
+
+fn insert_batch(pool: &Pool, records: &[RequestRecord]) -> Result<usize, TenKbError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    {
+        let mut statement = tx.prepare_cached(
+            r#"INSERT INTO requests (timestamp, path, status, latency_ms, ip, referrer)
+               VALUES (?, ?, ?, ?, ?, ?)"#,
+        )?;
+        for record in records {
+            statement.execute(params![
+                record.timestamp,
+                record.path,
+                record.status,
+                record.latency_ms,
+                record.ip,
+                record.referrer,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(records.len())
+}
+
+/// Delete records older than `retention_days`.
+pub fn prune(pool: &Pool, retention_days: u32) -> Result<usize, TenKbError> {
+    let conn = pool.get()?;
+    let deleted = conn.execute(
+        r#"DELETE FROM requests WHERE timestamp < DATETIME('now', ?)"#,
+        params![format!("-{retention_days} days")],
+    )?;
+    info!("pruned {deleted} access log records older than {retention_days} days");
+    Ok(deleted)
+}
+
+/// Middleware that queues a record for every request when the access log is enabled.
+pub async fn analytics_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(sender) = req.app_data::<web::Data<AnalyticsSender>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    let start = Instant::now();
+    let path = String::from(req.path());
+    // The access log never keeps full addresses, whatever the configured privacy mode.
+    let privacy = req
+        .app_data::<web::Data<Config>>()
+        .map_or(IpPrivacy::Truncate, |config| {
+            config.ip_privacy.max(IpPrivacy::Truncate)
+        });
+    let ip = get_client_ip_with(req.request(), privacy).unwrap_or_default();
+    let referrer = req
+        .headers()
+        .get(header::REFERER)
+        .and_then(|referrer| referrer.to_str().ok())
+        .map(String::from);
+
+    let res = next.call(req).await?;
+
+    let record = RequestRecord {
+        timestamp: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        path,
+        status: res.status().as_u16(),
+        latency_ms: start.elapsed().as_millis() as u64,
+        ip,
+        referrer,
+    };
+
+    if sender.try_send(record).is_err() {
+        warn!("access log queue full; dropping record");
+    }
+
+    Ok(res)
+}
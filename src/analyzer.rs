@@ -19,105 +19,884 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
-use std::error::Error;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::Display,
+    future::Future,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use sentry::{Hub, SentryFutureExt};
 
 use crate::{
-    cloudflare::urlscan,
-    config::Config,
-    database::{get_validation_queue, mark_bad, mark_bad_size, mark_good, update_related, Pool},
-    relatedlinks::{hackernews, lobsters, RelatedLink},
+    canonical_url,
+    cloudflare::{urlscan, ScanVantage, UrlScan, UrlScanResult},
+    config::{Config, SnapshotConfig},
+    database::{
+        acquire_lease, cache_scan, find_duplicate_content, get_cached_scan,
+        get_recertification_due, get_scanner_calls_today, get_snapshot_pair, recertify,
+        record_content_change, record_redirect, record_scanner_call, record_size, release_lease,
+        store_content_hash, store_favicon, store_regional_size, store_screenshot, store_site_feeds,
+        store_snapshot, Pool, SizeCheck, ValidationOutcome,
+    },
+    error::TenKbError,
+    events::{publish, EventSender, SiteEvent},
+    favicon::fetch_favicon,
+    planet::discover_feeds,
+    relatedlinks::{merge_related, RelatedLink, RelatedLinkResult, RelatedSources, SourceHealth},
+    resolver, screenshots,
+    snapshots::{change_stats, snapshot_html},
+    spam::train,
+    storage::Storage,
+    webmention::{announce, Announcement},
 };
-use tokio::runtime::Handle;
-use tracing::{debug, error, info};
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::{runtime::Handle, sync::Notify};
+use tracing::{debug, error, info, warn};
+use url::Url;
+
+const VALIDATION_LEASE: &str = "validation";
+const VALIDATION_LEASE_TTL: u32 = 600;
+const RECERTIFICATION_LEASE: &str = "recertification";
+/// Permanent redirects followed when checking whether a member has moved.
+const MAX_REDIRECTS: usize = 5;
+
+/// Snapshot of what the analyzer is doing, shared between the analyzer task and the web server.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AnalyzerStatus {
+    pub running: bool,
+    pub current_site: Option<String>,
+    pub cycles: u64,
+    pub last_cycle_start: Option<DateTime<Utc>>,
+    pub last_cycle_end: Option<DateTime<Utc>>,
+    pub last_cycle_queued: usize,
+    pub last_cycle_processed: usize,
+    pub last_cycle_errors: usize,
+    pub last_error: Option<String>,
+    pub last_error_time: Option<DateTime<Utc>>,
+    pub related_sources: BTreeMap<String, SourceHealth>,
+}
+
+pub type AnalyzerStatusHandle = Arc<Mutex<AnalyzerStatus>>;
+
+/// Wakes the validation job before its next scheduled run, e.g. when a site is submitted.
+pub type AnalyzerWakeup = Arc<Notify>;
+
+/// Identifies this process when taking job leases, so that several analyzers sharing a
+/// database don't process the same queue.
+pub(crate) fn instance_id() -> &'static str {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(|| {
+        let mut rand_bytes = [0u8; 8];
+        thread_rng().fill(&mut rand_bytes);
+        format!("{}-{}", std::process::id(), hex::encode(rand_bytes))
+    })
+}
+
+impl AnalyzerStatus {
+    fn begin_cycle(&mut self) {
+        self.running = true;
+        self.last_cycle_start = Some(Utc::now());
+        self.last_cycle_queued = 0;
+        self.last_cycle_processed = 0;
+        self.last_cycle_errors = 0;
+    }
+
+    fn end_cycle(&mut self) {
+        self.running = false;
+        self.current_site = None;
+        self.cycles += 1;
+        self.last_cycle_end = Some(Utc::now());
+    }
+
+    pub fn record_error(&mut self, msg: String) {
+        self.last_cycle_errors += 1;
+        self.last_error = Some(msg);
+        self.last_error_time = Some(Utc::now());
+    }
+
+    /// End a cycle cut short by `msg`.
+    fn abandon_cycle(&mut self, msg: String) {
+        self.record_error(msg);
+        self.end_cycle();
+    }
+}
+
+/// Run one pass over the validation queue, provided no other analyzer holds the validation
+/// lease.  The queue and the listing are in `storage`; the lease, the scan cache, and what
+/// is kept beside a new listing (favicons, feeds, snapshots and so on) are in `pool`.
+pub async fn validation_sweep(
+    storage: &dyn Storage,
+    pool: &Pool,
+    config: &Config,
+    status: &AnalyzerStatusHandle,
+    events: &EventSender,
+    scanner: &impl Scanner,
+    sources: &impl RelatedSources,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !acquire_lease(pool, VALIDATION_LEASE, instance_id(), VALIDATION_LEASE_TTL)? {
+        info!("another analyzer holds the validation lease; skipping sweep");
+        return Ok(());
+    }
+
+    let res = run_validation_sweep(storage, pool, config, status, events, scanner, sources).await;
+    release_lease(pool, VALIDATION_LEASE, instance_id())?;
+    res
+}
 
-pub async fn analyzer(pool: &Pool, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let mut first = true;
+async fn run_validation_sweep(
+    storage: &dyn Storage,
+    pool: &Pool,
+    config: &Config,
+    status: &AnalyzerStatusHandle,
+    events: &EventSender,
+    scanner: &impl Scanner,
+    sources: &impl RelatedSources,
+) -> Result<(), Box<dyn std::error::Error>> {
+    status.lock().unwrap().begin_cycle();
 
-    loop {
-        if !first {
-            info!("sleeping");
-            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    let sites = match storage.get_validation_queue() {
+        Ok(sites) => sites,
+        Err(e) => {
+            error!("unable to get site list: {e:?}");
+            status
+                .lock()
+                .unwrap()
+                .abandon_cycle(format!("unable to get site list: {e}"));
+            return Err(e);
         }
+    };
 
-        first = false;
+    info!("processing {} sites in the validation queue", sites.len());
+    status.lock().unwrap().last_cycle_queued = sites.len();
 
-        let sites = match get_validation_queue(pool) {
-            Ok(sites) => sites,
+    for site in sites {
+        let held = match acquire_lease(pool, VALIDATION_LEASE, instance_id(), VALIDATION_LEASE_TTL)
+        {
+            Ok(held) => held,
             Err(e) => {
-                error!("unable to get site list: {e:?}");
-                continue;
+                error!("unable to renew the validation lease: {e:?}");
+                status
+                    .lock()
+                    .unwrap()
+                    .abandon_cycle(format!("unable to renew the validation lease: {e}"));
+                return Err(e);
+            }
+        };
+        if !held {
+            warn!("lost the validation lease; abandoning sweep");
+            break;
+        }
+
+        let spent = match scan_budget_spent(pool, config) {
+            Ok(spent) => spent,
+            Err(e) => {
+                error!("unable to check the scanner budget: {e:?}");
+                status
+                    .lock()
+                    .unwrap()
+                    .abandon_cycle(format!("unable to check the scanner budget: {e}"));
+                return Err(e);
             }
         };
+        if spent {
+            warn!("today's scanner budget is spent; leaving the rest of the queue for tomorrow");
+            break;
+        }
+
+        info!("processing {site}");
+        status.lock().unwrap().current_site = Some(site.clone());
+
+        // Errors reported while processing the site are tagged with it.
+        let hub = Arc::new(Hub::new_from_top(Hub::current()));
+        hub.configure_scope(|scope| scope.set_tag("site", &site));
+
+        let res = process_site(
+            storage, pool, config, status, events, scanner, sources, &site,
+        )
+        .bind_hub(hub.clone())
+        .await;
+
+        let mut status = status.lock().unwrap();
+        status.last_cycle_processed += 1;
+        if let Err(e) = res {
+            Hub::run(hub, || error!("error processing {site}: {e:?}"));
+            status.record_error(format!("{site}: {e}"));
+        }
+    }
+
+    refresh_related(storage, config, status, sources).await;
+
+    status.lock().unwrap().end_cycle();
+    Ok(())
+}
+
+/// Harvest related links again for the sites a visitor asked to refresh.  If a source
+/// doesn't answer, the stored links are kept and the request waits for the next sweep.
+async fn refresh_related(
+    storage: &dyn Storage,
+    config: &Config,
+    status: &AnalyzerStatusHandle,
+    sources: &impl RelatedSources,
+) {
+    let sites = match storage.get_requested_refreshes() {
+        Ok(sites) => sites,
+        Err(e) => {
+            error!("unable to get requested related link refreshes: {e:?}");
+            return;
+        }
+    };
+
+    for site in sites {
+        info!("refreshing related links for {site}");
+        let (links, complete) = related_links(config, status, sources, &site).await;
+        if !complete {
+            warn!("not all related link sources answered for {site}; will retry");
+            continue;
+        }
+        if let Err(e) = record(config, format!("refresh related links for {site}"), || {
+            storage.update_related(&site, &links)
+        }) {
+            error!("unable to store related links for {site}: {e:?}");
+            status
+                .lock()
+                .unwrap()
+                .record_error(format!("{site}: unable to store related links: {e}"));
+        }
+    }
+}
+
+/// Re-measure listed sites whose last measurement is over a year old.  Failures are recorded
+/// in the size history but don't delist the site; that is left to an admin.
+pub async fn recertification_sweep(
+    pool: &Pool,
+    config: &Config,
+    scanner: &impl Scanner,
+) -> Result<(), Box<dyn Error>> {
+    if !acquire_lease(
+        pool,
+        RECERTIFICATION_LEASE,
+        instance_id(),
+        VALIDATION_LEASE_TTL,
+    )? {
+        info!("another analyzer holds the recertification lease; skipping sweep");
+        return Ok(());
+    }
 
-        info!("processing {} sites in the validation queue", sites.len());
+    let res = run_recertification_sweep(pool, config, scanner).await;
+    release_lease(pool, RECERTIFICATION_LEASE, instance_id())?;
+    res
+}
+
+async fn run_recertification_sweep(
+    pool: &Pool,
+    config: &Config,
+    scanner: &impl Scanner,
+) -> Result<(), Box<dyn Error>> {
+    let sites = get_recertification_due(pool)?;
+    info!("{} sites are due for recertification", sites.len());
 
-        for site in sites {
-            info!("processing {site}");
-            match site_live(&site[..]).await {
-                Ok(_) => info!("live check succeeded for {site}"),
-                Err(e) => {
-                    error!("site_live check: unable to retrieve {site}: {e:?}; marking bad");
-                    mark_bad(pool, &site[..])?;
-                    continue;
+    for (site_id, site) in sites {
+        if !acquire_lease(
+            pool,
+            RECERTIFICATION_LEASE,
+            instance_id(),
+            VALIDATION_LEASE_TTL,
+        )? {
+            warn!("lost the recertification lease; abandoning sweep");
+            break;
+        }
+
+        if scan_budget_spent(pool, config)? {
+            warn!("today's scanner budget is spent; leaving recertification for tomorrow");
+            break;
+        }
+
+        let site = match permanent_redirect(&site).await {
+            Ok(Some(target)) => {
+                let moved = record(config, format!("move {site} to {target}"), || {
+                    record_redirect(pool, site_id, &site, &target)
+                })?;
+                match moved {
+                    Some(true) => {
+                        info!("{site} has moved permanently to {target}");
+                        target
+                    }
+                    Some(false) => {
+                        warn!("{site} redirects to {target}, which is another entry");
+                        site
+                    }
+                    None => site,
                 }
             }
+            Ok(None) => site,
+            Err(e) => {
+                warn!("recertification: unable to check {site} for redirects: {e:?}");
+                site
+            }
+        };
 
-            match urlscan(&site[..], Handle::current(), config).await {
-                Ok(url) if url.acceptable => {
-                    info!("urlscan complete for '{site}'; marking good");
-                    mark_good(pool, &site[..], url.size)?;
-                }
-                Ok(url) => {
-                    error!(
-                        "site '{site}' exceeds max size (is '{}' bytes); marking bad",
-                        url.size
-                    );
-                    mark_bad_size(pool, &site[..], url.size)?;
-                    continue;
-                }
-                Err(e) => {
-                    error!("urlscan check: unable to scan {site}: {e:?}; marking bad");
-                    mark_bad(pool, &site[..])?;
-                    continue;
-                }
+        let body = match scanner.fetch(&site).await {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("recertification: unable to retrieve {site}: {e:?}");
+                record(
+                    config,
+                    format!("record failed recertification of {site}"),
+                    || record_size(pool, site_id, None, SizeCheck::Recertification, false),
+                )?;
+                continue;
+            }
+        };
+
+        let recertified = match scan_desktop_and_mobile(pool, config, scanner, &site).await {
+            Ok((scan, mobile_size)) if scan.acceptable => {
+                info!("{site} recertified at {} bytes", scan.size);
+                record(config, format!("recertify {site}"), || {
+                    recertify(pool, site_id, scan.size, mobile_size)
+                })?
+                .is_some()
+            }
+            Ok((scan, _)) => {
+                warn!("{site} failed recertification ({} bytes)", scan.size);
+                record(
+                    config,
+                    format!("record failed recertification of {site}"),
+                    || {
+                        record_size(
+                            pool,
+                            site_id,
+                            Some(scan.size),
+                            SizeCheck::Recertification,
+                            false,
+                        )
+                    },
+                )?;
+                false
+            }
+            // Nothing is recorded, so the site is tried again on the next run.
+            Err(e) => {
+                error!("recertification: unable to scan {site}: {e:?}");
+                false
             }
+        };
+
+        if recertified {
+            scan_regions(pool, config, scanner, site_id, &site).await?;
+        }
+
+        if let (true, Some(snapshots)) = (recertified, &config.snapshots) {
+            let html = snapshot_html(&site, &body, snapshots).await;
+            store_snapshot(pool, site_id, &html)?;
+            compare_snapshots(pool, snapshots, site_id, &site)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn process_site(
+    storage: &dyn Storage,
+    pool: &Pool,
+    config: &Config,
+    status: &AnalyzerStatusHandle,
+    events: &EventSender,
+    scanner: &impl Scanner,
+    sources: &impl RelatedSources,
+    site: &String,
+) -> Result<(), Box<dyn Error>> {
+    let body = match scanner.fetch(&site[..]).await {
+        Ok(body) => {
+            info!("live check succeeded for {site}");
+            body
+        }
+        Err(e) => {
+            error!("site_live check: unable to retrieve {site}: {e:?}; marking bad");
+            record(config, format!("mark {site} unreachable"), || {
+                storage.mark_bad(
+                    &site[..],
+                    ValidationOutcome::FailedUnreachable,
+                    &e.to_string(),
+                )
+            })?;
+            return Ok(());
+        }
+    };
+
+    // Mirror spam: the same page under a different URL.  The hashes are kept by SQLite site
+    // id, so they are only compared when the sites are in SQLite too.
+    let duplicate = match storage.sqlite() {
+        Some(pool) => {
+            let hash = content_hash(&body);
+            let duplicate = find_duplicate_content(pool, site, &hash)?;
+            record(config, format!("store content hash for {site}"), || {
+                store_content_hash(pool, site, &hash, duplicate.as_ref().map(|(id, _)| *id))
+            })?;
+            duplicate
+        }
+        None => None,
+    };
+
+    if let Some((_, original)) = duplicate {
+        warn!("site '{site}' has the same content as listed site '{original}'");
+        if config.reject_duplicate_content {
+            record(config, format!("mark {site} duplicate"), || {
+                storage.mark_bad(
+                    &site[..],
+                    ValidationOutcome::FailedDuplicate,
+                    &format!("same content as {original}"),
+                )
+            })?;
+            train_spam(pool, config, site, true);
+            return Ok(());
+        }
+    }
+
+    let (scan, mobile_size) = match scan_desktop_and_mobile(pool, config, scanner, site).await {
+        Ok((url, mobile_size)) if url.acceptable => (url, mobile_size),
+        Ok((url, _)) if url.malicious => {
+            error!("site '{site}' was flagged as malicious; marking bad");
+            record(config, format!("mark {site} malicious"), || {
+                storage.mark_bad(
+                    &site[..],
+                    ValidationOutcome::FailedMalicious,
+                    "flagged malicious by urlscan",
+                )
+            })?;
+            train_spam(pool, config, site, true);
+            return Ok(());
+        }
+        Ok((url, _)) => {
+            error!(
+                "site '{site}' exceeds max size (is '{}' bytes); marking bad",
+                url.size
+            );
+            record(config, format!("mark {site} bad (size)"), || {
+                storage.mark_bad_size(&site[..], url.size)
+            })?;
+            return Ok(());
+        }
+        Err(e) => {
+            error!("urlscan check: unable to scan {site}: {e:?}; marking bad");
+            record(config, format!("mark {site} bad"), || {
+                storage.mark_bad(&site[..], ValidationOutcome::FailedError, &e.to_string())
+            })?;
+            return Err(format!("urlscan failed: {e}").into());
+        }
+    };
+
+    // Related links are stored with the listing, so a listed site always has them.
+    info!("urlscan complete for '{site}'; retrieving related links");
+    let (links, _) = related_links(config, status, sources, site).await;
 
-            info!("retrieving related links for hacker news");
-            let mut links = hackernews(&site, Handle::current()).await?;
-            debug!("hn links: {links:?}");
+    info!("marking '{site}' good with {} related links", links.len());
+    let marked = record(
+        config,
+        format!("mark {site} good with {} related links", links.len()),
+        || storage.mark_good(&site[..], scan.size, mobile_size, &links),
+    )?;
+    if let Some(site_id) = marked {
+        publish(
+            events,
+            SiteEvent::NewSite {
+                site_id,
+                url: site.clone(),
+            },
+        );
+    }
+    let listed = marked.map(|site_id| (site_id, scan));
+
+    if listed.is_some() {
+        train_spam(pool, config, site, false);
+    }
 
-            if links.len() > 5 {
-                debug!("more than 5 links returned, truncating");
-                links = links.into_iter().take(5).collect::<Vec<RelatedLink>>();
+    // Fetched once here so the index can serve icons without visitors contacting the site.
+    // All of it is keyed by SQLite site id, so it is skipped when the sites live elsewhere.
+    if let (Some((site_id, scan)), Some(pool)) = (&listed, storage.sqlite()) {
+        let feeds = discover_feeds(site, &body);
+        if !feeds.is_empty() {
+            info!("found {} feeds on {site}", feeds.len());
+            store_site_feeds(pool, *site_id, &feeds)?;
+        }
+
+        match fetch_favicon(site, &body, config.favicon_max_bytes).await {
+            Ok(favicon) => store_favicon(pool, *site_id, &favicon)?,
+            Err(e) => warn!("no favicon stored for {site}: {e:?}"),
+        }
+
+        if let Some(snapshots) = &config.snapshots {
+            let html = snapshot_html(site, &body, snapshots).await;
+            store_snapshot(pool, *site_id, &html)?;
+            compare_snapshots(pool, snapshots, *site_id, site)?;
+        }
+
+        scan_regions(pool, config, scanner, *site_id, site).await?;
+
+        if let Some(shots) = &config.screenshots {
+            match screenshots::capture(config, shots, site, *site_id, scan).await {
+                Ok((path, thumbnail)) => store_screenshot(pool, *site_id, &path, &thumbnail)?,
+                Err(e) => warn!("no screenshot stored for {site}: {e:?}"),
             }
+        }
+
+        if let Err(e) = announce(pool, config, *site_id, site, Announcement::Listed).await {
+            warn!("unable to announce listing to {site}: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Hacker News and Lobsters discussions of `site`, merged according to each source's
+/// settings, and whether every source answered.
+async fn related_links(
+    config: &Config,
+    status: &AnalyzerStatusHandle,
+    sources: &impl RelatedSources,
+    site: &str,
+) -> (Vec<RelatedLink>, bool) {
+    info!("retrieving related links for hacker news");
+    let hn_links = fetch_related(config, status, "hackernews", sources.hackernews(site)).await;
+    debug!("hn links: {hn_links:?}");
+
+    info!("retrieving related links for lobsters");
+    let lobsters_links = fetch_related(config, status, "lobsters", sources.lobsters(site)).await;
+    debug!("lobsters links: {lobsters_links:?}");
+
+    let complete = hn_links.is_some() && lobsters_links.is_some();
+    let links = merge_related(vec![
+        (
+            &config.related_sources.hackernews,
+            hn_links.unwrap_or_default(),
+        ),
+        (
+            &config.related_sources.lobsters,
+            lobsters_links.unwrap_or_default(),
+        ),
+    ]);
+
+    debug!("combined links: {links:?}");
+    (links, complete)
+}
+
+/// Compare the snapshot just stored for `site` with the one before it, flagging the site as
+/// at risk if it grew by more than the configured amount.
+fn compare_snapshots(
+    pool: &Pool,
+    snapshots: &SnapshotConfig,
+    site_id: u32,
+    site: &str,
+) -> Result<(), Box<dyn Error>> {
+    let pair = get_snapshot_pair(pool, site_id, None).map_err(|TenKbError::Msg(msg)| msg)?;
+    let Some((current, Some(previous))) = pair else {
+        return Ok(());
+    };
+
+    let stats = change_stats(&previous.html, &current.html);
+    let at_risk = stats.growth() > snapshots.at_risk_growth_bytes;
+    if at_risk {
+        warn!(
+            "{site} grew by {} bytes since {}; flagging as at risk",
+            stats.growth(),
+            previous.captured
+        );
+    }
+
+    record_content_change(
+        pool,
+        site_id,
+        &current.captured,
+        &previous.captured,
+        &stats,
+        at_risk,
+    )
+}
+
+/// Teach the spam filter from our verdict on a submission.  A failure only loses one
+/// training example, so it is logged rather than failing the site.
+fn train_spam(pool: &Pool, config: &Config, site: &str, spam: bool) {
+    if config.spam.is_none() {
+        return;
+    }
+
+    let label = if spam { "spam" } else { "ham" };
+    if let Err(e) = record(
+        config,
+        format!("train spam filter on {site} as {label}"),
+        || train(pool, site, spam).map_err(|TenKbError::Msg(msg)| msg.into()),
+    ) {
+        warn!("unable to train spam filter on {site}: {e:?}");
+    }
+}
+
+/// Run a database update, or under `analyzer_dry_run` just log `action` and return `None`.
+fn record<T>(
+    config: &Config,
+    action: impl Display,
+    update: impl FnOnce() -> Result<T, Box<dyn Error>>,
+) -> Result<Option<T>, Box<dyn Error>> {
+    if config.analyzer_dry_run {
+        info!("dry run: would {action}");
+        return Ok(None);
+    }
+
+    update().map(Some)
+}
+
+/// Query a related-link source through its circuit breaker.  A failing or disabled source
+/// contributes no links rather than failing the whole site.
+async fn fetch_related(
+    config: &Config,
+    status: &AnalyzerStatusHandle,
+    source: &str,
+    request: impl Future<Output = RelatedLinkResult>,
+) -> Option<Vec<RelatedLink>> {
+    let limits = &config.related_sources;
+
+    let allowed = status
+        .lock()
+        .unwrap()
+        .related_sources
+        .entry(String::from(source))
+        .or_default()
+        .allow(limits.cooldown_secs);
+
+    if !allowed {
+        warn!("related link source {source} is disabled by its circuit breaker; skipping");
+        return None;
+    }
+
+    let res = request.await;
+
+    let mut status = status.lock().unwrap();
+    let health = status
+        .related_sources
+        .entry(String::from(source))
+        .or_default();
+
+    match res {
+        Ok(links) => {
+            health.record_success();
+            Some(links)
+        }
+        Err(e) => {
+            error!("unable to retrieve related links from {source}: {e:?}");
+            health.record_failure(e.to_string(), limits.failure_threshold);
+            None
+        }
+    }
+}
+
+/// Scan `site`, reusing a recent result for the same canonical URL if there is one.  Dry
+/// runs always scan afresh and don't cache, since cached verdicts reflect the old limits.
+/// Mobile scans are cached separately, under the URL with a `#mobile` fragment.
+async fn scan_site(
+    pool: &Pool,
+    config: &Config,
+    scanner: &impl Scanner,
+    site: &str,
+    vantage: ScanVantage<'_>,
+) -> Result<UrlScan, Box<dyn Error>> {
+    if config.analyzer_dry_run {
+        return counted_scan(pool, config, scanner, site, vantage).await;
+    }
+
+    let mut canonical = canonical_url(site)?;
+    if vantage.user_agent.is_some() {
+        canonical.push_str("#mobile");
+    }
+
+    if let Some(scan) = get_cached_scan(pool, &canonical, config.scan_cache_ttl_secs)? {
+        info!("using cached scan for {canonical}: {scan:?}");
+        return Ok(scan);
+    }
+
+    let scan = counted_scan(pool, config, scanner, site, vantage).await?;
+    cache_scan(pool, &canonical, &scan)?;
+
+    Ok(scan)
+}
 
-            info!("retrieving related links for lobsters");
-            let mut lobsters_links = lobsters(&site, Handle::current()).await?;
-            debug!("lobsters links: {lobsters_links:?}");
+/// Scan `site` as a desktop browser and, if that passes and `mobile_scans` is configured,
+/// as a phone, returning the scan that decides the site's fate along with its mobile size.
+/// When mobile scans are `required`, a failed (or failing) mobile scan decides it;
+/// otherwise the desktop scan does and the mobile size is only for the record.
+async fn scan_desktop_and_mobile(
+    pool: &Pool,
+    config: &Config,
+    scanner: &impl Scanner,
+    site: &str,
+) -> Result<(UrlScan, Option<f64>), Box<dyn Error>> {
+    let scan = scan_site(pool, config, scanner, site, ScanVantage::default()).await?;
+    let (Some(mobile), true) = (&config.mobile_scans, scan.acceptable) else {
+        return Ok((scan, None));
+    };
+
+    let vantage = ScanVantage {
+        user_agent: Some(&mobile.user_agent),
+        ..ScanVantage::default()
+    };
+    match scan_site(pool, config, scanner, site, vantage).await {
+        Ok(phone) if mobile.required && !phone.acceptable => {
+            warn!(
+                "{site} passes on the desktop but is {} bytes on mobile",
+                phone.size
+            );
+            let size = phone.size;
+            Ok((phone, Some(size)))
+        }
+        Ok(phone) => {
+            info!("{site} is {} bytes on mobile", phone.size);
+            Ok((scan, Some(phone.size)))
+        }
+        Err(e) if mobile.required => Err(e),
+        Err(e) => {
+            warn!("unable to scan {site} as a phone: {e:?}");
+            Ok((scan, None))
+        }
+    }
+}
+
+/// Scan `site` with the scanner, counting the call against today's budget whether or not
+/// it succeeds.
+async fn counted_scan(
+    pool: &Pool,
+    config: &Config,
+    scanner: &impl Scanner,
+    site: &str,
+    vantage: ScanVantage<'_>,
+) -> UrlScanResult {
+    let scan = scanner.scan(site, vantage, config).await;
+    record_scanner_call(pool)?;
+    scan
+}
+
+/// Scan a listed site again from each of the `regional_scans` countries, storing the size
+/// seen from each.  A site that is over `max_size_ratio` times larger in one country than
+/// another is serving geography-dependent payloads, from a bloated CDN or from cloaking,
+/// and is flagged on the admin dashboard.  Countries left when the day's scanner budget
+/// runs out are skipped until the site is next scanned.
+async fn scan_regions(
+    pool: &Pool,
+    config: &Config,
+    scanner: &impl Scanner,
+    site_id: u32,
+    site: &str,
+) -> Result<(), Box<dyn Error>> {
+    let Some(regional) = &config.regional_scans else {
+        return Ok(());
+    };
+
+    let mut sizes = vec![];
+    for country in &regional.countries {
+        if scan_budget_spent(pool, config)? {
+            warn!("today's scanner budget is spent; not scanning {site} from {country}");
+            break;
+        }
 
-            if lobsters_links.len() > 5 {
-                debug!("more than 5 links retruned, truncating");
-                lobsters_links = lobsters_links
-                    .into_iter()
-                    .take(5)
-                    .collect::<Vec<RelatedLink>>();
+        let vantage = ScanVantage {
+            country: Some(country),
+            ..ScanVantage::default()
+        };
+        match counted_scan(pool, config, scanner, site, vantage).await {
+            Ok(scan) => {
+                info!("{site} is {} bytes from {country}", scan.size);
+                record(config, format!("store {country} size of {site}"), || {
+                    store_regional_size(pool, site_id, country, scan.size)
+                })?;
+                sizes.push((country, scan.size));
             }
+            Err(e) => warn!("unable to scan {site} from {country}: {e:?}"),
+        }
+    }
+
+    let smallest = sizes.iter().min_by(|a, b| a.1.total_cmp(&b.1));
+    let largest = sizes.iter().max_by(|a, b| a.1.total_cmp(&b.1));
+    if let (Some((small, min)), Some((large, max))) = (smallest, largest) {
+        if *max > regional.max_size_ratio * min {
+            warn!("{site} is {min} bytes from {small} but {max} bytes from {large}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether today's `scanner_daily_budget` is spent, in which case sites wait for tomorrow
+/// rather than being processed.
+fn scan_budget_spent(pool: &Pool, config: &Config) -> Result<bool, Box<dyn Error>> {
+    let Some(budget) = config.scanner_daily_budget else {
+        return Ok(false);
+    };
+
+    let calls = get_scanner_calls_today(pool).map_err(|TenKbError::Msg(msg)| msg)?;
+    Ok(calls >= budget)
+}
 
-            links.extend(lobsters_links);
+/// How the analyzer looks at a site: fetching its page and measuring it.  `NetworkScanner`
+/// does this for real; `testing::MockScanner` answers from canned pages.
+pub trait Scanner: Send + Sync {
+    /// Fetch `url`, returning the page body if it answered with a 200.
+    fn fetch(&self, url: &str) -> impl Future<Output = Result<Vec<u8>, Box<dyn Error>>> + Send;
 
-            debug!("combined links: {links:?}");
+    /// Measure `site` as seen from `vantage` and check it for malware.
+    fn scan(
+        &self,
+        site: &str,
+        vantage: ScanVantage<'_>,
+        config: &Config,
+    ) -> impl Future<Output = UrlScanResult> + Send;
+}
+
+/// Fetches sites directly and scans them with Cloudflare's URL scanner.
+pub struct NetworkScanner;
 
-            info!("updating related links in database");
-            update_related(pool, &site[..], links)?;
+impl Scanner for NetworkScanner {
+    async fn fetch(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let req = resolver::client().get(url).send().await?;
+        if req.status() != 200 {
+            Err(format!("status code is {}", req.status()).into())
+        } else {
+            Ok(req.bytes().await?.to_vec())
         }
     }
+
+    async fn scan(&self, site: &str, vantage: ScanVantage<'_>, config: &Config) -> UrlScanResult {
+        urlscan(site, vantage, Handle::current(), config).await
+    }
 }
 
-async fn site_live(url: &str) -> Result<(), Box<dyn Error>> {
-    let req = reqwest::get(url).await?;
-    if req.status() != 200 {
-        Err(format!("status code is {}", req.status()).into())
-    } else {
-        Ok(())
+/// Where `url` has permanently moved to: the end of its chain of 301 and 308 redirects,
+/// stopping at the first temporary one.  `None` if it doesn't permanently redirect.
+async fn permanent_redirect(url: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let client = resolver::client_builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let mut current = Url::parse(url)?;
+    for _ in 0..MAX_REDIRECTS {
+        let res = client.get(current.clone()).send().await?;
+        if !matches!(res.status().as_u16(), 301 | 308) {
+            break;
+        }
+
+        let Some(location) = res
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+        else {
+            break;
+        };
+        current = current.join(location)?;
     }
+
+    Ok(Some(current.to_string()).filter(|target| target != url))
+}
+
+fn content_hash(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
 }
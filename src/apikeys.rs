@@ -0,0 +1,198 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! API keys and per-key request quotas for the JSON API.  Requests with an `X-API-Key`
+//! header are counted against that key's quota; anonymous requests are counted per client IP
+//! against the anonymous quota.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue, RETRY_AFTER},
+    middleware::Next,
+    web, Error, HttpResponse,
+};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{blocking, config::Config, database::get_api_key, database::Pool, get_client_ip};
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiKey {
+    pub id: u32,
+    pub name: String,
+    pub quota: u32,
+    pub created: String,
+    pub revoked: bool,
+}
+
+/// Generate a new random API key, returning the key (shown to the operator once) and the
+/// hash that is stored in the database.
+pub fn generate_key() -> (String, String) {
+    let mut rand_bytes = [0u8; 24];
+    thread_rng().fill(&mut rand_bytes);
+
+    let key = format!("10kb_{}", hex::encode(rand_bytes));
+    let hash = hash_key(&key);
+    (key, hash)
+}
+
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+/// Fixed-window request counters, keyed by API key id or client IP.
+pub struct RateLimiter {
+    window: Duration,
+    counters: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: Duration,
+    pub allowed: bool,
+}
+
+impl RateLimiter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            counters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count one request against `bucket`.
+    pub fn check(&self, bucket: &str, limit: u32) -> RateLimit {
+        let now = Instant::now();
+        let mut counters = self.counters.lock().unwrap();
+
+        // Forget windows that have already ended so the map doesn't grow without bound.
+        if counters.len() > 10_000 {
+            counters.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+        }
+
+        let (start, count) = counters.entry(String::from(bucket)).or_insert((now, 0));
+        if now.duration_since(*start) >= self.window {
+            *start = now;
+            *count = 0;
+        }
+
+        let allowed = *count < limit;
+        if allowed {
+            *count += 1;
+        }
+
+        RateLimit {
+            limit,
+            remaining: limit - *count,
+            reset: self.window.saturating_sub(now.duration_since(*start)),
+            allowed,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RateLimitResponse {
+    code: usize,
+    status: String,
+}
+
+fn reject(code: u16, status: &str) -> HttpResponse {
+    HttpResponse::build(actix_web::http::StatusCode::from_u16(code).unwrap()).json(
+        RateLimitResponse {
+            code: code as usize,
+            status: String::from(status),
+        },
+    )
+}
+
+/// Middleware enforcing API quotas and adding `X-RateLimit-*` headers to every response.
+pub async fn rate_limit_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let (Some(limiter), Some(pool), Some(config)) = (
+        req.app_data::<web::Data<RateLimiter>>().cloned(),
+        req.app_data::<web::Data<Pool>>().cloned(),
+        req.app_data::<web::Data<Config>>().cloned(),
+    ) else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    let api_key = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|key| key.to_str().ok())
+        .map(hash_key);
+
+    let (bucket, limit) = match api_key {
+        Some(hash) => match blocking::block("rate_limit_middleware.get_api_key", move || {
+            get_api_key(&pool, &hash)
+        })
+        .await?
+        {
+            Ok(Some(key)) if !key.revoked => (format!("key:{}", key.id), key.quota),
+            _ => {
+                let res = reject(401, "invalid API key");
+                return Ok(req.into_response(res).map_into_right_body());
+            }
+        },
+        None => {
+            let client_ip = get_client_ip(req.request()).unwrap_or_default();
+            (format!("ip:{client_ip}"), config.api.anonymous_quota)
+        }
+    };
+
+    let status = limiter.check(&bucket, limit);
+
+    let mut res = if status.allowed {
+        next.call(req).await?.map_into_left_body()
+    } else {
+        let mut res = reject(429, "rate limit exceeded");
+        res.headers_mut().insert(
+            RETRY_AFTER,
+            HeaderValue::from(status.reset.as_secs().max(1)),
+        );
+        req.into_response(res).map_into_right_body()
+    };
+
+    let headers = res.headers_mut();
+    for (name, value) in [
+        ("x-ratelimit-limit", status.limit as u64),
+        ("x-ratelimit-remaining", status.remaining as u64),
+        ("x-ratelimit-reset", status.reset.as_secs()),
+    ] {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+
+    Ok(res)
+}
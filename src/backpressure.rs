@@ -0,0 +1,128 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A cap on the requests being handled at once (`limits.in_flight`).  Each request holds a
+//! permit until its response is ready; with none left, the server answers 503 and a
+//! `Retry-After` straight away instead of queueing work behind busy workers and a
+//! saturated `web::block` pool.
+
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderValue, RETRY_AFTER},
+    middleware::Next,
+    web, Error, ResponseError,
+};
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::error::{wants_html, HtmlError, JsonError};
+
+const BUSY_MESSAGE: &str = "The server is busy; please try again shortly.";
+
+#[derive(Debug)]
+pub struct InFlight {
+    permits: Arc<Semaphore>,
+    limit: usize,
+    retry_after: Duration,
+    rejected: AtomicU64,
+}
+
+impl InFlight {
+    pub fn new(limit: usize, retry_after: Duration) -> Self {
+        let limit = limit.min(Semaphore::MAX_PERMITS);
+        Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            limit,
+            retry_after,
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Requests being handled right now.
+    pub fn current(&self) -> usize {
+        self.limit - self.permits.available_permits()
+    }
+
+    /// Prometheus text for `/admin/metrics`.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let metrics = [
+            ("in_flight_requests", "gauge", self.current() as u64),
+            ("max_in_flight_requests", "gauge", self.limit as u64),
+            (
+                "busy_rejections_total",
+                "counter",
+                self.rejected.load(Ordering::Relaxed),
+            ),
+        ];
+        for (metric, kind, value) in metrics {
+            let _ = writeln!(out, "# TYPE tenkb_{metric} {kind}");
+            let _ = writeln!(out, "tenkb_{metric} {value}");
+        }
+        out
+    }
+}
+
+/// Middleware holding an `InFlight` permit for each request, or answering 503 when there
+/// are none.  Does nothing unless an `InFlight` is registered as app data.
+pub async fn in_flight_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(in_flight) = req.app_data::<web::Data<InFlight>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    if let Ok(_permit) = in_flight.permits.clone().try_acquire_owned() {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    // Every hundredth, so a surge is visible in the logs without flooding them.
+    let rejected = in_flight.rejected.fetch_add(1, Ordering::Relaxed) + 1;
+    if rejected % 100 == 1 {
+        warn!(
+            "{} requests in flight; answering 503 ({rejected} so far)",
+            in_flight.limit
+        );
+    }
+
+    let mut res = if wants_html(req.headers()) {
+        HtmlError::new(503, BUSY_MESSAGE).error_response()
+    } else {
+        JsonError::new(503, BUSY_MESSAGE).error_response()
+    };
+    res.headers_mut().insert(
+        RETRY_AFTER,
+        HeaderValue::from(in_flight.retry_after.as_secs().max(1)),
+    );
+    Ok(req.into_response(res).map_into_right_body())
+}
@@ -0,0 +1,146 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Command-line administration of a 10KB Club instance, working directly on its database.
+
+use clap::{Parser, Subcommand};
+use std::{env, path::PathBuf, process::ExitCode, sync::Arc};
+
+use tenkbclub::{
+    config::{Config, StorageBackend},
+    database::init_db,
+    error::TenKbError,
+    export::{export_asset, export_static},
+    i18n::Catalogs,
+    seed::{self, SeedOptions},
+    templates::Templates,
+};
+
+#[derive(Parser)]
+#[command(version, about = "Administrative tasks for a 10KB Club instance")]
+struct Args {
+    /// Configuration file; defaults to $TENKB_CONFIG, then /etc/tenkb.json.
+    #[arg(long)]
+    config: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Render the public site to static files in DIR, for a read-only mirror.
+    ExportStatic { dir: PathBuf },
+    /// Fill an empty database with fake sites, votes, and discussions, creating it if need
+    /// be.  The same seed always gives the same data.
+    Seed {
+        #[arg(long, default_value_t = 500)]
+        sites: u32,
+        #[arg(long, default_value_t = 10_000)]
+        votes: u32,
+        #[arg(long, default_value_t = 1)]
+        seed: u64,
+    },
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let path = args
+        .config
+        .unwrap_or_else(|| env::var("TENKB_CONFIG").unwrap_or("/etc/tenkb.json".into()));
+
+    let config = match Config::load(&path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("unable to load {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Exports and seeding read and write `database_path` only.
+    if config.storage.backend != StorageBackend::Sqlite {
+        eprintln!("tenkb_admin only works with the sqlite storage backend");
+        return ExitCode::FAILURE;
+    }
+
+    match args.command {
+        Command::ExportStatic { dir } => {
+            let catalogs =
+                match Catalogs::load(config.locale_path.as_deref(), &config.default_locale) {
+                    Ok(catalogs) => Arc::new(catalogs),
+                    Err(e) => {
+                        eprintln!("unable to load translations: {e}");
+                        return ExitCode::FAILURE;
+                    }
+                };
+            let templates = Templates::new(&config, catalogs);
+            let pool = init_db(&config.database_path, None);
+
+            let res = export_static(&pool, &config, &templates, &dir).and_then(|stats| {
+                export_asset(
+                    &dir,
+                    "/10kb.css",
+                    include_str!("/home/marcusb/code/10kbclub/static/10kb.css"),
+                )?;
+                export_asset(
+                    &dir,
+                    "/10kb.js",
+                    include_str!("/home/marcusb/code/10kbclub/static/10kb.js"),
+                )?;
+                Ok(stats)
+            });
+
+            match res {
+                Ok(stats) => {
+                    println!(
+                        "exported {} index pages, {} sites, and {} other files to {}",
+                        stats.index_pages,
+                        stats.sites,
+                        stats.files + 2,
+                        dir.display()
+                    );
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("export failed: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Seed { sites, votes, seed } => {
+            let pool = init_db(&config.database_path, None);
+            match seed::seed(&pool, &SeedOptions { sites, votes, seed }) {
+                Ok(stats) => {
+                    println!(
+                        "added {} sites, {} voters, {} votes, and {} related links",
+                        stats.sites, stats.voters, stats.votes, stats.related
+                    );
+                    ExitCode::SUCCESS
+                }
+                Err(TenKbError::Msg(msg)) => {
+                    eprintln!("seeding failed: {msg}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
+}
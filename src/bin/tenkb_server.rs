@@ -20,84 +20,609 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use std::{env, str};
+use std::{
+    env, str,
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
 
 use actix_web::{
-    get, http::header::ContentType, post, web, App, HttpRequest, HttpResponse, HttpServer,
-    Responder, Result,
+    cookie::Key,
+    get,
+    guard::GuardContext,
+    http::{
+        header::{self, ContentType},
+        Method,
+    },
+    middleware, post, web, App, Either, HttpRequest, HttpResponse, HttpServer, Responder, Result,
 };
-use minijinja::{context, Environment};
+use minijinja::context;
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, Level};
-use tracing_subscriber::FmtSubscriber;
-use url::Url;
+use tracing::{error, info, warn};
+use url::{form_urlencoded, Url};
 
 use tenkbclub::{
-    analyzer::analyzer,
-    config::{Config, LogLevel},
+    admin::admin_auth_middleware,
+    analytics::{self, analytics_middleware},
+    analyzer::{
+        recertification_sweep, validation_sweep, AnalyzerStatus, AnalyzerStatusHandle,
+        AnalyzerWakeup, NetworkScanner,
+    },
+    apikeys::{generate_key, rate_limit_middleware, RateLimiter},
+    backpressure::{in_flight_middleware, InFlight},
+    blocking, check_path_policy,
+    cloudflare::SIZE_LIMIT,
+    config::{Config, PathPolicy},
     database::{
-        cast_vote, generate_id, get_related, get_site_count, get_site_url, get_sites, get_votes,
-        init_db, submit_site, Pool,
+        add_comment, add_country_counts, add_discussion_suggestion, add_shadow_flag,
+        check_site_active, check_site_pending, clear_featured, create_api_key, delete_webmention,
+        find_site, flag_homograph, get_at_risk_changes, get_comment_body, get_comments,
+        get_country_counts, get_discussion_suggestions, get_duplicate_content, get_favicon,
+        get_featured_sites, get_homograph_flags, get_member_hosts, get_moderation_queue,
+        get_newest_sites, get_newest_validation, get_peer_sites, get_planet_posts,
+        get_regional_differences, get_scanner_calls_today, get_scanner_usage, get_sent_webmentions,
+        get_shadow_flags, get_shadowed_comments, get_shadowed_voters, get_site_detail,
+        get_site_record, get_site_url, get_snapshot, get_snapshot_pair, get_spam_scores,
+        get_thumbnail, get_vote_anomalies, get_webmentions, init_db, init_replica,
+        invalidate_vote_anomaly, is_shadowed, list_api_keys, merge_sites, moderate_comment,
+        moderate_discussion_suggestion, record_spam_score, record_vote_bursts, record_vote_cohorts,
+        remove_shadow_flag, requeue_site, review_shadowed_votes, revoke_api_key, set_featured,
+        set_note, store_webmention, CommentStatus, Pool, QueuePriority, ReadPool, ShadowKind,
+    },
+    discussions,
+    error::{
+        self, body_error, form_error, json_error, query_error, wants_json, BodyError, HtmlError,
+        JsonError, TenKbError,
     },
-    error::{HtmlError, JsonError},
-    get_client_ip, get_page_links, SortOptions,
+    etag,
+    events::{self, forward_events, publish, EventSender, SiteEvent},
+    federation::{self, federation_sweep},
+    feeds::{FeedVersion, JsonFeed, FEED_LENGTH},
+    flash::{self, flash_middleware, Flash},
+    geoip::{geoip_middleware, GeoIp},
+    get_client_ip, get_page_links,
+    head::head_middleware,
+    i18n::{locale_middleware, Catalogs},
+    idn::find_homograph,
+    index_uri, listeners, logging, owners,
+    page::PageContext,
+    planet::planet_sweep,
+    poolstats::{self, PoolStats, PoolStatsHandle},
+    prefs::{self, prefs_middleware, Prefs, Theme, View},
+    preview,
+    rankings::{Period, TopCache},
+    readonly::{is_read_only, read_only_middleware, set_read_only, ReadOnlyHandle},
+    relatedlinks::{LiveSources, RelatedLink},
+    replication::{self, Replicator},
+    resolver,
+    scheduler::Job,
+    site_domain,
+    snapshots::{change_stats, diff_lines},
+    spam::{is_spam, score, train, Sample, SpamKind},
+    storage::{self, ReadStorage, Storage},
+    systemd,
+    templates::Templates,
+    uptime::uptime_sweep,
+    webmention::{announce, target_site, verify, Announcement, Verification, Webmention},
+    Comment, FeaturedSite, Site, SiteDetail, SiteFilter, SortOptions,
 };
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let config = Config::load(&env::var("TENKB_CONFIG").unwrap_or("/etc/tenkb.json".into())[..])?;
-
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(match config.log_level {
-            LogLevel::Info => Level::INFO,
-            LogLevel::Warn => Level::WARN,
-            LogLevel::Debug => Level::DEBUG,
-            LogLevel::Trace => Level::TRACE,
-        })
-        .without_time()
-        .finish();
+    let mut config =
+        Config::load(&env::var("TENKB_CONFIG").unwrap_or("/etc/tenkb.json".into())[..])?;
+    if env::args().any(|arg| arg == "--dry-run") {
+        config.analyzer_dry_run = true;
+    }
+
+    let _log_guard = logging::init(&config)?;
+    if config.analyzer_dry_run {
+        info!("analyzer dry run: verdicts will be logged, not recorded");
+    }
+
+    // Handler panics, 5xx responses, and anything logged at error level are reported.
+    let _sentry = match &config.sentry {
+        Some(sentry_config) => {
+            let dsn: sentry::types::Dsn = sentry_config
+                .dsn
+                .parse()
+                .map_err(|e| std::io::Error::other(format!("invalid Sentry DSN: {e}")))?;
+            let mut options = sentry::ClientOptions::new()
+                .maybe_release(sentry::release_name!())
+                .sample_rate(sentry_config.sample_rate);
+            options.dsn = Some(dsn);
+
+            Some(sentry::init(match &sentry_config.environment {
+                Some(environment) => options.environment(environment.clone()),
+                None => options,
+            }))
+        }
+        None => None,
+    };
+
+    resolver::configure(&config.resolver).map_err(|e| std::io::Error::other(e.to_string()))?;
+    error::configure(&config);
+
+    if let Some(replication) = &config.replication {
+        replication::restore(replication, &config.database_path)?;
+    }
+
+    let slow_checkout = Duration::from_millis(config.pool.slow_checkout_ms);
+    let primary_stats = PoolStats::new("primary", slow_checkout);
+    let pool = init_db(&config.database_path, Some(&primary_stats));
+    let replicator = match &config.replication {
+        Some(replication) => {
+            replication::enable_wal(&pool)
+                .map_err(|TenKbError::Msg(msg)| std::io::Error::other(msg))?;
+            Some(Replicator::spawn(replication, &config.database_path)?)
+        }
+        None => None,
+    };
 
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("Could not set default global tracing subscriber");
+    let mut pool_stats = vec![(pool.clone(), primary_stats)];
+    let read_pool = web::Data::new(ReadPool(match &config.read_replica {
+        Some(path) => {
+            info!("serving listings from the replica at {path:?}");
+            let replica_stats = PoolStats::new("replica", slow_checkout);
+            let replica = init_replica(path, Some(&replica_stats));
+            pool_stats.push((replica.clone(), replica_stats));
+            replica
+        }
+        None => pool.clone(),
+    }));
+    let pool_stats = web::Data::new(PoolMetrics(pool_stats));
+    let (storage, read_storage) = storage::open(&config, &pool, &read_pool.0)?;
 
-    let pool = init_db(&config.database_path);
+    let analyzer_status = AnalyzerStatusHandle::default();
+    let analyzer_wakeup = AnalyzerWakeup::default();
+    let events = events::channel();
 
+    let analyzer_storage = storage.clone();
     let analyzer_pool = pool.clone();
     let analyzer_config = config.clone();
-    tokio::task::spawn(async move {
-        loop {
-            match analyzer(&analyzer_pool, &analyzer_config).await {
-                Ok(_) => error!("analyzer exited unexpectedly with Ok. Restarting."),
-                Err(e) => error!("analyzer exited with error: {e:?}. Restarting."),
+    let status = analyzer_status.clone();
+    let analyzer_events = events.clone();
+    Job::new("validation", &config.schedules.validation)
+        .map_err(std::io::Error::other)?
+        .with_wakeup(analyzer_wakeup.clone())
+        .spawn(move || {
+            let storage = analyzer_storage.clone();
+            let pool = analyzer_pool.clone();
+            let config = analyzer_config.clone();
+            let status = status.clone();
+            let events = analyzer_events.clone();
+            async move {
+                if let Err(e) = validation_sweep(
+                    &*storage,
+                    &pool,
+                    &config,
+                    &status,
+                    &events,
+                    &NetworkScanner,
+                    &LiveSources,
+                )
+                .await
+                {
+                    error!("validation sweep failed: {e:?}");
+                }
+            }
+        });
+
+    if config.recertification {
+        let recert_pool = pool.clone();
+        let recert_config = config.clone();
+        Job::new("recertification", &config.schedules.recertification)
+            .map_err(std::io::Error::other)?
+            .spawn(move || {
+                let pool = recert_pool.clone();
+                let config = recert_config.clone();
+                async move {
+                    if let Err(e) = recertification_sweep(&pool, &config, &NetworkScanner).await {
+                        error!("recertification sweep failed: {e:?}");
+                    }
+                }
+            });
+    }
+
+    if config.uptime_checks {
+        let uptime_pool = pool.clone();
+        Job::new("uptime", &config.schedules.uptime)
+            .map_err(std::io::Error::other)?
+            .spawn(move || {
+                let pool = uptime_pool.clone();
+                async move {
+                    if let Err(e) = uptime_sweep(&pool, &NetworkScanner).await {
+                        error!("uptime sweep failed: {e:?}");
+                    }
+                }
+            });
+    }
+
+    if config.federation.is_some() {
+        let federation_pool = pool.clone();
+        let federation_config = config.clone();
+        Job::new("federation", &config.schedules.federation)
+            .map_err(std::io::Error::other)?
+            .spawn(move || {
+                let pool = federation_pool.clone();
+                let config = federation_config.clone();
+                async move {
+                    if let Err(e) = federation_sweep(&pool, &config).await {
+                        error!("federation sweep failed: {e:?}");
+                    }
+                }
+            });
+    }
+
+    if config.planet.is_some() {
+        let planet_pool = pool.clone();
+        let planet_config = config.clone();
+        Job::new("planet", &config.schedules.planet)
+            .map_err(std::io::Error::other)?
+            .spawn(move || {
+                let pool = planet_pool.clone();
+                let config = planet_config.clone();
+                async move {
+                    if let Err(e) = planet_sweep(&pool, &config).await {
+                        error!("planet sweep failed: {e:?}");
+                    }
+                }
+            });
+    }
+
+    let snapshot_storage = storage.clone();
+    Job::new("vote_snapshots", &config.schedules.vote_snapshots)
+        .map_err(std::io::Error::other)?
+        .spawn(move || {
+            let storage = snapshot_storage.clone();
+            async move {
+                match blocking::block("snapshot_vote_counts", move || {
+                    storage.snapshot_vote_counts()
+                })
+                .await
+                {
+                    Ok(Ok(count)) => info!("recorded vote counts for {count} sites"),
+                    Ok(Err(e)) => error!("unable to record vote counts: {e:?}"),
+                    Err(e) => error!("unable to record vote counts: {e:?}"),
+                }
             }
+        });
+
+    if let Some(anomalies) = config.vote_anomalies.clone() {
+        let anomaly_pool = pool.clone();
+        Job::new("vote_anomalies", &config.schedules.vote_anomalies)
+            .map_err(std::io::Error::other)?
+            .spawn(move || {
+                let pool = anomaly_pool.clone();
+                let anomalies = anomalies.clone();
+                async move {
+                    let found = blocking::block("vote_anomalies", move || {
+                        Ok::<_, TenKbError>((
+                            record_vote_bursts(
+                                &pool,
+                                anomalies.burst_votes,
+                                anomalies.burst_minutes,
+                            )?,
+                            record_vote_cohorts(
+                                &pool,
+                                anomalies.cohort_voters,
+                                anomalies.cohort_sites,
+                                anomalies.new_voter_hours,
+                            )?,
+                        ))
+                    })
+                    .await;
+                    match found {
+                        Ok(Ok((0, 0))) => {}
+                        Ok(Ok((bursts, cohorts))) => {
+                            warn!("found {bursts} vote bursts and {cohorts} voter cohorts")
+                        }
+                        Ok(Err(e)) => error!("vote anomaly check failed: {e:?}"),
+                        Err(e) => error!("vote anomaly check failed: {e:?}"),
+                    }
+                }
+            });
+    }
+
+    // Run a validation sweep at startup rather than waiting for the first scheduled run.
+    analyzer_wakeup.notify_one();
+
+    let catalogs = Arc::new(Catalogs::load(
+        config.locale_path.as_deref(),
+        &config.default_locale,
+    )?);
+    let templates = web::Data::new(Templates::new(&config, catalogs.clone()));
+    let cookie_key = prefs::cookie_key(&config).map_err(std::io::Error::other)?;
+    let rate_limiter = web::Data::new(RateLimiter::new(Duration::from_secs(
+        config.api.window_secs,
+    )));
+
+    let top_cache = web::Data::new(TopCache::new(
+        Duration::from_secs(config.top.cache_secs),
+        config.top.limit,
+    ));
+
+    let rescan_limiter = web::Data::new(RescanLimiter(RateLimiter::new(Duration::from_secs(
+        config.rescan.window_secs,
+    ))));
+
+    let comment_limiter = web::Data::new(CommentLimiter(RateLimiter::new(Duration::from_secs(
+        config
+            .comments
+            .as_ref()
+            .map_or(0, |comments| comments.window_secs),
+    ))));
+
+    let refresh_limiter = web::Data::new(RefreshLimiter(RateLimiter::new(Duration::from_secs(
+        config.related_refresh.window_secs,
+    ))));
+
+    let discussion_limiter =
+        web::Data::new(DiscussionLimiter(RateLimiter::new(Duration::from_secs(
+            config
+                .discussions
+                .as_ref()
+                .map_or(0, |discussions| discussions.window_secs),
+        ))));
+
+    let read_only: ReadOnlyHandle = Arc::new(AtomicBool::new(config.read_only));
+    if config.read_only {
+        info!("starting in read-only mode");
+    }
+
+    let analytics_sender = match &config.analytics {
+        Some(analytics) => {
+            let analytics_pool = analytics::open(&analytics.database_path).map_err(|e| {
+                std::io::Error::other(format!("unable to open analytics database: {e:?}"))
+            })?;
+            let retention_days = analytics.retention_days;
+
+            let prune_pool = analytics_pool.clone();
+            Job::new("analytics_prune", &config.schedules.analytics_prune)
+                .map_err(std::io::Error::other)?
+                .spawn(move || {
+                    let pool = prune_pool.clone();
+                    async move {
+                        match blocking::block("analytics_prune", move || {
+                            analytics::prune(&pool, retention_days)
+                        })
+                        .await
+                        {
+                            Ok(Ok(_)) => {}
+                            Ok(Err(e)) => error!("access log pruning failed: {e:?}"),
+                            Err(e) => error!("access log pruning failed: {e:?}"),
+                        }
+                    }
+                });
+
+            Some(analytics::spawn_writer(
+                analytics_pool,
+                analytics.batch_size,
+                Duration::from_secs(analytics.flush_secs),
+            ))
+        }
+        None => None,
+    };
+
+    let geoip = match &config.geoip_database {
+        Some(path) => {
+            let geoip = Arc::new(GeoIp::open(path).map_err(std::io::Error::other)?);
+
+            let flush_geoip = geoip.clone();
+            let flush_pool = pool.clone();
+            Job::new("geoip_flush", &config.schedules.geoip_flush)
+                .map_err(std::io::Error::other)?
+                .spawn(move || {
+                    let counts = flush_geoip.take();
+                    let pool = flush_pool.clone();
+                    async move {
+                        if counts.is_empty() {
+                            return;
+                        }
+                        match blocking::block("add_country_counts", move || {
+                            add_country_counts(&pool, &counts)
+                        })
+                        .await
+                        {
+                            Ok(Ok(())) => {}
+                            Ok(Err(e)) => error!("unable to save country stats: {e:?}"),
+                            Err(e) => error!("unable to save country stats: {e:?}"),
+                        }
+                    }
+                });
+
+            Some(geoip)
         }
+        None => None,
+    };
+
+    let in_flight = (config.limits.in_flight > 0).then(|| {
+        web::Data::new(InFlight::new(
+            config.limits.in_flight,
+            Duration::from_secs(config.limits.busy_retry_secs),
+        ))
     });
 
-    let mut env = Environment::new();
-    env.set_loader(minijinja::path_loader(config.template_path));
+    let app_config = config.clone();
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         let app = App::new()
+            .wrap(middleware::Condition::new(
+                app_config.sentry.is_some(),
+                sentry::integrations::actix::Sentry::new(),
+            ))
+            .wrap(middleware::from_fn(analytics_middleware))
+            .wrap(middleware::from_fn(geoip_middleware))
+            .wrap(middleware::from_fn(flash_middleware))
+            .wrap(middleware::from_fn(locale_middleware))
+            .wrap(middleware::from_fn(prefs_middleware))
+            .wrap(middleware::from_fn(read_only_middleware))
+            .wrap(middleware::from_fn(head_middleware))
+            .wrap(middleware::from_fn(in_flight_middleware))
+            .app_data(web::Data::new(catalogs.clone()))
+            .app_data(web::Data::new(cookie_key.clone()))
+            .app_data(web::Data::new(app_config.clone()))
             .app_data(web::Data::new(pool.clone()))
-            .app_data(web::Data::new(env.clone()))
+            .app_data(read_pool.clone())
+            .app_data(web::Data::from(storage.clone()))
+            .app_data(web::Data::new(read_storage.clone()))
+            .app_data(pool_stats.clone())
+            .app_data(templates.clone())
+            .app_data(web::Data::new(analyzer_status.clone()))
+            .app_data(web::Data::new(analyzer_wakeup.clone()))
+            .app_data(web::Data::new(events.clone()))
+            .app_data(rate_limiter.clone())
+            .app_data(rescan_limiter.clone())
+            .app_data(top_cache.clone())
+            .app_data(comment_limiter.clone())
+            .app_data(discussion_limiter.clone())
+            .app_data(refresh_limiter.clone())
+            .app_data(web::Data::new(read_only.clone()))
+            .app_data(
+                web::FormConfig::default()
+                    .limit(app_config.limits.form_bytes)
+                    .error_handler(form_error),
+            )
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(app_config.limits.form_bytes)
+                    .error_handler(json_error),
+            )
+            .app_data(web::QueryConfig::default().error_handler(query_error))
             .service(index)
             .service(submit)
             .service(submithtml)
+            .service(prefshtml)
+            .service(save_prefs)
             .service(related)
+            .service(refresh_related)
+            .service(site_detail)
+            .service(favicon)
+            .service(thumbnail)
+            .service(snapshot)
+            .service(snapshot_diff)
+            .service(atom_feed)
+            .service(json_feed)
             .service(id)
             .service(vote)
-            .service(votes);
+            .service(votes_json)
+            .service(votes)
+            .service(rescan)
+            .service(owner_token)
+            .service(owner_record)
+            .service(webmention)
+            .service(
+                web::scope("/api/v1")
+                    .wrap(middleware::from_fn(rate_limit_middleware))
+                    .service(analyzer_status_api)
+                    .service(sites_api)
+                    .service(site_api)
+                    .service(top_api),
+            )
+            .default_service(web::to(not_found));
+
+        let app = if app_config.admin.is_some() {
+            app.service(
+                web::scope("/admin")
+                    .wrap(middleware::from_fn(admin_auth_middleware))
+                    .service(admin_dashboard)
+                    .service(admin_metrics)
+                    .service(admin_keys)
+                    .service(admin_create_key)
+                    .service(admin_revoke_key)
+                    .service(admin_read_only)
+                    .service(admin_feature_site)
+                    .service(admin_unfeature_site)
+                    .service(admin_merge_sites)
+                    .service(admin_site_note)
+                    .service(admin_approve_comment)
+                    .service(admin_reject_comment)
+                    .service(admin_approve_discussion)
+                    .service(admin_reject_discussion)
+                    .service(admin_add_shadow)
+                    .service(admin_remove_shadow)
+                    .service(admin_approve_votes)
+                    .service(admin_purge_votes)
+                    .service(admin_invalidate_anomaly),
+            )
+        } else {
+            app
+        };
+
+        let app = match &analytics_sender {
+            Some(sender) => app.app_data(web::Data::new(sender.clone())),
+            None => app,
+        };
+
+        let app = match &geoip {
+            Some(geoip) => app.app_data(web::Data::new(geoip.clone())),
+            None => app,
+        };
+
+        let app = match &in_flight {
+            Some(in_flight) => app.app_data(in_flight.clone()),
+            None => app,
+        };
+
+        let app = if app_config.websocket.enabled {
+            app.service(websocket)
+        } else {
+            app
+        };
+
+        let app = if app_config.comments.is_some() {
+            app.service(own_comments).service(post_comment)
+        } else {
+            app
+        };
+
+        let app = if app_config.discussions.is_some() {
+            app.service(suggest_discussion)
+        } else {
+            app
+        };
+
+        let app = if app_config.planet.is_some() {
+            app.service(planet_page).service(planet_feed)
+        } else {
+            app
+        };
+
+        let app = if app_config.federation.is_some() {
+            app.service(friends_page)
+        } else {
+            app
+        };
 
         if cfg!(debug_assertions) {
             app.service(css).service(js)
         } else {
             app
         }
-    })
-    .bind((config.listen_addr, config.listen_port))?
-    .run()
-    .await
+    });
+
+    let server = match systemd::activated_listener()? {
+        Some(listener) => server.listen(listener)?,
+        None => {
+            let mut server = server;
+            for listener in listeners::bind(&config.listen_addrs())? {
+                server = server.listen(listener)?;
+            }
+            server
+        }
+    }
+    .run();
+
+    systemd::notify_ready();
+    systemd::spawn_watchdog();
+
+    let res = server.await;
+    systemd::notify_stopping();
+    if let Some(replicator) = replicator {
+        replicator.stop().await;
+    }
+    res
 }
 
 #[get("/10kb.css")]
@@ -115,229 +640,3006 @@ async fn js() -> HttpResponse {
 }
 
 #[get("/submit.html")]
-#[allow(clippy::needless_lifetimes)]
-async fn submithtml<'a>(template: web::Data<Environment<'a>>) -> Result<impl Responder, HtmlError> {
+async fn submithtml(
+    template: web::Data<Templates>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
     Ok(HttpResponse::Ok()
         .content_type(ContentType(mime::TEXT_HTML))
         .body(
-            template
-                .get_template("submit.html")?
-                .render(context!(title => format!("Submit a site")))?,
+            PageContext::new(&config, "Submit a site", "/submit.html")
+                .description("Submit a website of 10KiB or less to the 10KB Club")
+                .request(&req)
+                .render(&template, "submit.html", context!())?,
         ))
 }
 
-#[derive(Deserialize)]
-struct ViewRequest {
-    sortby: Option<SortOptions>,
-    paginate: Option<usize>,
-    page: Option<usize>,
+#[get("/prefs.html")]
+async fn prefshtml(
+    template: web::Data<Templates>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(mime::TEXT_HTML))
+        .body(
+            PageContext::new(&config, "Preferences", "/prefs.html")
+                .description("Display preferences for the 10KB Club")
+                .request(&req)
+                .render(
+                    &template,
+                    "prefs.html",
+                    context!(
+                        prefs => Prefs::from_request(&req),
+                        min_paginate => config.index.min_paginate,
+                        max_paginate => config.index.max_paginate,
+                    ),
+                )?,
+        ))
 }
 
-#[get("/")]
-#[allow(clippy::needless_lifetimes)]
-async fn index<'a>(
-    query: web::Query<ViewRequest>,
-    template: web::Data<Environment<'a>>,
-    pool: web::Data<Pool>,
-    req: HttpRequest,
+/// Form fields for `/prefs/`; an empty value resets that preference to the site default.
+#[derive(Debug, Deserialize)]
+struct PrefsRequest {
+    #[serde(default)]
+    theme: String,
+    #[serde(default)]
+    sortby: String,
+    #[serde(default)]
+    paginate: String,
+    #[serde(default)]
+    view: String,
+}
+
+#[post("/prefs/")]
+async fn save_prefs(
+    form: web::Form<PrefsRequest>,
+    key: web::Data<Key>,
+    config: web::Data<Config>,
 ) -> Result<impl Responder, HtmlError> {
-    let page = match query.page {
-        Some(0) | None => 1,
-        Some(page) => page,
-    };
-    let sortby = query.sortby.unwrap_or(SortOptions::Votes);
-    let paginate = query.paginate.unwrap_or(25);
-    let offset = paginate * (page - 1);
-    let client_ip = get_client_ip(&req)?;
+    let mut prefs = Prefs::default();
 
-    info!("Generating index for {client_ip}");
+    if !form.theme.is_empty() {
+        let Some(theme) = Theme::parse(&form.theme) else {
+            return Ok(flash::redirect(
+                "/prefs.html",
+                Flash::error(format!("unknown theme '{}'", form.theme)),
+            ));
+        };
+        prefs.theme = Some(theme);
+    }
 
-    let tmp = pool.clone();
-    let count = web::block(move || get_site_count(&tmp)).await??;
+    if !form.sortby.is_empty() {
+        match form.sortby.parse() {
+            Ok(sortby) => prefs.sortby = Some(sortby),
+            Err(e) => return Ok(flash::redirect("/prefs.html", Flash::error(e))),
+        }
+    }
 
-    let (page_links, prev_link, next_link) =
-        get_page_links(page, count as f32, paginate as f32, sortby);
+    if !form.paginate.is_empty() {
+        match form.paginate.parse::<usize>() {
+            Ok(paginate) if config.index.clamp_paginate(paginate) == paginate => {
+                prefs.paginate = Some(paginate)
+            }
+            Ok(_) => {
+                return Ok(flash::redirect(
+                    "/prefs.html",
+                    Flash::error(format!(
+                        "page size must be between {} and {}",
+                        config.index.min_paginate, config.index.max_paginate
+                    )),
+                ))
+            }
+            Err(_) => {
+                return Ok(flash::redirect(
+                    "/prefs.html",
+                    Flash::error(format!("invalid page size '{}'", form.paginate)),
+                ))
+            }
+        }
+    }
 
-    let sites = web::block(move || get_sites(&pool, sortby, offset, paginate)).await??;
+    if !form.view.is_empty() {
+        let Some(view) = View::parse(&form.view) else {
+            return Ok(flash::redirect(
+                "/prefs.html",
+                Flash::error(format!("unknown view '{}'", form.view)),
+            ));
+        };
+        prefs.view = Some(view);
+    }
 
-    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
-        template.get_template("index.html")?.render(context!(
-            sites => sites,
-            page_links => page_links,
-            next_link => next_link,
-            prev_link => prev_link,
-        ))?,
-    ))
+    let mut res = flash::redirect("/prefs.html", Flash::success("Preferences saved."));
+    res.add_cookie(&prefs.cookie(&key))
+        .map_err(|e| HtmlError::new(500, e.to_string()))?;
+    Ok(res)
 }
 
-#[get("/related/{site}/")]
-#[allow(clippy::needless_lifetimes)]
-async fn related<'a>(
-    path: web::Path<u32>,
-    template: web::Data<Environment<'a>>,
-    pool: web::Data<Pool>,
-    req: HttpRequest,
-) -> Result<impl Responder, HtmlError> {
-    let site = path.into_inner();
-    let client_ip = get_client_ip(&req)?;
-    info!("getting related links for '{site}' {client_ip}");
+/// The first path segments of the GET routes that end in a slash.
+const DIRECTORY_ROUTES: [&str; 4] = ["admin", "related", "site", "ws"];
 
-    let related = get_related(&pool, site)?;
-    let url = get_site_url(&pool, site)?;
+/// Fallback for unmatched routes: redirect directory-style paths that are missing their
+/// trailing slash (e.g. `/related/1`), and 404 everything else.
+async fn not_found(req: HttpRequest) -> Result<HttpResponse, HtmlError> {
+    let path = req.path();
 
-    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
-        template.get_template("related.html")?.render(context!(
-            url => url,
-            related => related,
-            title => format!("Related links for {url}"),
-        ))?,
-    ))
-}
+    if req.method() == Method::GET {
+        if let Some(location) = trailing_slash_location(path, req.query_string()) {
+            return Ok(HttpResponse::MovedPermanently()
+                .insert_header((header::LOCATION, location))
+                .finish());
+        }
+    }
 
-#[derive(Debug, Deserialize)]
-struct SubmitRequest {
-    site: String,
+    Err(HtmlError::new(404, format!("{path} not found")))
 }
 
-#[post("/dosubmit/")]
-#[allow(clippy::needless_lifetimes)]
-async fn submit<'a>(
-    query: web::Form<SubmitRequest>,
-    template: web::Data<Environment<'a>>,
-    pool: web::Data<Pool>,
-    req: HttpRequest,
-) -> Result<impl Responder, HtmlError> {
-    let client_ip = get_client_ip(&req)?;
-    let site = query.site.clone();
+/// Where to send a request for `path` that is missing its trailing slash, if anywhere.  Only
+/// paths under one of `DIRECTORY_ROUTES` qualify, so that a path like `//example.com` can't
+/// turn into a protocol-relative redirect off the site.
+fn trailing_slash_location(path: &str, query: &str) -> Option<String> {
+    let rest = path.strip_prefix('/')?;
+    let first_segment = rest.split('/').next().unwrap_or("");
+    let last_segment = rest.rsplit('/').next().unwrap_or("");
 
-    Url::parse(&site[..])?;
+    if path.ends_with('/')
+        || last_segment.contains('.')
+        || !DIRECTORY_ROUTES.contains(&first_segment)
+    {
+        return None;
+    }
 
-    info!("adding '{site}' to submission queue for {client_ip}");
-    submit_site(pool, site.clone())?;
+    Some(match query {
+        "" => format!("{path}/"),
+        query => format!("{path}/?{query}"),
+    })
+}
 
-    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
-        template.get_template("submitted.html")?.render(context!(
-            title => format!("Site Submitted: {site}"),
-            site => site,
-        ))?,
-    ))
+/// The index's query string, kept as text so that `validate` can check each field on its own.
+#[derive(Deserialize)]
+struct ViewRequest {
+    sortby: Option<String>,
+    paginate: Option<String>,
+    view: Option<String>,
+    page: Option<String>,
+    has_related: Option<String>,
+    lang: Option<String>,
 }
 
-#[derive(Serialize)]
-struct IdResponse {
-    code: usize,
-    status: String,
-    voter_id: String,
+/// A validated `ViewRequest`, with the visitor's preferences filling in anything left out.
+struct IndexView {
+    sortby: SortOptions,
+    paginate: usize,
+    view: View,
+    page: usize,
+    offset: usize,
+    filter: SiteFilter,
 }
 
-#[post("/id/")]
-async fn id(pool: web::Data<Pool>, req: HttpRequest) -> Result<impl Responder, JsonError> {
-    let mut response = IdResponse {
-        code: 200,
-        status: String::from("OK"),
-        voter_id: String::from(""),
-    };
+impl ViewRequest {
+    /// Parses every field, answering 400 with all of the bad ones named.  A `paginate` outside
+    /// `index.min_paginate..=index.max_paginate` is clamped rather than refused.
+    fn validate(&self, prefs: &Prefs) -> Result<IndexView, HtmlError> {
+        let mut errors = vec![];
 
-    let client_ip = get_client_ip(&req)?;
+        let sortby = parse_field(&mut errors, "sortby", &self.sortby, |s| s.parse());
+        let view = parse_field(&mut errors, "view", &self.view, |s| {
+            View::parse(s).ok_or_else(|| format!("unknown view '{s}'"))
+        });
+        let paginate = parse_field(&mut errors, "paginate", &self.paginate, positive_integer);
+        let page = parse_field(&mut errors, "page", &self.page, positive_integer);
+        let has_related = parse_field(&mut errors, "has_related", &self.has_related, boolean);
 
-    let mut rand_bytes = [0u8; 32];
-    thread_rng().fill(&mut rand_bytes);
+        let paginate = paginate
+            .map(|paginate| prefs.site.clamp_paginate(paginate))
+            .unwrap_or(prefs.default_paginate());
+        let page = page.unwrap_or(1);
+        let offset = page_offset(&mut errors, page, paginate);
 
-    let id = hex::encode(rand_bytes);
-    response.voter_id = id.clone();
+        match offset {
+            Some(offset) if errors.is_empty() => Ok(IndexView {
+                sortby: sortby.unwrap_or(prefs.default_sort()),
+                paginate,
+                view: view.unwrap_or(prefs.default_view()),
+                page,
+                offset,
+                filter: SiteFilter {
+                    has_related: has_related.unwrap_or(false),
+                },
+            }),
+            _ => Err(HtmlError::new(
+                400,
+                format!("invalid query: {}", errors.join("; ")),
+            )),
+        }
+    }
+}
 
-    info!("Generating new ID '{id}' for client {client_ip}");
+/// Parses one query field, recording a "field: reason" message if it doesn't parse.
+fn parse_field<T>(
+    errors: &mut Vec<String>,
+    name: &str,
+    value: &Option<String>,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Option<T> {
+    match parse(value.as_deref()?) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            errors.push(format!("{name}: {e}"));
+            None
+        }
+    }
+}
 
-    web::block(move || generate_id(pool, id)).await??;
-    Ok(web::Json(response))
+fn positive_integer(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("must be at least 1".into()),
+        Ok(n) => Ok(n),
+        Err(_) => Err(format!("'{s}' is not a positive integer")),
+    }
 }
 
-#[derive(Deserialize)]
-struct VoteRequest {
-    voter_id: String,
-    site_id: u32,
-    vote: isize,
+fn boolean(s: &str) -> Result<bool, String> {
+    s.parse::<bool>()
+        .map_err(|_| format!("'{s}' is not true or false"))
 }
 
-#[derive(Serialize)]
-struct VoteResponse {
-    code: usize,
-    status: String,
+/// The offset of the first site on `page`, recording an error if it's out of range.
+fn page_offset(errors: &mut Vec<String>, page: usize, per_page: usize) -> Option<usize> {
+    // SQLite takes the offset as an i64.
+    let offset = (page - 1)
+        .checked_mul(per_page)
+        .filter(|&offset| i64::try_from(offset).is_ok());
+    if offset.is_none() {
+        errors.push(format!("page: {page} is out of range"));
+    }
+    offset
 }
 
-#[post("/vote/")]
-async fn vote(
-    data: web::Form<VoteRequest>,
-    pool: web::Data<Pool>,
+#[get("/")]
+async fn index(
+    query: web::Query<ViewRequest>,
+    template: web::Data<Templates>,
+    storage: web::Data<ReadStorage>,
+    config: web::Data<Config>,
+    top_cache: web::Data<TopCache>,
     req: HttpRequest,
-) -> Result<impl Responder, JsonError> {
-    let voter_id = data.voter_id.clone();
-    let site_id = data.site_id;
-    let vote = data.vote;
+) -> Result<HttpResponse, HtmlError> {
+    let prefs = Prefs::from_request(&req);
+    let IndexView {
+        sortby,
+        paginate,
+        view,
+        page,
+        offset,
+        filter,
+    } = query.validate(&prefs)?;
+    let client_ip = get_client_ip(&req)?;
 
-    let response = VoteResponse {
-        code: 200,
-        status: String::from("OK"),
-    };
+    // Every view of a listing shares the default view's canonical address.
+    let site_prefs = Prefs::site(&config);
+    let canonical = index_uri(
+        page,
+        paginate,
+        sortby,
+        site_prefs.default_view(),
+        &filter,
+        &site_prefs,
+    );
+    let mut location = index_uri(page, paginate, sortby, view, &filter, &prefs);
+    if let Some(lang) = &query.lang {
+        location.push(if location.contains('?') { '&' } else { '?' });
+        location.push_str("lang=");
+        location.extend(form_urlencoded::byte_serialize(lang.as_bytes()));
+    }
 
-    if !(0..=1).contains(&vote) {
-        return Err("invalid vote".into());
+    if !req.query_string().is_empty() && location != req.uri().to_string() {
+        return Ok(HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, location))
+            .finish());
     }
 
-    let client_ip = get_client_ip(&req)?;
+    info!("Generating index for {client_ip}");
 
-    info!(
-        "casting vote '{vote}' for commenter: '{voter_id}' for site {site_id} from ip {client_ip}"
+    let tmp = storage.0.clone();
+    let count =
+        blocking::block("index.get_site_count", move || tmp.get_site_count(&filter)).await??;
+
+    let (page_links, prev_link, next_link) = get_page_links(
+        page,
+        count as f32,
+        paginate as f32,
+        sortby,
+        view,
+        &filter,
+        &prefs,
     );
 
-    web::block(move || cast_vote(pool, voter_id, site_id, vote)).await??;
+    let toggled = SiteFilter {
+        has_related: !filter.has_related,
+    };
+    let filter_link = index_uri(1, paginate, sortby, view, &toggled, &prefs);
+    let view_links = [View::Table, View::Compact, View::Gallery].map(|other| {
+        (
+            other,
+            index_uri(1, paginate, sortby, other, &filter, &prefs),
+        )
+    });
 
-    Ok(web::Json(response))
-}
+    let tmp = storage.0.clone();
+    let sites = blocking::block("index.get_sites", move || {
+        tmp.get_sites(sortby, &filter, offset, paginate)
+    })
+    .await??;
 
-#[derive(Deserialize)]
-struct VotesRequest {
-    voter_id: String,
-    site_ids: String,
+    // Featured sites and this week's risers head the first page whatever the sort.
+    let (featured, top) = if page == 1 {
+        // Featuring is done by SQLite site id.
+        let tmp = storage.0.clone();
+        let featured = blocking::block("index.get_featured_sites", move || {
+            tmp.sqlite().map_or(Ok(vec![]), get_featured_sites)
+        })
+        .await??;
+        let top = match sortby {
+            SortOptions::Week => None,
+            _ => Some(
+                blocking::block("index.top_cache", move || {
+                    top_cache.get(&*storage.0, Period::Week)
+                })
+                .await??,
+            ),
+        };
+        (featured, top)
+    } else {
+        (vec![], None)
+    };
+
+    if wants_json(req.headers()) {
+        let response = IndexResponse {
+            code: 200,
+            status: String::from("OK"),
+            page,
+            paginate,
+            count,
+            sortby,
+            filter,
+            sites,
+            featured,
+            top,
+            prev_link: Some(prev_link).filter(|link| !link.is_empty()),
+            next_link: Some(next_link).filter(|link| !link.is_empty()),
+        };
+        let etag = etag::weak_etag(&response);
+        return Ok(vary_accept(etag::json_response(&req, etag, &response)));
+    }
+
+    Ok(vary_accept(
+        HttpResponse::Ok().content_type(ContentType::html()).body(
+            PageContext::new(&config, config.site_name.clone(), &canonical)
+                .request(&req)
+                .sortby(sortby)
+                .voting()
+                .live()
+                .render(
+                    &template,
+                    "index.html",
+                    context!(
+                        sites => sites,
+                        page_links => page_links,
+                        next_link => next_link,
+                        prev_link => prev_link,
+                        filter => filter,
+                        filter_link => filter_link,
+                        featured => featured,
+                        top => top,
+                        view => view,
+                        view_links => view_links,
+                        friends => config.federation.is_some(),
+                    ),
+                )?,
+        ),
+    ))
 }
 
+/// The index as JSON, for clients sending `Accept: application/json`.
 #[derive(Serialize)]
-struct VotesResponse {
+struct IndexResponse {
     code: usize,
     status: String,
-    site_ids: Vec<u32>,
+    page: usize,
+    paginate: usize,
+    count: usize,
+    sortby: SortOptions,
+    filter: SiteFilter,
+    sites: Vec<Site>,
+    featured: Vec<FeaturedSite>,
+    top: Option<Vec<Site>>,
+    prev_link: Option<String>,
+    next_link: Option<String>,
 }
 
-#[post("/votes/")]
-async fn votes(
-    data: web::Form<VotesRequest>,
-    pool: web::Data<Pool>,
-    req: HttpRequest,
-) -> Result<impl Responder, JsonError> {
-    let voter_id = data.voter_id.clone();
-    let site_ids = data
-        .site_ids
-        .split(",")
-        .filter_map(|s| if let Ok(n) = s.parse() { Some(n) } else { None })
-        .collect::<Vec<u32>>();
+/// The site `key` names in a `/site/{key}/` or `/related/{key}/` path, as its id and
+/// primary slug.
+async fn find_site_by_key(pool: &Pool, key: String) -> Result<(u32, String), HtmlError> {
+    let tmp = pool.clone();
+    let site = blocking::block("find_site", move || find_site(&tmp, &key)).await??;
+    site.ok_or_else(|| HtmlError::new(404, "no such site"))
+}
 
-    let mut response = VotesResponse {
-        code: 200,
-        status: String::from("OK"),
-        site_ids: vec![],
-    };
+/// As [`find_site_by_key`], but in the configured storage rather than SQLite, for the pages
+/// built from what the storage holds.
+async fn find_stored_site(
+    storage: &Arc<dyn Storage>,
+    key: String,
+) -> Result<(u32, String), HtmlError> {
+    let tmp = storage.clone();
+    let site = blocking::block("find_site", move || tmp.find_site(&key)).await??;
+    site.ok_or_else(|| HtmlError::new(404, "no such site"))
+}
 
-    let client_ip = get_client_ip(&req)?;
+/// The site `key` names in an API path, as its id and primary slug.
+async fn find_api_site(pool: &Pool, key: String) -> Result<(u32, String), JsonError> {
+    let tmp = pool.clone();
+    let site = blocking::block("find_site", move || find_site(&tmp, &key)).await??;
+    site.ok_or_else(|| JsonError::new(404, "no such site"))
+}
 
-    info!("getting votes for '{voter_id}' from ip {client_ip}");
+/// Pages reached by a legacy numeric id or a merged site's slug move permanently to the
+/// site's primary slug.
+fn slug_redirect(req: &HttpRequest, key: &str, slug: &str) -> Option<HttpResponse> {
+    if key == slug {
+        return None;
+    }
 
-    let sites = web::block(move || get_votes(pool, voter_id)).await??;
+    // The key is a whole path segment, or one with an extension as in `/thumb/{key}.webp`.
+    let path = req.path();
+    let start = path
+        .match_indices(&format!("/{key}"))
+        .map(|(i, _)| i + 1)
+        .find(|&i| matches!(path.as_bytes().get(i + key.len()), None | Some(b'/' | b'.')))?;
+    let mut location = format!("{}{slug}{}", &path[..start], &path[start + key.len()..]);
+    if !req.query_string().is_empty() {
+        location.push('?');
+        location.push_str(req.query_string());
+    }
+    Some(
+        HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, location))
+            .finish(),
+    )
+}
 
-    for site in sites {
-        if site_ids.contains(&site) {
-            response.site_ids.push(site);
-        }
+/// Pages that also answer in JSON vary with the `Accept` header.
+fn vary_accept(mut res: HttpResponse) -> HttpResponse {
+    res.headers_mut()
+        .insert(header::VARY, header::HeaderValue::from_static("Accept"));
+    res
+}
+
+#[get("/related/{site}/")]
+async fn related(
+    path: web::Path<String>,
+    template: web::Data<Templates>,
+    storage: web::Data<ReadStorage>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<HttpResponse, HtmlError> {
+    let key = path.into_inner();
+    let (site, slug) = find_stored_site(&storage.0, key.clone()).await?;
+    if let Some(redirect) = slug_redirect(&req, &key, &slug) {
+        return Ok(redirect);
     }
 
-    Ok(web::Json(response))
+    let client_ip = get_client_ip(&req)?;
+    info!("getting related links for '{site}' {client_ip}");
+
+    // Webmentions are kept by SQLite site id.
+    let tmp = storage.0.clone();
+    let (related, url, mentions, (refreshed, refresh_requested)) =
+        blocking::block("related.get_related", move || {
+            Ok::<_, TenKbError>((
+                tmp.get_related(site)?,
+                tmp.get_site_url(site)?,
+                match tmp.sqlite() {
+                    Some(pool) => get_webmentions(pool, site)?,
+                    None => vec![],
+                },
+                tmp.get_related_refresh(site)?,
+            ))
+        })
+        .await??;
+
+    if wants_json(req.headers()) {
+        let response = RelatedResponse {
+            code: 200,
+            status: String::from("OK"),
+            site_id: site,
+            slug: slug.clone(),
+            url,
+            related,
+            mentions,
+            refreshed,
+            refresh_requested,
+        };
+        let etag = etag::weak_etag(&response);
+        return Ok(vary_accept(etag::json_response(&req, etag, &response)));
+    }
+
+    Ok(vary_accept(
+        HttpResponse::Ok().content_type(ContentType::html()).body(
+            PageContext::new(
+                &config,
+                format!("Related links for {url}"),
+                &format!("/related/{slug}/"),
+            )
+            .request(&req)
+            .description(format!(
+                "Hacker News and Lobsters discussions related to {url}"
+            ))
+            .render(
+                &template,
+                "related.html",
+                context!(
+                    slug => slug,
+                    url => url,
+                    related => related,
+                    mentions => mentions,
+                    refreshed => refreshed,
+                    refresh_requested => refresh_requested,
+                ),
+            )?,
+        ),
+    ))
+}
+
+/// A site's related links as JSON, for clients sending `Accept: application/json`.
+#[derive(Serialize)]
+struct RelatedResponse {
+    code: usize,
+    status: String,
+    site_id: u32,
+    slug: String,
+    url: String,
+    related: Vec<RelatedLink>,
+    mentions: Vec<Webmention>,
+    refreshed: Option<String>,
+    refresh_requested: Option<String>,
+}
+
+/// Related link refreshes are throttled separately from rescans.
+struct RefreshLimiter(RateLimiter);
+
+/// Ask for a site's related links to be harvested again on the analyzer's next pass.
+#[post("/related/{site}/refresh/")]
+async fn refresh_related(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    limiter: web::Data<RefreshLimiter>,
+    wakeup: web::Data<AnalyzerWakeup>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let (site_id, slug) = find_stored_site(&storage, path.into_inner()).await?;
+    let location = format!("/related/{slug}/");
+    let client_ip = get_client_ip(&req)?;
+
+    let tmp = storage.clone();
+    let listed = blocking::block("refresh_related.get_site_url", move || {
+        let url = tmp.get_site_url(site_id)?;
+        tmp.check_site_active(&url)
+    })
+    .await?;
+    if !matches!(listed, Ok(true)) {
+        return Err(HtmlError::new(404, format!("no site with id {site_id}")));
+    }
+
+    let limits = &config.related_refresh;
+    if !limiter
+        .0
+        .check(&format!("ip:{client_ip}"), limits.per_ip)
+        .allowed
+        || !limiter
+            .0
+            .check(&format!("site:{site_id}"), limits.per_site)
+            .allowed
+    {
+        return Ok(flash::redirect(
+            &location,
+            Flash::error("These links were refreshed recently; try again later"),
+        ));
+    }
+
+    let requested = blocking::block("refresh_related.request_related_refresh", move || {
+        storage.request_related_refresh(site_id)
+    })
+    .await??;
+    if requested {
+        info!("related links refresh for site {site_id} requested by {client_ip}");
+        wakeup.notify_one();
+    }
+
+    Ok(flash::redirect(
+        &location,
+        Flash::success("The links will be refreshed shortly"),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct WebmentionRequest {
+    source: String,
+    target: String,
+}
+
+/// Receive a webmention for a club page.  Only the request is checked here; fetching the
+/// source to confirm the link happens in the background, as the spec allows, so a slow
+/// source can't hold up the sender.
+#[post("/webmention")]
+async fn webmention(
+    form: web::Form<WebmentionRequest>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let (Ok(source), Ok(target)) = (Url::parse(&form.source), Url::parse(&form.target)) else {
+        return Err(HtmlError::new(400, "source and target must be URLs"));
+    };
+
+    if !matches!(source.scheme(), "http" | "https") {
+        return Err(HtmlError::new(400, "source must be an http(s) URL"));
+    }
+
+    if source == target {
+        return Err(HtmlError::new(400, "source and target must differ"));
+    }
+
+    let Some(key) = target_site(&config.base_url, &target) else {
+        return Err(HtmlError::new(400, "target is not a club page"));
+    };
+
+    let tmp = pool.clone();
+    let listed = blocking::block("webmention.get_site_url", move || {
+        let Some((site_id, _)) = find_site(&tmp, &key)? else {
+            return Ok(None);
+        };
+        let url = get_site_url(&tmp, site_id)?;
+        Ok::<_, TenKbError>(check_site_active(&tmp, &url)?.then_some(site_id))
+    })
+    .await?;
+    let Ok(Some(site_id)) = listed else {
+        return Err(HtmlError::new(400, "target is not a club page"));
+    };
+
+    let client_ip = get_client_ip(&req)?;
+    info!("received webmention from {source} for site {site_id} ({client_ip})");
+
+    let max_bytes = config.webmention_max_bytes;
+    actix_web::rt::spawn(async move {
+        let source_url = source.to_string();
+        let res = match verify(&source, &target, max_bytes).await {
+            Ok(Verification::Links(title)) => {
+                info!("verified webmention from {source_url} for site {site_id}");
+                blocking::block("webmention.store_webmention", move || {
+                    store_webmention(&pool, site_id, &source_url, title.as_deref())
+                })
+                .await
+            }
+            Ok(Verification::Gone) => {
+                info!("{source_url} doesn't link to site {site_id}; dropping its webmention");
+                blocking::block("webmention.delete_webmention", move || {
+                    delete_webmention(&pool, site_id, &source_url)
+                })
+                .await
+            }
+            Err(e) => {
+                error!("unable to verify webmention from {source_url}: {e}");
+                return;
+            }
+        };
+
+        if let Err(e) = res.map_err(TenKbError::from).and_then(|res| res) {
+            error!("unable to record webmention for site {site_id}: {e:?}");
+        }
+    });
+
+    Ok(HttpResponse::Accepted().body("Webmention accepted; the source will be checked shortly."))
+}
+
+#[get("/site/{id}/")]
+async fn site_detail(
+    path: web::Path<String>,
+    template: web::Data<Templates>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<HttpResponse, HtmlError> {
+    let key = path.into_inner();
+    let (site_id, slug) = find_site_by_key(&pool, key.clone()).await?;
+    if let Some(redirect) = slug_redirect(&req, &key, &slug) {
+        return Ok(redirect);
+    }
+
+    let tmp = pool.clone();
+    let Some(site) = blocking::block("site_detail.get_site_detail", move || {
+        get_site_detail(&tmp, site_id)
+    })
+    .await??
+    else {
+        return Err(HtmlError::new(404, format!("no site named {slug}")));
+    };
+
+    let comments = match &config.comments {
+        Some(_) => Some(
+            blocking::block("site_detail.get_comments", move || {
+                get_comments(&pool, site_id)
+            })
+            .await??,
+        ),
+        None => None,
+    };
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        PageContext::new(&config, site.url.clone(), &format!("/site/{slug}/"))
+            .request(&req)
+            .description(format!("{} on {}", site.url, config.site_name))
+            .render(
+                &template,
+                "site.html",
+                context!(
+                    site => site,
+                    comments => comments,
+                    comment_length => config.comments.as_ref().map(|c| c.max_length),
+                    suggest_discussions => config.discussions.is_some(),
+                ),
+            )?,
+    ))
+}
+
+#[derive(Deserialize)]
+struct OwnCommentsRequest {
+    voter_id: String,
+}
+
+#[derive(Serialize)]
+struct OwnCommentsResponse {
+    code: usize,
+    status: String,
+    comments: Vec<Comment>,
+}
+
+/// The visitor's own shadowed comments on a site, so that shadowing isn't obvious to them.
+/// With `premoderate` set nobody sees their comments straight away, so there is nothing to
+/// return.
+#[post("/site/{id}/comments/mine/")]
+async fn own_comments(
+    path: web::Path<String>,
+    form: web::Form<OwnCommentsRequest>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, JsonError> {
+    let (site_id, _) = find_api_site(&pool, path.into_inner()).await?;
+    let comments = if config.comments.as_ref().is_some_and(|c| !c.premoderate) {
+        let voter_id = form.into_inner().voter_id;
+        blocking::block("own_comments.get_shadowed_comments", move || {
+            get_shadowed_comments(&pool, site_id, &voter_id)
+        })
+        .await??
+    } else {
+        vec![]
+    };
+
+    Ok(web::Json(OwnCommentsResponse {
+        code: 200,
+        status: String::from("OK"),
+        comments,
+    }))
+}
+
+/// Comments are throttled separately from the API and rescans.
+struct CommentLimiter(RateLimiter);
+
+#[derive(Deserialize)]
+struct CommentRequest {
+    voter_id: String,
+    body: String,
+}
+
+/// Post to a site's comment thread.  Commenters are identified only by their voter id, and
+/// with `premoderate` set their comments wait in the admin queue before being shown.
+#[post("/site/{id}/comments/")]
+async fn post_comment(
+    path: web::Path<String>,
+    form: web::Form<CommentRequest>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    limiter: web::Data<CommentLimiter>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let (site_id, slug) = find_site_by_key(&pool, path.into_inner()).await?;
+    let location = format!("/site/{slug}/");
+    let Some(limits) = &config.comments else {
+        return Err(HtmlError::new(404, "comments are disabled"));
+    };
+
+    let CommentRequest { voter_id, body } = form.into_inner();
+    let body = body.trim().to_owned();
+    if body.is_empty() {
+        return Ok(flash::redirect(
+            &location,
+            Flash::error("Comments can't be empty"),
+        ));
+    }
+
+    if body.chars().count() > limits.max_length {
+        return Ok(flash::redirect(
+            &location,
+            Flash::error(format!(
+                "Comments are limited to {} characters",
+                limits.max_length
+            )),
+        ));
+    }
+
+    let tmp = pool.clone();
+    let listed = blocking::block("post_comment.get_site_url", move || {
+        let url = get_site_url(&tmp, site_id)?;
+        check_site_active(&tmp, &url)
+    })
+    .await?;
+    if !matches!(listed, Ok(true)) {
+        return Err(HtmlError::new(404, format!("no site with id {site_id}")));
+    }
+
+    let client_ip = get_client_ip(&req)?;
+    if !limiter
+        .0
+        .check(&format!("ip:{client_ip}"), limits.per_ip)
+        .allowed
+        || !limiter
+            .0
+            .check(&format!("voter:{voter_id}"), limits.per_voter)
+            .allowed
+    {
+        return Ok(flash::redirect(
+            &location,
+            Flash::error("Too many comments; try again later"),
+        ));
+    }
+
+    // Likely spam waits for a moderator even when comments are otherwise shown at once.
+    let spam_score = match &config.spam {
+        Some(_) => match score(
+            &pool,
+            &config,
+            &Sample::new(SpamKind::Comment, &*body, &req),
+        )
+        .await
+        {
+            Ok(score) => Some(score),
+            Err(e) => {
+                error!("unable to score comment on site {site_id}: {e}");
+                None
+            }
+        },
+        None => None,
+    };
+    let held = config
+        .spam
+        .as_ref()
+        .zip(spam_score)
+        .is_some_and(|(spam, score)| is_spam(spam, score));
+
+    let ip = client_ip.clone();
+    let premoderate = limits.premoderate || held;
+    let tmp = pool.clone();
+    let Some((comment_id, status)) = blocking::block("post_comment.add_comment", move || {
+        let pool = tmp;
+        let status = if is_shadowed(&pool, &voter_id, &ip)? {
+            CommentStatus::Shadowed
+        } else if premoderate {
+            CommentStatus::Pending
+        } else {
+            CommentStatus::Approved
+        };
+        Ok::<_, TenKbError>(
+            add_comment(&pool, site_id, &voter_id, &body, status)?
+                .map(|comment_id| (comment_id, status)),
+        )
+    })
+    .await??
+    else {
+        return Ok(flash::redirect(&location, Flash::error("Unknown voter id")));
+    };
+    info!(
+        "comment {comment_id} ({}) posted on site {site_id} from ip {client_ip}",
+        status.as_str()
+    );
+
+    if let Some(score) = spam_score {
+        blocking::block("post_comment.record_spam_score", move || {
+            record_spam_score(&pool, "comment", &comment_id.to_string(), score)
+        })
+        .await??;
+    }
+
+    Ok(flash::redirect(
+        &location,
+        if premoderate {
+            Flash::success("Thanks! Your comment will appear once a moderator has approved it.")
+        } else {
+            Flash::success("Thanks for your comment")
+        },
+    ))
+}
+
+/// Discussion suggestions are throttled separately from comments.
+struct DiscussionLimiter(RateLimiter);
+
+#[derive(Deserialize)]
+struct DiscussionRequest {
+    discussion_url: String,
+}
+
+/// Suggest a discussion of a site.  It's fetched straight away to check that it links to
+/// the site, then waits in the admin queue.
+#[post("/site/{id}/discussions/")]
+async fn suggest_discussion(
+    path: web::Path<String>,
+    form: web::Form<DiscussionRequest>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    limiter: web::Data<DiscussionLimiter>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let (site_id, slug) = find_site_by_key(&pool, path.into_inner()).await?;
+    let location = format!("/site/{slug}/");
+    let Some(limits) = &config.discussions else {
+        return Err(HtmlError::new(404, "discussion suggestions are disabled"));
+    };
+
+    let discussion = match Url::parse(form.discussion_url.trim()) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+        _ => {
+            return Ok(flash::redirect(
+                &location,
+                Flash::error("Discussion links must be http(s) URLs"),
+            ))
+        }
+    };
+
+    let tmp = pool.clone();
+    let site = blocking::block("suggest_discussion.get_site_url", move || {
+        let url = get_site_url(&tmp, site_id)?;
+        Ok::<_, TenKbError>(check_site_active(&tmp, &url)?.then_some(url))
+    })
+    .await?;
+    let Some(site) = site.ok().flatten().and_then(|url| Url::parse(&url).ok()) else {
+        return Err(HtmlError::new(404, format!("no site with id {site_id}")));
+    };
+
+    let client_ip = get_client_ip(&req)?;
+    if !limiter
+        .0
+        .check(&format!("ip:{client_ip}"), limits.per_ip)
+        .allowed
+    {
+        return Ok(flash::redirect(
+            &location,
+            Flash::error("Too many suggestions; try again later"),
+        ));
+    }
+
+    let found = match discussions::verify(&discussion, &site, limits.max_bytes).await {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            return Ok(flash::redirect(
+                &location,
+                Flash::error(format!("{discussion} doesn't link to {site}")),
+            ))
+        }
+        Err(e) => {
+            info!("unable to fetch suggested discussion {discussion}: {e}");
+            return Ok(flash::redirect(
+                &location,
+                Flash::error(format!("Unable to fetch {discussion}: {e}")),
+            ));
+        }
+    };
+
+    let discussion_url = discussion.to_string();
+    let ip = client_ip.clone();
+    let added = blocking::block("suggest_discussion.add_discussion_suggestion", move || {
+        add_discussion_suggestion(&pool, site_id, &discussion_url, &found, &ip)
+    })
+    .await??;
+    if !added {
+        return Ok(flash::redirect(
+            &location,
+            Flash::error("That discussion has already been suggested"),
+        ));
+    }
+    info!("discussion {discussion} suggested for site {site_id} from ip {client_ip}");
+
+    Ok(flash::redirect(
+        &location,
+        Flash::success("Thanks! The discussion will be listed once a moderator has approved it."),
+    ))
+}
+
+#[get("/favicon/{id}")]
+async fn favicon(
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    req: HttpRequest,
+) -> Result<HttpResponse, HtmlError> {
+    let key = path.into_inner();
+    let (site_id, slug) = find_site_by_key(&pool, key.clone()).await?;
+    if let Some(redirect) = slug_redirect(&req, &key, &slug) {
+        return Ok(redirect);
+    }
+
+    let Some((icon, fetched)) =
+        blocking::block("favicon.get_favicon", move || get_favicon(&pool, site_id)).await??
+    else {
+        return Err(HtmlError::new(404, format!("no favicon for {slug}")));
+    };
+
+    let etag = etag::weak_etag(&(site_id, &fetched));
+    if etag::not_modified(&req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(icon.content_type)
+        .insert_header(header::ETag(etag))
+        .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(icon.data))
+}
+
+#[get("/thumb/{id}.webp")]
+async fn thumbnail(
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    req: HttpRequest,
+) -> Result<HttpResponse, HtmlError> {
+    let key = path.into_inner();
+    let (site_id, slug) = find_site_by_key(&pool, key.clone()).await?;
+    if let Some(redirect) = slug_redirect(&req, &key, &slug) {
+        return Ok(redirect);
+    }
+
+    let Some((thumb, captured)) = blocking::block("thumbnail.get_thumbnail", move || {
+        get_thumbnail(&pool, site_id)
+    })
+    .await??
+    else {
+        return Err(HtmlError::new(404, format!("no thumbnail for {slug}")));
+    };
+
+    let etag = etag::weak_etag(&(site_id, &captured));
+    if etag::not_modified(&req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+
+    let data = match blocking::block("thumbnail.read_file", move || std::fs::read(thumb)).await? {
+        Ok(data) => data,
+        Err(e) => {
+            error!("unable to read thumbnail for site {site_id}: {e:?}");
+            return Err(HtmlError::new(404, format!("no thumbnail for {slug}")));
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/webp")
+        .insert_header(header::ETag(etag))
+        .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+        .body(data))
+}
+
+/// Archived pages are served sandboxed, with nothing but inline styles and `data:` images
+/// allowed, so whatever they contain can't run on our origin.
+const SNAPSHOT_POLICY: &str =
+    "sandbox; default-src 'none'; style-src 'unsafe-inline'; img-src data:";
+
+#[get("/snapshot/{id}/{timestamp}")]
+async fn snapshot(
+    path: web::Path<(String, i64)>,
+    pool: web::Data<Pool>,
+    req: HttpRequest,
+) -> Result<HttpResponse, HtmlError> {
+    let (key, timestamp) = path.into_inner();
+    let (site_id, slug) = find_site_by_key(&pool, key.clone()).await?;
+    if let Some(redirect) = slug_redirect(&req, &key, &slug) {
+        return Ok(redirect);
+    }
+
+    let Some(html) = blocking::block("snapshot.get_snapshot", move || {
+        get_snapshot(&pool, site_id, timestamp)
+    })
+    .await??
+    else {
+        return Err(HtmlError::new(
+            404,
+            format!("no snapshot of {slug} at {timestamp}"),
+        ));
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header((header::CONTENT_SECURITY_POLICY, SNAPSHOT_POLICY))
+        .insert_header((header::CACHE_CONTROL, "public, max-age=31536000, immutable"))
+        .insert_header(("x-robots-tag", "noindex"))
+        .body(html))
+}
+
+#[get("/snapshot/{id}/{timestamp}/diff")]
+async fn snapshot_diff(
+    path: web::Path<(String, i64)>,
+    template: web::Data<Templates>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<HttpResponse, HtmlError> {
+    let (key, timestamp) = path.into_inner();
+    let (site_id, slug) = find_site_by_key(&pool, key.clone()).await?;
+    if let Some(redirect) = slug_redirect(&req, &key, &slug) {
+        return Ok(redirect);
+    }
+
+    let tmp = pool.clone();
+    let pair = blocking::block("snapshot_diff.get_snapshot_pair", move || {
+        get_snapshot_pair(&tmp, site_id, Some(timestamp))
+    })
+    .await??;
+    let Some((current, Some(previous))) = pair else {
+        return Err(HtmlError::new(
+            404,
+            format!("no earlier snapshot of {slug} to compare with"),
+        ));
+    };
+    let url = blocking::block("snapshot_diff.get_site_url", move || {
+        get_site_url(&pool, site_id)
+    })
+    .await??;
+
+    let stats = change_stats(&previous.html, &current.html);
+    let lines = diff_lines(&previous.html, &current.html);
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        PageContext::new(
+            &config,
+            format!("Changes to {url}"),
+            &format!("/snapshot/{slug}/{timestamp}/diff"),
+        )
+        .request(&req)
+        .render(
+            &template,
+            "snapshot_diff.html",
+            context!(
+                slug => slug,
+                url => url,
+                timestamp => timestamp,
+                previous => previous.captured,
+                captured => current.captured,
+                bytes_added => stats.bytes_added,
+                bytes_removed => stats.bytes_removed,
+                lines => lines,
+            ),
+        )?,
+    ))
+}
+
+#[get("/feed.atom")]
+async fn atom_feed(
+    template: web::Data<Templates>,
+    pool: web::Data<ReadPool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<HttpResponse, HtmlError> {
+    let tmp = pool.0.clone();
+    let (newest, count) = blocking::block("atom_feed.get_newest_validation", move || {
+        get_newest_validation(&tmp)
+    })
+    .await??;
+    let version = FeedVersion::new(newest.as_deref(), count);
+    if version.not_modified(&req) {
+        return Ok(version.not_modified_response());
+    }
+
+    let entries = blocking::block("atom_feed.get_newest_sites", move || {
+        get_newest_sites(&pool.0, FEED_LENGTH)
+    })
+    .await??;
+
+    Ok(version
+        .response(HttpResponse::Ok())
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(template.render(
+            "feed.xml",
+            context!(
+                site_name => config.site_name,
+                base_url => config.base_url,
+                updated => newest.unwrap_or_default(),
+                entries => entries,
+            ),
+        )?))
+}
+
+#[get("/planet.html")]
+async fn planet_page(
+    template: web::Data<Templates>,
+    pool: web::Data<ReadPool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let length = config.planet.as_ref().map_or(0, |planet| planet.length);
+    let posts = blocking::block("planet_page.get_planet_posts", move || {
+        get_planet_posts(&pool.0, length)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        PageContext::new(&config, "Planet", "/planet.html")
+            .request(&req)
+            .description(format!("Recent posts from {} members", config.site_name))
+            .render(&template, "planet.html", context!(posts => posts))?,
+    ))
+}
+
+/// Members of the configured peers that aren't listed here.
+#[get("/friends.html")]
+async fn friends_page(
+    template: web::Data<Templates>,
+    pool: web::Data<ReadPool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let Some(federation) = &config.federation else {
+        return Err(HtmlError::new(404, "federation is disabled"));
+    };
+
+    let sites = blocking::block("friends_page.get_peer_sites", move || {
+        get_peer_sites(&pool.0)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        PageContext::new(&config, "Friends of the club", "/friends.html")
+            .request(&req)
+            .description(format!(
+                "Members of clubs like {} that aren't listed here",
+                config.site_name
+            ))
+            .render(
+                &template,
+                "friends.html",
+                context!(
+                    peers => federation::friends(federation, sites),
+                    offer_submission => federation.offer_submission,
+                ),
+            )?,
+    ))
+}
+
+#[get("/planet.atom")]
+async fn planet_feed(
+    template: web::Data<Templates>,
+    pool: web::Data<ReadPool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<HttpResponse, HtmlError> {
+    let length = config.planet.as_ref().map_or(0, |planet| planet.length);
+    let posts = blocking::block("planet_feed.get_planet_posts", move || {
+        get_planet_posts(&pool.0, length)
+    })
+    .await??;
+
+    let newest = posts.first().map(|post| post.published.clone());
+    let version = FeedVersion::new(newest.as_deref(), posts.len());
+    if version.not_modified(&req) {
+        return Ok(version.not_modified_response());
+    }
+
+    Ok(version
+        .response(HttpResponse::Ok())
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(template.render(
+            "planet.xml",
+            context!(
+                site_name => config.site_name,
+                base_url => config.base_url,
+                updated => newest.unwrap_or_default(),
+                posts => posts,
+            ),
+        )?))
+}
+
+#[get("/feed.json")]
+async fn json_feed(
+    pool: web::Data<ReadPool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<HttpResponse, JsonError> {
+    let tmp = pool.0.clone();
+    let (newest, count) = blocking::block("json_feed.get_newest_validation", move || {
+        get_newest_validation(&tmp)
+    })
+    .await??;
+    let version = FeedVersion::new(newest.as_deref(), count);
+    if version.not_modified(&req) {
+        return Ok(version.not_modified_response());
+    }
+
+    let entries = blocking::block("json_feed.get_newest_sites", move || {
+        get_newest_sites(&pool.0, FEED_LENGTH)
+    })
+    .await??;
+
+    Ok(version
+        .response(HttpResponse::Ok())
+        .content_type("application/feed+json")
+        .json(JsonFeed::new(&config, &entries)))
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitRequest {
+    site: String,
+    /// From the preview page.  Without one the site is previewed instead of queued.
+    #[serde(default)]
+    token: Option<String>,
+}
+
+/// Submit a site in two steps: the first previews it (see [`preview`]), and the second,
+/// carrying the preview's token, puts it in the validation queue.
+#[post("/dosubmit/")]
+#[allow(clippy::too_many_arguments)]
+async fn submit(
+    query: web::Form<SubmitRequest>,
+    pool: web::Data<Pool>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    template: web::Data<Templates>,
+    key: web::Data<Key>,
+    wakeup: web::Data<AnalyzerWakeup>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let client_ip = get_client_ip(&req)?;
+    let site = query.site.clone();
+
+    if site.len() > config.limits.url_length {
+        return Err(HtmlError::new(
+            413,
+            format!("urls are limited to {} bytes", config.limits.url_length),
+        ));
+    }
+
+    let url = match Url::parse(&site[..]) {
+        Ok(url) => url,
+        Err(e) => {
+            return Ok(flash::redirect(
+                "/submit.html",
+                Flash::error(format!("invalid url '{site}': {e}")),
+            ))
+        }
+    };
+
+    // Every submission is stored in its canonical form, as `canonical_url` gives it, so that
+    // spellings of a site differing only in case, punycode, or a trailing slash are the same
+    // submission.
+    let site = url.to_string();
+
+    if let Err(msg) = check_path_policy(config.path_policy, &url) {
+        return Ok(flash::redirect("/submit.html", Flash::error(msg)));
+    }
+
+    let Some(token) = &query.token else {
+        return preview_submission(&pool, &storage, &config, &template, &key, &req, url).await;
+    };
+
+    if !preview::check_token(&key, &site, token) {
+        return Ok(flash::redirect(
+            "/submit.html",
+            Flash::error(format!(
+                "the preview of {site} is out of date; please submit it again"
+            )),
+        ));
+    }
+
+    info!("adding '{site}' to submission queue for {client_ip}");
+    let pages = pages_per_domain(&config);
+    let (tmp, subject) = (storage.clone(), site.clone());
+    if let Err(TenKbError::Msg(msg)) = blocking::block("submit.submit_site", move || {
+        tmp.submit_site(subject, pages)
+    })
+    .await?
+    {
+        return Ok(flash::redirect("/submit.html", Flash::error(msg)));
+    }
+
+    // Lookalikes are flagged against the members in SQLite.
+    if let (Some(host), Some(_)) = (site_domain(&site), storage.sqlite()) {
+        let tmp = pool.clone();
+        let members =
+            blocking::block("submit.get_member_hosts", move || get_member_hosts(&tmp)).await??;
+        if let Some(homograph) = find_homograph(&host, &members) {
+            warn!("flagging '{site}' from {client_ip} for review: {homograph}");
+            let (tmp, url) = (pool.clone(), site.clone());
+            blocking::block("submit.flag_homograph", move || {
+                flag_homograph(&tmp, &url, &homograph.to_string(), homograph.lookalike_of())
+            })
+            .await??;
+        }
+    }
+    wakeup.notify_one();
+
+    Ok(flash::redirect(
+        "/submit.html",
+        Flash::success(format!(
+            "Thank you for submitting {site}! It will be reviewed and, if it meets the \
+             eligibility criteria, added to the site."
+        )),
+    ))
+}
+
+/// The first step of a submission: the spam filter and the listed, queued, and blocked
+/// checks, then a look at the size of the site's front page.  Sites that pass are shown to
+/// the submitter with a token to confirm them; the rest never cost a Cloudflare scan.
+async fn preview_submission(
+    pool: &web::Data<Pool>,
+    storage: &web::Data<dyn Storage>,
+    config: &Config,
+    template: &Templates,
+    key: &Key,
+    req: &HttpRequest,
+    url: Url,
+) -> Result<HttpResponse, HtmlError> {
+    let client_ip = get_client_ip(req)?;
+    let site = url.to_string();
+
+    if let Some(spam) = &config.spam {
+        let sample = Sample::new(SpamKind::Submission, site.clone(), req);
+        match score(pool, config, &sample).await {
+            Ok(score) => {
+                let (tmp, subject) = (pool.clone(), site.clone());
+                blocking::block("submit.record_spam_score", move || {
+                    record_spam_score(&tmp, "submission", &subject, score)
+                })
+                .await??;
+                if is_spam(spam, score) {
+                    warn!(
+                        "refusing submission of '{site}' from {client_ip}: spam score {score:.2}"
+                    );
+                    return Ok(flash::redirect(
+                        "/submit.html",
+                        Flash::error(format!("{site} was refused as likely spam")),
+                    ));
+                }
+            }
+            Err(e) => error!("unable to score submission of '{site}': {e}"),
+        }
+    }
+
+    let (tmp, subject, pages) = (storage.clone(), site.clone(), pages_per_domain(config));
+    if let Err(TenKbError::Msg(msg)) = blocking::block("submit.check_submission", move || {
+        tmp.check_submission(&subject, pages)
+    })
+    .await?
+    {
+        return Ok(flash::redirect("/submit.html", Flash::error(msg)));
+    }
+
+    let bytes = match preview::estimate_size(&url).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            info!("not previewing '{site}' for {client_ip}: {e}");
+            return Ok(flash::redirect("/submit.html", Flash::error(e.to_string())));
+        }
+    };
+    info!("previewed '{site}' for {client_ip}: front page is {bytes} bytes");
+
+    let token = preview::token(key, &site);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        PageContext::new(config, "Confirm your submission", "/submit.html")
+            .description("Submit a website of 10KiB or less to the 10KB Club")
+            .request(req)
+            .render(
+                template,
+                "submit_preview.html",
+                context!(
+                    site => site,
+                    bytes => bytes,
+                    limit => SIZE_LIMIT,
+                    token => token,
+                    minutes => preview::TOKEN_LIFETIME.as_secs() / 60,
+                ),
+            )?,
+    ))
+}
+
+/// How many pages of one domain may be listed or pending at once, or 0 for no limit.
+fn pages_per_domain(config: &Config) -> usize {
+    match config.path_policy {
+        PathPolicy::PathAllowedWithDomainDedup => config.limits.pages_per_domain,
+        PathPolicy::HomepageOnly | PathPolicy::PathAllowed => 0,
+    }
+}
+
+#[derive(Serialize)]
+struct IdResponse {
+    code: usize,
+    status: String,
+    voter_id: String,
+}
+
+#[post("/id/")]
+async fn id(
+    storage: web::Data<dyn Storage>,
+    req: HttpRequest,
+) -> Result<impl Responder, JsonError> {
+    let mut response = IdResponse {
+        code: 200,
+        status: String::from("OK"),
+        voter_id: String::from(""),
+    };
+
+    let client_ip = get_client_ip(&req)?;
+
+    let mut rand_bytes = [0u8; 32];
+    thread_rng().fill(&mut rand_bytes);
+
+    let id = hex::encode(rand_bytes);
+    response.voter_id = id.clone();
+
+    info!("Generating new ID '{id}' for client {client_ip}");
+
+    blocking::block("id.generate_id", move || storage.generate_id(id)).await??;
+    Ok(web::Json(response))
+}
+
+#[derive(Deserialize)]
+struct VoteRequest {
+    voter_id: String,
+    site_id: u32,
+    vote: isize,
+}
+
+/// The voter's vote on the site after the request, which is the same however many times it
+/// is repeated.
+#[derive(Serialize)]
+struct VoteResponse {
+    code: usize,
+    status: String,
+    site_id: u32,
+    voted: bool,
+    votes: u32,
+}
+
+/// Takes a form post from the page's script, or the same fields as a JSON body.
+#[post("/vote/")]
+async fn vote(
+    data: Result<Either<web::Form<VoteRequest>, web::Json<VoteRequest>>, BodyError>,
+    pool: web::Data<Pool>,
+    storage: web::Data<dyn Storage>,
+    events: web::Data<EventSender>,
+    req: HttpRequest,
+) -> Result<HttpResponse, JsonError> {
+    let VoteRequest {
+        voter_id,
+        site_id,
+        vote,
+    } = match data {
+        Ok(data) => data.into_inner(),
+        Err(e) => return Ok(body_error(e, &req).error_response()),
+    };
+
+    if !(0..=1).contains(&vote) {
+        return Err("invalid vote".into());
+    }
+
+    let client_ip = get_client_ip(&req)?;
+
+    info!(
+        "casting vote '{vote}' for commenter: '{voter_id}' for site {site_id} from ip {client_ip}"
+    );
+
+    let (changed, shadowed, total) = blocking::block("vote.voter_exists", move || {
+        if !storage.voter_exists(&voter_id)? {
+            return Ok(None);
+        }
+        let shadowed = is_shadowed(&pool, &voter_id, &client_ip)?;
+        let (changed, total) = storage.cast_vote(voter_id, site_id, vote, shadowed)?;
+        Ok::<_, TenKbError>(Some((changed, shadowed, total)))
+    })
+    .await??
+    .ok_or_else(|| JsonError::new(404, "unknown voter id"))?;
+
+    if changed && !shadowed {
+        publish(
+            &events,
+            SiteEvent::Votes {
+                site_id,
+                votes: total,
+            },
+        );
+    }
+
+    Ok(HttpResponse::Ok().json(VoteResponse {
+        code: 200,
+        status: String::from("OK"),
+        site_id,
+        voted: vote == 1,
+        votes: total,
+    }))
+}
+
+#[derive(Deserialize)]
+struct VotesRequest {
+    voter_id: String,
+    site_ids: String,
+}
+
+#[derive(Serialize)]
+struct VotesResponse {
+    code: usize,
+    status: String,
+    site_ids: Vec<u32>,
+}
+
+/// Rescan requests are throttled separately from the API, over a much longer window.
+struct RescanLimiter(RateLimiter);
+
+#[derive(Serialize)]
+struct RescanResponse {
+    code: usize,
+    status: String,
+}
+
+/// Queue a rejected site for another measurement, e.g. after its owner slimmed it down.
+#[post("/site/{id}/rescan/")]
+async fn rescan(
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    limiter: web::Data<RescanLimiter>,
+    wakeup: web::Data<AnalyzerWakeup>,
+    req: HttpRequest,
+) -> Result<impl Responder, JsonError> {
+    let (site_id, _) = find_api_site(&pool, path.into_inner()).await?;
+    let client_ip = get_client_ip(&req)?;
+
+    let tmp = pool.clone();
+    let url = blocking::block("rescan.get_site_url", move || get_site_url(&tmp, site_id)).await??;
+
+    let tmp = pool.clone();
+    let site = url.clone();
+    let (active, queued) = blocking::block("rescan.check_site", move || {
+        Ok::<_, TenKbError>((
+            check_site_active(&tmp, &site)?,
+            check_site_pending(&tmp, site_id)?,
+        ))
+    })
+    .await??;
+
+    if active {
+        return Err(JsonError::new(409, format!("{url} is already listed")));
+    }
+
+    if queued {
+        return Err(JsonError::new(
+            409,
+            format!("{url} is already waiting to be scanned"),
+        ));
+    }
+
+    let limits = &config.rescan;
+    if !limiter
+        .0
+        .check(&format!("ip:{client_ip}"), limits.per_ip)
+        .allowed
+        || !limiter
+            .0
+            .check(&format!("site:{site_id}"), limits.per_site)
+            .allowed
+    {
+        return Err(JsonError::new(
+            429,
+            "too many rescan requests; try again later",
+        ));
+    }
+
+    info!("queueing {url} for a rescan requested by {client_ip}");
+    blocking::block("rescan.requeue_site", move || {
+        requeue_site(&pool, site_id, QueuePriority::Rescan)
+    })
+    .await??;
+    wakeup.notify_one();
+
+    Ok(web::Json(RescanResponse {
+        code: 200,
+        status: format!("{url} will be measured again shortly"),
+    }))
+}
+
+#[derive(Serialize)]
+struct OwnerTokenResponse {
+    code: usize,
+    status: String,
+    token: String,
+    publish_at: String,
+}
+
+/// A token for a site's owner to publish on the site, proving they can fetch its record.
+#[post("/site/{id}/owner/")]
+async fn owner_token(
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    key: web::Data<Key>,
+) -> Result<impl Responder, JsonError> {
+    let site = path.into_inner();
+    let tmp = pool.clone();
+    let Some((site_id, slug)) =
+        blocking::block("owner_token.find_site", move || find_site(&tmp, &site)).await??
+    else {
+        return Err(JsonError::new(404, "no such site"));
+    };
+
+    let url = blocking::block("owner_token.get_site_url", move || {
+        get_site_url(&pool, site_id)
+    })
+    .await??;
+    let publish_at = owners::token_url(&url).map_err(|e| {
+        JsonError::new(
+            500,
+            format!("unable to find where {url} would publish a token: {e}"),
+        )
+    })?;
+
+    let minutes = owners::TOKEN_LIFETIME.as_secs() / 60;
+    Ok(web::Json(OwnerTokenResponse {
+        code: 200,
+        status: format!(
+            "publish the token at {publish_at} within {minutes} minutes, then fetch \
+             /site/{slug}/record.json with it as a bearer token"
+        ),
+        token: owners::token(&key, site_id),
+        publish_at: publish_at.to_string(),
+    }))
+}
+
+/// Everything stored about a site, for an owner presenting a token from `owner_token` that
+/// the site publishes.  Each request fetches the token from the site, so they're throttled
+/// along with rescans.
+#[get("/site/{id}/record.json")]
+async fn owner_record(
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    key: web::Data<Key>,
+    config: web::Data<Config>,
+    limiter: web::Data<RescanLimiter>,
+    req: HttpRequest,
+) -> Result<impl Responder, JsonError> {
+    let site = path.into_inner();
+    let client_ip = get_client_ip(&req)?;
+
+    let tmp = pool.clone();
+    let Some((site_id, _)) =
+        blocking::block("owner_record.find_site", move || find_site(&tmp, &site)).await??
+    else {
+        return Err(JsonError::new(404, "no such site"));
+    };
+
+    let Some(token) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .filter(|token| owners::check_token(&key, site_id, token))
+    else {
+        return Err(JsonError::new(
+            401,
+            "a current owner token for this site is required as a bearer token",
+        ));
+    };
+
+    if !limiter
+        .0
+        .check(&format!("owner:{client_ip}"), config.rescan.per_ip)
+        .allowed
+    {
+        return Err(JsonError::new(
+            429,
+            "too many record requests; try again later",
+        ));
+    }
+
+    let tmp = pool.clone();
+    let url = blocking::block("owner_record.get_site_url", move || {
+        get_site_url(&tmp, site_id)
+    })
+    .await??;
+    match owners::publishes(&url, token).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(JsonError::new(
+                403,
+                format!("{url} doesn't publish this token at {}", owners::TOKEN_PATH),
+            ))
+        }
+        Err(e) => return Err(JsonError::new(502, e.to_string())),
+    }
+
+    info!("sending the record of {url} to its owner at {client_ip}");
+    let record = blocking::block("owner_record.get_site_record", move || {
+        get_site_record(&pool, site_id)
+    })
+    .await??
+    .ok_or_else(|| JsonError::new(404, "no such site"))?;
+
+    Ok(web::Json(record))
+}
+
+#[post("/votes/")]
+async fn votes(
+    data: web::Form<VotesRequest>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<impl Responder, JsonError> {
+    let site_ids = data
+        .site_ids
+        .split(",")
+        .filter_map(|s| s.parse().ok())
+        .collect::<Vec<u32>>();
+
+    let voted = voted_sites(&storage, &config, &req, data.voter_id.clone(), &site_ids).await?;
+
+    let response = VotesResponse {
+        code: 200,
+        status: String::from("OK"),
+        site_ids: voted,
+    };
+
+    let etag = etag::weak_etag(&response.site_ids);
+    Ok(etag::json_response(&req, etag, &response))
+}
+
+#[derive(Deserialize)]
+struct VotesJsonRequest {
+    voter_id: String,
+    site_ids: Vec<u32>,
+}
+
+#[derive(Serialize)]
+struct SiteVote {
+    site_id: u32,
+    voted: bool,
+}
+
+#[derive(Serialize)]
+struct VotesJsonResponse {
+    code: usize,
+    status: String,
+    votes: Vec<SiteVote>,
+}
+
+fn json_body(ctx: &GuardContext) -> bool {
+    ctx.header::<header::ContentType>()
+        .is_some_and(|content_type| content_type.0.essence_str() == "application/json")
+}
+
+/// `/votes/` for API clients: a JSON body in, and a verdict per requested site out.
+#[post("/votes/", guard = "json_body")]
+async fn votes_json(
+    data: web::Json<VotesJsonRequest>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<impl Responder, JsonError> {
+    let voted = voted_sites(
+        &storage,
+        &config,
+        &req,
+        data.voter_id.clone(),
+        &data.site_ids,
+    )
+    .await?;
+
+    let response = VotesJsonResponse {
+        code: 200,
+        status: String::from("OK"),
+        votes: data
+            .site_ids
+            .iter()
+            .map(|&site_id| SiteVote {
+                site_id,
+                voted: voted.contains(&site_id),
+            })
+            .collect(),
+    };
+
+    let etag = etag::weak_etag(&response.votes);
+    Ok(etag::json_response(&req, etag, &response))
+}
+
+/// Which of `site_ids` the voter has voted for.
+async fn voted_sites(
+    storage: &web::Data<dyn Storage>,
+    config: &Config,
+    req: &HttpRequest,
+    voter_id: String,
+    site_ids: &[u32],
+) -> Result<Vec<u32>, JsonError> {
+    if site_ids.len() > config.limits.site_ids {
+        return Err(JsonError::new(
+            413,
+            format!("at most {} site ids per request", config.limits.site_ids),
+        ));
+    }
+
+    let client_ip = get_client_ip(req)?;
+
+    info!("getting votes for '{voter_id}' from ip {client_ip}");
+
+    let storage = storage.clone();
+    let sites =
+        blocking::block("voted_sites.get_votes", move || storage.get_votes(voter_id)).await??;
+
+    Ok(sites
+        .into_iter()
+        .filter(|site| site_ids.contains(site))
+        .collect())
+}
+
+#[derive(Serialize)]
+struct AnalyzerStatusResponse {
+    code: usize,
+    status: String,
+    analyzer: AnalyzerStatus,
+}
+
+#[get("/analyzer/status")]
+async fn analyzer_status_api(
+    analyzer_status: web::Data<AnalyzerStatusHandle>,
+) -> Result<impl Responder, JsonError> {
+    let analyzer = analyzer_status
+        .lock()
+        .map_err(|e| format!("analyzer status unavailable: {e}"))?
+        .clone();
+
+    Ok(web::Json(AnalyzerStatusResponse {
+        code: 200,
+        status: String::from("OK"),
+        analyzer,
+    }))
+}
+
+#[derive(Serialize)]
+struct SiteResponse {
+    code: usize,
+    status: String,
+    site: SiteDetail,
+}
+
+#[get("/sites/{id}")]
+async fn site_api(
+    path: web::Path<String>,
+    pool: web::Data<Pool>,
+    req: HttpRequest,
+) -> Result<HttpResponse, JsonError> {
+    let key = path.into_inner();
+    let (site_id, slug) = find_api_site(&pool, key.clone()).await?;
+    if let Some(redirect) = slug_redirect(&req, &key, &slug) {
+        return Ok(redirect);
+    }
+
+    let Some(site) = blocking::block("site_api.get_site_detail", move || {
+        get_site_detail(&pool, site_id)
+    })
+    .await??
+    else {
+        return Err(JsonError::new(404, format!("no site named {slug}")));
+    };
+
+    let etag = etag::weak_etag(&site);
+    Ok(etag::json_response(
+        &req,
+        etag,
+        &SiteResponse {
+            code: 200,
+            status: String::from("OK"),
+            site,
+        },
+    ))
+}
+
+#[derive(Deserialize)]
+struct SitesRequest {
+    sortby: Option<String>,
+    page: Option<String>,
+    per_page: Option<String>,
+    has_related: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SitesResponse {
+    code: usize,
+    status: String,
+    /// Listed sites matching the filter, over all pages.
+    total: usize,
+    page: usize,
+    per_page: usize,
+    sortby: SortOptions,
+    filter: SiteFilter,
+    sites: Vec<Site>,
+}
+
+/// The directory a page at a time, in any of the index's orders.  As on the index, a
+/// `per_page` outside `index.min_paginate..=index.max_paginate` is clamped rather than
+/// refused.
+#[get("/sites")]
+async fn sites_api(
+    query: web::Query<SitesRequest>,
+    storage: web::Data<ReadStorage>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<HttpResponse, JsonError> {
+    let mut errors = vec![];
+    let sortby = parse_field(&mut errors, "sortby", &query.sortby, |s| s.parse());
+    let page = parse_field(&mut errors, "page", &query.page, positive_integer).unwrap_or(1);
+    let per_page = parse_field(&mut errors, "per_page", &query.per_page, positive_integer)
+        .map(|per_page| config.index.clamp_paginate(per_page))
+        .unwrap_or(config.index.clamp_paginate(config.index.paginate));
+    let has_related = parse_field(&mut errors, "has_related", &query.has_related, boolean);
+    let offset = page_offset(&mut errors, page, per_page);
+
+    let (Some(offset), true) = (offset, errors.is_empty()) else {
+        return Err(JsonError::new(
+            400,
+            format!("invalid query: {}", errors.join("; ")),
+        ));
+    };
+    let sortby = sortby.unwrap_or(config.index.sortby);
+    let filter = SiteFilter {
+        has_related: has_related.unwrap_or(false),
+    };
+
+    let (total, sites) = blocking::block("sites_api.get_sites", move || {
+        Ok::<_, TenKbError>((
+            storage.0.get_site_count(&filter)?,
+            storage.0.get_sites(sortby, &filter, offset, per_page)?,
+        ))
+    })
+    .await??;
+
+    let response = SitesResponse {
+        code: 200,
+        status: String::from("OK"),
+        total,
+        page,
+        per_page,
+        sortby,
+        filter,
+        sites,
+    };
+    Ok(etag::json_response(
+        &req,
+        etag::weak_etag(&response),
+        &response,
+    ))
+}
+
+#[derive(Deserialize)]
+struct TopRequest {
+    #[serde(default)]
+    period: Period,
+}
+
+#[derive(Serialize)]
+struct TopResponse {
+    code: usize,
+    status: String,
+    period: Period,
+    sites: Vec<Site>,
+}
+
+#[get("/top")]
+async fn top_api(
+    query: web::Query<TopRequest>,
+    storage: web::Data<ReadStorage>,
+    top_cache: web::Data<TopCache>,
+    req: HttpRequest,
+) -> Result<HttpResponse, JsonError> {
+    let period = query.period;
+    let sites = blocking::block("top_api.top_cache", move || {
+        top_cache.get(&*storage.0, period)
+    })
+    .await??;
+
+    let response = TopResponse {
+        code: 200,
+        status: String::from("OK"),
+        period,
+        sites,
+    };
+    Ok(etag::json_response(
+        &req,
+        etag::weak_etag(&response),
+        &response,
+    ))
+}
+
+/// Live index updates; only routed when `websocket.enabled` is set.
+#[get("/ws/")]
+async fn websocket(
+    req: HttpRequest,
+    body: web::Payload,
+    events: web::Data<EventSender>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse> {
+    let (res, session, stream) = actix_ws::handle(&req, body)?;
+
+    actix_web::rt::spawn(forward_events(
+        session,
+        stream.aggregate_continuations(),
+        events.subscribe(),
+        Duration::from_millis(config.websocket.throttle_ms),
+    ));
+
+    Ok(res)
+}
+
+#[get("/")]
+async fn admin_dashboard(
+    template: web::Data<Templates>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    read_only: web::Data<ReadOnlyHandle>,
+    analyzer_status: web::Data<AnalyzerStatusHandle>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let analyzer = analyzer_status
+        .lock()
+        .map_err(|e| format!("analyzer status unavailable: {e}"))?
+        .clone();
+
+    let tmp = pool.clone();
+    let featured = blocking::block("admin_dashboard.get_featured_sites", move || {
+        get_featured_sites(&tmp)
+    })
+    .await??;
+
+    let tmp = pool.clone();
+    let duplicates = blocking::block("admin_dashboard.get_duplicate_content", move || {
+        get_duplicate_content(&tmp, 30)
+    })
+    .await??;
+
+    let tmp = pool.clone();
+    let at_risk = blocking::block("admin_dashboard.get_at_risk_changes", move || {
+        get_at_risk_changes(&tmp, 30)
+    })
+    .await??;
+
+    let comments = if config.comments.is_some() {
+        let tmp = pool.clone();
+        Some(
+            blocking::block("admin_dashboard.get_moderation_queue", move || {
+                get_moderation_queue(&tmp, 50)
+            })
+            .await??,
+        )
+    } else {
+        None
+    };
+
+    let tmp = pool.clone();
+    let (shadow_flags, shadowed_voters) =
+        blocking::block("admin_dashboard.get_shadow_flags", move || {
+            Ok::<_, TenKbError>((get_shadow_flags(&tmp)?, get_shadowed_voters(&tmp)?))
+        })
+        .await??;
+
+    let spam_scores = match &config.spam {
+        Some(spam) => {
+            let (tmp, threshold) = (pool.clone(), spam.threshold);
+            Some(
+                blocking::block("admin_dashboard.get_spam_scores", move || {
+                    get_spam_scores(&tmp, threshold, 30)
+                })
+                .await??,
+            )
+        }
+        None => None,
+    };
+
+    let discussions = if config.discussions.is_some() {
+        let tmp = pool.clone();
+        Some(
+            blocking::block("admin_dashboard.get_discussion_suggestions", move || {
+                get_discussion_suggestions(&tmp, 50)
+            })
+            .await??,
+        )
+    } else {
+        None
+    };
+
+    let vote_anomalies = if config.vote_anomalies.is_some() {
+        let tmp = pool.clone();
+        Some(
+            blocking::block("admin_dashboard.get_vote_anomalies", move || {
+                get_vote_anomalies(&tmp, 30)
+            })
+            .await??,
+        )
+    } else {
+        None
+    };
+
+    let tmp = pool.clone();
+    let homographs = blocking::block("admin_dashboard.get_homograph_flags", move || {
+        get_homograph_flags(&tmp, 30)
+    })
+    .await??;
+
+    let tmp = pool.clone();
+    let sent_webmentions = blocking::block("admin_dashboard.get_sent_webmentions", move || {
+        get_sent_webmentions(&tmp, 30)
+    })
+    .await??;
+
+    let regional_differences = match &config.regional_scans {
+        Some(regional) => {
+            let (tmp, ratio) = (pool.clone(), regional.max_size_ratio);
+            Some(
+                blocking::block("admin_dashboard.get_regional_differences", move || {
+                    get_regional_differences(&tmp, ratio, 30)
+                })
+                .await??,
+            )
+        }
+        None => None,
+    };
+
+    let tmp = pool.clone();
+    let (scans_today, scanner_usage) =
+        blocking::block("admin_dashboard.get_scanner_usage", move || {
+            Ok::<_, TenKbError>((get_scanner_calls_today(&tmp)?, get_scanner_usage(&tmp, 30)?))
+        })
+        .await??;
+
+    let countries = if config.geoip_database.is_some() {
+        Some(
+            blocking::block("admin_dashboard.get_country_counts", move || {
+                get_country_counts(&pool, 30)
+            })
+            .await??,
+        )
+    } else {
+        None
+    };
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        PageContext::new(&config, "Admin", "/admin/")
+            .request(&req)
+            .render(
+                &template,
+                "admin.html",
+                context!(
+                    analyzer => analyzer,
+                    countries => countries,
+                    featured => featured,
+                    duplicates => duplicates,
+                    homographs => homographs,
+                    at_risk => at_risk,
+                    sent_webmentions => sent_webmentions,
+                    scans_today => scans_today,
+                    scanner_budget => config.scanner_daily_budget,
+                    scanner_usage => scanner_usage,
+                    regional_differences => regional_differences,
+                    comments => comments,
+                    discussions => discussions,
+                    shadow_flags => shadow_flags,
+                    shadowed_voters => shadowed_voters,
+                    spam_scores => spam_scores,
+                    vote_anomalies => vote_anomalies,
+                    note_length => config.limits.note_length,
+                    read_only => is_read_only(&read_only),
+                ),
+            )?,
+    ))
+}
+
+/// Every connection pool the server uses, for `/admin/metrics`.
+struct PoolMetrics(Vec<(Pool, PoolStatsHandle)>);
+
+#[get("/metrics")]
+async fn admin_metrics(
+    pools: web::Data<PoolMetrics>,
+    in_flight: Option<web::Data<InFlight>>,
+) -> HttpResponse {
+    let mut body = poolstats::render(&pools.0);
+    body.push_str(&blocking::render());
+    if let Some(in_flight) = in_flight {
+        body.push_str(&in_flight.render());
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+#[get("/keys/")]
+async fn admin_keys(
+    template: web::Data<Templates>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    req: HttpRequest,
+) -> Result<impl Responder, HtmlError> {
+    let keys = blocking::block("admin_keys.list_api_keys", move || list_api_keys(&pool)).await??;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        PageContext::new(&config, "API keys", "/admin/keys/")
+            .request(&req)
+            .render(
+                &template,
+                "admin_keys.html",
+                context!(
+                    keys => keys,
+                    default_quota => config.api.default_key_quota,
+                ),
+            )?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateKeyRequest {
+    name: String,
+    #[serde(default)]
+    quota: String,
+}
+
+#[post("/keys/")]
+async fn admin_create_key(
+    form: web::Form<CreateKeyRequest>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, HtmlError> {
+    let name = form.name.trim().to_string();
+    if name.is_empty() {
+        return Ok(flash::redirect(
+            "/admin/keys/",
+            Flash::error("API keys need a name"),
+        ));
+    }
+
+    let quota = match form.quota.trim() {
+        "" => config.api.default_key_quota,
+        quota => match quota.parse() {
+            Ok(quota) => quota,
+            Err(_) => {
+                return Ok(flash::redirect(
+                    "/admin/keys/",
+                    Flash::error(format!("invalid quota '{quota}'")),
+                ))
+            }
+        },
+    };
+
+    let (key, hash) = generate_key();
+    let key_name = name.clone();
+    blocking::block("admin_create_key.create_api_key", move || {
+        create_api_key(&pool, &key_name, &hash, quota)
+    })
+    .await??;
+    info!("minted API key '{name}' with quota {quota}");
+
+    Ok(flash::redirect(
+        "/admin/keys/",
+        Flash::success(format!(
+            "Created key '{name}': {key} (it will not be shown again)"
+        )),
+    ))
+}
+
+#[post("/keys/{id}/revoke/")]
+async fn admin_revoke_key(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    let key_id = path.into_inner();
+    blocking::block("admin_revoke_key.revoke_api_key", move || {
+        revoke_api_key(&pool, key_id)
+    })
+    .await??;
+    info!("revoked API key {key_id}");
+
+    Ok(flash::redirect(
+        "/admin/keys/",
+        Flash::info(format!("Revoked key {key_id}")),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct NoteRequest {
+    site_id: u32,
+    /// Empty to remove the site's note.
+    #[serde(default)]
+    note: String,
+}
+
+#[post("/notes/")]
+async fn admin_site_note(
+    form: web::Form<NoteRequest>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, HtmlError> {
+    let NoteRequest { site_id, note } = form.into_inner();
+    let note = note.trim().to_owned();
+
+    if note.chars().count() > config.limits.note_length {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!(
+                "notes are limited to {} characters",
+                config.limits.note_length
+            )),
+        ));
+    }
+
+    let cleared = note.is_empty();
+    let updated = blocking::block("admin_site_note.set_note", move || {
+        set_note(
+            &pool,
+            site_id,
+            if cleared { None } else { Some(note.as_str()) },
+        )
+    })
+    .await??;
+
+    if !updated {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!("no listed site with id {site_id}")),
+        ));
+    }
+    info!("updated note on site {site_id}");
+
+    let message = if cleared {
+        format!("Removed the note on site {site_id}")
+    } else {
+        format!("Saved the note on site {site_id}")
+    };
+    Ok(flash::redirect("/admin/", Flash::success(message)))
+}
+
+#[derive(Debug, Deserialize)]
+struct FeatureRequest {
+    site_id: u32,
+    /// Empty to feature the site until it is unpinned.
+    #[serde(default)]
+    days: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShadowRequest {
+    kind: ShadowKind,
+    value: String,
+    #[serde(default)]
+    reason: String,
+}
+
+/// Shadow a voter number or IP.  Content they post from now on is only shown to them.
+#[post("/shadow/")]
+async fn admin_add_shadow(
+    form: web::Form<ShadowRequest>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    let ShadowRequest {
+        kind,
+        value,
+        reason,
+    } = form.into_inner();
+    let value = value.trim().trim_start_matches('#').to_owned();
+
+    if value.is_empty() || (kind == ShadowKind::Voter && value.parse::<u32>().is_err()) {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!("invalid {} '{value}'", kind.as_str())),
+        ));
+    }
+
+    let reason = reason.trim().to_owned();
+    let flagged = value.clone();
+    blocking::block("admin_add_shadow.add_shadow_flag", move || {
+        add_shadow_flag(
+            &pool,
+            kind,
+            &flagged,
+            (!reason.is_empty()).then_some(reason.as_str()),
+        )
+    })
+    .await??;
+    info!("shadowing {} {value}", kind.as_str());
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::success(format!("Shadowed {} {value}", kind.as_str())),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct UnshadowRequest {
+    kind: ShadowKind,
+    value: String,
+}
+
+#[post("/shadow/remove/")]
+async fn admin_remove_shadow(
+    form: web::Form<UnshadowRequest>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    let UnshadowRequest { kind, value } = form.into_inner();
+    let flagged = value.clone();
+    if !blocking::block("admin_remove_shadow.remove_shadow_flag", move || {
+        remove_shadow_flag(&pool, kind, &flagged)
+    })
+    .await??
+    {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!("{} {value} isn't shadowed", kind.as_str())),
+        ));
+    }
+    info!("no longer shadowing {} {value}", kind.as_str());
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::info(format!(
+            "{} {value} is no longer shadowed; review their existing content below",
+            kind.as_str()
+        )),
+    ))
+}
+
+#[post("/shadow/votes/{voter}/approve/")]
+async fn admin_approve_votes(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    review_votes(path.into_inner(), pool, true).await
+}
+
+#[post("/shadow/votes/{voter}/purge/")]
+async fn admin_purge_votes(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    review_votes(path.into_inner(), pool, false).await
+}
+
+async fn review_votes(
+    voter: u32,
+    pool: web::Data<Pool>,
+    approve: bool,
+) -> Result<HttpResponse, HtmlError> {
+    let count = blocking::block("review_votes.review_shadowed_votes", move || {
+        review_shadowed_votes(&pool, voter, approve)
+    })
+    .await??;
+    let action = if approve { "approved" } else { "purged" };
+    info!("{action} {count} shadowed votes from voter {voter}");
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::success(format!(
+            "{} {count} shadowed votes from voter #{voter}",
+            if approve { "Approved" } else { "Purged" }
+        )),
+    ))
+}
+
+#[post("/anomalies/{id}/invalidate/")]
+async fn admin_invalidate_anomaly(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    let anomaly = path.into_inner();
+    let Some(count) = blocking::block(
+        "admin_invalidate_anomaly.invalidate_vote_anomaly",
+        move || invalidate_vote_anomaly(&pool, anomaly),
+    )
+    .await??
+    else {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!("no open anomaly report #{anomaly}")),
+        ));
+    };
+    info!("invalidated {count} votes from anomaly report {anomaly}");
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::success(format!("Invalidated {count} votes from report #{anomaly}")),
+    ))
+}
+
+#[post("/comments/{id}/approve/")]
+async fn admin_approve_comment(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, HtmlError> {
+    moderate(path.into_inner(), pool, config, CommentStatus::Approved).await
+}
+
+#[post("/comments/{id}/reject/")]
+async fn admin_reject_comment(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, HtmlError> {
+    moderate(path.into_inner(), pool, config, CommentStatus::Rejected).await
+}
+
+/// Set a comment's status; with spam scoring enabled, the decision also trains the filter.
+async fn moderate(
+    comment_id: u32,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+    status: CommentStatus,
+) -> Result<HttpResponse, HtmlError> {
+    let train_filter = config.spam.is_some();
+    let moderated = blocking::block("moderate.moderate_comment", move || {
+        if !moderate_comment(&pool, comment_id, status)? {
+            return Ok(false);
+        }
+        if train_filter {
+            if let Some(body) = get_comment_body(&pool, comment_id)? {
+                train(&pool, &body, matches!(status, CommentStatus::Rejected))?;
+            }
+        }
+        Ok::<_, TenKbError>(true)
+    })
+    .await??;
+
+    if !moderated {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!("no comment with id {comment_id}")),
+        ));
+    }
+    info!("comment {comment_id} {}", status.as_str());
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::success(format!("Comment {comment_id} {}", status.as_str())),
+    ))
+}
+
+#[post("/discussions/{id}/approve/")]
+async fn admin_approve_discussion(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    moderate_discussion(path.into_inner(), pool, true).await
+}
+
+#[post("/discussions/{id}/reject/")]
+async fn admin_reject_discussion(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    moderate_discussion(path.into_inner(), pool, false).await
+}
+
+async fn moderate_discussion(
+    suggestion: u32,
+    pool: web::Data<Pool>,
+    approve: bool,
+) -> Result<HttpResponse, HtmlError> {
+    let moderated = blocking::block(
+        "moderate_discussion.moderate_discussion_suggestion",
+        move || moderate_discussion_suggestion(&pool, suggestion, approve),
+    )
+    .await??;
+
+    if !moderated {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!("no pending discussion suggestion #{suggestion}")),
+        ));
+    }
+    let action = if approve { "approved" } else { "rejected" };
+    info!("discussion suggestion {suggestion} {action}");
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::success(format!("Discussion suggestion #{suggestion} {action}")),
+    ))
+}
+
+#[post("/featured/")]
+async fn admin_feature_site(
+    form: web::Form<FeatureRequest>,
+    pool: web::Data<Pool>,
+    config: web::Data<Config>,
+) -> Result<impl Responder, HtmlError> {
+    let site_id = form.site_id;
+    let days = match form.days.trim() {
+        "" => None,
+        days => match days.parse() {
+            Ok(days) => Some(days),
+            Err(_) => {
+                return Ok(flash::redirect(
+                    "/admin/",
+                    Flash::error(format!("invalid number of days '{days}'")),
+                ))
+            }
+        },
+    };
+
+    let tmp = pool.clone();
+    if !blocking::block("admin_feature_site.set_featured", move || {
+        set_featured(&tmp, site_id, days)
+    })
+    .await??
+    {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!("no listed site with id {site_id}")),
+        ));
+    }
+    info!("featured site {site_id} for {days:?} days");
+
+    if config.announcements.is_some() {
+        let tmp = pool.clone();
+        let url = blocking::block("admin_feature_site.get_site_url", move || {
+            get_site_url(&tmp, site_id)
+        })
+        .await??;
+        actix_web::rt::spawn(async move {
+            if let Err(e) = announce(&pool, &config, site_id, &url, Announcement::Featured).await {
+                error!("unable to announce feature to {url}: {e}");
+            }
+        });
+    }
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::success(format!("Featured site {site_id}")),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    /// The duplicate entry, which is removed.
+    from: u32,
+    /// The entry that survives.
+    into: u32,
+}
+
+#[post("/merge/")]
+async fn admin_merge_sites(
+    form: web::Form<MergeRequest>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    let MergeRequest { from, into } = form.into_inner();
+    if from == into {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error("a site can't be merged into itself"),
+        ));
+    }
+
+    let Some(url) = blocking::block("admin_merge_sites.merge_sites", move || {
+        merge_sites(&pool, from, into)
+    })
+    .await??
+    else {
+        return Ok(flash::redirect(
+            "/admin/",
+            Flash::error(format!("no sites with ids {from} and {into}")),
+        ));
+    };
+    info!("merged site {from} ('{url}') into site {into}");
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::success(format!("Merged {url} into site {into}")),
+    ))
+}
+
+#[post("/featured/{id}/remove/")]
+async fn admin_unfeature_site(
+    path: web::Path<u32>,
+    pool: web::Data<Pool>,
+) -> Result<impl Responder, HtmlError> {
+    let site_id = path.into_inner();
+    blocking::block("admin_unfeature_site.clear_featured", move || {
+        clear_featured(&pool, site_id)
+    })
+    .await??;
+    info!("unfeatured site {site_id}");
+
+    Ok(flash::redirect(
+        "/admin/",
+        Flash::info(format!("Site {site_id} is no longer featured")),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadOnlyRequest {
+    enabled: bool,
+}
+
+#[post("/read-only/")]
+async fn admin_read_only(
+    form: web::Form<ReadOnlyRequest>,
+    read_only: web::Data<ReadOnlyHandle>,
+) -> Result<impl Responder, HtmlError> {
+    set_read_only(&read_only, form.enabled);
+    info!("read-only mode {}", if form.enabled { "on" } else { "off" });
+
+    let msg = if form.enabled {
+        "The site is now read-only"
+    } else {
+        "Submissions and voting are open again"
+    };
+    Ok(flash::redirect("/admin/", Flash::info(msg)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        test::{call_service, init_service, TestRequest},
+        App, ResponseError,
+    };
+
+    #[test]
+    fn trailing_slash_location_adds_the_slash() {
+        assert_eq!(
+            trailing_slash_location("/related/1", ""),
+            Some(String::from("/related/1/"))
+        );
+        assert_eq!(
+            trailing_slash_location("/site/abc", "lang=fr"),
+            Some(String::from("/site/abc/?lang=fr"))
+        );
+        assert_eq!(
+            trailing_slash_location("/admin", ""),
+            Some(String::from("/admin/"))
+        );
+    }
+
+    #[test]
+    fn trailing_slash_location_skips_files_and_unknown_routes() {
+        assert_eq!(trailing_slash_location("/related/1/", ""), None);
+        assert_eq!(trailing_slash_location("/site/abc/record.json", ""), None);
+        assert_eq!(trailing_slash_location("/nowhere", ""), None);
+    }
+
+    #[test]
+    fn trailing_slash_location_never_leaves_the_site() {
+        assert_eq!(trailing_slash_location("//evil%2Ecom", ""), None);
+        assert_eq!(trailing_slash_location("//2130706433", ""), None);
+        assert_eq!(trailing_slash_location("///site/1", ""), None);
+        assert_eq!(trailing_slash_location("/\\evil.com", ""), None);
+    }
+
+    fn view_request(query: &[(&str, &str)]) -> ViewRequest {
+        let field = |name: &str| {
+            query
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| String::from(*value))
+        };
+        ViewRequest {
+            sortby: field("sortby"),
+            paginate: field("paginate"),
+            view: field("view"),
+            page: field("page"),
+            has_related: field("has_related"),
+            lang: field("lang"),
+        }
+    }
+
+    #[test]
+    fn view_request_defaults_to_the_visitor_preferences() {
+        let prefs = Prefs {
+            sortby: Some(SortOptions::Size),
+            view: Some(View::Gallery),
+            ..Prefs::default()
+        };
+
+        let view = view_request(&[]).validate(&prefs).unwrap();
+        assert_eq!(view.sortby, SortOptions::Size);
+        assert_eq!(view.view, View::Gallery);
+        assert_eq!(view.paginate, prefs.default_paginate());
+        assert_eq!((view.page, view.offset), (1, 0));
+        assert_eq!(view.filter, SiteFilter::default());
+    }
+
+    #[test]
+    fn view_request_parses_every_field() {
+        let view = view_request(&[
+            ("sortby", "New"),
+            ("paginate", "10"),
+            ("view", "compact"),
+            ("page", "3"),
+            ("has_related", "true"),
+        ])
+        .validate(&Prefs::default())
+        .unwrap();
+
+        assert_eq!(view.sortby, SortOptions::New);
+        assert_eq!(view.view, View::Compact);
+        assert_eq!((view.paginate, view.page, view.offset), (10, 3, 20));
+        assert!(view.filter.has_related);
+    }
+
+    #[test]
+    fn view_request_clamps_paginate() {
+        let prefs = Prefs::default();
+        let view = view_request(&[("paginate", "100000")])
+            .validate(&prefs)
+            .unwrap();
+        assert_eq!(view.paginate, prefs.site.max_paginate);
+
+        let view = view_request(&[("paginate", "1")]).validate(&prefs).unwrap();
+        assert_eq!(view.paginate, prefs.site.min_paginate);
+    }
+
+    #[test]
+    fn view_request_names_every_bad_field() {
+        let err = view_request(&[
+            ("sortby", "Random"),
+            ("view", "list"),
+            ("page", "0"),
+            ("has_related", "yes"),
+        ])
+        .validate(&Prefs::default())
+        .err()
+        .unwrap();
+
+        assert_eq!(err.error_response().status(), 400);
+        let message = err.to_string();
+        for field in ["sortby:", "view:", "page:", "has_related:"] {
+            assert!(message.contains(field), "{field} missing from {message}");
+        }
+    }
+
+    #[test]
+    fn page_offset_refuses_pages_past_an_i64() {
+        let mut errors = vec![];
+        assert_eq!(page_offset(&mut errors, 1, 25), Some(0));
+        assert_eq!(page_offset(&mut errors, 4, 25), Some(75));
+        assert!(errors.is_empty());
+
+        assert_eq!(page_offset(&mut errors, usize::MAX, 25), None);
+        assert_eq!(page_offset(&mut errors, (i64::MAX as usize) / 2, 4), None);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("page: "));
+    }
+
+    #[actix_web::test]
+    async fn not_found_does_not_redirect_to_another_host() {
+        let app = init_service(App::new().default_service(web::to(not_found))).await;
+
+        let req = TestRequest::get().uri("//evil%2Ecom").to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 404);
+        assert!(res.headers().get(header::LOCATION).is_none());
+
+        let req = TestRequest::get().uri("/related/1").to_request();
+        let res = call_service(&app, req).await;
+        assert_eq!(res.status(), 301);
+        assert_eq!(res.headers().get(header::LOCATION).unwrap(), "/related/1/");
+    }
 }
@@ -0,0 +1,114 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! `web::block` with timing.  Each call site names itself, and for each one we keep how long
+//! calls waited for a blocking-pool thread and how long they then ran; both are exported on
+//! `/admin/metrics`.  Waits growing while run times hold steady mean the pool is saturated;
+//! run times growing mean SQLite itself is contended.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use actix_web::{error::BlockingError, web};
+
+#[derive(Debug, Default)]
+struct CallStats {
+    calls: u64,
+    wait: Duration,
+    max_wait: Duration,
+    run: Duration,
+    max_run: Duration,
+}
+
+type Metric = fn(&CallStats) -> f64;
+
+fn stats() -> &'static Mutex<BTreeMap<&'static str, CallStats>> {
+    static STATS: OnceLock<Mutex<BTreeMap<&'static str, CallStats>>> = OnceLock::new();
+    STATS.get_or_init(Default::default)
+}
+
+/// `web::block(f)`, recorded under `site`.
+pub async fn block<F, R>(site: &'static str, f: F) -> Result<R, BlockingError>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let queued = Instant::now();
+    let (result, wait, run) = web::block(move || {
+        let wait = queued.elapsed();
+        let started = Instant::now();
+        let result = f();
+        (result, wait, started.elapsed())
+    })
+    .await?;
+
+    if let Ok(mut stats) = stats().lock() {
+        let call = stats.entry(site).or_default();
+        call.calls += 1;
+        call.wait += wait;
+        call.max_wait = call.max_wait.max(wait);
+        call.run += run;
+        call.max_run = call.max_run.max(run);
+    }
+
+    Ok(result)
+}
+
+/// Prometheus text for every call site seen so far.
+pub fn render() -> String {
+    let metrics: [(&str, &str, Metric); 5] = [
+        ("calls_total", "counter", |call| call.calls as f64),
+        ("wait_seconds_total", "counter", |call| {
+            call.wait.as_secs_f64()
+        }),
+        ("wait_seconds_max", "gauge", |call| {
+            call.max_wait.as_secs_f64()
+        }),
+        ("run_seconds_total", "counter", |call| {
+            call.run.as_secs_f64()
+        }),
+        ("run_seconds_max", "gauge", |call| {
+            call.max_run.as_secs_f64()
+        }),
+    ];
+
+    let Ok(stats) = stats().lock() else {
+        return String::new();
+    };
+
+    let mut out = String::new();
+    for (metric, kind, value) in metrics {
+        let _ = writeln!(out, "# TYPE tenkb_blocking_{metric} {kind}");
+        for (site, call) in stats.iter() {
+            let _ = writeln!(
+                out,
+                "tenkb_blocking_{metric}{{site=\"{site}\"}} {}",
+                value(call)
+            );
+        }
+    }
+    out
+}
@@ -22,8 +22,8 @@
 
 use crate::config::Config;
 use reqwest::header::{HeaderMap, HeaderName};
-use serde::Deserialize;
-use std::{collections::HashMap, error::Error};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
 use tokio::runtime::Handle;
 use tracing::{debug, info};
 
@@ -31,9 +31,40 @@ use tracing::{debug, info};
 pub struct UrlScan {
     pub size: f64,
     pub acceptable: bool,
+    pub malicious: bool,
+    /// Cloudflare's id for the scan, used to fetch its screenshot.
+    pub scan_id: Option<String>,
 }
 
-type UrlScanResult = Result<UrlScan, Box<dyn Error>>;
+pub type UrlScanResult = Result<UrlScan, Box<dyn Error>>;
+
+/// Where, and as what browser, a scan looks at a site.  The default leaves both to
+/// Cloudflare.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScanVantage<'a> {
+    /// ISO 3166-1 alpha-2 code of the country to scan from.
+    pub country: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+}
+
+/// A listed site that measured very differently from two of the countries it was scanned
+/// from.
+#[derive(Debug, Serialize)]
+pub struct RegionalDifference {
+    pub site_id: u32,
+    pub url: String,
+    pub smallest_country: String,
+    pub smallest: f64,
+    pub largest_country: String,
+    pub largest: f64,
+}
+
+/// How many scans were submitted to Cloudflare on one (UTC) day.
+#[derive(Debug, Serialize)]
+pub struct ScannerUsage {
+    pub day: String,
+    pub scans: u32,
+}
 
 #[derive(Debug, Deserialize)]
 struct UrlScanSubmit {
@@ -84,11 +115,26 @@ struct UrlScanReportVerdictsOverall {
     malicious: bool,
 }
 
-pub async fn urlscan(host: &str, _handle: Handle, config: &Config) -> UrlScanResult {
-    let mut body = HashMap::new();
+/// Scan `host` with Cloudflare's URL scanner from `vantage`.
+pub async fn urlscan(
+    host: &str,
+    vantage: ScanVantage<'_>,
+    _handle: Handle,
+    config: &Config,
+) -> UrlScanResult {
+    let mut body = serde_json::Map::new();
     let mut headers = HeaderMap::new();
 
-    body.insert("url", host);
+    body.insert("url".into(), host.into());
+    if let Some(country) = vantage.country {
+        body.insert("country".into(), country.into());
+    }
+    if let Some(user_agent) = vantage.user_agent {
+        body.insert(
+            "customHeaders".into(),
+            serde_json::json!({ "User-Agent": user_agent }),
+        );
+    }
 
     let auth_header = format!("Bearer {}", config.cloudflare_api_token).parse()?;
     headers.insert(HeaderName::from_static("authorization"), auth_header);
@@ -118,6 +164,7 @@ pub async fn urlscan(host: &str, _handle: Handle, config: &Config) -> UrlScanRes
     let scan_id = res_json.result.uuid;
 
     debug!("got uuid: {scan_id}");
+    sentry::configure_scope(|scope| scope.set_tag("scan_id", &scan_id));
 
     for _ in 0..3 {
         debug!("sleeping...");
@@ -165,10 +212,35 @@ pub async fn urlscan(host: &str, _handle: Handle, config: &Config) -> UrlScanRes
         return Ok(UrlScan {
             size: res_json.result.scan.stats.requests.transfer_size as f64,
             acceptable: acceptable_size && !res_json.result.scan.verdicts.overall.malicious,
+            malicious: res_json.result.scan.verdicts.overall.malicious,
+            scan_id: Some(scan_id),
         });
     }
 
     Err("unknown error".into())
 }
 
-const SIZE_LIMIT: usize = 10_240;
+/// The desktop-resolution PNG screenshot Cloudflare took during scan `scan_id`.
+pub async fn screenshot(scan_id: &str, config: &Config) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut headers = HeaderMap::new();
+    let auth_header = format!("Bearer {}", config.cloudflare_api_token).parse()?;
+    headers.insert(HeaderName::from_static("authorization"), auth_header);
+
+    let client = reqwest::Client::new();
+    let res = client
+        .get(format!(
+            "https://api.cloudflare.com/client/v4/accounts/{}/urlscanner/scan/{scan_id}/screenshot?resolution=desktop",
+            config.cloudflare_account
+        ))
+        .headers(headers)
+        .send()
+        .await?;
+
+    if res.status() != 200 {
+        return Err(format!("error status: {}", res.status()).into());
+    }
+
+    Ok(res.bytes().await?.to_vec())
+}
+
+pub const SIZE_LIMIT: usize = 10_240;
@@ -20,19 +20,77 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use serde::Deserialize;
+use crate::{prefs::View, SortOptions};
+use serde::{Deserialize, Serialize};
 use std::{
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
 };
 
 #[derive(Clone, Deserialize)]
 pub struct Config {
     pub database_path: PathBuf,
+    /// Read-only replica of the database that listings, feeds and rankings are served
+    /// from; writes always go to `database_path`.
+    #[serde(default)]
+    pub read_replica: Option<PathBuf>,
+    /// Where listings, submissions, votes, the validation queue and related links are
+    /// stored.  Everything else stays in `database_path` whichever backend this names.
+    #[serde(default)]
+    pub storage: StorageConfig,
     pub template_path: PathBuf,
+    /// Re-read templates from disk on every render; defaults to true in debug builds.
+    #[serde(default)]
+    pub template_reload: Option<bool>,
+    /// Strip comments and collapse whitespace in rendered HTML.
+    #[serde(default)]
+    pub minify_html: bool,
+
+    /// Directory of `<lang>.json` translation catalogs.
+    #[serde(default)]
+    pub locale_path: Option<PathBuf>,
+    #[serde(default = "default_locale_default")]
+    pub default_locale: String,
+
+    /// Public origin of the site, used to build canonical links.
+    #[serde(default = "base_url_default")]
+    pub base_url: String,
+    #[serde(default = "site_name_default")]
+    pub site_name: String,
+    /// One-line summary of the site, opening the front page and describing pages that
+    /// don't have a description of their own.
+    #[serde(default = "tagline_default")]
+    pub tagline: String,
+    /// Links in the footer of every page, in order.
+    #[serde(default = "footer_links_default")]
+    pub footer_links: Vec<FooterLink>,
+    /// Address visitors can write to the operator at, shown in the footer when set.
+    #[serde(default)]
+    pub contact_email: Option<String>,
+    /// Image URL used for OpenGraph/Twitter preview cards.
+    #[serde(default)]
+    pub og_image: Option<String>,
+
+    /// Secret (at least 32 bytes) used to sign preference cookies.
+    #[serde(default)]
+    pub cookie_secret: Option<String>,
 
     #[serde(default = "log_level_default")]
     pub log_level: LogLevel,
+    /// Where console logs go: stderr, or the local syslog daemon.
+    #[serde(default = "log_target_default")]
+    pub log_target: LogTarget,
+    #[serde(default = "syslog_facility_default")]
+    pub syslog_facility: SyslogFacility,
+    /// Also (or instead of stderr) write logs to a rotated file.
+    #[serde(default)]
+    pub log_file: Option<LogFileConfig>,
+    /// How client IPs are reduced before they are logged or stored anywhere.
+    #[serde(default)]
+    pub ip_privacy: IpPrivacy,
+    /// Salt for `ip_privacy: hash`; a random one (changing on every restart) is used if unset.
+    #[serde(default)]
+    pub ip_hash_salt: Option<String>,
     pub cloudflare_account: String,
     pub cloudflare_api_token: String,
 
@@ -40,6 +98,809 @@ pub struct Config {
     pub listen_addr: IpAddr,
     #[serde(default = "listen_port_default")]
     pub listen_port: u16,
+    /// Addresses to listen on, e.g. `["0.0.0.0:3003", "[::]:3003"]`.  When empty, the server
+    /// listens on `listen_addr` and `listen_port` alone.
+    #[serde(default)]
+    pub listen: Vec<SocketAddr>,
+
+    #[serde(default)]
+    pub schedules: Schedules,
+
+    #[serde(default = "scan_cache_ttl_default")]
+    pub scan_cache_ttl_secs: u64,
+    /// Cloudflare URL Scanner calls allowed per (UTC) day.  Once they are spent, the rest of
+    /// the validation and recertification queues wait for the next day.  Unlimited when
+    /// unset.
+    #[serde(default)]
+    pub scanner_daily_budget: Option<u32>,
+
+    /// Re-measure each listed site once a year, recording the result in its size history.
+    #[serde(default)]
+    pub recertification: bool,
+
+    /// Check that listed sites are up on `schedules.uptime`, showing each one's uptime on
+    /// its detail page.
+    #[serde(default)]
+    pub uptime_checks: bool,
+
+    /// Check and scan queued sites, but only log the verdicts instead of recording them.
+    /// Also enabled by passing `--dry-run` to the server.
+    #[serde(default)]
+    pub analyzer_dry_run: bool,
+
+    /// Reject submissions whose page content matches an existing member's, rather than just
+    /// flagging them on the admin dashboard.
+    #[serde(default)]
+    pub reject_duplicate_content: bool,
+
+    /// Whether submissions may point below a site's homepage.
+    #[serde(default)]
+    pub path_policy: PathPolicy,
+
+    /// Largest webmention source page fetched when checking that it links to us.
+    #[serde(default = "webmention_max_bytes_default")]
+    pub webmention_max_bytes: usize,
+
+    /// Largest favicon the analyzer will store for a site.
+    #[serde(default = "favicon_max_bytes_default")]
+    pub favicon_max_bytes: usize,
+
+    #[serde(default)]
+    pub related_sources: RelatedSources,
+
+    /// How the analyzer's live and link checks resolve names.
+    #[serde(default)]
+    pub resolver: ResolverConfig,
+
+    #[serde(default)]
+    pub websocket: WebSocketConfig,
+
+    #[serde(default)]
+    pub api: ApiConfig,
+
+    #[serde(default)]
+    pub limits: Limits,
+
+    #[serde(default)]
+    pub rescan: RescanConfig,
+
+    #[serde(default)]
+    pub related_refresh: RelatedRefreshConfig,
+
+    #[serde(default)]
+    pub pool: PoolConfig,
+
+    #[serde(default)]
+    pub top: TopConfig,
+
+    #[serde(default)]
+    pub index: IndexConfig,
+
+    /// Start in read-only mode: no submissions or votes.  Admins can toggle it at runtime.
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Error reporting to Sentry (or a compatible service); disabled when unset.
+    #[serde(default)]
+    pub sentry: Option<SentryConfig>,
+
+    /// MaxMind country (or city) database used for per-country statistics.
+    #[serde(default)]
+    pub geoip_database: Option<PathBuf>,
+
+    /// Screenshots of newly listed sites, served as thumbnails; disabled when unset.
+    #[serde(default)]
+    pub screenshots: Option<ScreenshotConfig>,
+
+    /// Archive each site's front page whenever it passes validation; disabled when unset.
+    #[serde(default)]
+    pub snapshots: Option<SnapshotConfig>,
+
+    /// Scan sites a second time as a phone, since some small desktop pages send mobile
+    /// browsers a much heavier bundle; disabled when unset.
+    #[serde(default)]
+    pub mobile_scans: Option<MobileScanConfig>,
+
+    /// Scan newly listed and recertified sites again from other countries, flagging those
+    /// whose size depends on where they are seen from; disabled when unset.
+    #[serde(default)]
+    pub regional_scans: Option<RegionalScanConfig>,
+
+    /// Aggregated feed of member blogs at `/planet.html`; disabled when unset.
+    #[serde(default)]
+    pub planet: Option<PlanetConfig>,
+
+    /// Other instances whose members are shown at `/friends.html`; disabled when unset.
+    #[serde(default)]
+    pub federation: Option<FederationConfig>,
+
+    /// Spam scoring for submissions and comments; disabled when unset.
+    #[serde(default)]
+    pub spam: Option<SpamConfig>,
+
+    /// Looks for vote bursts and cohorts of new voters voting alike, reported on `/admin/`;
+    /// disabled when unset.
+    #[serde(default)]
+    pub vote_anomalies: Option<VoteAnomalyConfig>,
+
+    /// Comment threads on `/site/{slug}/`; disabled when unset.
+    #[serde(default)]
+    pub comments: Option<CommentConfig>,
+
+    /// Visitor suggestions of discussions about a site, held for moderation; disabled when
+    /// unset.
+    #[serde(default)]
+    pub discussions: Option<DiscussionConfig>,
+
+    /// Send a webmention to member sites when they're listed or featured; disabled when
+    /// unset.
+    #[serde(default)]
+    pub announcements: Option<AnnounceConfig>,
+
+    /// First-party access log; disabled when unset.
+    #[serde(default)]
+    pub analytics: Option<AnalyticsConfig>,
+
+    /// Credentials for the `/admin/` pages, which are disabled when unset.
+    #[serde(default)]
+    pub admin: Option<AdminCredentials>,
+    /// Continuous replication of the database by an external tool such as Litestream.
+    #[serde(default)]
+    pub replication: Option<ReplicationConfig>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SentryConfig {
+    pub dsn: String,
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// Fraction of error events sent, from 0.0 to 1.0.
+    #[serde(default = "sentry_sample_rate_default")]
+    pub sample_rate: f32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ReplicationConfig {
+    /// Replicator run for as long as the server is, e.g.
+    /// `["litestream", "replicate", "-config", "/etc/litestream.yml"]`.  It gets the
+    /// database path in `TENKB_DATABASE`.
+    pub command: Vec<String>,
+    /// Run before the database is opened if the file is missing, e.g.
+    /// `["litestream", "restore", "-if-replica-exists", "/var/lib/tenkb/10kb.db"]`.
+    #[serde(default)]
+    pub restore: Vec<String>,
+    #[serde(default = "replication_restart_default")]
+    pub restart_secs: u64,
+    /// How long the replicator gets to ship the last writes when the server stops.
+    #[serde(default = "replication_shutdown_default")]
+    pub shutdown_secs: u64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct AnalyticsConfig {
+    /// SQLite database for request records, kept apart from the main database.
+    pub database_path: PathBuf,
+    #[serde(default = "analytics_retention_default")]
+    pub retention_days: u32,
+    #[serde(default = "analytics_batch_size_default")]
+    pub batch_size: usize,
+    #[serde(default = "analytics_flush_default")]
+    pub flush_secs: u64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ScreenshotConfig {
+    /// Where full screenshots and thumbnails are written.
+    pub directory: PathBuf,
+    #[serde(default)]
+    pub source: ScreenshotSource,
+    /// Headless browser run for `source: "browser"`; it must accept Chromium's
+    /// `--headless --screenshot` flags.
+    #[serde(default = "screenshot_browser_default")]
+    pub browser: String,
+    #[serde(default = "thumbnail_width_default")]
+    pub thumbnail_width: u32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct PlanetConfig {
+    /// Newest posts kept from each member feed.
+    #[serde(default = "planet_posts_per_feed_default")]
+    pub posts_per_feed: usize,
+    /// Posts shown on the planet page and feed.
+    #[serde(default = "planet_length_default")]
+    pub length: usize,
+    #[serde(default = "planet_max_feed_bytes_default")]
+    pub max_feed_bytes: usize,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct FederationConfig {
+    pub peers: Vec<PeerConfig>,
+    /// Give each friend a button submitting it here, through the usual preview and scan.
+    #[serde(default)]
+    pub offer_submission: bool,
+    /// Most members imported from any one peer.
+    #[serde(default = "federation_max_sites_default")]
+    pub max_sites: usize,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct PeerConfig {
+    /// Heads the peer's members on `/friends.html`.
+    pub name: String,
+    /// The peer's base URL; its members are read from `/api/v1/sites` under it.
+    pub url: String,
+    /// Sent as `X-API-Key`, for peers whose anonymous quota is too small to page through
+    /// their directory.
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SpamConfig {
+    /// Score, from 0 to 1, at which a submission is refused or a comment held for
+    /// moderation.
+    #[serde(default = "spam_threshold_default")]
+    pub threshold: f64,
+    /// Ask Akismet instead of using the built-in filter.
+    #[serde(default)]
+    pub akismet: Option<AkismetConfig>,
+}
+
+/// Thresholds for the `schedules.vote_anomalies` job.
+#[derive(Clone, Deserialize)]
+pub struct VoteAnomalyConfig {
+    /// Votes for one site within `burst_minutes` that count as a burst.
+    #[serde(default = "burst_votes_default")]
+    pub burst_votes: u32,
+    #[serde(default = "burst_minutes_default")]
+    pub burst_minutes: u32,
+    /// New voters with identical votes that count as a cohort...
+    #[serde(default = "cohort_voters_default")]
+    pub cohort_voters: usize,
+    /// ...when they voted for at least this many sites.
+    #[serde(default = "cohort_sites_default")]
+    pub cohort_sites: usize,
+    /// How recently a voter id must have been issued to count as new.
+    #[serde(default = "new_voter_hours_default")]
+    pub new_voter_hours: u32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct AkismetConfig {
+    pub api_key: String,
+    #[serde(default = "akismet_endpoint_default")]
+    pub endpoint: String,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CommentConfig {
+    #[serde(default = "comment_length_default")]
+    pub max_length: usize,
+    /// Hold new comments until an admin approves them.
+    #[serde(default = "comment_premoderate_default")]
+    pub premoderate: bool,
+    #[serde(default = "comment_window_default")]
+    pub window_secs: u64,
+    /// Comments one voter id may post per window.
+    #[serde(default = "comment_per_voter_default")]
+    pub per_voter: u32,
+    /// Comments from one IP per window.
+    #[serde(default = "comment_per_ip_default")]
+    pub per_ip: u32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct DiscussionConfig {
+    #[serde(default = "discussion_window_default")]
+    pub window_secs: u64,
+    /// Suggestions from one IP per window.
+    #[serde(default = "discussion_per_ip_default")]
+    pub per_ip: u32,
+    /// Largest discussion page fetched when checking that it links to the site.
+    #[serde(default = "discussion_max_bytes_default")]
+    pub max_bytes: usize,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct AnnounceConfig {
+    /// Hours before another webmention may be sent to the same homepage.
+    #[serde(default = "announce_interval_default")]
+    pub min_interval_hours: u32,
+    /// Largest page fetched while looking for a site's webmention endpoint.
+    #[serde(default = "announce_max_bytes_default")]
+    pub max_bytes: usize,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SnapshotConfig {
+    /// Fetch the page's stylesheets and images into the snapshot itself.
+    #[serde(default)]
+    pub inline_assets: bool,
+    /// Most bytes of assets inlined into one snapshot; the rest stay as links.
+    #[serde(default = "snapshot_asset_bytes_default")]
+    pub max_asset_bytes: usize,
+    /// Net growth between consecutive snapshots that flags a site as at risk of outgrowing
+    /// the club.
+    #[serde(default = "at_risk_growth_default")]
+    pub at_risk_growth_bytes: i64,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct FooterLink {
+    pub text: String,
+    pub url: String,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct MobileScanConfig {
+    /// User agent the mobile scan is made with.
+    #[serde(default = "mobile_user_agent_default")]
+    pub user_agent: String,
+    /// Reject sites, and fail recertifications, whose mobile size is over the limit.  When
+    /// false the mobile size is only recorded.
+    #[serde(default = "mobile_required_default")]
+    pub required: bool,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct RegionalScanConfig {
+    /// ISO 3166-1 alpha-2 codes of the countries to scan from, e.g. `["US", "DE", "JP"]`.
+    pub countries: Vec<String>,
+    /// How many times smaller than the largest a site's smallest regional size must be
+    /// to flag it.
+    #[serde(default = "regional_size_ratio_default")]
+    pub max_size_ratio: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PathPolicy {
+    /// Only a site's root page, with no query string.
+    HomepageOnly,
+    /// Any page, with no limit on how many from one domain.
+    PathAllowed,
+    /// Any page, but no more than `limits.pages_per_domain` from one domain.
+    #[default]
+    PathAllowedWithDomainDedup,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenshotSource {
+    /// The screenshot Cloudflare takes during the URL scan.
+    #[default]
+    Cloudflare,
+    /// A local headless browser.
+    Browser,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct AdminCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// Request quotas for the JSON API, counted over fixed windows of `window_secs`.
+#[derive(Clone, Deserialize)]
+pub struct ApiConfig {
+    #[serde(default = "api_window_default")]
+    pub window_secs: u64,
+    /// Requests per window for clients without an API key, counted per IP.
+    #[serde(default = "api_anonymous_quota_default")]
+    pub anonymous_quota: u32,
+    /// Quota given to newly minted keys unless another is chosen.
+    #[serde(default = "api_key_quota_default")]
+    pub default_key_quota: u32,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: api_window_default(),
+            anonymous_quota: api_anonymous_quota_default(),
+            default_key_quota: api_key_quota_default(),
+        }
+    }
+}
+
+/// Size limits for form posts, enforced before the handlers run.
+#[derive(Clone, Deserialize)]
+pub struct Limits {
+    /// Largest accepted form body; anything bigger gets a 413.
+    #[serde(default = "form_bytes_default")]
+    pub form_bytes: usize,
+    #[serde(default = "url_length_default")]
+    pub url_length: usize,
+    /// Most site ids a single `/votes/` lookup may ask about.
+    #[serde(default = "site_ids_default")]
+    pub site_ids: usize,
+    /// Longest curator note an admin may attach to a site, in characters.
+    #[serde(default = "note_length_default")]
+    pub note_length: usize,
+    /// Pages from one domain that may be listed or waiting for validation at once, under
+    /// `path_policy: path-allowed-with-domain-dedup`; 0 for no limit.
+    #[serde(default = "pages_per_domain_default")]
+    pub pages_per_domain: usize,
+    /// Requests handled at once before the rest are answered 503; 0 for no limit.
+    #[serde(default = "in_flight_default")]
+    pub in_flight: usize,
+    /// The `Retry-After` sent with those 503s.
+    #[serde(default = "busy_retry_secs_default")]
+    pub busy_retry_secs: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            form_bytes: form_bytes_default(),
+            url_length: url_length_default(),
+            site_ids: site_ids_default(),
+            note_length: note_length_default(),
+            pages_per_domain: pages_per_domain_default(),
+            in_flight: in_flight_default(),
+            busy_retry_secs: busy_retry_secs_default(),
+        }
+    }
+}
+
+/// Limits on `/site/{slug}/rescan/`, which anyone may call.
+#[derive(Clone, Deserialize)]
+pub struct RescanConfig {
+    #[serde(default = "rescan_window_default")]
+    pub window_secs: u64,
+    /// Rescans of any one site per window.
+    #[serde(default = "rescan_per_site_default")]
+    pub per_site: u32,
+    /// Rescan requests from one IP per window.
+    #[serde(default = "rescan_per_ip_default")]
+    pub per_ip: u32,
+}
+
+/// Limits on `/related/{site}/refresh/`, which anyone may call.
+#[derive(Clone, Deserialize)]
+pub struct RelatedRefreshConfig {
+    #[serde(default = "related_refresh_window_default")]
+    pub window_secs: u64,
+    /// Refreshes of any one site per window.
+    #[serde(default = "related_refresh_per_site_default")]
+    pub per_site: u32,
+    /// Refresh requests from one IP per window.
+    #[serde(default = "related_refresh_per_ip_default")]
+    pub per_ip: u32,
+}
+
+/// The resolver used by the analyzer's outbound checks.
+#[derive(Clone, Deserialize)]
+pub struct ResolverConfig {
+    #[serde(default)]
+    pub mode: ResolverMode,
+    /// Name servers for `servers` and `doh`: IPs, each with an optional port.
+    #[serde(default)]
+    pub servers: Vec<String>,
+    /// Name the DNS-over-HTTPS servers present in their certificates, e.g.
+    /// `cloudflare-dns.com`.
+    #[serde(default)]
+    pub tls_name: Option<String>,
+    /// Time allowed for each lookup.
+    #[serde(default = "resolver_timeout_default")]
+    pub timeout_ms: u64,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolverMode {
+    /// The host's own resolver.
+    #[default]
+    System,
+    /// Plain DNS to `servers`.
+    Servers,
+    /// DNS-over-HTTPS to `servers`.
+    Doh,
+}
+
+/// The backend behind [`crate::storage::Storage`].
+#[derive(Clone, Deserialize)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: StorageBackend,
+    /// Connection string for the Postgres backend, e.g.
+    /// `host=localhost user=tenkb dbname=tenkb` or `postgresql://tenkb@localhost/tenkb`.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Most connections the Postgres backend keeps open.
+    #[serde(default = "storage_pool_size_default")]
+    pub pool_size: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// The SQLite database at `database_path`, with listings read from `read_replica` if
+    /// one is set.
+    #[default]
+    Sqlite,
+    /// A Postgres database at `storage.url`; needs a build with the `postgres` feature.
+    Postgres,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::default(),
+            url: None,
+            pool_size: storage_pool_size_default(),
+        }
+    }
+}
+
+fn storage_pool_size_default() -> u32 {
+    10
+}
+
+/// Database connection pool monitoring.
+#[derive(Clone, Deserialize)]
+pub struct PoolConfig {
+    /// Checkouts that wait at least this long for a connection are logged.
+    #[serde(default = "slow_checkout_default")]
+    pub slow_checkout_ms: u64,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            slow_checkout_ms: slow_checkout_default(),
+        }
+    }
+}
+
+/// Front-page defaults, for visitors who haven't chosen their own on `/prefs.html`.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct IndexConfig {
+    #[serde(default = "index_sortby_default")]
+    pub sortby: SortOptions,
+    #[serde(default = "index_paginate_default")]
+    pub paginate: usize,
+    /// The page sizes a visitor may ask for; requests outside them are clamped.
+    #[serde(default = "index_min_paginate_default")]
+    pub min_paginate: usize,
+    #[serde(default = "index_max_paginate_default")]
+    pub max_paginate: usize,
+    #[serde(default)]
+    pub view: View,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            sortby: index_sortby_default(),
+            paginate: index_paginate_default(),
+            min_paginate: index_min_paginate_default(),
+            max_paginate: index_max_paginate_default(),
+            view: View::default(),
+        }
+    }
+}
+
+impl IndexConfig {
+    /// `paginate` brought within `min_paginate..=max_paginate`.
+    pub fn clamp_paginate(&self, paginate: usize) -> usize {
+        paginate.clamp(self.min_paginate, self.max_paginate)
+    }
+}
+
+/// The "top this week/month" rankings on the index and `/api/v1/top`.
+#[derive(Clone, Deserialize)]
+pub struct TopConfig {
+    /// Sites in each ranking.
+    #[serde(default = "top_limit_default")]
+    pub limit: usize,
+    /// How long a ranking is served before it is recomputed.
+    #[serde(default = "top_cache_default")]
+    pub cache_secs: u64,
+}
+
+impl Default for TopConfig {
+    fn default() -> Self {
+        Self {
+            limit: top_limit_default(),
+            cache_secs: top_cache_default(),
+        }
+    }
+}
+
+impl Default for RescanConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: rescan_window_default(),
+            per_site: rescan_per_site_default(),
+            per_ip: rescan_per_ip_default(),
+        }
+    }
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        Self {
+            mode: ResolverMode::default(),
+            servers: vec![],
+            tls_name: None,
+            timeout_ms: resolver_timeout_default(),
+        }
+    }
+}
+
+impl Default for RelatedRefreshConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: related_refresh_window_default(),
+            per_site: related_refresh_per_site_default(),
+            per_ip: related_refresh_per_ip_default(),
+        }
+    }
+}
+
+/// Live index updates pushed over `/ws/`.
+#[derive(Clone, Deserialize)]
+pub struct WebSocketConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Minimum interval between batches sent to one connection.
+    #[serde(default = "websocket_throttle_default")]
+    pub throttle_ms: u64,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            throttle_ms: websocket_throttle_default(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct RelatedSources {
+    #[serde(default)]
+    pub hackernews: RelatedSourceConfig,
+    #[serde(default)]
+    pub lobsters: RelatedSourceConfig,
+
+    /// Consecutive failures after which a source is skipped for `cooldown_secs`.
+    #[serde(default = "breaker_failure_threshold_default")]
+    pub failure_threshold: u32,
+    #[serde(default = "breaker_cooldown_default")]
+    pub cooldown_secs: u64,
+}
+
+impl Default for RelatedSources {
+    fn default() -> Self {
+        Self {
+            hackernews: RelatedSourceConfig::default(),
+            lobsters: RelatedSourceConfig::default(),
+            failure_threshold: breaker_failure_threshold_default(),
+            cooldown_secs: breaker_cooldown_default(),
+        }
+    }
+}
+
+/// Limits applied to the links harvested from one related-link source.
+#[derive(Clone, Deserialize)]
+pub struct RelatedSourceConfig {
+    #[serde(default = "related_limit_default")]
+    pub limit: usize,
+    #[serde(default)]
+    pub min_score: usize,
+    #[serde(default = "related_min_comments_default")]
+    pub min_comments: usize,
+}
+
+impl Default for RelatedSourceConfig {
+    fn default() -> Self {
+        Self {
+            limit: related_limit_default(),
+            min_score: 0,
+            min_comments: related_min_comments_default(),
+        }
+    }
+}
+
+/// Cron expressions (with a leading seconds field) for the background jobs.
+#[derive(Clone, Deserialize)]
+pub struct Schedules {
+    #[serde(default = "validation_schedule_default")]
+    pub validation: String,
+    #[serde(default = "analytics_prune_schedule_default")]
+    pub analytics_prune: String,
+    #[serde(default = "geoip_flush_schedule_default")]
+    pub geoip_flush: String,
+    #[serde(default = "recertification_schedule_default")]
+    pub recertification: String,
+    #[serde(default = "planet_schedule_default")]
+    pub planet: String,
+    #[serde(default = "vote_snapshot_schedule_default")]
+    pub vote_snapshots: String,
+    #[serde(default = "vote_anomaly_schedule_default")]
+    pub vote_anomalies: String,
+    #[serde(default = "uptime_schedule_default")]
+    pub uptime: String,
+    #[serde(default = "federation_schedule_default")]
+    pub federation: String,
+}
+
+impl Default for Schedules {
+    fn default() -> Self {
+        Self {
+            validation: validation_schedule_default(),
+            analytics_prune: analytics_prune_schedule_default(),
+            geoip_flush: geoip_flush_schedule_default(),
+            recertification: recertification_schedule_default(),
+            planet: planet_schedule_default(),
+            vote_snapshots: vote_snapshot_schedule_default(),
+            vote_anomalies: vote_anomaly_schedule_default(),
+            uptime: uptime_schedule_default(),
+            federation: federation_schedule_default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum IpPrivacy {
+    /// Full addresses.
+    #[default]
+    Off,
+    /// The network part only: a /24 for IPv4, a /48 for IPv6.
+    Truncate,
+    /// A salted hash, still distinguishing clients without revealing their addresses.
+    Hash,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    #[serde(default = "log_rotation_default")]
+    pub rotation: LogRotation,
+    /// Also rotate once the file reaches this size.
+    #[serde(default)]
+    pub max_size_mb: Option<u64>,
+    /// Rotated files kept, besides the current one.
+    #[serde(default = "log_max_files_default")]
+    pub max_files: usize,
+    /// Keep logging to stderr (or syslog, per `log_target`) as well.
+    #[serde(default = "log_stderr_default")]
+    pub stderr: bool,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    Stderr,
+    Syslog,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFacility {
+    User,
+    Daemon,
+    Local0,
+    Local1,
+    Local2,
+    Local3,
+    Local4,
+    Local5,
+    Local6,
+    Local7,
 }
 
 #[derive(Clone, Deserialize)]
@@ -53,14 +914,102 @@ pub enum LogLevel {
 impl Config {
     pub fn load(path: &str) -> Result<Self, std::io::Error> {
         let contents = std::fs::read_to_string(path)?;
-        Ok(serde_json::from_str(&contents[..])?)
+        let config: Self = serde_json::from_str(&contents[..])?;
+        if config.index.min_paginate == 0 {
+            return Err(std::io::Error::other(
+                "index.min_paginate must be at least 1",
+            ));
+        }
+        if !(config.index.min_paginate..=config.index.max_paginate).contains(&config.index.paginate)
+        {
+            return Err(std::io::Error::other(
+                "index.paginate must be between index.min_paginate and index.max_paginate",
+            ));
+        }
+        if config
+            .replication
+            .as_ref()
+            .is_some_and(|replication| replication.command.is_empty())
+        {
+            return Err(std::io::Error::other(
+                "replication.command must not be empty",
+            ));
+        }
+        if config.storage.backend == StorageBackend::Postgres {
+            if !cfg!(feature = "postgres") {
+                return Err(std::io::Error::other(
+                    "storage.backend is postgres, but this build lacks the postgres feature",
+                ));
+            }
+            if config.storage.url.is_none() {
+                return Err(std::io::Error::other(
+                    "storage.url must be set for the postgres backend",
+                ));
+            }
+        }
+        Ok(config)
     }
+
+    /// Every address the server listens on.
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        if self.listen.is_empty() {
+            vec![SocketAddr::new(self.listen_addr, self.listen_port)]
+        } else {
+            self.listen.clone()
+        }
+    }
+}
+
+fn base_url_default() -> String {
+    String::from("https://10kb.club")
+}
+
+fn site_name_default() -> String {
+    String::from("The 10KB Club")
+}
+
+fn tagline_default() -> String {
+    String::from(
+        "The 10kb club is an index of small websites with home pages less than 10KiB, or \
+         10,240 bytes.",
+    )
+}
+
+fn footer_links_default() -> Vec<FooterLink> {
+    vec![FooterLink {
+        text: String::from("Site made by Marcus Butler"),
+        url: String::from("https://marcusb.org"),
+    }]
+}
+
+fn default_locale_default() -> String {
+    String::from("en")
 }
 
 fn log_level_default() -> LogLevel {
     LogLevel::Info
 }
 
+fn log_target_default() -> LogTarget {
+    LogTarget::Stderr
+}
+
+fn syslog_facility_default() -> SyslogFacility {
+    SyslogFacility::Daemon
+}
+
+fn log_rotation_default() -> LogRotation {
+    LogRotation::Daily
+}
+
+fn log_max_files_default() -> usize {
+    7
+}
+
+fn log_stderr_default() -> bool {
+    true
+}
+
 fn listen_addr_default() -> IpAddr {
     IpAddr::from(Ipv4Addr::LOCALHOST)
 }
@@ -68,3 +1017,306 @@ fn listen_addr_default() -> IpAddr {
 fn listen_port_default() -> u16 {
     3003
 }
+
+fn validation_schedule_default() -> String {
+    String::from("0 * * * * *")
+}
+
+fn analytics_prune_schedule_default() -> String {
+    String::from("0 0 4 * * *")
+}
+
+fn geoip_flush_schedule_default() -> String {
+    String::from("0 * * * * *")
+}
+
+fn recertification_schedule_default() -> String {
+    String::from("0 0 3 * * *")
+}
+
+fn planet_schedule_default() -> String {
+    String::from("0 15 * * * *")
+}
+
+fn vote_snapshot_schedule_default() -> String {
+    String::from("0 30 0 * * *")
+}
+
+fn vote_anomaly_schedule_default() -> String {
+    String::from("0 */10 * * * *")
+}
+
+fn uptime_schedule_default() -> String {
+    String::from("0 45 */6 * * *")
+}
+
+fn federation_schedule_default() -> String {
+    String::from("0 20 */6 * * *")
+}
+
+fn scan_cache_ttl_default() -> u64 {
+    86_400
+}
+
+fn related_limit_default() -> usize {
+    5
+}
+
+fn related_min_comments_default() -> usize {
+    1
+}
+
+fn breaker_failure_threshold_default() -> u32 {
+    3
+}
+
+fn breaker_cooldown_default() -> u64 {
+    1800
+}
+
+fn slow_checkout_default() -> u64 {
+    100
+}
+
+fn rescan_window_default() -> u64 {
+    86_400
+}
+
+fn rescan_per_site_default() -> u32 {
+    1
+}
+
+fn rescan_per_ip_default() -> u32 {
+    5
+}
+
+fn related_refresh_window_default() -> u64 {
+    3600
+}
+
+fn related_refresh_per_site_default() -> u32 {
+    1
+}
+
+fn related_refresh_per_ip_default() -> u32 {
+    10
+}
+
+fn resolver_timeout_default() -> u64 {
+    5_000
+}
+
+fn index_sortby_default() -> SortOptions {
+    SortOptions::Votes
+}
+
+fn index_paginate_default() -> usize {
+    25
+}
+
+fn index_min_paginate_default() -> usize {
+    5
+}
+
+fn index_max_paginate_default() -> usize {
+    100
+}
+
+fn top_limit_default() -> usize {
+    10
+}
+
+fn top_cache_default() -> u64 {
+    3600
+}
+
+fn websocket_throttle_default() -> u64 {
+    1000
+}
+
+fn sentry_sample_rate_default() -> f32 {
+    1.0
+}
+
+fn analytics_retention_default() -> u32 {
+    90
+}
+
+fn analytics_batch_size_default() -> usize {
+    100
+}
+
+fn analytics_flush_default() -> u64 {
+    10
+}
+
+fn api_window_default() -> u64 {
+    3600
+}
+
+fn api_anonymous_quota_default() -> u32 {
+    600
+}
+
+fn api_key_quota_default() -> u32 {
+    10_000
+}
+
+fn form_bytes_default() -> usize {
+    8192
+}
+
+fn url_length_default() -> usize {
+    2048
+}
+
+fn site_ids_default() -> usize {
+    100
+}
+
+fn note_length_default() -> usize {
+    280
+}
+
+fn pages_per_domain_default() -> usize {
+    1
+}
+
+fn in_flight_default() -> usize {
+    256
+}
+
+fn busy_retry_secs_default() -> u64 {
+    5
+}
+
+fn webmention_max_bytes_default() -> usize {
+    1_048_576
+}
+
+fn spam_threshold_default() -> f64 {
+    0.9
+}
+
+fn burst_votes_default() -> u32 {
+    20
+}
+
+fn burst_minutes_default() -> u32 {
+    60
+}
+
+fn cohort_voters_default() -> usize {
+    5
+}
+
+fn cohort_sites_default() -> usize {
+    2
+}
+
+fn new_voter_hours_default() -> u32 {
+    24
+}
+
+fn akismet_endpoint_default() -> String {
+    String::from("https://rest.akismet.com/1.1/comment-check")
+}
+
+fn comment_length_default() -> usize {
+    2000
+}
+
+fn comment_premoderate_default() -> bool {
+    true
+}
+
+fn comment_window_default() -> u64 {
+    3600
+}
+
+fn comment_per_voter_default() -> u32 {
+    5
+}
+
+fn comment_per_ip_default() -> u32 {
+    10
+}
+
+fn discussion_window_default() -> u64 {
+    3600
+}
+
+fn discussion_per_ip_default() -> u32 {
+    5
+}
+
+fn discussion_max_bytes_default() -> usize {
+    1_048_576
+}
+
+fn announce_interval_default() -> u32 {
+    720
+}
+
+fn announce_max_bytes_default() -> usize {
+    1_048_576
+}
+
+fn favicon_max_bytes_default() -> usize {
+    16384
+}
+
+fn planet_posts_per_feed_default() -> usize {
+    5
+}
+
+fn planet_length_default() -> usize {
+    50
+}
+
+fn planet_max_feed_bytes_default() -> usize {
+    1_048_576
+}
+
+fn federation_max_sites_default() -> usize {
+    1000
+}
+
+fn snapshot_asset_bytes_default() -> usize {
+    65536
+}
+
+fn at_risk_growth_default() -> i64 {
+    2048
+}
+
+fn screenshot_browser_default() -> String {
+    String::from("chromium")
+}
+
+fn thumbnail_width_default() -> u32 {
+    320
+}
+
+fn regional_size_ratio_default() -> f64 {
+    2.0
+}
+
+fn mobile_user_agent_default() -> String {
+    String::from(
+        "Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 \
+         (KHTML, like Gecko) Version/17.5 Mobile/15E148 Safari/604.1",
+    )
+}
+
+fn mobile_required_default() -> bool {
+    true
+}
+
+fn replication_restart_default() -> u64 {
+    10
+}
+
+fn replication_shutdown_default() -> u64 {
+    30
+}
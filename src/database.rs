@@ -19,34 +19,220 @@
 // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
-use actix_web::{web, Result};
+use actix_web::Result;
 use r2d2_sqlite::SqliteConnectionManager;
-use regex::Regex;
-use rusqlite::params;
-use std::{error::Error, path::PathBuf};
+use rand::Rng;
+use rusqlite::{params, Connection, OpenFlags, Params, Row};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 use tracing::info;
 
+use crate::apikeys::ApiKey;
+use crate::cloudflare::{RegionalDifference, ScannerUsage, UrlScan};
+use crate::discussions::{Discussion, DiscussionSuggestion};
 use crate::error::TenKbError;
+use crate::favicon::Favicon;
+use crate::federation::PeerSite;
+use crate::feeds::FeedEntry;
+use crate::geoip::CountryCount;
+use crate::owners::{Listing, LogEntry, Measurement, RegionalSize, SiteRecord, UptimeCheck};
+use crate::planet::{DiscoveredFeed, PlanetPost};
+use crate::poolstats::PoolStatsHandle;
 use crate::relatedlinks::RelatedLink;
-use crate::{Site, SortOptions};
+use crate::slugs;
+use crate::snapshots::ChangeStats;
+use crate::storage::Storage;
+use crate::uptime::{UptimeSummary, UPTIME_DAYS};
+use crate::webmention::{SentWebmention, Webmention};
+use crate::{
+    canonical_url, site_domain, AtRiskChange, Comment, FeaturedSite, HomographFlag, ShadowFlag,
+    Site, SiteDetail, SiteFilter, SizeHistorySummary, SnapshotInfo, SortOptions, SpamScore,
+    VoteAnomaly,
+};
 
 pub type Pool = r2d2::Pool<SqliteConnectionManager>;
 
-pub fn init_db(path: &PathBuf) -> Pool {
+/// Connections for the heavy read-only endpoints (listings, feeds, rankings): a replica
+/// of the database if one is configured, the primary otherwise.  Submissions, votes and
+/// everything else that writes stay on the primary `Pool`.
+#[derive(Clone)]
+pub struct ReadPool(pub Pool);
+
+/// Statements kept prepared on each pooled connection.  Every query below goes through
+/// the cache, so this needs to be at least the number of distinct statements run
+/// regularly or the cache just churns.
+pub const STATEMENT_CACHE_CAPACITY: usize = 256;
+
+fn statement_cache(conn: &mut Connection) -> rusqlite::Result<()> {
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    Ok(())
+}
+
+/// `execute` and `query_row` through the connection's prepared statement cache.
+trait CachedStatements {
+    fn execute_cached<P: Params>(&self, sql: &str, params: P) -> rusqlite::Result<usize>;
+
+    fn query_row_cached<T, P, F>(&self, sql: &str, params: P, f: F) -> rusqlite::Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> rusqlite::Result<T>;
+}
+
+impl CachedStatements for Connection {
+    fn execute_cached<P: Params>(&self, sql: &str, params: P) -> rusqlite::Result<usize> {
+        self.prepare_cached(sql)?.execute(params)
+    }
+
+    fn query_row_cached<T, P, F>(&self, sql: &str, params: P, f: F) -> rusqlite::Result<T>
+    where
+        P: Params,
+        F: FnOnce(&Row<'_>) -> rusqlite::Result<T>,
+    {
+        self.prepare_cached(sql)?.query_row(params, f)
+    }
+}
+
+/// Validation queue priority; higher priorities are scanned first, oldest first within a
+/// priority.
+#[derive(Copy, Clone, Debug)]
+pub enum QueuePriority {
+    Bulk = 0,
+    /// Re-measurement requested by a visitor who isn't a verified owner.
+    Rescan = 5,
+    Submission = 10,
+    OwnerRecheck = 20,
+    AdminRescan = 30,
+}
+
+/// Result of a validation attempt.  The queue holds each site's latest outcome; only
+/// `Pending` sites are scanned.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationOutcome {
+    Pending,
+    Passed,
+    FailedSize,
+    FailedMalicious,
+    FailedUnreachable,
+    FailedError,
+    /// Same page content as a site that is already listed.
+    FailedDuplicate,
+}
+
+impl ValidationOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidationOutcome::Pending => "pending",
+            ValidationOutcome::Passed => "passed",
+            ValidationOutcome::FailedSize => "failed_size",
+            ValidationOutcome::FailedMalicious => "failed_malicious",
+            ValidationOutcome::FailedUnreachable => "failed_unreachable",
+            ValidationOutcome::FailedError => "failed_error",
+            ValidationOutcome::FailedDuplicate => "failed_duplicate",
+        }
+    }
+}
+
+impl fmt::Display for ValidationOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ValidationOutcome {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(ValidationOutcome::Pending),
+            "passed" => Ok(ValidationOutcome::Passed),
+            "failed_size" => Ok(ValidationOutcome::FailedSize),
+            "failed_malicious" => Ok(ValidationOutcome::FailedMalicious),
+            "failed_unreachable" => Ok(ValidationOutcome::FailedUnreachable),
+            "failed_error" => Ok(ValidationOutcome::FailedError),
+            "failed_duplicate" => Ok(ValidationOutcome::FailedDuplicate),
+            _ => Err(format!("unknown validation outcome '{s}'")),
+        }
+    }
+}
+
+/// Why a site's size was measured, as recorded in `size_history`.
+#[derive(Copy, Clone, Debug)]
+pub enum SizeCheck {
+    Listing,
+    Recertification,
+}
+
+impl SizeCheck {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SizeCheck::Listing => "listing",
+            SizeCheck::Recertification => "recertification",
+        }
+    }
+}
+
+/// One row of `validation_attempts`.
+#[derive(Debug, Serialize)]
+pub struct ValidationAttempt {
+    pub outcome: ValidationOutcome,
+    pub size: Option<f64>,
+    pub detail: Option<String>,
+    pub attempted: String,
+}
+
+/// A member feed followed by the planet, with the validators from its last fetch.
+#[derive(Debug)]
+pub struct SiteFeed {
+    pub id: u32,
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// One row of `snapshots`.
+#[derive(Debug)]
+pub struct StoredSnapshot {
+    pub captured: String,
+    pub html: String,
+}
+
+/// A site whose content hash matched an existing member's when it was validated.
+#[derive(Debug, Serialize)]
+pub struct DuplicateContent {
+    pub site_id: u32,
+    pub url: String,
+    pub duplicate_of: u32,
+    pub duplicate_url: String,
+    pub hashed: String,
+}
+
+pub fn init_db(path: &PathBuf, stats: Option<&PoolStatsHandle>) -> Pool {
     if !path.exists() {
-        panic!("database file {path:?} does not exist");
+        info!("database file {path:?} does not exist; creating it");
     }
 
-    let manager = SqliteConnectionManager::file(path);
-    let pool = match Pool::new(manager) {
+    let manager = SqliteConnectionManager::file(path).with_init(statement_cache);
+    let pool = match pool_builder(stats).build(manager) {
         Ok(pool) => pool,
         Err(e) => panic!("unable to get database pool: {e:?}"),
     };
 
-    let Ok(conn) = pool.clone().get() else {
+    let Ok(mut conn) = pool.clone().get() else {
         panic!("Unable to get conn to set foreign keys");
     };
 
+    if let Err(e) = migrate(&mut conn) {
+        panic!("Unable to migrate the database schema: {e:?}");
+    }
+
     let mut statement = conn.prepare("PRAGMA foreign_keys = ON;").unwrap();
     if let Err(e) = statement.execute([]) {
         panic!("Unable to enable foreign key enforcement: {e:?}");
@@ -55,41 +241,331 @@ pub fn init_db(path: &PathBuf) -> Pool {
     pool
 }
 
+/// Open a replica of the database read-only; it's kept up to date by whatever replicates
+/// it (LiteFS, a streamed restore, ...), never by this server.
+pub fn init_replica(path: &PathBuf, stats: Option<&PoolStatsHandle>) -> Pool {
+    if !path.exists() {
+        panic!("replica database file {path:?} does not exist");
+    }
+
+    let manager = SqliteConnectionManager::file(path)
+        .with_flags(
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_init(statement_cache);
+    match pool_builder(stats).build(manager) {
+        Ok(pool) => pool,
+        Err(e) => panic!("unable to get replica database pool: {e:?}"),
+    }
+}
+
+/// One step in bringing a database up to date.
+enum Migration {
+    /// Statements to run.
+    Sql(&'static str),
+    /// A change to existing rows that takes more than SQL.
+    Code(fn(&Connection) -> Result<(), rusqlite::Error>),
+}
+
+/// Every schema change in order; a database at schema version `n` has had the first `n`
+/// applied.  The first is the schema from before there were migrations.  Change the schema
+/// by appending a migration here, never by editing one that has already shipped.
+const MIGRATIONS: &[Migration] = &[
+    Migration::Sql(include_str!("../migrations/0001-schema.sql")),
+    Migration::Sql(include_str!("../migrations/0002-peer-sites.sql")),
+    Migration::Sql(include_str!("../migrations/0003-job-leases.sql")),
+    Migration::Sql(include_str!("../migrations/0004-validation-priority.sql")),
+    Migration::Sql(include_str!("../migrations/0005-scan-cache.sql")),
+    Migration::Sql(include_str!("../migrations/0006-api-keys.sql")),
+    Migration::Sql(include_str!("../migrations/0007-country-stats.sql")),
+    Migration::Sql(include_str!("../migrations/0008-validation-outcomes.sql")),
+    Migration::Sql(include_str!("../migrations/0009-size-history.sql")),
+    Migration::Sql(include_str!("../migrations/0010-featured-sites.sql")),
+    Migration::Sql(include_str!("../migrations/0011-site-notes.sql")),
+    Migration::Sql(include_str!("../migrations/0012-content-hashes.sql")),
+    Migration::Sql(include_str!("../migrations/0013-favicons.sql")),
+    Migration::Sql(include_str!("../migrations/0014-screenshots.sql")),
+    Migration::Sql(include_str!("../migrations/0015-snapshots.sql")),
+    Migration::Sql(include_str!("../migrations/0016-content-changes.sql")),
+    Migration::Sql(include_str!("../migrations/0017-planet.sql")),
+    Migration::Sql(include_str!("../migrations/0018-webmentions.sql")),
+    Migration::Sql(include_str!("../migrations/0019-webmentions-sent.sql")),
+    Migration::Sql(include_str!("../migrations/0020-comments.sql")),
+    Migration::Sql(include_str!("../migrations/0021-shadow-votes.sql")),
+    Migration::Sql(include_str!("../migrations/0022-spam-scores.sql")),
+    Migration::Sql(include_str!("../migrations/0023-site-hosts.sql")),
+    // 0024
+    Migration::Code(backfill_site_hosts),
+    Migration::Sql(include_str!("../migrations/0025-homograph-flags.sql")),
+    Migration::Sql(include_str!("../migrations/0026-site-aliases.sql")),
+    Migration::Sql(include_str!("../migrations/0027-vote-snapshots.sql")),
+    // 0028
+    Migration::Code(rename_voter_secrets),
+    Migration::Sql(include_str!("../migrations/0029-vote-anomalies.sql")),
+    Migration::Sql(include_str!("../migrations/0030-related-suggestions.sql")),
+    Migration::Sql(include_str!("../migrations/0031-related-refreshes.sql")),
+    Migration::Sql(include_str!("../migrations/0032-site-slugs.sql")),
+    // 0033
+    Migration::Code(backfill_site_slugs),
+    Migration::Sql(include_str!("../migrations/0034-scanner-usage.sql")),
+    Migration::Sql(include_str!("../migrations/0035-regional-sizes.sql")),
+    Migration::Sql(include_str!("../migrations/0036-mobile-sizes.sql")),
+    Migration::Sql(include_str!("../migrations/0037-uptime-checks.sql")),
+    Migration::Code(canonicalize_site_urls),
+];
+
+/// Bring the database up to date by running, each in its own transaction, the migrations it
+/// hasn't had yet; running them against an empty database creates the whole schema.
+/// Databases created before there were migrations have no `schema_version` row and are
+/// taken to be at version 1.
+pub fn migrate(conn: &mut Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS schema_version(version INTEGER NOT NULL);")?;
+
+    let mut version: usize = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?;
+    if version == 0 {
+        let unversioned: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'site_ids'",
+            [],
+            |row| row.get(0),
+        )?;
+        if unversioned {
+            info!("database predates schema versions; taking it to be at version 1");
+            conn.execute("INSERT INTO schema_version (version) VALUES (1)", [])?;
+            version = 1;
+        }
+    }
+
+    for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        match migration {
+            Migration::Sql(sql) => tx.execute_batch(sql)?,
+            Migration::Code(change) => change(&tx)?,
+        }
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES (?)",
+            [applied + 1],
+        )?;
+        tx.commit()?;
+        info!("migrated the database to schema version {}", applied + 1);
+    }
+
+    Ok(())
+}
+
+/// Fill in `site_ids.host` for sites added before it existed, so they count toward the
+/// per-domain cap.
+fn backfill_site_hosts(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let mut statement = conn.prepare(r#"SELECT id, url FROM site_ids WHERE host IS NULL"#)?;
+    let sites = statement
+        .query_map([], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+        })?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+
+    let mut update = conn.prepare(r#"UPDATE site_ids SET host = ? WHERE id = ?"#)?;
+    let mut updated = 0;
+    for (id, url) in sites {
+        if let Some(host) = site_domain(&url) {
+            updated += update.execute(params![host, id])?;
+        }
+    }
+
+    if updated > 0 {
+        info!("recorded the domain of {updated} existing sites");
+    }
+    Ok(())
+}
+
+/// Rename `voter_ids.secret`, as the schema from before migrations had it, to the `uuid` the
+/// code has always used.  Databases whose column was already called `uuid` are left alone.
+fn rename_voter_secrets(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let has_secret: bool = conn.query_row(
+        r#"SELECT COUNT(*) > 0 FROM pragma_table_info('voter_ids') WHERE name = 'secret'"#,
+        [],
+        |row| row.get(0),
+    )?;
+    if has_secret {
+        conn.execute_batch("ALTER TABLE voter_ids RENAME COLUMN secret TO uuid;")?;
+    }
+
+    Ok(())
+}
+
+/// Give a slug to every site added before slugs existed.
+fn backfill_site_slugs(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let mut statement = conn.prepare(
+        r#"SELECT id FROM site_ids
+           WHERE NOT EXISTS (SELECT 1 FROM site_slugs
+                             WHERE site_slugs.site_id = site_ids.id AND is_primary)"#,
+    )?;
+    let sites = statement
+        .query_map([], |row| row.get::<_, u32>(0))?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+
+    for &id in &sites {
+        assign_slug(conn, id, &mut rand::thread_rng())?;
+    }
+
+    if !sites.is_empty() {
+        info!("named {} existing sites", sites.len());
+    }
+    Ok(())
+}
+
+/// Rewrite the URLs of sites submitted before submissions were canonicalized in their
+/// canonical form, so that typing a listed site differently can't submit it again.  A URL
+/// whose canonical form is already taken by another site is left for an admin to merge.
+fn canonicalize_site_urls(conn: &Connection) -> Result<(), rusqlite::Error> {
+    let mut statement = conn.prepare(r#"SELECT id, url FROM site_ids"#)?;
+    let sites = statement
+        .query_map([], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+        })?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
+
+    let mut update = conn.prepare(
+        r#"UPDATE site_ids SET url = ?1
+           WHERE id = ?2 AND NOT EXISTS (SELECT 1 FROM site_ids WHERE url = ?1)"#,
+    )?;
+    let mut updated = 0;
+    for (id, url) in sites {
+        match canonical_url(&url) {
+            Ok(canonical) if canonical != url => {
+                if update.execute(params![canonical, id])? > 0 {
+                    updated += 1;
+                } else {
+                    info!("site {id} ({url}) duplicates {canonical}; leaving it to be merged");
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if updated > 0 {
+        info!("canonicalized the urls of {updated} existing sites");
+    }
+    Ok(())
+}
+
+/// A fresh database with the full schema that lives only as long as the returned pool,
+/// for tests that shouldn't need a prebuilt database file.
+pub fn init_db_in_memory() -> Pool {
+    static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+    // Every connection opening the same named in-memory database shares it; the database
+    // goes away with the last connection, so the pool mustn't retire idle ones.
+    let uri = format!(
+        "file:tenkb-{}-{}?mode=memory&cache=shared",
+        std::process::id(),
+        DATABASES.fetch_add(1, Ordering::Relaxed)
+    );
+    let manager = SqliteConnectionManager::file(uri)
+        .with_flags(
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+                | OpenFlags::SQLITE_OPEN_CREATE
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_init(statement_cache);
+    let pool = match Pool::builder()
+        .max_lifetime(None)
+        .idle_timeout(None)
+        .build(manager)
+    {
+        Ok(pool) => pool,
+        Err(e) => panic!("unable to get in-memory database pool: {e:?}"),
+    };
+
+    let Ok(mut conn) = pool.get() else {
+        panic!("Unable to get conn to create the schema");
+    };
+    if let Err(e) = migrate(&mut conn) {
+        panic!("Unable to create the schema: {e:?}");
+    }
+    if let Err(e) = conn.execute_batch("PRAGMA foreign_keys = ON;") {
+        panic!("Unable to enable foreign key enforcement: {e:?}");
+    }
+
+    pool
+}
+
+fn pool_builder(stats: Option<&PoolStatsHandle>) -> r2d2::Builder<SqliteConnectionManager> {
+    match stats {
+        Some(stats) => Pool::builder().event_handler(stats.handler()),
+        None => Pool::builder(),
+    }
+}
+
 pub fn get_sites(
     pool: &Pool,
     sortby: SortOptions,
+    filter: &SiteFilter,
     skip: usize,
     paginate: usize,
 ) -> Result<Vec<Site>, TenKbError> {
     let pool = pool.clone();
 
-    let db_query = match sortby {
-        SortOptions::Votes => {
-            r#"SELECT site_ids.id, site_ids.url, sites.size,
-                      (SELECT COUNT(*) FROM related WHERE related.id = site_ids.id) AS related,
-                      (SELECT COUNT(*) FROM votes WHERE votes.id = site_ids.id) AS upvotes
-               FROM site_ids LEFT JOIN sites
-               WHERE site_ids.id = sites.id AND valid = true
-               ORDER BY upvotes DESC, size ASC LIMIT ?,?"#
-        }
-        SortOptions::Size => {
-            r#"SELECT site_ids.id, site_ids.url, sites.size,
-                      (SELECT COUNT(*) FROM related WHERE related.id = site_ids.id) AS related
-               FROM site_ids LEFT JOIN sites WHERE site_ids.id = sites.id AND valid = true
-               ORDER BY size LIMIT ?,?"#
-        }
-        SortOptions::New => {
-            r#"SELECT site_ids.id, site_ids.url, sites.size,
-                      (SELECT COUNT(*) FROM related WHERE related.id = site_ids.id) AS related
-               FROM site_ids LEFT JOIN sites WHERE site_ids.id = sites.id AND valid = true
-               ORDER BY date_added LIMIT ?,?"#
-        }
+    // Every sort returns the same columns; only the ordering differs.
+    let order = match sortby {
+        SortOptions::Votes => "upvotes DESC, size ASC",
+        SortOptions::Size => "size",
+        SortOptions::New => "date_added",
+        SortOptions::Discussed => "points DESC, comments DESC, size ASC",
+        SortOptions::Week | SortOptions::Month => "recent DESC, upvotes DESC, size ASC",
+    };
+
+    // Votes gained since the last snapshot before the window began.  A site listed during
+    // the window counts all its votes; one older than every snapshot counts from the first.
+    let recent = match sortby.window() {
+        Some(window) => format!(
+            r#"(SELECT COUNT(*) FROM votes
+                WHERE votes.id = site_ids.id AND NOT votes.shadowed) - COALESCE(
+                   (SELECT votes FROM vote_snapshots
+                    WHERE site_id = site_ids.id AND day <= DATE('now', '{window}')
+                    ORDER BY day DESC LIMIT 1),
+                   CASE WHEN sites.date_added >= DATE('now', '{window}') THEN 0 ELSE
+                       (SELECT votes FROM vote_snapshots WHERE site_id = site_ids.id
+                        ORDER BY day LIMIT 1) END,
+                   0)"#
+        ),
+        None => String::from("NULL"),
     };
 
+    let db_query = format!(
+        r#"SELECT site_ids.id, site_ids.url, sites.size,
+                  (SELECT COUNT(*) FROM related WHERE related.id = site_ids.id) AS related,
+                  CAST((JULIANDAY('now') - JULIANDAY(sites.date_added)) / 365.25 AS INTEGER),
+                  (SELECT COUNT(*) FROM votes
+                   WHERE votes.id = site_ids.id AND NOT votes.shadowed) AS upvotes,
+                  sites.date_added,
+                  (SELECT COALESCE(SUM(score), 0) FROM related
+                   WHERE related.id = site_ids.id) AS points,
+                  (SELECT COALESCE(SUM(comments), 0) FROM related
+                   WHERE related.id = site_ids.id) AS comments,
+                  sites.note,
+                  EXISTS (SELECT 1 FROM favicons WHERE favicons.site_id = site_ids.id),
+                  MAX({recent}, 0) AS recent,
+                  EXISTS (SELECT 1 FROM screenshots WHERE screenshots.site_id = site_ids.id),
+                  {SITE_SLUG}
+           FROM site_ids LEFT JOIN sites
+           WHERE site_ids.id = sites.id AND valid = true{}
+           ORDER BY {order} LIMIT ?,?"#,
+        filter.sql()
+    );
+
     let mut offset = skip;
 
     let conn = pool.clone().get()?;
-    let mut statement = conn.prepare(db_query)?;
+    let mut statement = conn.prepare_cached(&db_query)?;
 
     let rows = statement.query_map([&skip, &paginate], |row| {
         offset += 1;
@@ -98,19 +574,285 @@ pub fn get_sites(
             offset,
             id: row.get(0)?,
             url: row.get(1)?,
-            size: format!("{:0.3}", size / 1024.0),
+            size_bytes: size as u64,
             related: row.get(3)?,
+            member_years: row.get(4)?,
+            votes: row.get(5)?,
+            date_added: row.get(6)?,
+            discussion_points: row.get(7)?,
+            discussion_comments: row.get(8)?,
+            note: row.get(9)?,
+            favicon: row.get(10)?,
+            recent_votes: row.get(11)?,
+            thumbnail: row.get(12)?,
+            slug: row.get(13)?,
         })
     })?;
 
     Ok(rows.filter_map(Result::ok).collect::<Vec<Site>>())
 }
 
-pub fn get_site_count(pool: &Pool) -> Result<usize, TenKbError> {
-    let db_query = r#"SELECT COUNT(id) FROM sites WHERE valid = true;"#;
+/// Listed sites that are currently featured, most recently featured first.
+pub fn get_featured_sites(pool: &Pool) -> Result<Vec<FeaturedSite>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(&format!(
+        r#"SELECT site_ids.id, site_ids.url, sites.size, sites.featured_until, {SITE_SLUG}
+           FROM site_ids JOIN sites ON site_ids.id = sites.id
+           WHERE sites.valid = true AND sites.featured = true
+                 AND (sites.featured_until IS NULL OR sites.featured_until > DATETIME())
+           ORDER BY sites.featured_until IS NULL, sites.featured_until DESC"#
+    ))?;
+
+    let rows = statement.query_map([], |row| {
+        let size: f64 = row.get(2)?;
+        Ok(FeaturedSite {
+            id: row.get(0)?,
+            url: row.get(1)?,
+            size_bytes: size as u64,
+            featured_until: row.get(3)?,
+            slug: row.get(4)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Feature a listed site, for `days` days or until unpinned.  Returns false if no listed
+/// site has this id.
+pub fn set_featured(pool: &Pool, id: u32, days: Option<u32>) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    let until = days.map(|days| format!("+{days} days"));
+    let updated = conn.execute_cached(
+        r#"UPDATE sites SET featured = true,
+                            featured_until = CASE WHEN ?1 IS NULL THEN NULL
+                                                  ELSE DATETIME('now', ?1) END
+           WHERE id = ?2 AND valid = true"#,
+        params![until, id],
+    )?;
+
+    Ok(updated > 0)
+}
+
+pub fn clear_featured(pool: &Pool, id: u32) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"UPDATE sites SET featured = false, featured_until = NULL WHERE id = ?"#,
+        params![id],
+    )?;
+
+    Ok(())
+}
+
+/// Attach a curator note to a listed site, or remove it with `None`.  Returns false if no
+/// listed site has this id.
+pub fn set_note(pool: &Pool, id: u32, note: Option<&str>) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    let updated = conn.execute_cached(
+        r#"UPDATE sites SET note = ? WHERE id = ? AND valid = true"#,
+        params![note, id],
+    )?;
+
+    Ok(updated > 0)
+}
+
+/// Per-site rows that belong to whichever entry they were recorded for, and so are dropped
+/// rather than moved when that entry is merged away.
+const MERGED_SITE_DATA: &[(&str, &str)] = &[
+    ("sites", "id"),
+    ("validation_queue", "id"),
+    ("validation_log", "id"),
+    ("validation_attempts", "site_id"),
+    ("size_history", "site_id"),
+    ("content_hashes", "site_id"),
+    ("screenshots", "site_id"),
+    ("snapshots", "site_id"),
+    ("content_changes", "site_id"),
+    ("homograph_flags", "site_id"),
+    ("favicons", "site_id"),
+    ("related_refreshes", "site_id"),
+    ("regional_sizes", "site_id"),
+    ("uptime_checks", "site_id"),
+];
+
+/// Merge the duplicate entry `from` into `into`: its votes, related links, comments,
+/// webmentions, and feeds move to `into`, the rest of its rows are removed, and its URL is
+/// kept as an alias of `into` so it can't be submitted again.  A voter who voted for both
+/// keeps their vote on `into`.  Returns the merged URL, or `None` if either site is unknown.
+pub fn merge_sites(pool: &Pool, from: u32, into: u32) -> Result<Option<String>, TenKbError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let url = {
+        let mut statement = tx.prepare_cached(r#"SELECT url FROM site_ids WHERE id = ?"#)?;
+        let Some(url) = statement
+            .query_map([from], |row| row.get::<_, String>(0))?
+            .next()
+            .transpose()?
+        else {
+            return Ok(None);
+        };
+
+        if statement
+            .query_map([into], |row| row.get::<_, String>(0))?
+            .next()
+            .is_none()
+        {
+            return Ok(None);
+        }
+        url
+    };
+
+    // Rows unique per site: those `into` already has a counterpart for are left behind and
+    // deleted with the rest.
+    let unique = [
+        ("votes", "id"),
+        ("vote_anomaly_votes", "site_id"),
+        ("related_suggestions", "site_id"),
+        ("webmentions", "site_id"),
+        ("site_feeds", "site_id"),
+    ];
+    for (table, column) in unique {
+        tx.execute(
+            &format!(r#"UPDATE OR IGNORE {table} SET {column} = ? WHERE {column} = ?"#),
+            params![into, from],
+        )?;
+    }
+    tx.execute_cached(
+        r#"DELETE FROM planet_posts
+           WHERE feed_id IN (SELECT id FROM site_feeds WHERE site_id = ?)"#,
+        params![from],
+    )?;
+    for (table, column) in unique {
+        tx.execute(
+            &format!(r#"DELETE FROM {table} WHERE {column} = ?"#),
+            params![from],
+        )?;
+    }
+
+    for (table, column) in [
+        ("related", "id"),
+        ("comments", "site_id"),
+        ("vote_anomalies", "site_id"),
+        ("webmentions_sent", "site_id"),
+        ("site_aliases", "site_id"),
+        ("content_hashes", "duplicate_of"),
+        ("homograph_flags", "lookalike_of"),
+    ] {
+        tx.execute(
+            &format!(r#"UPDATE {table} SET {column} = ? WHERE {column} = ?"#),
+            params![into, from],
+        )?;
+    }
+
+    for (table, column) in MERGED_SITE_DATA {
+        tx.execute(
+            &format!(r#"DELETE FROM {table} WHERE {column} = ?"#),
+            params![from],
+        )?;
+    }
+
+    // The merged entry's slugs keep working, as redirects to `into`'s own.
+    tx.execute_cached(
+        r#"UPDATE site_slugs SET site_id = ?, is_primary = false WHERE site_id = ?"#,
+        params![into, from],
+    )?;
+
+    tx.execute_cached(r#"DELETE FROM site_ids WHERE id = ?"#, params![from])?;
+    tx.execute_cached(
+        r#"INSERT OR REPLACE INTO site_aliases (url, site_id, reason, added)
+           VALUES (?, ?, 'merged', DATETIME())"#,
+        params![&url, into],
+    )?;
+
+    tx.commit()?;
+    Ok(Some(url))
+}
+
+/// Point site `site_id` at `new_url`, the target of a permanent redirect from `old_url`, which
+/// is kept as an alias.  Returns false, changing nothing, if `new_url` is already another
+/// entry; those are for an admin to merge.
+pub fn record_redirect(
+    pool: &Pool,
+    site_id: u32,
+    old_url: &str,
+    new_url: &str,
+) -> Result<bool, Box<dyn Error>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let existing = tx
+        .prepare_cached(r#"SELECT id FROM site_ids WHERE url = ?"#)?
+        .query_map([new_url], |row| row.get::<_, u32>(0))?
+        .next()
+        .transpose()?;
+    if existing.is_some_and(|id| id != site_id) {
+        return Ok(false);
+    }
+
+    tx.execute_cached(
+        r#"DELETE FROM site_aliases WHERE url = ?"#,
+        params![new_url],
+    )?;
+    tx.execute_cached(
+        r#"INSERT OR REPLACE INTO site_aliases (url, site_id, reason, added)
+           VALUES (?, ?, 'redirect', DATETIME())"#,
+        params![old_url, site_id],
+    )?;
+    tx.execute_cached(
+        r#"UPDATE site_ids SET url = ?, host = ? WHERE id = ?"#,
+        params![new_url, site_domain(new_url), site_id],
+    )?;
+    tx.execute_cached(
+        r#"INSERT INTO validation_log VALUES (?, DATETIME(), ?)"#,
+        params![
+            site_id,
+            format!("moved permanently from {old_url} to {new_url}")
+        ],
+    )?;
+
+    tx.commit()?;
+    Ok(true)
+}
+
+/// Former URLs of a site, newest first.
+pub fn get_site_aliases(pool: &Pool, site_id: u32) -> Result<Vec<String>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn
+        .prepare_cached(r#"SELECT url FROM site_aliases WHERE site_id = ? ORDER BY added DESC"#)?;
+    let rows = statement.query_map([site_id], |row| row.get(0))?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// The site `url` is an alias of, if any.
+pub fn get_alias_target(pool: &Pool, url: &str) -> Result<Option<u32>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(r#"SELECT site_id FROM site_aliases WHERE url = ?"#)?;
+    let mut rows = statement.query_map([url], |row| row.get(0))?;
+    Ok(rows.next().transpose()?)
+}
+
+/// Record today's public vote count for every listed site, for the "top this week/month"
+/// rankings.  Returns how many sites were recorded.
+pub fn snapshot_vote_counts(pool: &Pool) -> Result<usize, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.execute_cached(
+        r#"INSERT OR REPLACE INTO vote_snapshots (site_id, day, votes)
+           SELECT sites.id, DATE('now'),
+                  (SELECT COUNT(*) FROM votes WHERE votes.id = sites.id AND NOT votes.shadowed)
+           FROM sites WHERE valid = true"#,
+        [],
+    )?)
+}
+
+pub fn get_site_count(pool: &Pool, filter: &SiteFilter) -> Result<usize, TenKbError> {
+    let db_query = format!(
+        r#"SELECT COUNT(site_ids.id) FROM site_ids JOIN sites ON site_ids.id = sites.id
+           WHERE valid = true{};"#,
+        filter.sql()
+    );
 
     let conn = pool.clone().get()?;
-    let mut statement = conn.prepare(db_query)?;
+    let mut statement = conn.prepare_cached(&db_query)?;
     let res = statement.query_map([], |row| row.get(0))?;
 
     let res = res.into_iter().next();
@@ -125,7 +867,7 @@ pub fn get_site_url(pool: &Pool, id: u32) -> Result<String, TenKbError> {
     let db_query = r#"SELECT url FROM site_ids WHERE id = ?;"#;
 
     let conn = pool.clone().get()?;
-    let mut statement = conn.prepare(db_query)?;
+    let mut statement = conn.prepare_cached(db_query)?;
     let res = statement.query_map([&id], |row| row.get(0))?;
 
     let res = res.into_iter().next();
@@ -136,154 +878,1410 @@ pub fn get_site_url(pool: &Pool, id: u32) -> Result<String, TenKbError> {
     }
 }
 
-pub fn submit_site(pool: web::Data<Pool>, site: String) -> Result<(), TenKbError> {
-    if check_site_active(&pool, &site)? {
-        info!("site '{site}' is already active");
-        return Err(TenKbError::Msg(format!(
-            "site '{site}' is already in the database"
-        )));
-    }
+/// Queue a submitted site for validation.  `pages_per_domain` (0 for no limit) caps how many
+/// pages of one domain may be listed or pending at once.
+pub fn submit_site(pool: &Pool, site: String, pages_per_domain: usize) -> Result<(), TenKbError> {
+    pool.check_submission(&site, pages_per_domain)?;
 
-    if check_site_blocked(&pool, &site)? {
-        info!("site '{site}' is blocked");
-        return Err(TenKbError::Msg(format!(
-            "sorry! site '{site}' is blocked from submission"
-        )));
-    }
+    let host = site_domain(&site);
+    let conn = pool.clone().get()?;
+
+    let query = r#"INSERT INTO site_ids (url, host) VALUES (?, ?);"#;
+    let mut statement = conn.prepare_cached(query)?;
+    statement.execute(params![&site, &host])?;
+    assign_slug(
+        &conn,
+        conn.last_insert_rowid() as u32,
+        &mut rand::thread_rng(),
+    )?;
+
+    let query = r#"INSERT INTO validation_queue (id, date_added, outcome, priority)
+        VALUES ((SELECT id FROM site_ids WHERE url = ?), DATETIME(), 'pending', ?);"#;
+
+    let mut statement = conn.prepare_cached(query)?;
+    statement.execute(params![&site, QueuePriority::Submission as i64])?;
+
+    Ok(())
+}
+
+/// The site's primary slug, falling back to its id for sites that don't have one yet.
+/// `site_ids` must be in scope.
+pub(crate) const SITE_SLUG: &str = r#"COALESCE((SELECT slug FROM site_slugs
+                                     WHERE site_id = site_ids.id AND is_primary),
+                                    CAST(site_ids.id AS TEXT))"#;
 
-    if check_site_queued(&pool, &site)? {
-        info!("site '{site}' is already queued for validation");
-        return Err(TenKbError::Msg(format!(
-            "site '{site}' is already pending validation"
-        )));
+/// Give the site a primary slug, drawing from `rng` until one isn't taken.
+pub(crate) fn assign_slug(
+    conn: &Connection,
+    site_id: u32,
+    rng: &mut impl Rng,
+) -> Result<String, rusqlite::Error> {
+    loop {
+        let slug = slugs::generate(rng);
+        let inserted = conn.execute_cached(
+            r#"INSERT OR IGNORE INTO site_slugs (slug, site_id, is_primary, added)
+               VALUES (?, ?, true, DATETIME())"#,
+            params![&slug, site_id],
+        )?;
+        if inserted > 0 {
+            return Ok(slug);
+        }
     }
+}
 
-    let conn = pool.clone().get()?;
+/// The site a `/site/{key}/` path names, as its id and primary slug.  `key` is a slug,
+/// primary or not, or a legacy numeric id.
+pub fn find_site(pool: &Pool, key: &str) -> Result<Option<(u32, String)>, TenKbError> {
+    let conn = pool.get()?;
+    let query = if key.bytes().all(|b| b.is_ascii_digit()) {
+        format!(r#"SELECT site_ids.id, {SITE_SLUG} FROM site_ids WHERE site_ids.id = ?"#)
+    } else {
+        format!(
+            r#"SELECT site_ids.id, {SITE_SLUG}
+               FROM site_slugs JOIN site_ids ON site_ids.id = site_slugs.site_id
+               WHERE site_slugs.slug = ?"#
+        )
+    };
+
+    let mut statement = conn.prepare_cached(&query)?;
+    let site = statement
+        .query_map([key], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .next()
+        .transpose()?;
+    Ok(site)
+}
+
+/// The site's primary slug, or its id if it doesn't have one yet.
+pub fn get_site_slug(pool: &Pool, id: u32) -> Result<String, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.query_row_cached(
+        &format!(r#"SELECT {SITE_SLUG} FROM site_ids WHERE site_ids.id = ?"#),
+        params![id],
+        |row| row.get(0),
+    )?)
+}
+
+/// Ids and hosts of listed sites, for comparing new submissions against.
+pub fn get_member_hosts(pool: &Pool) -> Result<Vec<(u32, String)>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT site_ids.id, site_ids.host FROM site_ids
+           JOIN sites ON sites.id = site_ids.id
+           WHERE sites.valid = true AND site_ids.host IS NOT NULL"#,
+    )?;
+
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
 
-    let query = r#"INSERT INTO site_ids (url) VALUES (?);"#;
-    let mut statement = conn.prepare(query)?;
-    statement.execute([&site])?;
+/// Ids and URLs of every listed site.
+pub fn get_listed_sites(pool: &Pool) -> Result<Vec<(u32, String)>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT site_ids.id, site_ids.url FROM site_ids
+           JOIN sites ON sites.id = site_ids.id
+           WHERE sites.valid = true ORDER BY site_ids.id"#,
+    )?;
 
-    let query = r#"INSERT INTO validation_queue (id, date_added, scan)
-        VALUES ((SELECT id FROM site_ids WHERE url = ?), DATETIME(), true);"#;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
 
-    let mut statement = conn.prepare(query)?;
-    statement.execute([&site])?;
+/// Record a liveness check of a listed site: up if `failure` is `None`, otherwise down for
+/// that reason.
+pub fn record_uptime_check(
+    pool: &Pool,
+    site_id: u32,
+    failure: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT INTO uptime_checks (site_id, checked, up, detail)
+           VALUES (?, DATETIME(), ?, ?)"#,
+        params![site_id, failure.is_none(), failure],
+    )?;
 
     Ok(())
 }
 
-pub fn check_site_active(pool: &web::Data<Pool>, site: &String) -> Result<bool, TenKbError> {
-    let query = r#"SELECT site_ids.id FROM site_ids LEFT JOIN sites
-                   WHERE site_ids.id = sites.id AND site_ids.url = ? AND sites.valid = true;"#;
+/// Remove liveness checks over `days` days old, returning how many there were.
+pub fn prune_uptime_checks(pool: &Pool, days: u32) -> Result<usize, Box<dyn Error>> {
+    let conn = pool.get()?;
+    Ok(conn.execute_cached(
+        r#"DELETE FROM uptime_checks WHERE checked < DATETIME('now', ?)"#,
+        params![format!("-{days} days")],
+    )?)
+}
 
-    let conn = pool.clone().get()?;
-    let mut statement = conn.prepare(query)?;
+/// The share of liveness checks of the site in the last `days` days that found it up, or
+/// `None` if it hasn't been checked.
+pub fn get_uptime_summary(
+    pool: &Pool,
+    id: u32,
+    days: u32,
+) -> Result<Option<UptimeSummary>, TenKbError> {
+    let conn = pool.get()?;
+    let (checks, up): (u32, u32) = conn.query_row_cached(
+        r#"SELECT COUNT(*), COALESCE(SUM(up), 0) FROM uptime_checks
+           WHERE site_id = ? AND checked >= DATETIME('now', ?)"#,
+        params![id, format!("-{days} days")],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
 
-    let rows = statement.query_map([&site], |row| row.get::<usize, u32>(0))?;
+    Ok((checks > 0).then(|| UptimeSummary {
+        checks,
+        up,
+        percent: 100.0 * up as f64 / checks as f64,
+    }))
+}
 
-    Ok(!rows.filter_map(Result::ok).collect::<Vec<u32>>().is_empty())
+/// Hold a submitted site for review as a possible homograph of `lookalike_of`.
+pub fn flag_homograph(
+    pool: &Pool,
+    url: &str,
+    reason: &str,
+    lookalike_of: Option<u32>,
+) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT OR REPLACE INTO homograph_flags (site_id, reason, lookalike_of, flagged)
+           VALUES ((SELECT id FROM site_ids WHERE url = ?), ?, ?, DATETIME())"#,
+        params![url, reason, lookalike_of],
+    )?;
+    Ok(())
 }
 
-pub fn check_site_blocked(pool: &web::Data<Pool>, site: &String) -> Result<bool, TenKbError> {
-    let query = r#"SELECT pattern FROM blocked_site_patterns;"#;
+pub fn get_homograph_flags(pool: &Pool, limit: u32) -> Result<Vec<HomographFlag>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT homograph_flags.site_id, flagged_site.url, reason, lookalike_of,
+                  lookalike.url, flagged
+           FROM homograph_flags
+           JOIN site_ids AS flagged_site ON flagged_site.id = homograph_flags.site_id
+           LEFT JOIN site_ids AS lookalike ON lookalike.id = homograph_flags.lookalike_of
+           ORDER BY flagged DESC LIMIT ?"#,
+    )?;
 
-    let conn = pool.clone().get()?;
-    let mut statement = conn.prepare(query)?;
+    let rows = statement.query_map(params![limit], |row| {
+        Ok(HomographFlag {
+            site_id: row.get(0)?,
+            url: row.get(1)?,
+            reason: row.get(2)?,
+            lookalike_of: row.get(3)?,
+            lookalike_url: row.get(4)?,
+            flagged: row.get(5)?,
+        })
+    })?;
 
-    let rows = statement.query_map([], |row| row.get::<usize, String>(0))?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
 
-    for pattern in rows.filter_map(Result::ok).collect::<Vec<String>>() {
-        let Ok(re) = Regex::new(&pattern[..]) else {
-            continue;
-        };
+/// Pages of a domain that are listed or waiting for validation.
+pub fn count_domain_pages(pool: &Pool, host: &str) -> Result<usize, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.query_row_cached(
+        r#"SELECT COUNT(*) FROM site_ids
+           LEFT JOIN sites ON sites.id = site_ids.id
+           LEFT JOIN validation_queue ON validation_queue.id = site_ids.id
+           WHERE site_ids.host = ?
+             AND (sites.valid = true OR validation_queue.outcome = 'pending')"#,
+        params![host],
+        |row| row.get(0),
+    )?)
+}
 
-        if re.is_match(&site[..]) {
-            info!("site '{site}' matched block pattern '{pattern}'");
-            return Ok(true);
-        }
+/// Put an existing site back on the validation queue, raising its priority if it is already
+/// queued.
+pub fn requeue_site(pool: &Pool, id: u32, priority: QueuePriority) -> Result<(), TenKbError> {
+    let conn = pool.clone().get()?;
+
+    let updated = conn.execute_cached(
+        r#"UPDATE validation_queue SET outcome = 'pending', priority = MAX(priority, ?)
+           WHERE id = ?;"#,
+        params![priority as i64, id],
+    )?;
+
+    if updated == 0 {
+        conn.execute_cached(
+            r#"INSERT INTO validation_queue (id, date_added, outcome, priority)
+               VALUES (?, DATETIME(), 'pending', ?);"#,
+            params![id, priority as i64],
+        )?;
     }
 
-    Ok(false)
+    Ok(())
+}
+
+pub fn check_site_active(pool: &Pool, site: &str) -> Result<bool, TenKbError> {
+    let query = r#"SELECT site_ids.id FROM site_ids LEFT JOIN sites
+                   WHERE site_ids.id = sites.id AND site_ids.url = ? AND sites.valid = true;"#;
+
+    let conn = pool.clone().get()?;
+    let mut statement = conn.prepare_cached(query)?;
+
+    let rows = statement.query_map([&site], |row| row.get::<usize, u32>(0))?;
+
+    Ok(!rows.filter_map(Result::ok).collect::<Vec<u32>>().is_empty())
+}
+
+/// Regular expressions matching the URLs that may not be submitted.
+pub fn get_blocked_patterns(pool: &Pool) -> Result<Vec<String>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(r#"SELECT pattern FROM blocked_site_patterns;"#)?;
+    let rows = statement.query_map([], |row| row.get(0))?;
+    Ok(rows.filter_map(Result::ok).collect())
 }
 
-pub fn check_site_queued(pool: &web::Data<Pool>, site: &String) -> Result<bool, TenKbError> {
+pub fn check_site_queued(pool: &Pool, site: &str) -> Result<bool, TenKbError> {
     let query = r#"SELECT site_ids.id FROM site_ids LEFT JOIN validation_queue
                    WHERE validation_queue.id = site_ids.id AND site_ids.url = ?"#;
 
     let conn = pool.clone().get()?;
-    let mut statement = conn.prepare(query)?;
+    let mut statement = conn.prepare_cached(query)?;
 
     let rows = statement.query_map([&site], |row| row.get::<usize, u32>(0))?;
 
     Ok(!rows.filter_map(Result::ok).collect::<Vec<u32>>().is_empty())
 }
 
-pub fn generate_id(pool: web::Data<Pool>, id: String) -> Result<(), TenKbError> {
-    let query = r#"INSERT INTO voter_ids (uuid) VALUES (?);"#;
+/// Whether the site is in the validation queue waiting for a scan.
+pub fn check_site_pending(pool: &Pool, id: u32) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    let pending: u32 = conn.query_row_cached(
+        r#"SELECT COUNT(*) FROM validation_queue WHERE id = ? AND outcome = 'pending'"#,
+        params![id],
+        |row| row.get(0),
+    )?;
+
+    Ok(pending > 0)
+}
+
+pub fn generate_id(pool: &Pool, id: String) -> Result<(), TenKbError> {
+    let query = r#"INSERT INTO voter_ids (uuid, created) VALUES (?, DATETIME());"#;
 
     let conn = pool.clone().get()?;
-    let mut statement = conn.prepare(query)?;
+    let mut statement = conn.prepare_cached(query)?;
     statement.execute([&id])?;
 
     Ok(())
 }
 
+/// Set the voter's vote on a site and count the site's votes, in one transaction so the
+/// count always includes this vote.  Repeating a vote is a no-op; returns whether anything
+/// changed and the new count.  Shadowed votes are stored but left out of public counts,
+/// except that a shadowed voter's count includes their own.
 pub fn cast_vote(
-    pool: web::Data<Pool>,
+    pool: &Pool,
     voter_id: String,
     site_id: u32,
     vote: isize,
-) -> Result<(), TenKbError> {
-    let upsert_query = r#"INSERT INTO votes
-                          VALUES (?, (SELECT id FROM voter_ids WHERE uuid = ?))
+    shadowed: bool,
+) -> Result<(bool, u32), TenKbError> {
+    // Selecting the voter rather than looking it up in a subquery means an unknown voter id
+    // inserts nothing instead of a vote with a NULL voter.
+    let upsert_query = r#"INSERT INTO votes (id, voter_id, shadowed, voted)
+                          SELECT ?, id, ?, DATETIME() FROM voter_ids WHERE uuid = ?
                           ON CONFLICT(id, voter_id) DO NOTHING;"#;
     let unvote_query = r#"DELETE FROM votes
                           WHERE id = ? AND voter_id = (SELECT id FROM voter_ids WHERE uuid = ?);"#;
+    let count_query = r#"SELECT COUNT(*) FROM votes WHERE id = ?1
+                         AND (NOT shadowed
+                              OR (?2 AND voter_id = (SELECT id FROM voter_ids WHERE uuid = ?3)))"#;
 
-    let conn = pool.clone().get()?;
+    let mut conn = pool.clone().get()?;
+    let tx = conn.transaction()?;
 
-    let mut statement = conn.prepare(if vote == 0 {
-        unvote_query
+    let changed = if vote == 0 {
+        tx.execute_cached(unvote_query, params![&site_id, &voter_id])?
     } else {
-        upsert_query
-    })?;
+        tx.execute_cached(upsert_query, params![&site_id, &shadowed, &voter_id])?
+    };
+    let total = tx.query_row_cached(
+        count_query,
+        params![&site_id, &shadowed, &voter_id],
+        |row| row.get(0),
+    )?;
+
+    tx.commit()?;
+    Ok((changed > 0, total))
+}
+
+pub fn voter_exists(pool: &Pool, voter_id: &str) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    let count: u32 = conn.query_row_cached(
+        "SELECT COUNT(*) FROM voter_ids WHERE uuid = ?",
+        params![voter_id],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+pub fn get_vote_count(pool: &Pool, site_id: u32) -> Result<u32, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.query_row_cached(
+        "SELECT COUNT(*) FROM votes WHERE id = ? AND NOT shadowed",
+        params![site_id],
+        |row| row.get(0),
+    )?)
+}
+
+/// Whether content from this voter id or client IP should be shadowed.
+pub fn is_shadowed(pool: &Pool, voter_id: &str, client_ip: &str) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    let count: u32 = conn.query_row_cached(
+        r#"SELECT COUNT(*) FROM shadow_flags
+           WHERE (kind = 'voter'
+                  AND value = (SELECT CAST(id AS TEXT) FROM voter_ids WHERE uuid = ?))
+              OR (kind = 'ip' AND value = ?)"#,
+        params![voter_id, client_ip],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+pub fn add_shadow_flag(
+    pool: &Pool,
+    kind: ShadowKind,
+    value: &str,
+    reason: Option<&str>,
+) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT OR REPLACE INTO shadow_flags (kind, value, reason, flagged)
+           VALUES (?, ?, ?, DATETIME())"#,
+        params![kind.as_str(), value, reason],
+    )?;
+
+    Ok(())
+}
+
+/// Returns false if there was no such flag.
+pub fn remove_shadow_flag(pool: &Pool, kind: ShadowKind, value: &str) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.execute_cached(
+        r#"DELETE FROM shadow_flags WHERE kind = ? AND value = ?"#,
+        params![kind.as_str(), value],
+    )? > 0)
+}
+
+pub fn get_shadow_flags(pool: &Pool) -> Result<Vec<ShadowFlag>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT kind, value, reason, flagged FROM shadow_flags ORDER BY flagged DESC"#,
+    )?;
+
+    let rows = statement.query_map([], |row| {
+        Ok(ShadowFlag {
+            kind: row.get(0)?,
+            value: row.get(1)?,
+            reason: row.get(2)?,
+            flagged: row.get(3)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Voters with shadowed votes awaiting review, and how many each has.
+pub fn get_shadowed_voters(pool: &Pool) -> Result<Vec<(u32, u32)>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT voter_id, COUNT(*) FROM votes WHERE shadowed
+           GROUP BY voter_id ORDER BY COUNT(*) DESC"#,
+    )?;
+
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Count a voter's shadowed votes after all (`approve`), or delete them.  Returns how many
+/// votes were affected.
+pub fn review_shadowed_votes(pool: &Pool, voter: u32, approve: bool) -> Result<usize, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.execute_cached(
+        if approve {
+            r#"UPDATE votes SET shadowed = false WHERE voter_id = ? AND shadowed"#
+        } else {
+            r#"DELETE FROM votes WHERE voter_id = ? AND shadowed"#
+        },
+        params![voter],
+    )?)
+}
+
+/// Votes not yet part of any anomaly report.
+const UNREPORTED_VOTE: &str = r#"NOT EXISTS (SELECT 1 FROM vote_anomaly_votes AS reported
+                                            WHERE reported.site_id = votes.id
+                                                  AND reported.voter_id = votes.voter_id)"#;
+
+/// Report each site that got `min_votes` or more in the last `minutes`, counting only votes
+/// no earlier report covers.  Returns how many bursts were found.
+pub fn record_vote_bursts(pool: &Pool, min_votes: u32, minutes: u32) -> Result<usize, TenKbError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let window = format!("-{minutes} minutes");
+
+    let bursts = {
+        let mut statement = tx.prepare_cached(&format!(
+            r#"SELECT id, COUNT(*) FROM votes
+               WHERE voted >= DATETIME('now', ?) AND NOT shadowed AND {UNREPORTED_VOTE}
+               GROUP BY id HAVING COUNT(*) >= ?"#
+        ))?;
+        let rows = statement.query_map(params![window, min_votes], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?))
+        })?;
+        rows.collect::<Result<Vec<_>, _>>()?
+    };
+
+    for (site_id, votes) in &bursts {
+        tx.execute_cached(
+            r#"INSERT INTO vote_anomalies (kind, site_id, detail, votes, detected)
+               VALUES ('burst', ?, ?, ?, DATETIME())"#,
+            params![
+                site_id,
+                format!("{votes} votes in {minutes} minutes"),
+                votes
+            ],
+        )?;
+        tx.execute_cached(
+            &format!(
+                r#"INSERT INTO vote_anomaly_votes (anomaly_id, site_id, voter_id)
+                   SELECT ?, id, voter_id FROM votes
+                   WHERE id = ? AND voted >= DATETIME('now', ?) AND NOT shadowed
+                         AND {UNREPORTED_VOTE}"#
+            ),
+            params![tx.last_insert_rowid(), site_id, window],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(bursts.len())
+}
+
+/// Report each group of `min_voters` or more voters, all issued ids in the last `hours`,
+/// who voted for exactly the same `min_sites` or more sites.  Returns how many were found.
+pub fn record_vote_cohorts(
+    pool: &Pool,
+    min_voters: usize,
+    min_sites: usize,
+    hours: u32,
+) -> Result<usize, TenKbError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let mut ballots: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    {
+        let mut statement = tx.prepare_cached(&format!(
+            r#"SELECT votes.voter_id, votes.id FROM votes
+               JOIN voter_ids ON voter_ids.id = votes.voter_id
+               WHERE voter_ids.created >= DATETIME('now', ?) AND NOT votes.shadowed
+                     AND {UNREPORTED_VOTE}
+               ORDER BY votes.voter_id, votes.id"#
+        ))?;
+        let rows = statement.query_map([format!("-{hours} hours")], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?))
+        })?;
+        for row in rows {
+            let (voter, site) = row?;
+            ballots.entry(voter).or_default().push(site);
+        }
+    }
+
+    let mut cohorts: BTreeMap<Vec<u32>, Vec<u32>> = BTreeMap::new();
+    for (voter, sites) in ballots {
+        if sites.len() >= min_sites {
+            cohorts.entry(sites).or_default().push(voter);
+        }
+    }
+    cohorts.retain(|_, voters| voters.len() >= min_voters);
+
+    for (sites, voters) in &cohorts {
+        let mut listed = sites
+            .iter()
+            .take(5)
+            .map(|site| format!("#{site}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if sites.len() > 5 {
+            listed.push_str(", ...");
+        }
+        tx.execute_cached(
+            r#"INSERT INTO vote_anomalies (kind, site_id, detail, votes, detected)
+               VALUES ('cohort', NULL, ?, ?, DATETIME())"#,
+            params![
+                format!(
+                    "{} new voters all voted for the same {} sites ({listed})",
+                    voters.len(),
+                    sites.len()
+                ),
+                voters.len() * sites.len()
+            ],
+        )?;
+
+        let anomaly = tx.last_insert_rowid();
+        for voter in voters {
+            for site in sites {
+                tx.execute_cached(
+                    r#"INSERT INTO vote_anomaly_votes (anomaly_id, site_id, voter_id)
+                       VALUES (?, ?, ?)"#,
+                    params![anomaly, site, voter],
+                )?;
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(cohorts.len())
+}
+
+/// The most recent anomaly reports, newest first.
+pub fn get_vote_anomalies(pool: &Pool, limit: usize) -> Result<Vec<VoteAnomaly>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT vote_anomalies.id, kind, site_id, site_ids.url, detail, votes, detected,
+                  invalidated
+           FROM vote_anomalies LEFT JOIN site_ids ON site_ids.id = vote_anomalies.site_id
+           ORDER BY detected DESC, vote_anomalies.id DESC LIMIT ?"#,
+    )?;
+
+    let rows = statement.query_map([limit], |row| {
+        Ok(VoteAnomaly {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            site_id: row.get(2)?,
+            url: row.get(3)?,
+            detail: row.get(4)?,
+            votes: row.get(5)?,
+            detected: row.get(6)?,
+            invalidated: row.get(7)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Delete the votes an anomaly report covers.  Returns how many were deleted, or `None` if
+/// there is no such report or it was already invalidated.
+pub fn invalidate_vote_anomaly(pool: &Pool, anomaly: u32) -> Result<Option<usize>, TenKbError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    if tx.execute_cached(
+        r#"UPDATE vote_anomalies SET invalidated = DATETIME()
+           WHERE id = ? AND invalidated IS NULL"#,
+        params![anomaly],
+    )? == 0
+    {
+        return Ok(None);
+    }
+
+    let deleted = tx.execute_cached(
+        r#"DELETE FROM votes
+           WHERE EXISTS (SELECT 1 FROM vote_anomaly_votes AS batch
+                         WHERE batch.anomaly_id = ? AND batch.site_id = votes.id
+                               AND batch.voter_id = votes.voter_id)"#,
+        params![anomaly],
+    )?;
+
+    tx.commit()?;
+    Ok(Some(deleted))
+}
+
+pub fn get_votes(pool: &Pool, voter_id: String) -> Result<Vec<u32>, TenKbError> {
+    let query = r#"SELECT * FROM votes
+                   WHERE voter_id = (SELECT id FROM voter_ids WHERE uuid = ?);"#;
+
+    let conn = pool.clone().get()?;
+    let mut statement = conn.prepare_cached(query)?;
+
+    let rows = statement.query_map([&voter_id], |row| row.get::<usize, u32>(0))?;
+    Ok(rows.filter_map(Result::ok).collect::<Vec<u32>>())
+}
+
+pub fn get_validation_queue(pool: &Pool) -> Result<Vec<String>, Box<dyn Error>> {
+    let conn = pool.clone().get()?;
+
+    let db_query = r#"SELECT site_ids.url FROM site_ids LEFT JOIN validation_queue
+                      WHERE site_ids.id = validation_queue.id
+                            AND validation_queue.outcome = 'pending'
+                      ORDER BY validation_queue.priority DESC, validation_queue.date_added ASC"#;
+
+    let mut statement = conn.prepare_cached(db_query)?;
+    let rows = statement.query_map([], |row| row.get::<usize, String>(0))?;
+    Ok(rows.filter_map(Result::ok).collect::<Vec<String>>())
+}
+
+/// Take a site off the active queue with a failing `outcome`, recording the attempt.
+pub fn mark_bad(
+    pool: &Pool,
+    site: &str,
+    outcome: ValidationOutcome,
+    detail: &str,
+) -> Result<(), Box<dyn Error>> {
+    record_attempt(pool, site, outcome, None, Some(detail))?;
+
+    let conn = pool.clone().get()?;
+    conn.execute_cached(
+        r#"UPDATE validation_queue SET outcome = ?
+           WHERE id = (SELECT id FROM site_ids WHERE url = ?)"#,
+        params![outcome.as_str(), site],
+    )?;
+
+    Ok(())
+}
+
+/// A listed site, other than `site` itself, whose page content hashed to `hash`.
+pub fn find_duplicate_content(
+    pool: &Pool,
+    site: &str,
+    hash: &str,
+) -> Result<Option<(u32, String)>, Box<dyn Error>> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT site_ids.id, site_ids.url FROM content_hashes
+           JOIN site_ids ON site_ids.id = content_hashes.site_id
+           JOIN sites ON sites.id = content_hashes.site_id
+           WHERE content_hashes.hash = ? AND sites.valid = true AND site_ids.url != ?
+           LIMIT 1"#,
+    )?;
+
+    let mut rows =
+        statement.query_map(params![hash, site], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    Ok(rows.next().transpose()?)
+}
+
+/// Remember the hash of `site`'s content, and which member it duplicates, if any.
+pub fn store_content_hash(
+    pool: &Pool,
+    site: &str,
+    hash: &str,
+    duplicate_of: Option<u32>,
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT OR REPLACE INTO content_hashes (site_id, hash, duplicate_of, hashed)
+           SELECT id, ?, ?, DATETIME() FROM site_ids WHERE url = ?"#,
+        params![hash, duplicate_of, site],
+    )?;
+
+    Ok(())
+}
+
+/// Sites flagged as duplicating another member, most recent first.
+pub fn get_duplicate_content(pool: &Pool, limit: u32) -> Result<Vec<DuplicateContent>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT content_hashes.site_id, site_ids.url, content_hashes.duplicate_of,
+                  original.url, content_hashes.hashed
+           FROM content_hashes
+           JOIN site_ids ON site_ids.id = content_hashes.site_id
+           JOIN site_ids AS original ON original.id = content_hashes.duplicate_of
+           ORDER BY content_hashes.hashed DESC LIMIT ?"#,
+    )?;
+
+    let rows = statement.query_map([&limit], |row| {
+        Ok(DuplicateContent {
+            site_id: row.get(0)?,
+            url: row.get(1)?,
+            duplicate_of: row.get(2)?,
+            duplicate_url: row.get(3)?,
+            hashed: row.get(4)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+pub fn store_screenshot(
+    pool: &Pool,
+    site_id: u32,
+    path: &Path,
+    thumbnail_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT OR REPLACE INTO screenshots (site_id, path, thumbnail_path, captured)
+           VALUES (?, ?, ?, DATETIME())"#,
+        params![
+            site_id,
+            path.to_string_lossy(),
+            thumbnail_path.to_string_lossy()
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Where a listed site's thumbnail is stored and when it was captured.
+pub fn get_thumbnail(pool: &Pool, site_id: u32) -> Result<Option<(PathBuf, String)>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT screenshots.thumbnail_path, screenshots.captured FROM screenshots
+           JOIN sites ON sites.id = screenshots.site_id
+           WHERE screenshots.site_id = ? AND sites.valid = true"#,
+    )?;
+
+    let mut rows = statement.query_map([&site_id], |row| {
+        Ok((PathBuf::from(row.get::<_, String>(0)?), row.get(1)?))
+    })?;
+
+    Ok(rows.next().transpose()?)
+}
+
+pub fn store_snapshot(pool: &Pool, site_id: u32, html: &str) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT OR REPLACE INTO snapshots (site_id, captured, html) VALUES (?, DATETIME(), ?)"#,
+        params![site_id, html],
+    )?;
+
+    Ok(())
+}
+
+pub fn get_snapshots(pool: &Pool, site_id: u32) -> Result<Vec<SnapshotInfo>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT CAST(STRFTIME('%s', snapshots.captured) AS INTEGER), snapshots.captured,
+                  content_changes.bytes_added, content_changes.bytes_removed
+           FROM snapshots LEFT JOIN content_changes
+                ON content_changes.site_id = snapshots.site_id
+                   AND content_changes.captured = snapshots.captured
+           WHERE snapshots.site_id = ? ORDER BY snapshots.captured DESC"#,
+    )?;
+
+    let rows = statement.query_map([&site_id], |row| {
+        Ok(SnapshotInfo {
+            timestamp: row.get(0)?,
+            captured: row.get(1)?,
+            bytes_added: row.get(2)?,
+            bytes_removed: row.get(3)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// The archived HTML captured at `timestamp` (seconds since the epoch).  Snapshots outlive
+/// the listing, so delisted sites are included.
+pub fn get_snapshot(
+    pool: &Pool,
+    site_id: u32,
+    timestamp: i64,
+) -> Result<Option<String>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT html FROM snapshots
+           WHERE site_id = ? AND CAST(STRFTIME('%s', captured) AS INTEGER) = ?"#,
+    )?;
+
+    let mut rows = statement.query_map(params![site_id, timestamp], |row| row.get(0))?;
+
+    Ok(rows.next().transpose()?)
+}
+
+/// The snapshot captured at `timestamp` (or the latest, for `None`) and the one before it,
+/// if any.
+pub fn get_snapshot_pair(
+    pool: &Pool,
+    site_id: u32,
+    timestamp: Option<i64>,
+) -> Result<Option<(StoredSnapshot, Option<StoredSnapshot>)>, TenKbError> {
+    let conn = pool.get()?;
+    let to_snapshot = |row: &rusqlite::Row| {
+        Ok(StoredSnapshot {
+            captured: row.get(0)?,
+            html: row.get(1)?,
+        })
+    };
+
+    let mut statement = conn.prepare_cached(
+        r#"SELECT captured, html FROM snapshots
+           WHERE site_id = ?1
+                 AND (?2 IS NULL OR CAST(STRFTIME('%s', captured) AS INTEGER) = ?2)
+           ORDER BY captured DESC LIMIT 1"#,
+    )?;
+    let mut rows = statement.query_map(params![site_id, timestamp], to_snapshot)?;
+    let Some(current) = rows.next().transpose()? else {
+        return Ok(None);
+    };
+
+    let mut statement = conn.prepare_cached(
+        r#"SELECT captured, html FROM snapshots WHERE site_id = ? AND captured < ?
+           ORDER BY captured DESC LIMIT 1"#,
+    )?;
+    let mut rows = statement.query_map(params![site_id, current.captured], to_snapshot)?;
+    let previous = rows.next().transpose()?;
+
+    Ok(Some((current, previous)))
+}
+
+pub fn record_content_change(
+    pool: &Pool,
+    site_id: u32,
+    captured: &str,
+    previous: &str,
+    stats: &ChangeStats,
+    at_risk: bool,
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT OR REPLACE INTO content_changes
+           (site_id, captured, previous, bytes_added, bytes_removed, at_risk)
+           VALUES (?, ?, ?, ?, ?, ?)"#,
+        params![
+            site_id,
+            captured,
+            previous,
+            stats.bytes_added,
+            stats.bytes_removed,
+            at_risk
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Recent changes that flagged a listed site as at risk, newest first.
+pub fn get_at_risk_changes(pool: &Pool, limit: u32) -> Result<Vec<AtRiskChange>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(&format!(
+        r#"SELECT content_changes.site_id, {SITE_SLUG}, site_ids.url,
+                      CAST(STRFTIME('%s', content_changes.captured) AS INTEGER),
+                      content_changes.captured, content_changes.bytes_added,
+                      content_changes.bytes_removed
+               FROM content_changes
+           JOIN site_ids ON site_ids.id = content_changes.site_id
+           JOIN sites ON sites.id = content_changes.site_id
+               WHERE content_changes.at_risk = true AND sites.valid = true
+               ORDER BY content_changes.captured DESC LIMIT ?"#
+    ))?;
+
+    let rows = statement.query_map([&limit], |row| {
+        Ok(AtRiskChange {
+            site_id: row.get(0)?,
+            slug: row.get(1)?,
+            url: row.get(2)?,
+            timestamp: row.get(3)?,
+            captured: row.get(4)?,
+            bytes_added: row.get(5)?,
+            bytes_removed: row.get(6)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+pub fn store_site_feeds(
+    pool: &Pool,
+    site_id: u32,
+    feeds: &[DiscoveredFeed],
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    for feed in feeds {
+        conn.execute_cached(
+            r#"INSERT OR IGNORE INTO site_feeds (site_id, url, kind) VALUES (?, ?, ?)"#,
+            params![site_id, feed.url, feed.kind],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Feeds of listed sites.
+pub fn get_site_feeds(pool: &Pool) -> Result<Vec<SiteFeed>, Box<dyn Error>> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT site_feeds.id, site_feeds.url, site_feeds.etag, site_feeds.last_modified
+           FROM site_feeds JOIN sites ON sites.id = site_feeds.site_id
+           WHERE sites.valid = true"#,
+    )?;
+
+    let rows = statement.query_map([], |row| {
+        Ok(SiteFeed {
+            id: row.get(0)?,
+            url: row.get(1)?,
+            etag: row.get(2)?,
+            last_modified: row.get(3)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+pub fn update_feed_cache(
+    pool: &Pool,
+    feed_id: u32,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"UPDATE site_feeds SET etag = ?, last_modified = ?, fetched = DATETIME()
+           WHERE id = ?"#,
+        params![etag, last_modified, feed_id],
+    )?;
+
+    Ok(())
+}
+
+/// Replace a feed's stored posts with `posts`, its newest.
+pub fn store_planet_posts(
+    pool: &Pool,
+    feed_id: u32,
+    posts: &[PlanetPost],
+) -> Result<(), Box<dyn Error>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    tx.execute_cached(
+        r#"DELETE FROM planet_posts WHERE feed_id = ?"#,
+        params![feed_id],
+    )?;
+    for post in posts {
+        tx.execute_cached(
+            r#"INSERT OR IGNORE INTO planet_posts (feed_id, guid, title, link, published)
+               VALUES (?, ?, ?, ?, ?)"#,
+            params![feed_id, post.guid, post.title, post.link, post.published],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// The newest posts across listed members' feeds.
+pub fn get_planet_posts(pool: &Pool, limit: usize) -> Result<Vec<PlanetPost>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT planet_posts.guid, planet_posts.title, planet_posts.link,
+                  planet_posts.published, site_ids.url
+           FROM planet_posts
+           JOIN site_feeds ON site_feeds.id = planet_posts.feed_id
+           JOIN site_ids ON site_ids.id = site_feeds.site_id
+           JOIN sites ON sites.id = site_feeds.site_id
+           WHERE sites.valid = true
+           ORDER BY planet_posts.published DESC LIMIT ?"#,
+    )?;
+
+    let rows = statement.query_map([&limit], |row| {
+        Ok(PlanetPost {
+            guid: row.get(0)?,
+            title: row.get(1)?,
+            link: row.get(2)?,
+            published: row.get(3)?,
+            site_url: row.get(4)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Replace the members imported from `peer` with `sites`.
+pub fn replace_peer_sites(
+    pool: &Pool,
+    peer: &str,
+    sites: &[PeerSite],
+) -> Result<(), Box<dyn Error>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    tx.execute(r#"DELETE FROM peer_sites WHERE peer = ?"#, params![peer])?;
+    {
+        // Pages shift as the peer lists new sites, so a site can be seen twice.
+        let mut statement = tx.prepare_cached(
+            r#"INSERT OR IGNORE INTO peer_sites (peer, url, size, fetched)
+               VALUES (?, ?, ?, DATETIME())"#,
+        )?;
+        for site in sites {
+            statement.execute(params![peer, site.url, site.size_bytes as f64])?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Members imported from peers that aren't listed here, smallest first.
+pub fn get_peer_sites(pool: &Pool) -> Result<Vec<PeerSite>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT peer, url, size FROM peer_sites
+           WHERE url NOT IN (SELECT site_ids.url FROM site_ids
+                             JOIN sites ON sites.id = site_ids.id
+                             WHERE sites.valid = true)
+           ORDER BY size, url"#,
+    )?;
+
+    let rows = statement.query_map([], |row| {
+        let size: f64 = row.get(2)?;
+        Ok(PeerSite {
+            peer: row.get(0)?,
+            url: row.get(1)?,
+            size_bytes: size as u64,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+pub fn store_webmention(
+    pool: &Pool,
+    site_id: u32,
+    source: &str,
+    title: Option<&str>,
+) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT OR REPLACE INTO webmentions (site_id, source, title, verified)
+           VALUES (?, ?, ?, DATETIME())"#,
+        params![site_id, source, title],
+    )?;
+
+    Ok(())
+}
+
+pub fn delete_webmention(pool: &Pool, site_id: u32, source: &str) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"DELETE FROM webmentions WHERE site_id = ? AND source = ?"#,
+        params![site_id, source],
+    )?;
+
+    Ok(())
+}
+
+pub fn get_webmentions(pool: &Pool, site_id: u32) -> Result<Vec<Webmention>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT source, title, verified FROM webmentions WHERE site_id = ?
+           ORDER BY verified DESC"#,
+    )?;
+
+    let rows = statement.query_map([&site_id], |row| {
+        Ok(Webmention {
+            source: row.get(0)?,
+            title: row.get(1)?,
+            verified: row.get(2)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// What the spam filter has learned about a set of tokens.
+#[derive(Debug)]
+pub struct SpamCounts {
+    pub spam_documents: u32,
+    pub ham_documents: u32,
+    /// (spam, ham) counts for each token seen before; unseen tokens are left out.
+    pub tokens: Vec<(u32, u32)>,
+}
+
+pub fn get_spam_counts(pool: &Pool, tokens: &[String]) -> Result<SpamCounts, TenKbError> {
+    let conn = pool.get()?;
+    let documents = |label: &str| -> Result<u32, TenKbError> {
+        let mut statement =
+            conn.prepare_cached(r#"SELECT documents FROM spam_training WHERE label = ?"#)?;
+        let mut rows = statement.query_map([label], |row| row.get(0))?;
+        Ok(rows.next().transpose()?.unwrap_or(0))
+    };
+    let (spam_documents, ham_documents) = (documents("spam")?, documents("ham")?);
+
+    let mut statement =
+        conn.prepare_cached(r#"SELECT spam, ham FROM spam_tokens WHERE token = ?"#)?;
+    let mut counts = vec![];
+    for token in tokens {
+        let mut rows = statement.query_map([token], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        if let Some(count) = rows.next().transpose()? {
+            counts.push(count);
+        }
+    }
+
+    Ok(SpamCounts {
+        spam_documents,
+        ham_documents,
+        tokens: counts,
+    })
+}
+
+/// Count one more spam (or ham) document containing `tokens`.
+pub fn train_spam_tokens(pool: &Pool, tokens: &[String], spam: bool) -> Result<(), TenKbError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let (label, column) = if spam {
+        ("spam", "spam")
+    } else {
+        ("ham", "ham")
+    };
+
+    tx.execute_cached(
+        r#"INSERT INTO spam_training (label, documents) VALUES (?, 1)
+           ON CONFLICT(label) DO UPDATE SET documents = documents + 1"#,
+        params![label],
+    )?;
+
+    {
+        let mut statement = tx.prepare_cached(&format!(
+            r#"INSERT INTO spam_tokens (token, {column}) VALUES (?, 1)
+               ON CONFLICT(token) DO UPDATE SET {column} = {column} + 1"#
+        ))?;
+        for token in tokens {
+            statement.execute([token])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn record_spam_score(
+    pool: &Pool,
+    kind: &str,
+    subject: &str,
+    score: f64,
+) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT INTO spam_scores (kind, subject, score, scored) VALUES (?, ?, ?, DATETIME())"#,
+        params![kind, subject, score],
+    )?;
+
+    Ok(())
+}
+
+/// The most recent scores at or above `threshold`, newest first.
+pub fn get_spam_scores(
+    pool: &Pool,
+    threshold: f64,
+    limit: u32,
+) -> Result<Vec<SpamScore>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT kind, subject, score, scored FROM spam_scores WHERE score >= ?
+           ORDER BY scored DESC LIMIT ?"#,
+    )?;
+
+    let rows = statement.query_map(params![threshold, limit], |row| {
+        Ok(SpamScore {
+            kind: row.get(0)?,
+            subject: row.get(1)?,
+            score: row.get(2)?,
+            scored: row.get(3)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Moderation state of a comment, as stored in `comments.status`.
+#[derive(Copy, Clone, Debug)]
+pub enum CommentStatus {
+    Pending,
+    Approved,
+    Rejected,
+    /// From a flagged voter or IP: shown only to its author until an admin reviews it.
+    Shadowed,
+}
+
+impl CommentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommentStatus::Pending => "pending",
+            CommentStatus::Approved => "approved",
+            CommentStatus::Rejected => "rejected",
+            CommentStatus::Shadowed => "shadowed",
+        }
+    }
+}
+
+/// What a shadow flag matches: a voter number (as shown on comments) or a client IP.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadowKind {
+    Voter,
+    Ip,
+}
+
+impl ShadowKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShadowKind::Voter => "voter",
+            ShadowKind::Ip => "ip",
+        }
+    }
+}
+
+/// Add a comment to a listed site's thread.  Returns `None` if the voter id is unknown.
+pub fn add_comment(
+    pool: &Pool,
+    site_id: u32,
+    voter_id: &str,
+    body: &str,
+    status: CommentStatus,
+) -> Result<Option<u32>, TenKbError> {
+    let conn = pool.get()?;
+    let inserted = conn.execute_cached(
+        r#"INSERT INTO comments (site_id, voter_id, body, status, posted)
+           SELECT ?, id, ?, ?, DATETIME() FROM voter_ids WHERE uuid = ?"#,
+        params![site_id, body, status.as_str(), voter_id],
+    )?;
+
+    Ok((inserted > 0).then(|| conn.last_insert_rowid() as u32))
+}
+
+fn comment_from_row(row: &rusqlite::Row) -> rusqlite::Result<Comment> {
+    Ok(Comment {
+        id: row.get(0)?,
+        site_id: row.get(1)?,
+        commenter: row.get(2)?,
+        body: row.get(3)?,
+        status: row.get(4)?,
+        posted: row.get(5)?,
+    })
+}
+
+/// A site's approved comments, oldest first.
+pub fn get_comments(pool: &Pool, site_id: u32) -> Result<Vec<Comment>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT id, site_id, voter_id, body, status, posted FROM comments
+           WHERE site_id = ? AND status = 'approved' ORDER BY posted, id"#,
+    )?;
+
+    let rows = statement.query_map([&site_id], comment_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// A voter's own shadowed comments on a site, which only they are shown.
+pub fn get_shadowed_comments(
+    pool: &Pool,
+    site_id: u32,
+    voter_id: &str,
+) -> Result<Vec<Comment>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT id, site_id, voter_id, body, status, posted FROM comments
+           WHERE site_id = ? AND status = 'shadowed'
+             AND voter_id = (SELECT id FROM voter_ids WHERE uuid = ?)
+           ORDER BY posted, id"#,
+    )?;
+
+    let rows = statement.query_map(params![site_id, voter_id], comment_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Comments for the admin dashboard: everything awaiting moderation or shadowed, then the
+/// most recent approved ones so they can still be taken down.
+pub fn get_moderation_queue(pool: &Pool, limit: u32) -> Result<Vec<Comment>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT id, site_id, voter_id, body, status, posted FROM comments
+           WHERE status != 'rejected'
+           ORDER BY status IN ('pending', 'shadowed') DESC, posted DESC, id DESC LIMIT ?"#,
+    )?;
+
+    let rows = statement.query_map([&limit], comment_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+pub fn get_comment_body(pool: &Pool, id: u32) -> Result<Option<String>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(r#"SELECT body FROM comments WHERE id = ?"#)?;
+    let mut rows = statement.query_map([&id], |row| row.get(0))?;
+    Ok(rows.next().transpose()?)
+}
+
+/// Set a comment's moderation state.  Returns false if there is no such comment.
+pub fn moderate_comment(pool: &Pool, id: u32, status: CommentStatus) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.execute_cached(
+        r#"UPDATE comments SET status = ? WHERE id = ?"#,
+        params![status.as_str(), id],
+    )? > 0)
+}
+
+/// Whether a webmention was sent to `target` in the last `hours` hours.
+pub fn webmention_sent_recently(pool: &Pool, target: &str, hours: u32) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    let count: u32 = conn.query_row_cached(
+        r#"SELECT COUNT(*) FROM webmentions_sent
+           WHERE target = ? AND sent > DATETIME('now', '-' || ? || ' hours')"#,
+        params![target, hours],
+        |row| row.get(0),
+    )?;
+
+    Ok(count > 0)
+}
+
+pub fn record_webmention_sent(
+    pool: &Pool,
+    site_id: u32,
+    target: &str,
+    reason: &str,
+    endpoint: Option<&str>,
+    result: &str,
+) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT INTO webmentions_sent (site_id, target, reason, endpoint, result, sent)
+           VALUES (?, ?, ?, ?, ?, DATETIME())"#,
+        params![site_id, target, reason, endpoint, result],
+    )?;
 
-    statement.execute(params![&site_id, &voter_id])?;
     Ok(())
 }
 
-pub fn get_votes(pool: web::Data<Pool>, voter_id: String) -> Result<Vec<u32>, TenKbError> {
-    let query = r#"SELECT * FROM votes
-                   WHERE voter_id = (SELECT id FROM voter_ids WHERE uuid = ?);"#;
+/// The most recent webmentions sent, newest first.
+pub fn get_sent_webmentions(pool: &Pool, limit: u32) -> Result<Vec<SentWebmention>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT site_id, target, reason, endpoint, result, sent FROM webmentions_sent
+           ORDER BY sent DESC, id DESC LIMIT ?"#,
+    )?;
 
-    let conn = pool.clone().get()?;
-    let mut statement = conn.prepare(query)?;
+    let rows = statement.query_map([&limit], |row| {
+        Ok(SentWebmention {
+            site_id: row.get(0)?,
+            target: row.get(1)?,
+            reason: row.get(2)?,
+            endpoint: row.get(3)?,
+            result: row.get(4)?,
+            sent: row.get(5)?,
+        })
+    })?;
 
-    let rows = statement.query_map([&voter_id], |row| row.get::<usize, u32>(0))?;
-    Ok(rows.filter_map(Result::ok).collect::<Vec<u32>>())
+    Ok(rows.filter_map(Result::ok).collect())
 }
 
-pub fn get_validation_queue(pool: &Pool) -> Result<Vec<String>, Box<dyn Error>> {
-    let conn = pool.clone().get()?;
-
-    let db_query = r#"SELECT site_ids.url FROM site_ids LEFT JOIN validation_queue
-                      WHERE site_ids.id = validation_queue.id AND validation_queue.scan = true"#;
+pub fn store_favicon(pool: &Pool, site_id: u32, favicon: &Favicon) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT OR REPLACE INTO favicons (site_id, content_type, data, fetched)
+           VALUES (?, ?, ?, DATETIME())"#,
+        params![site_id, favicon.content_type, favicon.data],
+    )?;
 
-    let mut statement = conn.prepare(db_query)?;
-    let rows = statement.query_map([], |row| row.get::<usize, String>(0))?;
-    Ok(rows.filter_map(Result::ok).collect::<Vec<String>>())
+    Ok(())
 }
 
-pub fn mark_bad(pool: &Pool, site: &str) -> Result<(), Box<dyn Error>> {
-    let conn = pool.clone().get()?;
-    conn.execute(
-        r#"UPDATE validation_queue SET scan = false
-           WHERE id = (SELECT id FROM site_ids WHERE url = ?)"#,
-        params![site],
+/// The stored favicon for a listed site and when it was fetched.
+pub fn get_favicon(pool: &Pool, site_id: u32) -> Result<Option<(Favicon, String)>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT favicons.content_type, favicons.data, favicons.fetched FROM favicons
+           JOIN sites ON sites.id = favicons.site_id
+           WHERE favicons.site_id = ? AND sites.valid = true"#,
     )?;
 
-    Ok(())
+    let mut rows = statement.query_map([&site_id], |row| {
+        Ok((
+            Favicon {
+                content_type: row.get(0)?,
+                data: row.get(1)?,
+            },
+            row.get(2)?,
+        ))
+    })?;
+
+    Ok(rows.next().transpose()?)
 }
 
 pub fn mark_bad_size(pool: &Pool, site: &str, size: f64) -> Result<(), Box<dyn Error>> {
@@ -292,30 +2290,248 @@ pub fn mark_bad_size(pool: &Pool, site: &str, size: f64) -> Result<(), Box<dyn E
         site,
         format!("size validation failed: site is {size} bytes"),
     )?;
+    record_attempt(pool, site, ValidationOutcome::FailedSize, Some(size), None)?;
 
     let conn = pool.clone().get()?;
-    conn.execute(
-        r#"UPDATE validation_queue SET scan = false WHERE id = (SELECT id from site_ids WHERE url = ?)"#,
-        params![site],
+    conn.execute_cached(
+        r#"UPDATE validation_queue SET outcome = ?
+           WHERE id = (SELECT id from site_ids WHERE url = ?)"#,
+        params![ValidationOutcome::FailedSize.as_str(), site],
     )?;
     Ok(())
 }
 
-pub fn mark_good(pool: &Pool, site: &str, size: f64) -> Result<(), Box<dyn Error>> {
-    let pool = pool.clone();
-    let conn = pool.clone().get()?;
-    conn.execute(
-        r#"DELETE from validation_queue WHERE id = (SELECT id FROM site_ids WHERE url = ?)"#,
+/// Move a site from the validation queue into the listing, returning its id.
+/// List a site that passed validation, along with the related links found for it.  It all
+/// happens in one transaction, so a listed site always has its links.  `mobile_size` is
+/// its size on a phone, if that was measured.
+pub fn mark_good(
+    pool: &Pool,
+    site: &str,
+    size: f64,
+    mobile_size: Option<f64>,
+    related: &[RelatedLink],
+) -> Result<u32, Box<dyn Error>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let id: u32 = tx.query_row_cached(
+        r#"SELECT id FROM site_ids WHERE url = ?"#,
         params![site],
+        |row| row.get(0),
     )?;
 
-    conn.execute(
-        r#"INSERT INTO sites (id, date_added, size, valid)
-          VALUES((SELECT id FROM site_ids WHERE url = ?), DATETIME(), ?, true);"#,
-        params![site, size],
+    insert_attempt(&tx, site, ValidationOutcome::Passed, Some(size), None)?;
+    tx.execute_cached(r#"DELETE from validation_queue WHERE id = ?"#, params![id])?;
+
+    tx.execute_cached(
+        r#"INSERT INTO sites (id, date_added, size, mobile_size, valid)
+          VALUES(?, DATETIME(), ?, ?, true);"#,
+        params![id, size, mobile_size],
     )?;
+    insert_size(&tx, id, Some(size), SizeCheck::Listing, true)?;
+    replace_related(&tx, id, related)?;
 
-    Ok(())
+    tx.commit()?;
+    Ok(id)
+}
+
+/// Details of a listed site, or `None` if no listed site has this id.
+pub fn get_site_detail(pool: &Pool, id: u32) -> Result<Option<SiteDetail>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(&format!(
+        r#"SELECT site_ids.url, sites.size, sites.date_added,
+                  CAST((JULIANDAY('now') - JULIANDAY(sites.date_added)) / 365.25 AS INTEGER),
+                  sites.note,
+                  EXISTS (SELECT 1 FROM screenshots WHERE screenshots.site_id = site_ids.id),
+                  {SITE_SLUG}, sites.mobile_size
+           FROM site_ids JOIN sites ON site_ids.id = sites.id
+           WHERE site_ids.id = ? AND sites.valid = true"#
+    ))?;
+
+    let mut rows = statement.query_map([&id], |row| {
+        let size: f64 = row.get(1)?;
+        Ok((
+            row.get(0)?,
+            size as u64,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+            row.get::<_, Option<f64>>(7)?.map(|size| size as u64),
+        ))
+    })?;
+
+    let Some((url, size_bytes, date_added, member_years, note, thumbnail, slug, mobile_size_bytes)) =
+        rows.next().transpose()?
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(SiteDetail {
+        id,
+        slug,
+        url,
+        size_bytes,
+        mobile_size_bytes,
+        votes: get_vote_count(pool, id)?,
+        date_added,
+        member_years,
+        note,
+        thumbnail,
+        size_history: get_size_history_summary(pool, id)?,
+        uptime: get_uptime_summary(pool, id, UPTIME_DAYS)?,
+        related: get_related(pool, id)?,
+        mentions: get_webmentions(pool, id)?,
+        snapshots: get_snapshots(pool, id)?,
+        aliases: get_site_aliases(pool, id)?,
+    }))
+}
+
+/// Everything stored about site `id` for its owner, or `None` if there is no such site.
+pub fn get_site_record(pool: &Pool, id: u32) -> Result<Option<SiteRecord>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(&format!(
+        r#"SELECT site_ids.url, {SITE_SLUG},
+                  (SELECT outcome FROM validation_queue WHERE validation_queue.id = site_ids.id)
+           FROM site_ids WHERE site_ids.id = ?"#
+    ))?;
+    let Some((url, slug, queue_outcome)) = statement
+        .query_map([id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .next()
+        .transpose()?
+    else {
+        return Ok(None);
+    };
+
+    let mut statement = conn.prepare_cached(
+        r#"SELECT COALESCE(valid, false), COALESCE(banned, false), size, mobile_size,
+                  date_added, note
+           FROM sites WHERE id = ?"#,
+    )?;
+    let listing = statement
+        .query_map([id], |row| {
+            Ok(Listing {
+                listed: row.get(0)?,
+                banned: row.get(1)?,
+                size: row.get(2)?,
+                mobile_size: row.get(3)?,
+                date_added: row.get(4)?,
+                note: row.get(5)?,
+            })
+        })?
+        .next()
+        .transpose()?;
+
+    let mut statement = conn.prepare_cached(
+        r#"SELECT timestamp, comment FROM validation_log WHERE id = ?
+           ORDER BY timestamp DESC, rowid DESC"#,
+    )?;
+    let validation_log = statement
+        .query_map([id], |row| {
+            Ok(LogEntry {
+                timestamp: row.get(0)?,
+                comment: row.get(1)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut statement = conn.prepare_cached(
+        r#"SELECT size, measured, kind, passed FROM size_history WHERE site_id = ?
+           ORDER BY measured DESC, rowid DESC"#,
+    )?;
+    let size_history = statement
+        .query_map([id], |row| {
+            Ok(Measurement {
+                size: row.get(0)?,
+                measured: row.get(1)?,
+                kind: row.get(2)?,
+                passed: row.get(3)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut statement = conn.prepare_cached(
+        r#"SELECT country, size, scanned FROM regional_sizes WHERE site_id = ?
+           ORDER BY scanned DESC, country"#,
+    )?;
+    let regional_sizes = statement
+        .query_map([id], |row| {
+            Ok(RegionalSize {
+                country: row.get(0)?,
+                size: row.get(1)?,
+                scanned: row.get(2)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    let mut statement = conn.prepare_cached(
+        r#"SELECT checked, up, detail FROM uptime_checks WHERE site_id = ?
+           ORDER BY checked DESC, rowid DESC"#,
+    )?;
+    let uptime_checks = statement
+        .query_map([id], |row| {
+            Ok(UptimeCheck {
+                checked: row.get(0)?,
+                up: row.get(1)?,
+                detail: row.get(2)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(Some(SiteRecord {
+        id,
+        slug,
+        url,
+        listing,
+        queue_outcome,
+        votes: get_vote_count(pool, id)?,
+        aliases: get_site_aliases(pool, id)?,
+        related: get_related(pool, id)?,
+        validation_attempts: get_validation_attempts(pool, id)?,
+        validation_log,
+        size_history,
+        regional_sizes,
+        uptime_checks,
+    }))
+}
+
+pub fn get_size_history_summary(pool: &Pool, id: u32) -> Result<SizeHistorySummary, TenKbError> {
+    let conn = pool.get()?;
+    let (measurements, smallest, largest, last_measured) = conn.query_row_cached(
+        r#"SELECT COUNT(*), MIN(size), MAX(size), MAX(measured) FROM size_history
+           WHERE site_id = ?"#,
+        params![id],
+        |row| {
+            Ok((
+                row.get(0)?,
+                row.get::<_, Option<f64>>(1)?,
+                row.get::<_, Option<f64>>(2)?,
+                row.get(3)?,
+            ))
+        },
+    )?;
+
+    let last_passed = conn
+        .query_row_cached(
+            r#"SELECT passed FROM size_history WHERE site_id = ?
+               ORDER BY measured DESC, rowid DESC LIMIT 1"#,
+            params![id],
+            |row| row.get(0),
+        )
+        .ok();
+
+    Ok(SizeHistorySummary {
+        measurements,
+        smallest_bytes: smallest.map(|size| size as u64),
+        largest_bytes: largest.map(|size| size as u64),
+        last_measured,
+        last_passed,
+    })
 }
 
 pub fn get_related(pool: &Pool, site: u32) -> Result<Vec<RelatedLink>, TenKbError> {
@@ -324,7 +2540,7 @@ pub fn get_related(pool: &Pool, site: u32) -> Result<Vec<RelatedLink>, TenKbErro
     let db_query =
         r#"SELECT url, discussion_url, date, title, score, comments FROM related WHERE ID = ?"#;
 
-    let mut statement = conn.prepare(db_query)?;
+    let mut statement = conn.prepare_cached(db_query)?;
 
     let rows = statement.query_map([&site], |row| {
         Ok(RelatedLink {
@@ -343,38 +2559,184 @@ pub fn get_related(pool: &Pool, site: u32) -> Result<Vec<RelatedLink>, TenKbErro
 pub fn update_related(
     pool: &Pool,
     site: &str,
-    related: Vec<RelatedLink>,
+    related: &[RelatedLink],
 ) -> Result<(), Box<dyn Error>> {
-    let pool = pool.clone();
-    let conn = pool.clone().get()?;
-    conn.execute(
-        r#"DELETE from related WHERE id = (SELECT id from site_ids WHERE url = ?);"#,
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let id: u32 = tx.query_row_cached(
+        r#"SELECT id FROM site_ids WHERE url = ?"#,
         params![site],
+        |row| row.get(0),
     )?;
 
+    replace_related(&tx, id, related)?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+fn replace_related(conn: &Connection, id: u32, related: &[RelatedLink]) -> rusqlite::Result<()> {
+    conn.execute_cached(r#"DELETE from related WHERE id = ?;"#, params![id])?;
+
+    let mut statement =
+        conn.prepare_cached(r#"INSERT INTO related VALUES (?, ?, ?, ?, ?, ?, ?);"#)?;
     for link in related {
-        conn.execute(
-            r#"INSERT INTO related
-               VALUES ((SELECT id FROM site_ids WHERE url = ?), ?, ?, ?, ?, ?, ?);"#,
-            params![
-                site,
-                link.url,
-                link.discussion_url,
-                link.date,
-                link.description,
-                link.upvotes,
-                link.comments,
-            ],
-        )?;
+        statement.execute(params![
+            id,
+            link.url,
+            link.discussion_url,
+            link.date,
+            link.description,
+            link.upvotes,
+            link.comments,
+        ])?;
     }
 
+    // Approved suggestions aren't harvested, so put them back.
+    conn.execute_cached(
+        r#"INSERT OR IGNORE INTO related
+           SELECT site_id, url, discussion_url, suggested, title, 0, 0 FROM related_suggestions
+           WHERE site_id = ? AND status = 'approved'"#,
+        params![id],
+    )?;
+    conn.execute_cached(
+        r#"INSERT INTO related_refreshes (site_id, refreshed, requested) VALUES (?, DATETIME(), NULL)
+           ON CONFLICT(site_id) DO UPDATE SET refreshed = DATETIME(), requested = NULL"#,
+        params![id],
+    )?;
+
     Ok(())
 }
 
+/// When a site's related links were last harvested, and when a refresh was asked for if
+/// one is still waiting.
+pub fn get_related_refresh(
+    pool: &Pool,
+    site: u32,
+) -> Result<(Option<String>, Option<String>), TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT refreshed, requested FROM related_refreshes WHERE site_id = ?"#,
+    )?;
+    let row = statement
+        .query_map([site], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .next()
+        .transpose()?;
+    Ok(row.unwrap_or((None, None)))
+}
+
+/// Ask the analyzer to harvest a site's related links again.  Returns false if a refresh
+/// is already waiting.
+pub fn request_related_refresh(pool: &Pool, site: u32) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.execute_cached(
+        r#"INSERT INTO related_refreshes (site_id, refreshed, requested) VALUES (?, NULL, DATETIME())
+           ON CONFLICT(site_id) DO UPDATE SET requested = DATETIME() WHERE requested IS NULL"#,
+        params![site],
+    )? > 0)
+}
+
+/// Sites waiting for their related links to be refreshed, in the order they were asked for.
+pub fn get_requested_refreshes(pool: &Pool) -> Result<Vec<String>, Box<dyn Error>> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT site_ids.url FROM related_refreshes
+           JOIN site_ids ON site_ids.id = related_refreshes.site_id
+           WHERE requested IS NOT NULL ORDER BY requested"#,
+    )?;
+    let rows = statement.query_map([], |row| row.get(0))?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Queue a verified discussion suggestion for moderation.  Returns false if it was already
+/// suggested for this site, or is already listed.
+pub fn add_discussion_suggestion(
+    pool: &Pool,
+    site_id: u32,
+    discussion_url: &str,
+    discussion: &Discussion,
+    client_ip: &str,
+) -> Result<bool, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.execute_cached(
+        r#"INSERT OR IGNORE INTO related_suggestions
+               (site_id, discussion_url, url, title, status, client_ip, suggested)
+           SELECT ?, ?, ?, ?, 'pending', ?, DATETIME()
+           WHERE NOT EXISTS (SELECT 1 FROM related WHERE discussion_url = ?)"#,
+        params![
+            site_id,
+            discussion_url,
+            discussion.url,
+            discussion.title,
+            client_ip,
+            discussion_url
+        ],
+    )? > 0)
+}
+
+/// Suggestions awaiting moderation, oldest first.
+pub fn get_discussion_suggestions(
+    pool: &Pool,
+    limit: usize,
+) -> Result<Vec<DiscussionSuggestion>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT related_suggestions.id, site_id, site_ids.url, discussion_url,
+                  related_suggestions.url, title, suggested
+           FROM related_suggestions JOIN site_ids ON site_ids.id = related_suggestions.site_id
+           WHERE status = 'pending' ORDER BY suggested LIMIT ?"#,
+    )?;
+
+    let rows = statement.query_map([limit], |row| {
+        Ok(DiscussionSuggestion {
+            id: row.get(0)?,
+            site_id: row.get(1)?,
+            site_url: row.get(2)?,
+            discussion_url: row.get(3)?,
+            url: row.get(4)?,
+            title: row.get(5)?,
+            suggested: row.get(6)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Approve a pending suggestion, listing it in `related`, or reject it.  Returns false if
+/// there was no such pending suggestion.
+pub fn moderate_discussion_suggestion(
+    pool: &Pool,
+    suggestion: u32,
+    approve: bool,
+) -> Result<bool, TenKbError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    if tx.execute_cached(
+        r#"UPDATE related_suggestions SET status = ? WHERE id = ? AND status = 'pending'"#,
+        params![if approve { "approved" } else { "rejected" }, suggestion],
+    )? == 0
+    {
+        return Ok(false);
+    }
+
+    if approve {
+        tx.execute_cached(
+            r#"INSERT OR IGNORE INTO related
+               SELECT site_id, url, discussion_url, suggested, title, 0, 0
+               FROM related_suggestions WHERE id = ?"#,
+            params![suggestion],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(true)
+}
+
 pub fn log_validation_failure(pool: &Pool, site: &str, msg: String) -> Result<(), Box<dyn Error>> {
     let pool = pool.clone();
     let conn = pool.clone().get()?;
-    conn.execute(
+    conn.execute_cached(
         r#"INSERT INTO validation_log
            VALUES ((SELECT id FROM site_ids WHERE url = ?), DATETIME(), ?)"#,
         params![site, msg],
@@ -382,3 +2744,500 @@ pub fn log_validation_failure(pool: &Pool, site: &str, msg: String) -> Result<()
 
     Ok(())
 }
+
+pub fn record_attempt(
+    pool: &Pool,
+    site: &str,
+    outcome: ValidationOutcome,
+    size: Option<f64>,
+    detail: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    insert_attempt(&conn, site, outcome, size, detail)?;
+    Ok(())
+}
+
+fn insert_attempt(
+    conn: &Connection,
+    site: &str,
+    outcome: ValidationOutcome,
+    size: Option<f64>,
+    detail: Option<&str>,
+) -> rusqlite::Result<()> {
+    conn.execute_cached(
+        r#"INSERT INTO validation_attempts (site_id, outcome, size, detail, attempted)
+           VALUES ((SELECT id FROM site_ids WHERE url = ?), ?, ?, ?, DATETIME())"#,
+        params![site, outcome.as_str(), size, detail],
+    )?;
+
+    Ok(())
+}
+
+pub fn record_size(
+    pool: &Pool,
+    site_id: u32,
+    size: Option<f64>,
+    check: SizeCheck,
+    passed: bool,
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    insert_size(&conn, site_id, size, check, passed)?;
+    Ok(())
+}
+
+fn insert_size(
+    conn: &Connection,
+    site_id: u32,
+    size: Option<f64>,
+    check: SizeCheck,
+    passed: bool,
+) -> rusqlite::Result<()> {
+    conn.execute_cached(
+        r#"INSERT INTO size_history (site_id, size, measured, kind, passed)
+           VALUES (?, ?, DATETIME(), ?, ?)"#,
+        params![site_id, size, check.as_str(), passed],
+    )?;
+
+    Ok(())
+}
+
+/// Listed sites that haven't been measured for a year, oldest first.
+pub fn get_recertification_due(pool: &Pool) -> Result<Vec<(u32, String)>, Box<dyn Error>> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT site_ids.id, site_ids.url,
+                  COALESCE((SELECT MAX(measured) FROM size_history
+                            WHERE size_history.site_id = site_ids.id),
+                           sites.date_added) AS checked
+           FROM site_ids JOIN sites ON site_ids.id = sites.id
+           WHERE sites.valid = true AND checked < DATETIME('now', '-1 year')
+           ORDER BY checked"#,
+    )?;
+
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Record a passing recertification and bring the listed size up to date.
+pub fn recertify(
+    pool: &Pool,
+    site_id: u32,
+    size: f64,
+    mobile_size: Option<f64>,
+) -> Result<(), Box<dyn Error>> {
+    record_size(pool, site_id, Some(size), SizeCheck::Recertification, true)?;
+
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"UPDATE sites SET size = ?, mobile_size = COALESCE(?, mobile_size)
+           WHERE id = ? AND valid = true"#,
+        params![size, mobile_size, site_id],
+    )?;
+
+    Ok(())
+}
+
+/// Every validation attempt for a site, newest first.
+pub fn get_validation_attempts(
+    pool: &Pool,
+    site_id: u32,
+) -> Result<Vec<ValidationAttempt>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT outcome, size, detail, attempted FROM validation_attempts
+           WHERE site_id = ? ORDER BY attempted DESC, id DESC"#,
+    )?;
+
+    let rows = statement.query_map([&site_id], |row| {
+        let outcome: String = row.get(0)?;
+        Ok(ValidationAttempt {
+            outcome: outcome.parse().unwrap_or(ValidationOutcome::FailedError),
+            size: row.get(1)?,
+            detail: row.get(2)?,
+            attempted: row.get(3)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Take (or renew) the named lease for `holder`.  Returns false if another holder owns an
+/// unexpired lease.
+pub fn acquire_lease(
+    pool: &Pool,
+    name: &str,
+    holder: &str,
+    ttl_secs: u32,
+) -> Result<bool, Box<dyn Error>> {
+    let conn = pool.clone().get()?;
+    let changed = conn.execute_cached(
+        r#"INSERT INTO job_leases (name, holder, expires)
+           VALUES (?1, ?2, DATETIME('now', ?3))
+           ON CONFLICT(name) DO UPDATE SET holder = excluded.holder, expires = excluded.expires
+           WHERE job_leases.expires < DATETIME() OR job_leases.holder = excluded.holder;"#,
+        params![name, holder, format!("+{ttl_secs} seconds")],
+    )?;
+
+    Ok(changed == 1)
+}
+
+pub fn release_lease(pool: &Pool, name: &str, holder: &str) -> Result<(), Box<dyn Error>> {
+    let conn = pool.clone().get()?;
+    conn.execute_cached(
+        r#"DELETE FROM job_leases WHERE name = ? AND holder = ?;"#,
+        params![name, holder],
+    )?;
+
+    Ok(())
+}
+
+/// Return a cached scan of `url` if one was recorded within the last `ttl_secs` seconds.
+pub fn get_cached_scan(
+    pool: &Pool,
+    url: &str,
+    ttl_secs: u64,
+) -> Result<Option<UrlScan>, Box<dyn Error>> {
+    let conn = pool.clone().get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT size, acceptable, malicious, scan_id FROM scan_cache
+           WHERE url = ? AND scanned > DATETIME('now', ?);"#,
+    )?;
+
+    let mut rows = statement.query_map(params![url, format!("-{ttl_secs} seconds")], |row| {
+        Ok(UrlScan {
+            size: row.get(0)?,
+            acceptable: row.get(1)?,
+            malicious: row.get(2)?,
+            scan_id: row.get(3)?,
+        })
+    })?;
+
+    Ok(rows.next().transpose()?)
+}
+
+pub fn cache_scan(pool: &Pool, url: &str, scan: &UrlScan) -> Result<(), Box<dyn Error>> {
+    let conn = pool.clone().get()?;
+    conn.execute_cached(
+        r#"INSERT INTO scan_cache (url, size, acceptable, malicious, scan_id, scanned)
+           VALUES (?, ?, ?, ?, ?, DATETIME())
+           ON CONFLICT(url) DO UPDATE SET size = excluded.size,
+                                          acceptable = excluded.acceptable,
+                                          malicious = excluded.malicious,
+                                          scan_id = excluded.scan_id,
+                                          scanned = excluded.scanned;"#,
+        params![
+            url,
+            scan.size,
+            scan.acceptable,
+            scan.malicious,
+            scan.scan_id
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// The size of site `site_id` as last scanned from `country`.
+pub fn store_regional_size(
+    pool: &Pool,
+    site_id: u32,
+    country: &str,
+    size: f64,
+) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT INTO regional_sizes (site_id, country, size, scanned)
+           VALUES (?, ?, ?, DATETIME())
+           ON CONFLICT(site_id, country) DO UPDATE SET size = excluded.size,
+                                                      scanned = excluded.scanned"#,
+        params![site_id, country, size],
+    )?;
+
+    Ok(())
+}
+
+/// Listed sites whose largest regional size is over `ratio` times their smallest, most
+/// lopsided first.
+pub fn get_regional_differences(
+    pool: &Pool,
+    ratio: f64,
+    limit: u32,
+) -> Result<Vec<RegionalDifference>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT r.site_id, site_ids.url,
+                  (SELECT country FROM regional_sizes WHERE site_id = r.site_id
+                   ORDER BY size ASC LIMIT 1),
+                  MIN(r.size),
+                  (SELECT country FROM regional_sizes WHERE site_id = r.site_id
+                   ORDER BY size DESC LIMIT 1),
+                  MAX(r.size)
+           FROM regional_sizes r
+           JOIN site_ids ON site_ids.id = r.site_id
+           JOIN sites ON sites.id = r.site_id
+           GROUP BY r.site_id HAVING MAX(r.size) > ? * MIN(r.size)
+           ORDER BY MAX(r.size) / MAX(MIN(r.size), 1) DESC LIMIT ?"#,
+    )?;
+
+    let rows = statement.query_map(params![ratio, limit], |row| {
+        Ok(RegionalDifference {
+            site_id: row.get(0)?,
+            url: row.get(1)?,
+            smallest_country: row.get(2)?,
+            smallest: row.get(3)?,
+            largest_country: row.get(4)?,
+            largest: row.get(5)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// Count one more scan submitted to Cloudflare today.
+pub fn record_scanner_call(pool: &Pool) -> Result<(), Box<dyn Error>> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT INTO scanner_usage (day, scans) VALUES (DATE('now'), 1)
+           ON CONFLICT(day) DO UPDATE SET scans = scans + 1"#,
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// How many scans have been submitted to Cloudflare today.
+pub fn get_scanner_calls_today(pool: &Pool) -> Result<u32, TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.query_row_cached(
+        r#"SELECT COALESCE((SELECT scans FROM scanner_usage WHERE day = DATE('now')), 0)"#,
+        [],
+        |row| row.get(0),
+    )?)
+}
+
+/// Scans submitted to Cloudflare on each of the last `days` days that had any, newest
+/// first.
+pub fn get_scanner_usage(pool: &Pool, days: u32) -> Result<Vec<ScannerUsage>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT day, scans FROM scanner_usage WHERE day > DATE('now', ?)
+           ORDER BY day DESC"#,
+    )?;
+
+    let rows = statement.query_map([format!("-{days} days")], |row| {
+        Ok(ScannerUsage {
+            day: row.get(0)?,
+            scans: row.get(1)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+pub fn create_api_key(
+    pool: &Pool,
+    name: &str,
+    key_hash: &str,
+    quota: u32,
+) -> Result<u32, TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"INSERT INTO api_keys (name, key_hash, quota, created, revoked)
+           VALUES (?, ?, ?, DATETIME(), false)"#,
+        params![name, key_hash, quota],
+    )?;
+    Ok(conn.last_insert_rowid() as u32)
+}
+
+pub fn get_api_key(pool: &Pool, key_hash: &str) -> Result<Option<ApiKey>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT id, name, quota, created, revoked FROM api_keys WHERE key_hash = ?"#,
+    )?;
+
+    let mut rows = statement.query_map(params![key_hash], api_key_from_row)?;
+    Ok(rows.next().transpose()?)
+}
+
+pub fn list_api_keys(pool: &Pool) -> Result<Vec<ApiKey>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT id, name, quota, created, revoked FROM api_keys ORDER BY created DESC"#,
+    )?;
+
+    let rows = statement.query_map([], api_key_from_row)?;
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+pub fn revoke_api_key(pool: &Pool, id: u32) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    conn.execute_cached(
+        r#"UPDATE api_keys SET revoked = true WHERE id = ?"#,
+        params![id],
+    )?;
+    Ok(())
+}
+
+fn api_key_from_row(row: &rusqlite::Row) -> rusqlite::Result<ApiKey> {
+    Ok(ApiKey {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        quota: row.get(2)?,
+        created: row.get(3)?,
+        revoked: row.get(4)?,
+    })
+}
+
+/// The most recently listed sites, newest first.
+pub fn get_newest_sites(pool: &Pool, limit: usize) -> Result<Vec<FeedEntry>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT site_ids.id, site_ids.url, sites.size, sites.date_added
+           FROM site_ids LEFT JOIN sites WHERE site_ids.id = sites.id AND valid = true
+           ORDER BY date_added DESC LIMIT ?"#,
+    )?;
+
+    let rows = statement.query_map(params![limit], |row| {
+        let size: f64 = row.get(2)?;
+        Ok(FeedEntry {
+            id: row.get(0)?,
+            url: row.get(1)?,
+            size_bytes: size as u64,
+            date_added: row.get(3)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+/// When the newest site was validated, and how many sites are listed.
+pub fn get_newest_validation(pool: &Pool) -> Result<(Option<String>, usize), TenKbError> {
+    let conn = pool.get()?;
+    Ok(conn.query_row_cached(
+        r#"SELECT MAX(date_added), COUNT(*) FROM sites WHERE valid = true"#,
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
+
+pub fn add_country_counts(pool: &Pool, counts: &[CountryCount]) -> Result<(), TenKbError> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    {
+        let mut statement = tx.prepare_cached(
+            r#"INSERT INTO country_stats (day, country, visits, votes) VALUES (?, ?, ?, ?)
+               ON CONFLICT(day, country) DO UPDATE
+               SET visits = visits + excluded.visits, votes = votes + excluded.votes"#,
+        )?;
+        for count in counts {
+            statement.execute(params![count.day, count.country, count.visits, count.votes])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Visit and vote totals per country over the last `days` days, busiest first.  `day`
+/// is the start of the range.
+pub fn get_country_counts(pool: &Pool, days: u32) -> Result<Vec<CountryCount>, TenKbError> {
+    let conn = pool.get()?;
+    let mut statement = conn.prepare_cached(
+        r#"SELECT DATE('now', ?), country, SUM(visits) AS visits, SUM(votes)
+           FROM country_stats WHERE day >= DATE('now', ?)
+           GROUP BY country ORDER BY visits DESC"#,
+    )?;
+
+    let range = format!("-{days} days");
+    let rows = statement.query_map(params![range, range], |row| {
+        Ok(CountryCount {
+            day: row.get(0)?,
+            country: row.get(1)?,
+            visits: row.get(2)?,
+            votes: row.get(3)?,
+        })
+    })?;
+
+    Ok(rows.filter_map(Result::ok).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: &str = "https://small.example/";
+    const COPY: &str = "https://www.small.example/";
+
+    /// A listed site, as the analyzer leaves one.
+    fn listed(pool: &Pool, url: &str) -> u32 {
+        submit_site(pool, String::from(url), 0).unwrap();
+        mark_good(pool, url, 4096.0, None, &[]).unwrap()
+    }
+
+    fn votes_for(pool: &Pool, site_id: u32) -> u32 {
+        pool.get()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM votes WHERE id = ?",
+                [site_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn find_site_takes_a_slug_or_a_legacy_id() {
+        let pool = init_db_in_memory();
+        let id = listed(&pool, SMALL);
+        let slug = get_site_slug(&pool, id).unwrap();
+
+        assert_eq!(find_site(&pool, &slug).unwrap(), Some((id, slug.clone())));
+        assert_eq!(find_site(&pool, &id.to_string()).unwrap(), Some((id, slug)));
+        assert_eq!(find_site(&pool, "no-such-slug").unwrap(), None);
+        assert_eq!(find_site(&pool, &(id + 1).to_string()).unwrap(), None);
+    }
+
+    #[test]
+    fn merge_sites_moves_votes_and_keeps_the_old_names() {
+        let pool = init_db_in_memory();
+        let into = listed(&pool, SMALL);
+        let from = listed(&pool, COPY);
+        let (into_slug, from_slug) = (
+            get_site_slug(&pool, into).unwrap(),
+            get_site_slug(&pool, from).unwrap(),
+        );
+
+        for voter in ["both", "copy-only"] {
+            generate_id(&pool, String::from(voter)).unwrap();
+        }
+        cast_vote(&pool, String::from("both"), into, 1, false).unwrap();
+        cast_vote(&pool, String::from("both"), from, 1, false).unwrap();
+        cast_vote(&pool, String::from("copy-only"), from, 1, false).unwrap();
+
+        assert_eq!(
+            merge_sites(&pool, from, into).unwrap(),
+            Some(String::from(COPY))
+        );
+
+        // The voter who voted for both counts once.
+        assert_eq!(votes_for(&pool, into), 2);
+        assert_eq!(votes_for(&pool, from), 0);
+
+        assert_eq!(get_alias_target(&pool, COPY).unwrap(), Some(into));
+        assert_eq!(
+            find_site(&pool, &from_slug).unwrap(),
+            Some((into, into_slug))
+        );
+        assert_eq!(find_site(&pool, &from.to_string()).unwrap(), None);
+        assert!(pool.check_submission(COPY, 0).is_err());
+    }
+
+    #[test]
+    fn merge_sites_needs_both_sites() {
+        let pool = init_db_in_memory();
+        let into = listed(&pool, SMALL);
+
+        assert_eq!(merge_sites(&pool, into + 1, into).unwrap(), None);
+        assert_eq!(merge_sites(&pool, into, into + 1).unwrap(), None);
+        assert!(check_site_active(&pool, &String::from(SMALL)).unwrap());
+    }
+}
@@ -0,0 +1,97 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Discussions of a member site suggested by visitors on its `/site/{slug}/` page.  A
+//! suggestion is only queued for moderation if the discussion page really links to the
+//! site; once an admin approves it, it is listed in `related` with the harvested Hacker News
+//! and Lobsters links, and kept there when those are refreshed.
+
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    favicon::read_limited,
+    webmention::{links, title},
+};
+
+/// A suggestion awaiting moderation.
+#[derive(Debug, Serialize)]
+pub struct DiscussionSuggestion {
+    pub id: u32,
+    pub site_id: u32,
+    pub site_url: String,
+    pub discussion_url: String,
+    /// The page on the member site the discussion links to.
+    pub url: String,
+    pub title: Option<String>,
+    pub suggested: String,
+}
+
+/// What fetching a suggested discussion found.
+#[derive(Debug)]
+pub struct Discussion {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+/// Fetch `discussion` and look for a link to `site` (its host, at or below its path).
+/// `None` if the page has no such link.
+pub async fn verify(
+    discussion: &Url,
+    site: &Url,
+    max_bytes: usize,
+) -> Result<Option<Discussion>, String> {
+    let res = reqwest::get(discussion.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    if !res.status().is_success() {
+        return Err(format!("status code {}", res.status().as_u16()));
+    }
+
+    let (content_type, body) = read_limited(res, max_bytes)
+        .await
+        .map_err(|e| e.to_string())?;
+    if !content_type.is_empty() && !content_type.starts_with("text/") {
+        return Err(format!("unsupported content type '{content_type}'"));
+    }
+
+    let html = String::from_utf8_lossy(&body);
+    let Some(link) = links(&html, discussion).find(|link| references(link, site)) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Discussion {
+        url: link.to_string(),
+        title: title(&html),
+    }))
+}
+
+fn references(link: &Url, site: &Url) -> bool {
+    let bare = |url: &Url| {
+        url.host_str()
+            .map(|host| host.trim_start_matches("www.").to_ascii_lowercase())
+    };
+
+    bare(link).is_some()
+        && bare(link) == bare(site)
+        && link.path().starts_with(site.path().trim_end_matches('/'))
+}
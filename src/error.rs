@@ -23,11 +23,31 @@
 use std::{
     convert::From,
     fmt::{Display, Formatter, Result},
+    sync::OnceLock,
 };
 
-use actix_web::{error::BlockingError, http::StatusCode, HttpResponse, ResponseError};
+use actix_web::{
+    error::{BlockingError, JsonPayloadError, QueryPayloadError, UrlencodedError},
+    http::{
+        header::{self, HeaderMap},
+        StatusCode,
+    },
+    web::EitherExtractError,
+    HttpMessage, HttpRequest, HttpResponse, ResponseError,
+};
+use minijinja::Value;
 use serde::Serialize;
 
+use crate::{config::Config, page::branding};
+
+static BRANDING: OnceLock<Value> = OnceLock::new();
+
+/// Set the site name and footer shown on error pages.  Call once at startup; errors before
+/// then are shown without them.
+pub fn configure(config: &Config) {
+    let _ = BRANDING.set(branding(config));
+}
+
 #[derive(Debug)]
 pub enum TenKbError {
     Msg(String),
@@ -51,17 +71,34 @@ impl From<rusqlite::Error> for TenKbError {
     }
 }
 
+#[cfg(feature = "postgres")]
+impl From<postgres::Error> for TenKbError {
+    fn from(err: postgres::Error) -> Self {
+        Self::Msg(err.to_string())
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct HtmlError {
     code: u16,
     status: String,
 }
 
+impl HtmlError {
+    pub fn new(code: u16, status: impl Into<String>) -> Self {
+        Self {
+            code,
+            status: status.into(),
+        }
+    }
+}
+
 impl ResponseError for HtmlError {
     fn error_response(&self) -> HttpResponse {
         HttpResponse::build(StatusCode::from_u16(self.code).unwrap()).body(minijinja::render!(
                 include_str!("/home/marcusb/code/10kbclub/templates/error.html"),
                 message => self.status,
+                branding => BRANDING.get(),
         ))
     }
 }
@@ -134,6 +171,15 @@ pub struct JsonError {
     status: String,
 }
 
+impl JsonError {
+    pub fn new(code: u16, status: impl Into<String>) -> Self {
+        Self {
+            code,
+            status: status.into(),
+        }
+    }
+}
+
 impl ResponseError for JsonError {
     fn error_response(&self) -> HttpResponse {
         HttpResponse::build(StatusCode::from_u16(self.code).unwrap()).json(self)
@@ -202,3 +248,68 @@ impl From<TenKbError> for JsonError {
         }
     }
 }
+
+/// Error handler for `web::FormConfig`: 413 for bodies over the limit, 400 for anything else
+/// that doesn't parse.  Browsers posting an HTML form get the error page; scripts get JSON.
+pub fn form_error(err: UrlencodedError, req: &HttpRequest) -> actix_web::Error {
+    let code = match err {
+        UrlencodedError::Overflow { .. } => 413,
+        _ => 400,
+    };
+
+    if wants_html(req.headers()) {
+        HtmlError::new(code, err.to_string()).into()
+    } else {
+        JsonError::new(code, err.to_string()).into()
+    }
+}
+
+/// Error handler for `web::JsonConfig`, mirroring `form_error` for JSON bodies.
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let code = match err {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => 413,
+        _ => 400,
+    };
+
+    JsonError::new(code, err.to_string()).into()
+}
+
+/// Why an `Either<Form<T>, Json<T>>` body didn't parse.
+pub type BodyError = EitherExtractError<actix_web::Error, actix_web::Error>;
+
+/// The error for a body that is neither a valid form nor valid JSON: the JSON one if that's
+/// what the client said it was sending, otherwise the form one.
+pub fn body_error(err: BodyError, req: &HttpRequest) -> actix_web::Error {
+    match err {
+        EitherExtractError::Bytes(err) => err,
+        EitherExtractError::Extract(_, json) if req.content_type() == "application/json" => json,
+        EitherExtractError::Extract(form, _) => form,
+    }
+}
+
+/// Error handler for `web::QueryConfig`: a query string that doesn't deserialize is a 400.
+pub fn query_error(err: QueryPayloadError, req: &HttpRequest) -> actix_web::Error {
+    if wants_html(req.headers()) {
+        HtmlError::new(400, err.to_string()).into()
+    } else {
+        JsonError::new(400, err.to_string()).into()
+    }
+}
+
+/// Whether the client is a browser navigating (e.g. posting an HTML form) rather than a script.
+pub fn wants_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// Whether the client asked for JSON rather than a page, e.g. a script sending
+/// `Accept: application/json`.
+pub fn wants_json(headers: &HeaderMap) -> bool {
+    !wants_html(headers)
+        && headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|accept| accept.contains("application/json"))
+}
@@ -0,0 +1,106 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Weak ETags and conditional (`If-None-Match`) responses for the JSON endpoints.
+
+use actix_web::{
+    http::header::{self, EntityTag, IfNoneMatch},
+    HttpMessage, HttpRequest, HttpResponse,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// A weak ETag derived from `parts`, which should capture everything that changes the
+/// response (e.g. the newest timestamp and a checksum of the counts).
+pub fn weak_etag<T: Serialize>(parts: &T) -> EntityTag {
+    let bytes = serde_json::to_vec(parts).unwrap_or_default();
+    let digest = Sha256::digest(bytes);
+    EntityTag::new_weak(hex::encode(&digest[..8]))
+}
+
+/// True if the client already holds the representation tagged `etag`.
+pub fn not_modified(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        None => false,
+    }
+}
+
+/// Serve `body` as JSON tagged with `etag`, or a bodiless 304 if the client's copy is
+/// current.
+pub fn json_response<T: Serialize>(req: &HttpRequest, etag: EntityTag, body: &T) -> HttpResponse {
+    if not_modified(req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish();
+    }
+
+    HttpResponse::Ok()
+        .insert_header(header::ETag(etag))
+        .json(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test::TestRequest};
+    use serde_json::json;
+
+    #[test]
+    fn json_response_is_304_for_a_matching_tag() {
+        let body = json!({"count": 3});
+        let etag = weak_etag(&body);
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, etag.to_string()))
+            .to_http_request();
+        let res = json_response(&req, etag.clone(), &body);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get(header::ETAG).unwrap(), &etag.to_string());
+    }
+
+    #[test]
+    fn json_response_is_200_for_a_stale_or_missing_tag() {
+        let body = json!({"count": 3});
+        let etag = weak_etag(&body);
+
+        let stale = weak_etag(&json!({"count": 2}));
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, stale.to_string()))
+            .to_http_request();
+        assert_eq!(
+            json_response(&req, etag.clone(), &body).status(),
+            StatusCode::OK
+        );
+
+        let req = TestRequest::default().to_http_request();
+        assert_eq!(json_response(&req, etag, &body).status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn weak_etag_changes_with_its_parts() {
+        assert_eq!(weak_etag(&(1, "a")), weak_etag(&(1, "a")));
+        assert_ne!(weak_etag(&(1, "a")), weak_etag(&(2, "a")));
+        assert!(weak_etag(&(1, "a")).weak);
+    }
+}
@@ -0,0 +1,132 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Live updates for the index: vote-count changes and newly listed sites are published on a
+//! broadcast channel, and pushed to index pages over a WebSocket.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, Session};
+use serde::Serialize;
+use tokio::{sync::broadcast, time::interval};
+use tracing::{debug, warn};
+
+/// Events buffered per subscriber before the slowest ones start missing updates.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SiteEvent {
+    Votes { site_id: u32, votes: u32 },
+    NewSite { site_id: u32, url: String },
+}
+
+pub type EventSender = broadcast::Sender<SiteEvent>;
+
+pub fn channel() -> EventSender {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// Publish an event to any connected clients.  Having no subscribers is not an error.
+pub fn publish(events: &EventSender, event: SiteEvent) {
+    let _ = events.send(event);
+}
+
+/// Events waiting to be flushed to one connection.  Vote counts for the same site are
+/// coalesced, so only the latest total is sent.
+#[derive(Default)]
+struct Pending {
+    votes: BTreeMap<u32, u32>,
+    new_sites: Vec<SiteEvent>,
+}
+
+impl Pending {
+    fn push(&mut self, event: SiteEvent) {
+        match event {
+            SiteEvent::Votes { site_id, votes } => {
+                self.votes.insert(site_id, votes);
+            }
+            event @ SiteEvent::NewSite { .. } => self.new_sites.push(event),
+        }
+    }
+
+    fn take(&mut self) -> Vec<SiteEvent> {
+        let mut events = std::mem::take(&mut self.new_sites);
+        events.extend(
+            std::mem::take(&mut self.votes)
+                .into_iter()
+                .map(|(site_id, votes)| SiteEvent::Votes { site_id, votes }),
+        );
+        events
+    }
+}
+
+/// Forward events to one client, sending at most one batch (a JSON array) per `throttle`.
+pub async fn forward_events(
+    mut session: Session,
+    mut stream: AggregatedMessageStream,
+    mut events: broadcast::Receiver<SiteEvent>,
+    throttle: Duration,
+) {
+    let mut pending = Pending::default();
+    let mut ticker = interval(throttle);
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => pending.push(event),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("websocket client fell behind; dropped {n} events");
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = ticker.tick() => {
+                let batch = pending.take();
+                if batch.is_empty() {
+                    continue;
+                }
+
+                let Ok(json) = serde_json::to_string(&batch) else {
+                    continue;
+                };
+                if session.text(json).await.is_err() {
+                    break;
+                }
+            },
+            msg = stream.recv() => match msg {
+                Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(AggregatedMessage::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    debug!("websocket error: {e}");
+                    break;
+                }
+            },
+        }
+    }
+
+    let _ = session.close(None).await;
+}
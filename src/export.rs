@@ -0,0 +1,243 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Static export of the public site: the index, site detail and related pages, feeds,
+//! favicons, and thumbnails, rendered with the same templates and queries as the server
+//! into files any web server can host as a read-only mirror.  Pages are written as
+//! `<path>/index.html`, and index pages after the first live at `/page/<n>/`.
+
+use minijinja::context;
+use std::{collections::BTreeSet, error::Error, fs, path::Path};
+use tracing::warn;
+
+use crate::{
+    config::Config,
+    database::{
+        get_comments, get_favicon, get_featured_sites, get_newest_sites, get_newest_validation,
+        get_site_detail, get_site_url, get_thumbnail, get_webmentions, Pool,
+    },
+    error::TenKbError,
+    feeds::{JsonFeed, FEED_LENGTH},
+    page::PageContext,
+    prefs::Prefs,
+    storage::Storage,
+    templates::Templates,
+    PageLink, SiteFilter,
+};
+
+/// What an export wrote.
+#[derive(Debug, Default)]
+pub struct ExportStats {
+    pub index_pages: usize,
+    pub sites: usize,
+    pub files: usize,
+}
+
+/// The address of index page `page` in the export.
+fn page_path(page: usize) -> String {
+    if page == 1 {
+        String::from("/")
+    } else {
+        format!("/page/{page}/")
+    }
+}
+
+fn write(dir: &Path, path: &str, contents: impl AsRef<[u8]>) -> Result<(), Box<dyn Error>> {
+    let mut file = dir.join(path.trim_start_matches('/'));
+    if path.ends_with('/') {
+        file.push("index.html");
+    }
+
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&file, contents).map_err(|e| format!("unable to write {file:?}: {e}"))?;
+    Ok(())
+}
+
+/// Render the public site into `dir`, listed with the site's default sort, page size, and
+/// view.  Interactive parts (voting, comment forms, sort and view switchers) are left out.
+pub fn export_static(
+    pool: &Pool,
+    config: &Config,
+    templates: &Templates,
+    dir: &Path,
+) -> Result<ExportStats, Box<dyn Error>> {
+    let mut stats = ExportStats::default();
+    let prefs = Prefs::site(config);
+    let (sortby, paginate, view) = (
+        prefs.default_sort(),
+        prefs.default_paginate(),
+        prefs.default_view(),
+    );
+    let filter = SiteFilter::default();
+
+    let count = pool
+        .get_site_count(&filter)
+        .map_err(|TenKbError::Msg(msg)| msg)?;
+    let pages = count.div_ceil(paginate).max(1);
+    let featured = get_featured_sites(pool).map_err(|TenKbError::Msg(msg)| msg)?;
+    let mut site_ids = BTreeSet::new();
+
+    for page in 1..=pages {
+        let sites = pool
+            .get_sites(sortby, &filter, paginate * (page - 1), paginate)
+            .map_err(|TenKbError::Msg(msg)| msg)?;
+        site_ids.extend(sites.iter().map(|site| site.id));
+
+        let page_links = if pages > 1 {
+            (1..=pages)
+                .map(|index| PageLink {
+                    index,
+                    uri: if index == page {
+                        String::new()
+                    } else {
+                        page_path(index)
+                    },
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+        let prev_link = if page > 1 {
+            page_path(page - 1)
+        } else {
+            String::new()
+        };
+        let next_link = if page < pages {
+            page_path(page + 1)
+        } else {
+            String::new()
+        };
+
+        let html = PageContext::new(config, config.site_name.clone(), &page_path(page))
+            .sortby(sortby)
+            .render(
+                templates,
+                "index.html",
+                context!(
+                    sites => sites,
+                    page_links => page_links,
+                    next_link => next_link,
+                    prev_link => prev_link,
+                    filter => filter,
+                    featured => if page == 1 { &featured[..] } else { &[] },
+                    view => view,
+                    static_export => true,
+                ),
+            )?;
+        write(dir, &page_path(page), html)?;
+        stats.index_pages += 1;
+    }
+
+    for site_id in site_ids {
+        let Some(site) = get_site_detail(pool, site_id).map_err(|TenKbError::Msg(msg)| msg)? else {
+            continue;
+        };
+        let comments = match &config.comments {
+            Some(_) => Some(get_comments(pool, site_id).map_err(|TenKbError::Msg(msg)| msg)?),
+            None => None,
+        };
+
+        let slug = site.slug.clone();
+        let path = format!("/site/{slug}/");
+        let html = PageContext::new(config, site.url.clone(), &path)
+            .description(format!("{} on {}", site.url, config.site_name))
+            .render(
+                templates,
+                "site.html",
+                context!(
+                    site => site,
+                    comments => comments,
+                    static_export => true,
+                ),
+            )?;
+        write(dir, &path, html)?;
+
+        let url = get_site_url(pool, site_id).map_err(|TenKbError::Msg(msg)| msg)?;
+        let related = pool
+            .get_related(site_id)
+            .map_err(|TenKbError::Msg(msg)| msg)?;
+        let mentions = get_webmentions(pool, site_id).map_err(|TenKbError::Msg(msg)| msg)?;
+        let path = format!("/related/{slug}/");
+        let html = PageContext::new(config, format!("Related links for {url}"), &path)
+            .description(format!(
+                "Hacker News and Lobsters discussions related to {url}"
+            ))
+            .render(
+                templates,
+                "related.html",
+                context!(
+                    url => url,
+                    related => related,
+                    mentions => mentions,
+                    static_export => true,
+                ),
+            )?;
+        write(dir, &path, html)?;
+
+        if let Some((icon, _)) = get_favicon(pool, site_id).map_err(|TenKbError::Msg(msg)| msg)? {
+            write(dir, &format!("/favicon/{slug}"), icon.data)?;
+            stats.files += 1;
+        }
+
+        if let Some((thumbnail, _)) =
+            get_thumbnail(pool, site_id).map_err(|TenKbError::Msg(msg)| msg)?
+        {
+            match fs::read(&thumbnail) {
+                Ok(data) => {
+                    write(dir, &format!("/thumb/{slug}.webp"), data)?;
+                    stats.files += 1;
+                }
+                Err(e) => warn!("unable to read thumbnail {thumbnail:?} for site {site_id}: {e}"),
+            }
+        }
+
+        stats.sites += 1;
+    }
+
+    let (newest, _) = get_newest_validation(pool).map_err(|TenKbError::Msg(msg)| msg)?;
+    let entries = get_newest_sites(pool, FEED_LENGTH).map_err(|TenKbError::Msg(msg)| msg)?;
+    let atom = templates.render(
+        "feed.xml",
+        context!(
+            site_name => config.site_name,
+            base_url => config.base_url,
+            updated => newest.unwrap_or_default(),
+            entries => entries,
+        ),
+    )?;
+    write(dir, "/feed.atom", atom)?;
+    write(
+        dir,
+        "/feed.json",
+        serde_json::to_vec(&JsonFeed::new(config, &entries))?,
+    )?;
+    stats.files += 2;
+
+    Ok(stats)
+}
+
+/// Write a static file of the site's own, such as its stylesheet, into an export.
+pub fn export_asset(dir: &Path, path: &str, contents: &str) -> Result<(), Box<dyn Error>> {
+    write(dir, path, contents)
+}
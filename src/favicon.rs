@@ -0,0 +1,130 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Member-site favicons, fetched once by the analyzer and served from our own database so
+//! the index never sends visitors' browsers to the member sites.
+
+use std::error::Error;
+use url::Url;
+
+use crate::html::link_hrefs;
+
+/// Image types we are willing to store and serve.  SVG is left out since it can carry
+/// script.
+const ALLOWED_TYPES: [&str; 6] = [
+    "image/x-icon",
+    "image/vnd.microsoft.icon",
+    "image/png",
+    "image/gif",
+    "image/jpeg",
+    "image/webp",
+];
+
+#[derive(Debug)]
+pub struct Favicon {
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Fetch the favicon for `site`, whose front page is `body`: the first `<link rel="icon">`
+/// if there is one, otherwise `/favicon.ico`.  Icons over `max_bytes` or of a type not in
+/// [`ALLOWED_TYPES`] are refused.
+pub async fn fetch_favicon(
+    site: &str,
+    body: &[u8],
+    max_bytes: usize,
+) -> Result<Favicon, Box<dyn Error>> {
+    let base = Url::parse(site)?;
+    let url = match icon_link(&String::from_utf8_lossy(body)) {
+        Some(href) => base.join(&href)?,
+        None => base.join("/favicon.ico")?,
+    };
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("unsupported favicon URL {url}").into());
+    }
+
+    let (content_type, data) = fetch_limited(&url, max_bytes).await?;
+    if !ALLOWED_TYPES.contains(&&content_type[..]) {
+        return Err(format!("favicon {url}: unacceptable content type '{content_type}'").into());
+    }
+
+    if data.is_empty() {
+        return Err(format!("favicon {url} is empty").into());
+    }
+
+    Ok(Favicon { content_type, data })
+}
+
+/// Fetch `url`, returning its (lowercased, parameter-free) content type and body, or an
+/// error if it doesn't answer with a 200 or sends more than `max_bytes`.
+pub(crate) async fn fetch_limited(
+    url: &Url,
+    max_bytes: usize,
+) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+    let res = reqwest::get(url.clone()).await?;
+    if res.status() != 200 {
+        return Err(format!("{url}: status code is {}", res.status()).into());
+    }
+
+    read_limited(res, max_bytes).await
+}
+
+/// The content type and body of `res`, or an error if the body is over `max_bytes`.
+pub(crate) async fn read_limited(
+    mut res: reqwest::Response,
+    max_bytes: usize,
+) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+    let url = res.url().clone();
+    let content_type = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    if res
+        .content_length()
+        .is_some_and(|len| len as usize > max_bytes)
+    {
+        return Err(format!("{url} is larger than {max_bytes} bytes").into());
+    }
+
+    let mut data = vec![];
+    while let Some(chunk) = res.chunk().await? {
+        if data.len() + chunk.len() > max_bytes {
+            return Err(format!("{url} is larger than {max_bytes} bytes").into());
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    Ok((content_type, data))
+}
+
+/// The `href` of the first `<link>` whose `rel` includes `icon`, skipping inline `data:`
+/// icons, which we have no need to proxy.
+fn icon_link(html: &str) -> Option<String> {
+    link_hrefs(html, "icon")
+        .find(|href| !href.is_empty() && !href.starts_with("data:"))
+        .map(String::from)
+}
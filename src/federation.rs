@@ -0,0 +1,194 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Federation with other instances of this directory.  Each peer's members are read from
+//! its `/api/v1/sites` on `schedules.federation` and shown at `/friends.html`, less any
+//! that are listed here too.
+
+use std::{error::Error, time::Duration};
+
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+    analyzer::instance_id,
+    config::{Config, FederationConfig, PeerConfig},
+    database::{acquire_lease, release_lease, replace_peer_sites, Pool},
+    favicon::read_limited,
+    resolver,
+};
+
+const FEDERATION_LEASE: &str = "federation";
+const FEDERATION_LEASE_TTL: u32 = 600;
+
+/// How long a peer has to answer for each page of its directory.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sites asked for per page; peers clamp this to their own `index.max_paginate`.
+const PER_PAGE: usize = 100;
+
+/// The most of any one page of a peer's directory we read.
+const PAGE_BYTES: usize = 4 << 20;
+
+/// A member of a peer's directory.
+#[derive(Clone, Debug, Serialize)]
+pub struct PeerSite {
+    /// The `name` of the peer it was imported from.
+    pub peer: String,
+    pub url: String,
+    pub size_bytes: u64,
+}
+
+/// One peer's members, as shown on `/friends.html`.
+#[derive(Debug, Serialize)]
+pub struct Friends {
+    pub name: String,
+    pub url: String,
+    pub sites: Vec<PeerSite>,
+}
+
+/// The parts of a page of `/api/v1/sites` we use.
+#[derive(Deserialize)]
+struct DirectoryPage {
+    total: usize,
+    per_page: usize,
+    sites: Vec<DirectoryEntry>,
+}
+
+#[derive(Deserialize)]
+struct DirectoryEntry {
+    url: String,
+    size_bytes: u64,
+}
+
+/// Import the members of every configured peer.  A peer that can't be read keeps the
+/// members imported from it last time.
+pub async fn federation_sweep(pool: &Pool, config: &Config) -> Result<(), Box<dyn Error>> {
+    let Some(federation) = &config.federation else {
+        return Ok(());
+    };
+
+    if !acquire_lease(pool, FEDERATION_LEASE, instance_id(), FEDERATION_LEASE_TTL)? {
+        info!("another instance holds the federation lease; skipping sweep");
+        return Ok(());
+    }
+
+    for peer in &federation.peers {
+        if !acquire_lease(pool, FEDERATION_LEASE, instance_id(), FEDERATION_LEASE_TTL)? {
+            warn!("lost the federation lease; abandoning sweep");
+            return Ok(());
+        }
+
+        match fetch_members(federation, peer).await {
+            Ok(sites) => {
+                info!("federation: {} lists {} members", peer.name, sites.len());
+                replace_peer_sites(pool, &peer.name, &sites)?;
+            }
+            Err(e) => warn!(
+                "federation: unable to read the members of {}: {e}",
+                peer.name
+            ),
+        }
+    }
+
+    release_lease(pool, FEDERATION_LEASE, instance_id())
+}
+
+/// Page through `peer`'s directory, newest first, stopping after `max_sites`.  Entries that
+/// aren't http or https URLs are dropped, since they end up as links on our pages.
+async fn fetch_members(
+    federation: &FederationConfig,
+    peer: &PeerConfig,
+) -> Result<Vec<PeerSite>, Box<dyn Error>> {
+    let directory = Url::parse(&format!("{}/api/v1/sites", peer.url.trim_end_matches('/')))?;
+
+    let mut members = vec![];
+    let mut seen = 0;
+    for page in 1usize.. {
+        let mut url = directory.clone();
+        url.query_pairs_mut()
+            .append_pair("sortby", "New")
+            .append_pair("page", &page.to_string())
+            .append_pair("per_page", &PER_PAGE.to_string());
+
+        let mut req = resolver::client()
+            .get(url.clone())
+            .header(header::ACCEPT, "application/json")
+            .timeout(FETCH_TIMEOUT);
+        if let Some(key) = &peer.api_key {
+            req = req.header("x-api-key", key);
+        }
+        let res = req
+            .send()
+            .await
+            .map_err(|e| format!("unable to fetch {url}: {e}"))?;
+        if !res.status().is_success() {
+            return Err(format!("{url} answered with {}", res.status()).into());
+        }
+
+        let (_, body) = read_limited(res, PAGE_BYTES).await?;
+        let listing: DirectoryPage = serde_json::from_slice(&body)?;
+        let last = listing.sites.is_empty() || page * listing.per_page >= listing.total;
+
+        seen += listing.sites.len();
+        members.extend(
+            listing
+                .sites
+                .into_iter()
+                .filter(|entry| {
+                    Url::parse(&entry.url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+                })
+                .map(|entry| PeerSite {
+                    peer: peer.name.clone(),
+                    url: entry.url,
+                    size_bytes: entry.size_bytes,
+                }),
+        );
+
+        if last || seen >= federation.max_sites {
+            break;
+        }
+    }
+
+    members.truncate(federation.max_sites);
+    Ok(members)
+}
+
+/// `sites` grouped under the peers they came from, in the configured order.  Members of
+/// peers that have since been dropped from the config aren't shown.
+pub fn friends(federation: &FederationConfig, sites: Vec<PeerSite>) -> Vec<Friends> {
+    federation
+        .peers
+        .iter()
+        .map(|peer| Friends {
+            name: peer.name.clone(),
+            url: peer.url.clone(),
+            sites: sites
+                .iter()
+                .filter(|site| site.peer == peer.name)
+                .cloned()
+                .collect(),
+        })
+        .collect()
+}
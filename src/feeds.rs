@@ -0,0 +1,142 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Atom and JSON feeds of newly listed sites, served with `Last-Modified`/`ETag` so feed
+//! readers can poll cheaply.
+
+use actix_web::{
+    http::header::{self, EntityTag, HttpDate, IfModifiedSince, IfNoneMatch},
+    HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::SystemTime;
+
+use crate::{
+    config::Config,
+    etag,
+    filters::{filesize, parse_date},
+};
+
+/// Number of sites included in each feed.
+pub const FEED_LENGTH: usize = 25;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FeedEntry {
+    pub id: u32,
+    pub url: String,
+    pub size_bytes: u64,
+    pub date_added: String,
+}
+
+/// Validators for the feeds, derived from the newest validation timestamp.
+pub struct FeedVersion {
+    pub last_modified: Option<DateTime<Utc>>,
+    pub etag: EntityTag,
+}
+
+impl FeedVersion {
+    pub fn new(newest: Option<&str>, count: usize) -> Self {
+        Self {
+            last_modified: newest.and_then(parse_date),
+            etag: etag::weak_etag(&(newest, count)),
+        }
+    }
+
+    fn http_date(&self) -> Option<HttpDate> {
+        self.last_modified
+            .map(|date| HttpDate::from(SystemTime::from(date)))
+    }
+
+    /// True if the client's cached copy is current.  `If-None-Match` takes precedence over
+    /// `If-Modified-Since`, as RFC 9110 requires.
+    pub fn not_modified(&self, req: &HttpRequest) -> bool {
+        if req.get_header::<IfNoneMatch>().is_some() {
+            return etag::not_modified(req, &self.etag);
+        }
+
+        match (req.get_header::<IfModifiedSince>(), self.last_modified) {
+            (Some(IfModifiedSince(since)), Some(modified)) => {
+                // HTTP dates have one-second resolution.
+                DateTime::<Utc>::from(SystemTime::from(since)).timestamp() >= modified.timestamp()
+            }
+            _ => false,
+        }
+    }
+
+    /// Start a response carrying this version's validators.
+    pub fn response(&self, mut builder: HttpResponseBuilder) -> HttpResponseBuilder {
+        builder.insert_header(header::ETag(self.etag.clone()));
+        if let Some(date) = self.http_date() {
+            builder.insert_header(header::LastModified(date));
+        }
+        builder
+    }
+
+    pub fn not_modified_response(&self) -> HttpResponse {
+        self.response(HttpResponse::NotModified()).finish()
+    }
+}
+
+#[derive(Serialize)]
+pub struct JsonFeed {
+    version: &'static str,
+    title: String,
+    home_page_url: String,
+    feed_url: String,
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem {
+    id: String,
+    url: String,
+    title: String,
+    content_text: String,
+    date_published: Option<String>,
+}
+
+impl JsonFeed {
+    pub fn new(config: &Config, entries: &[FeedEntry]) -> Self {
+        Self {
+            version: "https://jsonfeed.org/version/1.1",
+            title: format!("New sites in {}", config.site_name),
+            home_page_url: format!("{}/", config.base_url),
+            feed_url: format!("{}/feed.json", config.base_url),
+            items: entries
+                .iter()
+                .map(|entry| JsonFeedItem {
+                    id: entry.url.clone(),
+                    url: entry.url.clone(),
+                    title: entry.url.clone(),
+                    content_text: format!(
+                        "{} joined {} at {}",
+                        entry.url,
+                        config.site_name,
+                        filesize(entry.size_bytes as f64)
+                    ),
+                    date_published: parse_date(&entry.date_added).map(|date| date.to_rfc3339()),
+                })
+                .collect(),
+        }
+    }
+}
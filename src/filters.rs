@@ -0,0 +1,125 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Formatting filters registered on the template environment.
+
+use crate::idn;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use minijinja::Environment;
+use url::Url;
+
+pub fn register(env: &mut Environment) {
+    env.add_filter("filesize", filesize);
+    env.add_filter("timeago", timeago);
+    env.add_filter("rfc3339", rfc3339);
+    env.add_filter("host", host);
+    env.add_filter("display_url", display_url);
+    env.add_filter("number", number);
+}
+
+/// Human-readable byte count, e.g. `4.883 KiB`.
+pub fn filesize(bytes: f64) -> String {
+    if bytes < 1024.0 {
+        format!("{bytes} bytes")
+    } else if bytes < 1024.0 * 1024.0 {
+        format!("{:0.3} KiB", bytes / 1024.0)
+    } else {
+        format!("{:0.3} MiB", bytes / (1024.0 * 1024.0))
+    }
+}
+
+/// Parse the timestamp formats we store: SQLite `DATETIME()`, RFC 3339 (Hacker News), and
+/// Lobsters' `YYYY-MM-DD HH:MM:SS -ZZZZ`.
+pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date.to_utc());
+    }
+
+    if let Ok(date) = DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S %z") {
+        return Some(date.to_utc());
+    }
+
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+/// RFC 3339 timestamp, as used by Atom feeds.  Unparseable input is returned unchanged.
+pub fn rfc3339(date: String) -> String {
+    parse_date(&date).map_or(date, |date| date.to_rfc3339())
+}
+
+/// Relative date, e.g. `3 days ago`.  Unparseable input is returned unchanged.
+pub fn timeago(date: String) -> String {
+    let Some(parsed) = parse_date(&date) else {
+        return date;
+    };
+
+    let secs = (Utc::now() - parsed).num_seconds().max(0);
+
+    let (count, unit) = match secs {
+        0..60 => return String::from("just now"),
+        60..3_600 => (secs / 60, "minute"),
+        3_600..86_400 => (secs / 3_600, "hour"),
+        86_400..2_592_000 => (secs / 86_400, "day"),
+        2_592_000..31_536_000 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+
+    if count == 1 {
+        format!("1 {unit} ago")
+    } else {
+        format!("{count} {unit}s ago")
+    }
+}
+
+/// Host name of a URL, or the input unchanged if it isn't one.
+pub fn host(url: String) -> String {
+    match Url::parse(&url) {
+        Ok(parsed) => parsed.host_str().map(idn::display_host).unwrap_or(url),
+        Err(_) => url,
+    }
+}
+
+/// A URL with an internationalized host shown in Unicode where that is safe.
+pub fn display_url(url: String) -> String {
+    idn::display_url(&url)
+}
+
+/// Integer with thousands separators, e.g. `12,345`.
+pub fn number(n: i64) -> String {
+    let digits = n.unsigned_abs().to_string();
+    let mut out = String::new();
+
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+
+    if n < 0 {
+        format!("-{out}")
+    } else {
+        out
+    }
+}
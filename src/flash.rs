@@ -0,0 +1,156 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! One-shot messages carried across a redirect-after-POST in a short-lived cookie.
+
+use actix_web::{
+    body::MessageBody,
+    cookie::{time::Duration, Cookie, SameSite},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    Error, HttpMessage, HttpRequest, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use url::form_urlencoded;
+
+const FLASH_COOKIE: &str = "10kb_flash";
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlashLevel {
+    Success,
+    Error,
+    Info,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Flash {
+    pub level: FlashLevel,
+    pub message: String,
+}
+
+impl Flash {
+    pub fn success(message: impl Into<String>) -> Self {
+        Self {
+            level: FlashLevel::Success,
+            message: message.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            level: FlashLevel::Error,
+            message: message.into(),
+        }
+    }
+
+    pub fn info(message: impl Into<String>) -> Self {
+        Self {
+            level: FlashLevel::Info,
+            message: message.into(),
+        }
+    }
+
+    /// The flash message (if any) that arrived with this request.
+    pub fn from_request(req: &HttpRequest) -> Option<Self> {
+        req.extensions().get::<Flash>().cloned()
+    }
+
+    fn cookie(&self) -> Cookie<'static> {
+        let level = match self.level {
+            FlashLevel::Success => "success",
+            FlashLevel::Error => "error",
+            FlashLevel::Info => "info",
+        };
+
+        let value = form_urlencoded::Serializer::new(String::new())
+            .append_pair("level", level)
+            .append_pair("message", &self.message)
+            .finish();
+
+        Cookie::build(FLASH_COOKIE, value)
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .max_age(Duration::minutes(5))
+            .finish()
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        let mut level = None;
+        let mut message = None;
+
+        for (key, val) in form_urlencoded::parse(value.as_bytes()) {
+            match &key[..] {
+                "level" => {
+                    level = match &val[..] {
+                        "success" => Some(FlashLevel::Success),
+                        "error" => Some(FlashLevel::Error),
+                        "info" => Some(FlashLevel::Info),
+                        _ => None,
+                    }
+                }
+                "message" => message = Some(val.into_owned()),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            level: level?,
+            message: message?,
+        })
+    }
+}
+
+/// Redirect (303 See Other) to `location`, showing `flash` on the next page rendered.
+pub fn redirect(location: &str, flash: Flash) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, location))
+        .cookie(flash.cookie())
+        .finish()
+}
+
+/// Middleware that makes an incoming flash cookie available to handlers and clears it once
+/// a non-redirect response has been served, so each message is shown exactly once.
+pub async fn flash_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let flash = req
+        .cookie(FLASH_COOKIE)
+        .and_then(|cookie| Flash::decode(cookie.value()));
+    let had_flash = flash.is_some();
+
+    if let Some(flash) = flash {
+        req.extensions_mut().insert(flash);
+    }
+
+    let mut res = next.call(req).await?;
+
+    if had_flash && !res.status().is_redirection() {
+        res.response_mut()
+            .add_removal_cookie(&Cookie::build(FLASH_COOKIE, "").path("/").finish())?;
+    }
+
+    Ok(res)
+}
@@ -0,0 +1,138 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Per-country visit and vote counts from an optional MaxMind database.  Addresses are
+//! looked up as requests arrive and only the per-day country totals are kept.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::raw_client_ip;
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, Method},
+    middleware::Next,
+    web, Error,
+};
+use chrono::Utc;
+use maxminddb::{geoip2, Reader};
+use serde::Serialize;
+
+/// Country code recorded when an address isn't in the database.
+const UNKNOWN_COUNTRY: &str = "??";
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CountryCount {
+    pub day: String,
+    pub country: String,
+    pub visits: u64,
+    pub votes: u64,
+}
+
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+    counts: Mutex<HashMap<(String, String), (u64, u64)>>,
+}
+
+pub type GeoIpHandle = Arc<GeoIp>;
+
+impl GeoIp {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let reader = Reader::open_readfile(path)
+            .map_err(|e| format!("unable to open GeoIP database {path:?}: {e}"))?;
+
+        Ok(Self {
+            reader,
+            counts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn country(&self, ip: &str) -> String {
+        let Ok(ip) = ip.parse::<IpAddr>() else {
+            return String::from(UNKNOWN_COUNTRY);
+        };
+
+        self.reader
+            .lookup(ip)
+            .ok()
+            .and_then(|result| result.decode::<geoip2::Country>().ok().flatten())
+            .and_then(|country| country.country.iso_code.map(String::from))
+            .unwrap_or_else(|| String::from(UNKNOWN_COUNTRY))
+    }
+
+    fn record(&self, ip: &str, visits: u64, votes: u64) {
+        let key = (Utc::now().format("%Y-%m-%d").to_string(), self.country(ip));
+        let mut counts = self.counts.lock().unwrap();
+        let entry = counts.entry(key).or_default();
+        entry.0 += visits;
+        entry.1 += votes;
+    }
+
+    /// Take the counts accumulated since the last call.
+    pub fn take(&self) -> Vec<CountryCount> {
+        std::mem::take(&mut *self.counts.lock().unwrap())
+            .into_iter()
+            .map(|((day, country), (visits, votes))| CountryCount {
+                day,
+                country,
+                visits,
+                votes,
+            })
+            .collect()
+    }
+}
+
+/// Middleware counting successful HTML page views as visits and successful `/vote/` posts
+/// as votes, by the client's country.
+pub async fn geoip_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(geoip) = req.app_data::<web::Data<GeoIpHandle>>().cloned() else {
+        return next.call(req).await;
+    };
+
+    let ip = raw_client_ip(req.request()).unwrap_or_default();
+    let is_vote = req.method() == Method::POST && req.path() == "/vote/";
+    let res = next.call(req).await?;
+
+    if res.status().is_success() {
+        let is_page = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+
+        match (is_page, is_vote) {
+            (true, _) => geoip.record(&ip, 1, 0),
+            (_, true) => geoip.record(&ip, 0, 1),
+            _ => {}
+        }
+    }
+
+    Ok(res)
+}
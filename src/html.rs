@@ -0,0 +1,92 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Tags in fetched pages that point elsewhere by `rel`: favicons, stylesheets, feeds, and
+//! webmention endpoints.
+
+use regex::{Match, Regex};
+use std::sync::OnceLock;
+
+/// A `<link>` (or `<a>`) tag whose `rel` includes the relation asked for.
+pub struct RelTag<'a> {
+    /// The whole tag, as it appears in the page.
+    pub tag: Match<'a>,
+    /// Its `href`, trimmed.
+    pub href: &'a str,
+}
+
+impl<'a> RelTag<'a> {
+    /// The value of one of the tag's attributes.
+    pub fn attribute(&self, name: &str) -> Option<&'a str> {
+        attribute(self.tag.as_str(), name)
+    }
+}
+
+/// The `href`s of the `<link>` tags in `html` whose `rel` includes `rel`.
+pub fn link_hrefs<'a>(html: &'a str, rel: &'a str) -> impl Iterator<Item = &'a str> {
+    rel_tags(html, rel, false).map(|tag| tag.href)
+}
+
+/// The `<link>` tags in `html`, and with `anchors` also the `<a>` tags, whose `rel` includes
+/// `rel` and that have an `href`, in page order.
+pub fn rel_tags<'a>(
+    html: &'a str,
+    rel: &'a str,
+    anchors: bool,
+) -> impl Iterator<Item = RelTag<'a>> {
+    static TAGS: OnceLock<(Regex, Regex)> = OnceLock::new();
+    let (links, links_and_anchors) = TAGS.get_or_init(|| {
+        (
+            Regex::new(r#"(?is)<link\b[^>]*>"#).unwrap(),
+            Regex::new(r#"(?is)<(?:link|a)\b[^>]*>"#).unwrap(),
+        )
+    });
+
+    let tags = if anchors { links_and_anchors } else { links };
+    tags.find_iter(html)
+        .filter(move |tag| {
+            attribute(tag.as_str(), "rel").is_some_and(|value| {
+                value
+                    .split_ascii_whitespace()
+                    .any(|token| token.eq_ignore_ascii_case(rel))
+            })
+        })
+        .filter_map(|tag| {
+            let href = attribute(tag.as_str(), "href")?;
+            Some(RelTag { tag, href })
+        })
+}
+
+/// The trimmed value of attribute `name` in `tag`, quoted or not.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    let attribute = ATTRIBUTE.get_or_init(|| {
+        Regex::new(r#"\s([A-Za-z][A-Za-z0-9_:.-]*)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#)
+            .unwrap()
+    });
+
+    attribute
+        .captures_iter(tag)
+        .find(|caps| caps[1].eq_ignore_ascii_case(name))
+        .and_then(|caps| caps.get(2).or(caps.get(3)).or(caps.get(4)))
+        .map(|value| value.as_str().trim())
+}
@@ -0,0 +1,151 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Gettext-style translations.  Catalogs are JSON files named `<lang>.json` in the
+//! configured locale directory, each mapping an English message to its translation.
+//! Messages without a translation are shown in English.
+
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web, Error, HttpMessage, HttpRequest,
+};
+use tracing::info;
+use url::form_urlencoded;
+
+/// The language negotiated for a request.
+#[derive(Clone, Debug)]
+pub struct Locale(pub String);
+
+pub struct Catalogs {
+    default: String,
+    catalogs: HashMap<String, HashMap<String, String>>,
+}
+
+impl Catalogs {
+    pub fn load(path: Option<&Path>, default: &str) -> Result<Self, std::io::Error> {
+        let mut catalogs = HashMap::new();
+
+        if let Some(path) = path {
+            for entry in fs::read_dir(path)? {
+                let path = entry?.path();
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+
+                let Some(lang) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+
+                let catalog: HashMap<String, String> =
+                    serde_json::from_str(&fs::read_to_string(&path)?)?;
+                info!("loaded {} messages for locale '{lang}'", catalog.len());
+                catalogs.insert(lang.to_lowercase(), catalog);
+            }
+        }
+
+        Ok(Self {
+            default: default.to_lowercase(),
+            catalogs,
+        })
+    }
+
+    pub fn default_locale(&self) -> &str {
+        &self.default
+    }
+
+    fn supports(&self, lang: &str) -> Option<String> {
+        let lang = lang.trim().to_lowercase();
+        let primary = lang.split('-').next().unwrap_or("").to_string();
+
+        [lang, primary]
+            .into_iter()
+            .find(|candidate| *candidate == self.default || self.catalogs.contains_key(candidate))
+    }
+
+    /// Pick a language from a `lang` query parameter, falling back to `Accept-Language` and
+    /// then the default.
+    pub fn negotiate(&self, query: &str, accept_language: Option<&str>) -> String {
+        if let Some(lang) = form_urlencoded::parse(query.as_bytes())
+            .find(|(key, _)| key == "lang")
+            .and_then(|(_, lang)| self.supports(&lang))
+        {
+            return lang;
+        }
+
+        let mut ranges = accept_language
+            .unwrap_or("")
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let lang = parts.next()?.trim();
+                let q = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                Some((lang, q))
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges
+            .into_iter()
+            .find_map(|(lang, _)| self.supports(lang))
+            .unwrap_or_else(|| self.default.clone())
+    }
+
+    pub fn translate(&self, lang: &str, msgid: &str) -> String {
+        self.catalogs
+            .get(lang)
+            .and_then(|catalog| catalog.get(msgid))
+            .cloned()
+            .unwrap_or_else(|| String::from(msgid))
+    }
+}
+
+impl Locale {
+    pub fn from_request(req: &HttpRequest) -> Option<Self> {
+        req.extensions().get::<Locale>().cloned()
+    }
+}
+
+/// Middleware that negotiates the language for each request and stores it as a `Locale`
+/// request extension.
+pub async fn locale_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if let Some(catalogs) = req.app_data::<web::Data<Arc<Catalogs>>>() {
+        let accept_language = req
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok());
+        let lang = catalogs.negotiate(req.query_string(), accept_language);
+        req.extensions_mut().insert(Locale(lang));
+    }
+
+    next.call(req).await
+}
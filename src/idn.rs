@@ -0,0 +1,171 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Internationalized domain names.  Hosts are stored in their ASCII (punycode) form, which
+//! the `url` crate produces when parsing, and shown in Unicode only when that can't mislead:
+//! a label mixing Latin, Greek, and Cyrillic letters stays in punycode.  Submissions whose
+//! host mixes scripts or imitates an existing member's are flagged for an admin to review.
+
+use std::{collections::BTreeSet, fmt, fmt::Display, fmt::Formatter};
+use url::Url;
+
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+/// Why a submitted host was flagged.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Homograph {
+    /// A label mixes Latin, Greek, and Cyrillic letters.
+    MixedScript,
+    /// Renders (nearly) the same as a listed member's host.
+    LooksLike { site_id: u32, host: String },
+}
+
+impl Homograph {
+    pub fn lookalike_of(&self) -> Option<u32> {
+        match self {
+            Homograph::MixedScript => None,
+            Homograph::LooksLike { site_id, .. } => Some(*site_id),
+        }
+    }
+}
+
+impl Display for Homograph {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Homograph::MixedScript => write!(f, "mixes scripts"),
+            Homograph::LooksLike { host, .. } => write!(f, "looks like {}", display_host(host)),
+        }
+    }
+}
+
+fn script(c: char) -> Option<Script> {
+    match c as u32 {
+        0x61..=0x7a | 0x41..=0x5a | 0xc0..=0x24f => Some(Script::Latin),
+        0x370..=0x3ff => Some(Script::Greek),
+        0x400..=0x52f => Some(Script::Cyrillic),
+        _ => None,
+    }
+}
+
+fn mixed_script(label: &str) -> bool {
+    label
+        .chars()
+        .filter_map(script)
+        .collect::<BTreeSet<_>>()
+        .len()
+        > 1
+}
+
+/// Letters from other scripts that are drawn like Latin ones, mapped to the Latin letter.
+fn confusable(c: char) -> char {
+    match c {
+        'а' | 'α' => 'a',
+        'ԁ' => 'd',
+        'е' | 'ε' => 'e',
+        'ɡ' => 'g',
+        'һ' => 'h',
+        'і' | 'ı' | 'ι' => 'i',
+        'ј' => 'j',
+        'κ' => 'k',
+        'ӏ' => 'l',
+        'о' | 'ο' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'ѕ' => 's',
+        'с' => 'c',
+        'υ' => 'u',
+        'ν' => 'v',
+        'ԝ' => 'w',
+        'х' | 'χ' => 'x',
+        'у' => 'y',
+        c => c,
+    }
+}
+
+fn to_unicode(host: &str) -> Option<String> {
+    let (unicode, result) = idna::domain_to_unicode(host);
+    result.ok().map(|_| unicode)
+}
+
+/// What a host looks like once confusable letters are replaced by their Latin twins.
+fn skeleton(host: &str) -> String {
+    to_unicode(host)
+        .unwrap_or_else(|| String::from(host))
+        .chars()
+        .map(confusable)
+        .collect()
+}
+
+/// The Unicode form of `host`, unless it fails to decode or a label mixes scripts, in which
+/// case the punycode form is kept.
+pub fn display_host(host: &str) -> String {
+    match to_unicode(host) {
+        Some(unicode) if !unicode.split('.').any(mixed_script) => unicode,
+        _ => String::from(host),
+    }
+}
+
+/// `url` with its host shown as [`display_host`] would.
+pub fn display_url(url: &str) -> String {
+    let Some(host) = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+    else {
+        return String::from(url);
+    };
+
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return String::from(url);
+    }
+
+    url.replacen(&host, &display_host(&host), 1)
+}
+
+/// Check an internationalized `host` against the hosts of listed members.  Plain ASCII hosts
+/// are never flagged.
+pub fn find_homograph(host: &str, members: &[(u32, String)]) -> Option<Homograph> {
+    if !host.split('.').any(|label| label.starts_with("xn--")) {
+        return None;
+    }
+
+    let skeleton = skeleton(host);
+    let lookalike = members
+        .iter()
+        .find(|(_, member)| member != host && *self::skeleton(member) == skeleton)
+        .map(|(site_id, member)| Homograph::LooksLike {
+            site_id: *site_id,
+            host: member.clone(),
+        });
+
+    match lookalike {
+        Some(lookalike) => Some(lookalike),
+        None if to_unicode(host).is_some_and(|unicode| unicode.split('.').any(mixed_script)) => {
+            Some(Homograph::MixedScript)
+        }
+        None => None,
+    }
+}
@@ -20,23 +20,98 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use actix_web::HttpRequest;
+use actix_web::{web, HttpRequest};
+use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
-use std::{fmt, fmt::Display, fmt::Formatter};
+use sha2::{Digest, Sha256};
+use std::{fmt, fmt::Display, fmt::Formatter, net::IpAddr, str::FromStr, sync::OnceLock};
 use tracing::error;
+use url::Url;
 
+use config::{Config, IpPrivacy, PathPolicy};
+use prefs::{Prefs, View};
+use relatedlinks::RelatedLink;
+use uptime::UptimeSummary;
+use webmention::Webmention;
+
+pub mod admin;
+pub mod analytics;
 pub mod analyzer;
+pub mod apikeys;
+pub mod backpressure;
+pub mod blocking;
 pub mod cloudflare;
 pub mod config;
 pub mod database;
+pub mod discussions;
 pub mod error;
+pub mod etag;
+pub mod events;
+pub mod export;
+pub mod favicon;
+pub mod federation;
+pub mod feeds;
+pub mod filters;
+pub mod flash;
+pub mod geoip;
+pub mod head;
+pub mod html;
+pub mod i18n;
+pub mod idn;
+pub mod listeners;
+pub mod logging;
+pub mod minify;
+pub mod owners;
+pub mod page;
+pub mod planet;
+pub mod poolstats;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod prefs;
+pub mod preview;
+pub mod rankings;
+pub mod readonly;
 pub mod relatedlinks;
+pub mod replication;
+pub mod resolver;
+pub mod scheduler;
+pub mod screenshots;
+pub mod seed;
+pub mod slugs;
+pub mod snapshots;
+pub mod spam;
+pub mod storage;
+pub mod systemd;
+pub mod templates;
+pub mod testing;
+pub mod uptime;
+pub mod webmention;
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum SortOptions {
     New,
     Size,
     Votes,
+    /// Most discussion points (then comments) across related HN/Lobsters threads.
+    Discussed,
+    /// Most votes gained over the last week, from the daily vote snapshots.
+    Week,
+    /// Most votes gained over the last month.
+    Month,
+}
+
+impl SortOptions {
+    /// How far back the votes counted by a "top this ..." ranking go, as an SQLite date
+    /// modifier.
+    pub fn window(&self) -> Option<&'static str> {
+        match self {
+            SortOptions::Week => Some("-7 days"),
+            SortOptions::Month => Some("-1 month"),
+            SortOptions::New | SortOptions::Size | SortOptions::Votes | SortOptions::Discussed => {
+                None
+            }
+        }
+    }
 }
 
 impl Display for SortOptions {
@@ -45,6 +120,43 @@ impl Display for SortOptions {
             SortOptions::New => write!(f, "New"),
             SortOptions::Size => write!(f, "Size"),
             SortOptions::Votes => write!(f, "Votes"),
+            SortOptions::Discussed => write!(f, "Discussed"),
+            SortOptions::Week => write!(f, "Week"),
+            SortOptions::Month => write!(f, "Month"),
+        }
+    }
+}
+
+impl FromStr for SortOptions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "New" => Ok(SortOptions::New),
+            "Size" => Ok(SortOptions::Size),
+            "Votes" => Ok(SortOptions::Votes),
+            "Discussed" => Ok(SortOptions::Discussed),
+            "Week" => Ok(SortOptions::Week),
+            "Month" => Ok(SortOptions::Month),
+            _ => Err(format!("unknown sort option '{s}'")),
+        }
+    }
+}
+
+/// Restrictions on which listed sites the index shows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct SiteFilter {
+    /// Only sites with at least one related discussion.
+    pub has_related: bool,
+}
+
+impl SiteFilter {
+    /// Extra `WHERE` conditions for a query over `site_ids`.
+    pub fn sql(&self) -> &'static str {
+        if self.has_related {
+            " AND EXISTS (SELECT 1 FROM related WHERE related.id = site_ids.id)"
+        } else {
+            ""
         }
     }
 }
@@ -55,16 +167,187 @@ pub struct PageLink {
     uri: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Site {
     offset: usize,
     id: u32,
+    /// Names the site in `/site/{slug}/` and `/related/{slug}/`.
+    #[serde(default)]
+    slug: String,
     url: String,
-    size: String,
+    size_bytes: u64,
     related: u32,
+    /// Whole years since the site was (most recently) listed.
+    member_years: u32,
+    votes: u32,
+    date_added: String,
+    /// Points and comments summed over the site's related discussions.
+    discussion_points: u64,
+    discussion_comments: u64,
+    /// Curator note set by an admin.
+    note: Option<String>,
+    /// Whether `/favicon/{slug}` has an icon to serve.
+    favicon: bool,
+    /// Whether `/thumb/{slug}.webp` has a screenshot to serve.
+    #[serde(default)]
+    thumbnail: bool,
+    /// Votes gained within the ranking's window, when sorting by one.
+    #[serde(default)]
+    recent_votes: Option<u32>,
+}
+
+/// A comment in a site's thread on `/site/{slug}/`.
+#[derive(Debug, Serialize)]
+pub struct Comment {
+    pub id: u32,
+    pub site_id: u32,
+    /// The commenter's voter number, which is all that identifies them publicly.
+    pub commenter: u32,
+    pub body: String,
+    /// `pending`, `approved`, or `rejected`; only approved comments are shown.
+    pub status: String,
+    pub posted: String,
+}
+
+/// A spam score recorded for a submission (by URL) or comment (by id).
+#[derive(Debug, Serialize)]
+pub struct SpamScore {
+    pub kind: String,
+    pub subject: String,
+    pub score: f64,
+    pub scored: String,
+}
+
+/// A submission whose internationalized host may be impersonating another site.
+#[derive(Debug, Serialize)]
+pub struct HomographFlag {
+    pub site_id: u32,
+    pub url: String,
+    pub reason: String,
+    pub lookalike_of: Option<u32>,
+    pub lookalike_url: Option<String>,
+    pub flagged: String,
+}
+
+/// A voter number or IP whose comments and votes are shadowed.
+#[derive(Debug, Serialize)]
+pub struct ShadowFlag {
+    pub kind: String,
+    pub value: String,
+    pub reason: Option<String>,
+    pub flagged: String,
+}
+
+/// A batch of votes the vote anomaly job thought suspicious: a burst for one site, or a
+/// cohort of new voters who all voted alike.
+#[derive(Debug, Serialize)]
+pub struct VoteAnomaly {
+    pub id: u32,
+    pub kind: String,
+    pub site_id: Option<u32>,
+    pub url: Option<String>,
+    pub detail: String,
+    pub votes: u32,
+    pub detected: String,
+    pub invalidated: Option<String>,
+}
+
+/// A site pinned to the top of the index by an admin.
+#[derive(Debug, Serialize)]
+pub struct FeaturedSite {
+    pub id: u32,
+    pub slug: String,
+    pub url: String,
+    pub size_bytes: u64,
+    /// When the site stops being featured; `None` keeps it featured until unpinned.
+    pub featured_until: Option<String>,
+}
+
+/// Everything shown about one listed site, on `/site/{slug}/` and from `/api/v1/sites/{slug}`.
+#[derive(Debug, Serialize)]
+pub struct SiteDetail {
+    pub id: u32,
+    pub slug: String,
+    pub url: String,
+    pub size_bytes: u64,
+    /// Size on a phone, if `mobile_scans` measured it.
+    pub mobile_size_bytes: Option<u64>,
+    pub votes: u32,
+    pub date_added: String,
+    pub member_years: u32,
+    pub note: Option<String>,
+    /// Whether `/thumb/{slug}.webp` has a screenshot to serve.
+    pub thumbnail: bool,
+    pub size_history: SizeHistorySummary,
+    /// Liveness over the last `uptime::UPTIME_DAYS` days, once the site has been checked.
+    pub uptime: Option<UptimeSummary>,
+    pub related: Vec<RelatedLink>,
+    pub mentions: Vec<Webmention>,
+    /// Archived copies, newest first.
+    pub snapshots: Vec<SnapshotInfo>,
+    /// URLs the site was formerly listed under, newest first.
+    pub aliases: Vec<String>,
+}
+
+/// An archived copy of a site, served from `/snapshot/{slug}/{timestamp}`.
+#[derive(Debug, Serialize)]
+pub struct SnapshotInfo {
+    /// Seconds since the epoch, as used in the snapshot's URL.
+    pub timestamp: i64,
+    pub captured: String,
+    /// Change from the previous snapshot, if there is one.
+    pub bytes_added: Option<i64>,
+    pub bytes_removed: Option<i64>,
 }
 
+/// A change between snapshots whose growth flagged the site as at risk.
+#[derive(Debug, Serialize)]
+pub struct AtRiskChange {
+    pub site_id: u32,
+    pub slug: String,
+    pub url: String,
+    pub timestamp: i64,
+    pub captured: String,
+    pub bytes_added: i64,
+    pub bytes_removed: i64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SizeHistorySummary {
+    pub measurements: u32,
+    pub smallest_bytes: Option<u64>,
+    pub largest_bytes: Option<u64>,
+    pub last_measured: Option<String>,
+    pub last_passed: Option<bool>,
+}
+
+/// The client's address, reduced according to the configured `ip_privacy`.  Use this for
+/// anything that logs or stores an IP.
 pub fn get_client_ip(req: &HttpRequest) -> Result<String, String> {
+    let privacy = req
+        .app_data::<web::Data<Config>>()
+        .map_or(IpPrivacy::Off, |config| config.ip_privacy);
+    get_client_ip_with(req, privacy)
+}
+
+/// The client's address, reduced according to `privacy`.
+pub fn get_client_ip_with(req: &HttpRequest, privacy: IpPrivacy) -> Result<String, String> {
+    let ip = raw_client_ip(req)?;
+    let salt = req
+        .app_data::<web::Data<Config>>()
+        .and_then(|config| config.ip_hash_salt.clone())
+        .unwrap_or_else(|| String::from(random_salt()));
+
+    Ok(match privacy {
+        IpPrivacy::Off => ip,
+        IpPrivacy::Truncate => truncate_ip(&ip),
+        IpPrivacy::Hash => hash_ip(&ip, &salt),
+    })
+}
+
+/// The client's full address.  Only for transient use (e.g. GeoIP lookups); never log or
+/// store it.
+pub(crate) fn raw_client_ip(req: &HttpRequest) -> Result<String, String> {
     match (req.headers().get("x-real-ip"), req.peer_addr()) {
         (Some(xri), _) => {
             let Ok(str) = xri.to_str() else {
@@ -79,11 +362,119 @@ pub fn get_client_ip(req: &HttpRequest) -> Result<String, String> {
     }
 }
 
+/// Drop the host part of an address: the last octet of IPv4, everything past the /48 for
+/// IPv6.  Unparseable input is dropped entirely.
+pub fn truncate_ip(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            let [a, b, c, _] = ip.octets();
+            format!("{a}.{b}.{c}.0")
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let segments = ip.segments();
+            format!("{:x}:{:x}:{:x}::", segments[0], segments[1], segments[2])
+        }
+        Err(_) => String::new(),
+    }
+}
+
+pub fn hash_ip(ip: &str, salt: &str) -> String {
+    let digest = Sha256::digest(format!("{salt}:{ip}").as_bytes());
+    hex::encode(&digest[..8])
+}
+
+fn random_salt() -> &'static str {
+    static SALT: OnceLock<String> = OnceLock::new();
+    SALT.get_or_init(|| {
+        let mut rand_bytes = [0u8; 16];
+        thread_rng().fill(&mut rand_bytes);
+        hex::encode(rand_bytes)
+    })
+}
+
+/// Normalize a submitted URL (lowercase scheme and host, default path, etc.) so the same
+/// site is recognized regardless of how it was typed.
+pub fn canonical_url(site: &str) -> Result<String, url::ParseError> {
+    Ok(Url::parse(site)?.to_string())
+}
+
+/// Check a submitted URL against the configured path policy, explaining any refusal.
+pub fn check_path_policy(policy: PathPolicy, url: &Url) -> Result<(), String> {
+    if policy != PathPolicy::HomepageOnly {
+        return Ok(());
+    }
+
+    if url.path() != "/" || url.query().is_some() || url.fragment().is_some() {
+        return Err(format!(
+            "only homepages may be submitted; try {}/ instead",
+            url.origin().ascii_serialization()
+        ));
+    }
+
+    Ok(())
+}
+
+/// The domain a site counts against for the per-domain submission cap: its lowercased host
+/// without any leading `www.`.
+pub fn site_domain(site: &str) -> Option<String> {
+    let host = Url::parse(site).ok()?.host_str()?.to_ascii_lowercase();
+    Some(match host.strip_prefix("www.") {
+        Some(rest) => String::from(rest),
+        None => host,
+    })
+}
+
+/// Build the URI for an index page, omitting any parameter that has its default value so
+/// that every listing has exactly one address.  `defaults` supplies the defaults: the site's
+/// own (`Prefs::site`) for canonical links, or a visitor's stored preferences for links they
+/// will follow.
+pub fn index_uri(
+    page: usize,
+    paginate: usize,
+    sortby: SortOptions,
+    view: View,
+    filter: &SiteFilter,
+    defaults: &Prefs,
+) -> String {
+    let mut params = vec![];
+
+    if sortby != defaults.default_sort() {
+        params.push(format!("sortby={sortby}"));
+    }
+
+    if filter.has_related {
+        params.push(String::from("has_related=true"));
+    }
+
+    if paginate != defaults.default_paginate() {
+        params.push(format!("paginate={paginate}"));
+    }
+
+    if view != defaults.default_view() {
+        params.push(format!("view={}", view.as_str()));
+    }
+
+    if page > 1 {
+        params.push(format!("page={page}"));
+    }
+
+    if params.is_empty() {
+        String::from("/")
+    } else {
+        format!("/?{}", params.join("&"))
+    }
+}
+
+/// Links to every page of the index.  Parameters matching the visitor's `defaults` are
+/// left out, so the links stay as short as the visitor's preferences allow.
 pub fn get_page_links(
     page: usize,
     count: f32,
     paginate: f32,
     sortby: SortOptions,
+    view: View,
+    filter: &SiteFilter,
+    defaults: &Prefs,
 ) -> (Vec<PageLink>, String, String) {
     if count > paginate {
         let mut page_links = vec![];
@@ -93,7 +484,7 @@ pub fn get_page_links(
             if i != page {
                 page_links.push(PageLink {
                     index: i,
-                    uri: format!("/?paginate={paginate}&sortby={sortby}&page={i}"),
+                    uri: index_uri(i, paginate as usize, sortby, view, filter, defaults),
                 });
             } else {
                 page_links.push(PageLink {
@@ -104,13 +495,13 @@ pub fn get_page_links(
         }
 
         let prev_link = if page > 1 {
-            format!("/?paginate={paginate}&sortby={sortby}&page={}", page - 1)
+            index_uri(page - 1, paginate as usize, sortby, view, filter, defaults)
         } else {
             "".into()
         };
 
         let next_link = if page < pages {
-            format!("/?paginate={paginate}&sortby={sortby}&page={}", page + 1)
+            index_uri(page + 1, paginate as usize, sortby, view, filter, defaults)
         } else {
             "".into()
         };
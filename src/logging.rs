@@ -0,0 +1,124 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Tracing subscriber setup: stderr or syslog, an optional rotated log file, and Sentry.
+
+use tracing::Level;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{filter::LevelFilter, fmt, layer::SubscriberExt, Layer};
+
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use syslog_tracing::{Facility, Options, Syslog};
+
+use crate::config::{Config, LogLevel, LogRotation, LogTarget, SyslogFacility};
+
+/// Keeps the background log writer alive; dropping it flushes and stops file logging.
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+pub fn init(config: &Config) -> std::io::Result<LogGuard> {
+    let level = LevelFilter::from_level(match config.log_level {
+        LogLevel::Info => Level::INFO,
+        LogLevel::Warn => Level::WARN,
+        LogLevel::Debug => Level::DEBUG,
+        LogLevel::Trace => Level::TRACE,
+    });
+
+    let (file_layer, file_guard) = match &config.log_file {
+        Some(log_file) => {
+            let mut condition = RollingConditionBasic::new();
+            condition = match log_file.rotation {
+                LogRotation::Hourly => condition.hourly(),
+                LogRotation::Daily => condition.daily(),
+                LogRotation::Never => condition,
+            };
+            if let Some(max_size_mb) = log_file.max_size_mb {
+                condition = condition.max_size(max_size_mb * 1024 * 1024);
+            }
+
+            let appender =
+                BasicRollingFileAppender::new(&log_file.path, condition, log_file.max_files)?;
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+
+            let layer = fmt::layer().with_writer(writer).with_ansi(false);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    let console = config
+        .log_file
+        .as_ref()
+        .is_none_or(|log_file| log_file.stderr);
+    let (stderr_layer, syslog_layer) = match config.log_target {
+        LogTarget::Stderr if console => (
+            Some(fmt::layer().without_time().with_writer(std::io::stderr)),
+            None,
+        ),
+        LogTarget::Syslog if console => {
+            let Some(syslog) = Syslog::new(c"tenkb_server", Options::LOG_PID, facility(config))
+            else {
+                return Err(std::io::Error::other("syslog is already open"));
+            };
+
+            // syslogd stamps each message, and colour codes would end up in the log.
+            let layer = fmt::layer()
+                .without_time()
+                .with_ansi(false)
+                .with_writer(syslog);
+            (None, Some(layer))
+        }
+        _ => (None, None),
+    };
+
+    let subscriber = tracing_subscriber::registry()
+        .with(stderr_layer.with_filter(level))
+        .with(syslog_layer.with_filter(level))
+        .with(file_layer.with_filter(level))
+        .with(
+            config
+                .sentry
+                .is_some()
+                .then(sentry::integrations::tracing::layer),
+        );
+
+    tracing::subscriber::set_global_default(subscriber)
+        .expect("Could not set default global tracing subscriber");
+
+    Ok(LogGuard { _file: file_guard })
+}
+
+fn facility(config: &Config) -> Facility {
+    match config.syslog_facility {
+        SyslogFacility::User => Facility::User,
+        SyslogFacility::Daemon => Facility::Daemon,
+        SyslogFacility::Local0 => Facility::Local0,
+        SyslogFacility::Local1 => Facility::Local1,
+        SyslogFacility::Local2 => Facility::Local2,
+        SyslogFacility::Local3 => Facility::Local3,
+        SyslogFacility::Local4 => Facility::Local4,
+        SyslogFacility::Local5 => Facility::Local5,
+        SyslogFacility::Local6 => Facility::Local6,
+        SyslogFacility::Local7 => Facility::Local7,
+    }
+}
@@ -0,0 +1,139 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A conservative HTML minifier: strips comments and collapses runs of whitespace to a
+//! single space, leaving `<pre>`, `<textarea>`, `<script>`, and `<style>` contents alone.
+
+const PRESERVE: [&str; 4] = ["pre", "textarea", "script", "style"];
+
+pub fn minify_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = match after.find("-->") {
+                Some(end) => &after[end + 3..],
+                None => "",
+            };
+            continue;
+        }
+
+        if let Some(tag) = PRESERVE.iter().find(|tag| opens(rest, tag)) {
+            let close = format!("</{tag}>");
+            let end = find_ignore_case(rest, &close)
+                .map(|i| i + close.len())
+                .unwrap_or(rest.len());
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap();
+        if c.is_whitespace() {
+            let end = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            if !out.is_empty() && end < rest.len() {
+                out.push(' ');
+            }
+            rest = &rest[end..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    out
+}
+
+fn opens(html: &str, tag: &str) -> bool {
+    let Some(after) = html.strip_prefix('<') else {
+        return false;
+    };
+
+    after
+        .get(..tag.len())
+        .is_some_and(|name| name.eq_ignore_ascii_case(tag))
+        && after[tag.len()..].starts_with(|c: char| c == '>' || c.is_whitespace())
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapses_whitespace_and_strips_comments() {
+        assert_eq!(
+            minify_html("<p>\n    Hello,   <!-- greeting -->world\n</p>\n"),
+            "<p> Hello, world </p>"
+        );
+    }
+
+    #[test]
+    fn leaves_preformatted_contents_alone() {
+        let html = "<div>\n  <pre>  two\n    lines</pre>\n  <textarea>a  b</textarea>\n</div>";
+        assert_eq!(
+            minify_html(html),
+            "<div> <pre>  two\n    lines</pre> <textarea>a  b</textarea> </div>"
+        );
+    }
+
+    #[test]
+    fn leaves_scripts_and_styles_alone() {
+        let html = "<SCRIPT type=\"module\">\n  let a =  1; // <!-- not a comment -->\n</SCRIPT>\n\
+                    <style>\n  p  { margin: 0 }\n</style>";
+        assert_eq!(
+            minify_html(html),
+            "<SCRIPT type=\"module\">\n  let a =  1; // <!-- not a comment -->\n</SCRIPT> \
+             <style>\n  p  { margin: 0 }\n</style>"
+        );
+    }
+
+    #[test]
+    fn keeps_an_unclosed_script_to_the_end() {
+        assert_eq!(
+            minify_html("<p> a </p>  <script>x  =  1;"),
+            "<p> a </p> <script>x  =  1;"
+        );
+    }
+
+    #[test]
+    fn does_not_mistake_longer_tag_names() {
+        assert_eq!(
+            minify_html("<prefix>  a  </prefix>"),
+            "<prefix> a </prefix>"
+        );
+    }
+
+    #[test]
+    fn copes_with_multibyte_tag_names() {
+        assert_eq!(minify_html("<aé>  x  </aé>"), "<aé> x </aé>");
+    }
+}
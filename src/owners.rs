@@ -0,0 +1,187 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Everything stored about a site, for its owner.  Owners prove they control a site the
+//! way ACME's http-01 challenge does: they're given a signed token, good for
+//! [`TOKEN_LIFETIME`], to publish at [`TOKEN_PATH`] under the site's URL, and presenting
+//! that token as a bearer token then gets them the site's record as JSON.  Anyone who reads
+//! the published file can use the token until it expires, so owners should take it down
+//! once they have their record.
+
+use std::{error::Error, time::Duration};
+
+use actix_web::cookie::Key;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    database::ValidationAttempt,
+    favicon::read_limited,
+    preview::{sign, verify},
+    relatedlinks::RelatedLink,
+    resolver,
+};
+
+/// How long an owner has to publish a token and fetch their record with it.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Where, relative to the site's URL, the owner publishes their token.
+pub const TOKEN_PATH: &str = ".well-known/10kbclub-owner.txt";
+
+/// Name the token is signed under; it is never sent as a cookie.
+const TOKEN_NAME: &str = "owner";
+
+/// How long we wait for the site to serve the token.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most of the token file we read.
+const TOKEN_FILE_BYTES: usize = 4096;
+
+/// A token for the owner of site `site_id` to publish, good for [`TOKEN_LIFETIME`].
+pub fn token(key: &Key, site_id: u32) -> String {
+    sign(key, TOKEN_NAME, &site_id.to_string(), TOKEN_LIFETIME)
+}
+
+/// Whether `token` came from [`token`] for site `site_id` and hasn't expired.
+pub fn check_token(key: &Key, site_id: u32, token: &str) -> bool {
+    verify(key, TOKEN_NAME, token).is_some_and(|signed| signed == site_id.to_string())
+}
+
+/// Where the owner of the site at `url` publishes their token.
+pub fn token_url(url: &str) -> Result<Url, Box<dyn Error>> {
+    Ok(Url::parse(url)?.join(TOKEN_PATH)?)
+}
+
+/// Whether the site at `url` publishes `token`, on a line of its own, at [`TOKEN_PATH`].
+pub async fn publishes(url: &str, token: &str) -> Result<bool, Box<dyn Error>> {
+    let location = token_url(url)?;
+    let res = resolver::client()
+        .get(location.clone())
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("unable to fetch {location}: {e}"))?;
+    if !res.status().is_success() {
+        return Ok(false);
+    }
+
+    let (_, body) = read_limited(res, TOKEN_FILE_BYTES).await?;
+    Ok(String::from_utf8_lossy(&body)
+        .lines()
+        .any(|line| line.trim() == token))
+}
+
+/// Everything stored about one site, listed or not.
+#[derive(Debug, Serialize)]
+pub struct SiteRecord {
+    pub id: u32,
+    pub slug: String,
+    pub url: String,
+    /// The site's directory entry, once a scan has passed.
+    pub listing: Option<Listing>,
+    /// Where the site is in the validation queue, if it has been queued.
+    pub queue_outcome: Option<String>,
+    pub votes: u32,
+    /// URLs the site was formerly listed under, newest first.
+    pub aliases: Vec<String>,
+    pub related: Vec<RelatedLink>,
+    /// Newest first, as are all the lists that follow.
+    pub validation_attempts: Vec<ValidationAttempt>,
+    pub validation_log: Vec<LogEntry>,
+    pub size_history: Vec<Measurement>,
+    pub regional_sizes: Vec<RegionalSize>,
+    pub uptime_checks: Vec<UptimeCheck>,
+}
+
+/// A site's row in the directory.
+#[derive(Debug, Serialize)]
+pub struct Listing {
+    /// Whether the site is shown in the directory.
+    pub listed: bool,
+    pub banned: bool,
+    pub size: f64,
+    pub mobile_size: Option<f64>,
+    pub date_added: String,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogEntry {
+    pub timestamp: String,
+    pub comment: String,
+}
+
+/// One recorded measurement of the site's size.
+#[derive(Debug, Serialize)]
+pub struct Measurement {
+    pub size: f64,
+    pub measured: String,
+    pub kind: Option<String>,
+    pub passed: Option<bool>,
+}
+
+/// The size of the site as last scanned from one country.
+#[derive(Debug, Serialize)]
+pub struct RegionalSize {
+    pub country: String,
+    pub size: f64,
+    pub scanned: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UptimeCheck {
+    pub checked: String,
+    pub up: bool,
+    /// Why the site was down.
+    pub detail: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_proves_ownership_of_one_site() {
+        let key = Key::generate();
+        let token = token(&key, 7);
+
+        assert!(check_token(&key, 7, &token));
+        assert!(!check_token(&key, 8, &token));
+        assert!(!check_token(&key, 7, &crate::preview::token(&key, "7")));
+    }
+
+    #[test]
+    fn expired_token_is_refused() {
+        let key = Key::generate();
+        let token = sign(&key, TOKEN_NAME, "7", Duration::ZERO);
+
+        assert!(!check_token(&key, 7, &token));
+    }
+
+    #[test]
+    fn token_url_is_under_the_site() {
+        assert_eq!(
+            token_url("https://small.example/blog/").unwrap().as_str(),
+            "https://small.example/blog/.well-known/10kbclub-owner.txt"
+        );
+    }
+}
@@ -0,0 +1,159 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use actix_web::{web, HttpRequest};
+use minijinja::{context, Value};
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    flash::Flash,
+    i18n::Locale,
+    prefs::{Prefs, Theme},
+    readonly::{is_read_only, ReadOnlyHandle},
+    templates::Templates,
+    SortOptions,
+};
+
+/// Per-page metadata used for the document title, description, canonical link, and
+/// OpenGraph/Twitter preview cards.
+#[derive(Debug, Serialize)]
+pub struct PageMeta {
+    pub title: String,
+    pub description: String,
+    pub canonical_url: String,
+    pub image: Option<String>,
+}
+
+/// Builds the template context for a page: the handler's own variables plus the globals
+/// the layout needs (page metadata, the operator's branding, version, language, theme,
+/// current sort, voting and live-update hints, read-only mode, and any pending flash
+/// message).
+pub struct PageContext<'a> {
+    config: &'a Config,
+    meta: PageMeta,
+    sortby: Option<SortOptions>,
+    voting: bool,
+    live: bool,
+    read_only: bool,
+    flash: Option<Flash>,
+    lang: String,
+    theme: Option<Theme>,
+}
+
+/// The site name, tagline, and footer, for pages rendered without a `PageContext`.
+pub fn branding(config: &Config) -> Value {
+    context! {
+        site_name => config.site_name,
+        tagline => config.tagline,
+        footer_links => config.footer_links,
+        contact_email => config.contact_email,
+    }
+}
+
+impl<'a> PageContext<'a> {
+    /// `path` is the canonical path of the page, relative to the configured base URL.
+    pub fn new(config: &'a Config, title: impl Into<String>, path: &str) -> Self {
+        Self {
+            config,
+            meta: PageMeta {
+                title: title.into(),
+                description: config.tagline.clone(),
+                canonical_url: format!("{}{path}", config.base_url),
+                image: config.og_image.clone(),
+            },
+            sortby: None,
+            voting: false,
+            live: false,
+            read_only: false,
+            flash: None,
+            lang: config.default_locale.clone(),
+            theme: None,
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.meta.description = description.into();
+        self
+    }
+
+    pub fn sortby(mut self, sortby: SortOptions) -> Self {
+        self.sortby = Some(sortby);
+        self
+    }
+
+    /// Mark the page as containing vote widgets, so the front-end looks up the visitor's
+    /// votes.
+    pub fn voting(mut self) -> Self {
+        self.voting = true;
+        self
+    }
+
+    /// Have the page subscribe to live updates, if the WebSocket endpoint is enabled.
+    pub fn live(mut self) -> Self {
+        self.live = self.config.websocket.enabled;
+        self
+    }
+
+    /// Pick up per-request state: the negotiated language, the visitor's theme, whether the
+    /// site is read-only, and any pending flash message.
+    pub fn request(mut self, req: &HttpRequest) -> Self {
+        self.read_only = req
+            .app_data::<web::Data<ReadOnlyHandle>>()
+            .is_some_and(|handle| is_read_only(handle));
+        self.flash = Flash::from_request(req);
+        if let Some(Locale(lang)) = Locale::from_request(req) {
+            self.lang = lang;
+        }
+        self.theme = Prefs::from_request(req).theme;
+        self
+    }
+
+    pub fn build(self, ctx: Value) -> Value {
+        context! {
+            meta => self.meta,
+            title => self.meta.title,
+            site_name => self.config.site_name,
+            tagline => self.config.tagline,
+            footer_links => self.config.footer_links,
+            contact_email => self.config.contact_email,
+            version => env!("CARGO_PKG_VERSION"),
+            sortby => self.sortby,
+            voting => self.voting && !self.read_only,
+            live => self.live,
+            read_only => self.read_only,
+            flash => self.flash,
+            lang => self.lang,
+            theme => self.theme,
+            ..ctx
+        }
+    }
+
+    pub fn render(
+        self,
+        templates: &Templates,
+        template: &str,
+        ctx: Value,
+    ) -> Result<String, minijinja::Error> {
+        templates.render(template, self.build(ctx))
+    }
+}
@@ -0,0 +1,198 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A "planet" of member blogs: feeds discovered on member front pages during validation,
+//! polled on a schedule, and merged into `/planet.html` and `/planet.atom`.
+
+use chrono::{DateTime, Utc};
+use reqwest::{header, StatusCode};
+use serde::Serialize;
+use std::error::Error;
+use tracing::{info, warn};
+use url::Url;
+
+use crate::{
+    analyzer::instance_id,
+    config::{Config, PlanetConfig},
+    database::{
+        acquire_lease, get_site_feeds, release_lease, store_planet_posts, update_feed_cache, Pool,
+        SiteFeed,
+    },
+    html::rel_tags,
+};
+
+const PLANET_LEASE: &str = "planet";
+const PLANET_LEASE_TTL: u32 = 600;
+
+/// Feed types worth following, as advertised in `<link type="...">`.
+const FEED_TYPES: [&str; 4] = [
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+    "application/json",
+];
+
+#[derive(Debug)]
+pub struct DiscoveredFeed {
+    pub url: String,
+    pub kind: String,
+}
+
+/// A post from a member's feed, as shown on the planet.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlanetPost {
+    pub guid: String,
+    pub title: String,
+    pub link: String,
+    pub published: String,
+    /// The member site the post came from; filled in when read back from the database.
+    pub site_url: String,
+}
+
+/// Feeds advertised by `<link rel="alternate">` tags in a member's front page.
+pub fn discover_feeds(site: &str, body: &[u8]) -> Vec<DiscoveredFeed> {
+    let Ok(base) = Url::parse(site) else {
+        return vec![];
+    };
+
+    let html = String::from_utf8_lossy(body);
+    let mut feeds: Vec<DiscoveredFeed> = vec![];
+    for link in rel_tags(&html, "alternate", false) {
+        // Ignoring any parameters, as in `application/rss+xml; charset=utf-8`.
+        let Some(feed_type) = link
+            .attribute("type")
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .filter(|feed_type| FEED_TYPES.contains(&&feed_type[..]))
+        else {
+            continue;
+        };
+
+        let Some(url) = base
+            .join(link.href)
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https"))
+        else {
+            continue;
+        };
+
+        if !feeds.iter().any(|feed| feed.url == url.as_str()) {
+            feeds.push(DiscoveredFeed {
+                url: url.to_string(),
+                kind: feed_type,
+            });
+        }
+    }
+
+    feeds
+}
+
+/// Poll every member feed, storing new posts.  Feeds are fetched conditionally, so unchanged
+/// feeds cost a 304.
+pub async fn planet_sweep(pool: &Pool, config: &Config) -> Result<(), Box<dyn Error>> {
+    let Some(planet) = &config.planet else {
+        return Ok(());
+    };
+
+    if !acquire_lease(pool, PLANET_LEASE, instance_id(), PLANET_LEASE_TTL)? {
+        info!("another instance holds the planet lease; skipping sweep");
+        return Ok(());
+    }
+
+    let feeds = get_site_feeds(pool)?;
+    info!("polling {} member feeds", feeds.len());
+
+    for feed in feeds {
+        if let Err(e) = poll_feed(pool, planet, &feed).await {
+            warn!("planet: unable to poll {}: {e:?}", feed.url);
+        }
+    }
+
+    release_lease(pool, PLANET_LEASE, instance_id())
+}
+
+async fn poll_feed(pool: &Pool, planet: &PlanetConfig, feed: &SiteFeed) -> Result<(), String> {
+    let client = reqwest::Client::new();
+    let mut req = client.get(&feed.url);
+    if let Some(etag) = &feed.etag {
+        req = req.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &feed.last_modified {
+        req = req.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let mut res = req.send().await.map_err(|e| e.to_string())?;
+    match res.status() {
+        StatusCode::NOT_MODIFIED => return Ok(()),
+        StatusCode::OK => {}
+        status => return Err(format!("status code is {status}")),
+    }
+
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|value: &header::HeaderValue| value.to_str().ok())
+            .map(String::from)
+    };
+    let etag = header(header::ETAG);
+    let last_modified = header(header::LAST_MODIFIED);
+
+    let mut body = vec![];
+    while let Some(chunk) = res.chunk().await.map_err(|e| e.to_string())? {
+        if body.len() + chunk.len() > planet.max_feed_bytes {
+            return Err(format!(
+                "feed is larger than {} bytes",
+                planet.max_feed_bytes
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let parsed = feed_rs::parser::parse(&body[..]).map_err(|e| e.to_string())?;
+    let base = Url::parse(&feed.url).map_err(|e| e.to_string())?;
+
+    let mut entries = parsed.entries;
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.published.or(entry.updated)));
+
+    let posts: Vec<PlanetPost> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let link = base.join(&entry.links.first()?.href).ok()?;
+            let published: DateTime<Utc> = entry.published.or(entry.updated)?;
+            Some(PlanetPost {
+                guid: entry.id,
+                title: entry
+                    .title
+                    .map(|title| title.content)
+                    .unwrap_or_else(|| link.to_string()),
+                link: link.to_string(),
+                published: published.format("%Y-%m-%d %H:%M:%S").to_string(),
+                site_url: String::new(),
+            })
+        })
+        .take(planet.posts_per_feed)
+        .collect();
+
+    store_planet_posts(pool, feed.id, &posts).map_err(|e| e.to_string())?;
+    update_feed_cache(pool, feed.id, etag.as_deref(), last_modified.as_deref())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,151 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Database connection pool statistics: how many connections are in use, and how long
+//! requests wait to get one.  Exported in the Prometheus text format on `/admin/metrics`,
+//! and checkouts slower than `pool.slow_checkout_ms` are logged as they happen.
+
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use r2d2::{
+    event::{CheckoutEvent, TimeoutEvent},
+    HandleEvent,
+};
+use tracing::warn;
+
+use crate::database::Pool;
+
+pub type PoolStatsHandle = Arc<PoolStats>;
+
+type Metric = fn(&Pool, &PoolStats) -> f64;
+
+#[derive(Debug)]
+pub struct PoolStats {
+    name: &'static str,
+    slow_checkout: Duration,
+    checkouts: AtomicU64,
+    wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+    slow_checkouts: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+impl PoolStats {
+    pub fn new(name: &'static str, slow_checkout: Duration) -> PoolStatsHandle {
+        Arc::new(Self {
+            name,
+            slow_checkout,
+            checkouts: AtomicU64::new(0),
+            wait_micros: AtomicU64::new(0),
+            max_wait_micros: AtomicU64::new(0),
+            slow_checkouts: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+        })
+    }
+
+    /// The r2d2 event handler that feeds these statistics.
+    pub fn handler(self: &Arc<Self>) -> Box<dyn HandleEvent> {
+        Box::new(StatsHandler(self.clone()))
+    }
+}
+
+/// The metrics of every pool, labelled with their names, in the Prometheus text format.
+pub fn render(pools: &[(Pool, PoolStatsHandle)]) -> String {
+    let metrics: [(&str, &str, Metric); 9] = [
+        ("connections", "gauge", |pool, _| {
+            pool.state().connections as f64
+        }),
+        ("idle_connections", "gauge", |pool, _| {
+            pool.state().idle_connections as f64
+        }),
+        ("in_use_connections", "gauge", |pool, _| {
+            let state = pool.state();
+            (state.connections - state.idle_connections) as f64
+        }),
+        ("max_connections", "gauge", |pool, _| pool.max_size() as f64),
+        ("checkouts_total", "counter", |_, stats| {
+            stats.checkouts.load(Ordering::Relaxed) as f64
+        }),
+        ("checkout_wait_seconds_total", "counter", |_, stats| {
+            stats.wait_micros.load(Ordering::Relaxed) as f64 / 1e6
+        }),
+        ("checkout_wait_seconds_max", "gauge", |_, stats| {
+            stats.max_wait_micros.load(Ordering::Relaxed) as f64 / 1e6
+        }),
+        ("slow_checkouts_total", "counter", |_, stats| {
+            stats.slow_checkouts.load(Ordering::Relaxed) as f64
+        }),
+        ("checkout_timeouts_total", "counter", |_, stats| {
+            stats.timeouts.load(Ordering::Relaxed) as f64
+        }),
+    ];
+
+    let mut out = String::new();
+    for (metric, kind, value) in metrics {
+        let _ = writeln!(out, "# TYPE tenkb_db_{metric} {kind}");
+        for (pool, stats) in pools {
+            let _ = writeln!(
+                out,
+                "tenkb_db_{metric}{{pool=\"{}\"}} {}",
+                stats.name,
+                value(pool, stats)
+            );
+        }
+    }
+    out
+}
+
+#[derive(Debug)]
+struct StatsHandler(PoolStatsHandle);
+
+impl HandleEvent for StatsHandler {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        let stats = &self.0;
+        let wait = event.duration();
+        let micros = wait.as_micros() as u64;
+
+        stats.checkouts.fetch_add(1, Ordering::Relaxed);
+        stats.wait_micros.fetch_add(micros, Ordering::Relaxed);
+        stats.max_wait_micros.fetch_max(micros, Ordering::Relaxed);
+
+        if wait >= stats.slow_checkout {
+            stats.slow_checkouts.fetch_add(1, Ordering::Relaxed);
+            warn!("waited {wait:?} for a {} database connection", stats.name);
+        }
+    }
+
+    fn handle_timeout(&self, event: TimeoutEvent) {
+        self.0.timeouts.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            "gave up waiting for a {} database connection after {:?}",
+            self.0.name,
+            event.timeout()
+        );
+    }
+}
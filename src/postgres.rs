@@ -0,0 +1,678 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A [`Storage`] backend on PostgreSQL, built with the `postgres` feature and chosen with
+//! `storage.backend = "postgres"`.  It holds the tables behind the storage operations, with
+//! the same columns as in SQLite and its own migrations in `migrations/postgres`.
+//!
+//! The client is the synchronous `postgres` crate, which runs its own small runtime for
+//! each call.  Tokio won't start one on a thread that is already running async tasks, as
+//! the analyzer's is, so calls made in a runtime's context run on a thread of their own.
+
+use std::error::Error;
+
+use postgres::{Client, GenericClient, NoTls};
+use r2d2_postgres::PostgresConnectionManager;
+use tracing::info;
+
+use crate::{
+    database::{QueuePriority, SizeCheck, ValidationOutcome, SITE_SLUG},
+    error::TenKbError,
+    relatedlinks::RelatedLink,
+    site_domain, slugs,
+    storage::Storage,
+    Site, SiteFilter, SortOptions,
+};
+
+type PgPool = r2d2::Pool<PostgresConnectionManager<NoTls>>;
+
+/// Every schema change in order; a database at schema version `n` has had the first `n`
+/// applied.  Change the schema by appending a migration here, never by editing one that
+/// has already shipped.
+const MIGRATIONS: &[&str] = &[include_str!("../migrations/postgres/0001-schema.sql")];
+
+pub struct PgStorage {
+    /// Only taken when the storage is dropped.
+    pool: Option<PgPool>,
+}
+
+/// Connections keep their times in UTC, as SQLite does.
+#[derive(Debug)]
+struct UtcSession;
+
+impl r2d2::CustomizeConnection<Client, postgres::Error> for UtcSession {
+    fn on_acquire(&self, client: &mut Client) -> Result<(), postgres::Error> {
+        client.batch_execute("SET TIME ZONE 'UTC'")
+    }
+}
+
+impl PgStorage {
+    /// Connect to the database at `url`, keeping at most `pool_size` connections, and bring
+    /// its schema up to date.
+    pub fn connect(url: &str, pool_size: u32) -> Result<Self, TenKbError> {
+        let config = url
+            .parse()
+            .map_err(|e| TenKbError::Msg(format!("invalid storage.url: {e}")))?;
+        let manager = PostgresConnectionManager::new(config, NoTls);
+        let pool = off_runtime(|| {
+            r2d2::Pool::builder()
+                .max_size(pool_size)
+                .connection_customizer(Box::new(UtcSession))
+                .build(manager)
+        })?;
+
+        let storage = Self { pool: Some(pool) };
+        storage.with_client(migrate)?;
+        Ok(storage)
+    }
+
+    /// Run `work` with a pooled connection, away from any runtime (see the module docs).
+    fn with_client<T: Send>(
+        &self,
+        work: impl FnOnce(&mut Client) -> Result<T, TenKbError> + Send,
+    ) -> Result<T, TenKbError> {
+        let pool = self.pool.as_ref().expect("the pool is only taken on drop");
+        off_runtime(move || work(&mut *pool.get()?))
+    }
+}
+
+impl Drop for PgStorage {
+    /// Closing a connection blocks on the client's runtime too.
+    fn drop(&mut self) {
+        let pool = self.pool.take();
+        off_runtime(move || drop(pool));
+    }
+}
+
+fn off_runtime<T: Send>(work: impl FnOnce() -> T + Send) -> T {
+    if tokio::runtime::Handle::try_current().is_err() {
+        return work();
+    }
+
+    std::thread::scope(|scope| {
+        scope
+            .spawn(work)
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Bring the database up to date, applying the migrations it hasn't had yet in one
+/// transaction.  The version table is locked first, so servers starting together don't
+/// both apply them.
+fn migrate(client: &mut Client) -> Result<(), TenKbError> {
+    client.batch_execute("CREATE TABLE IF NOT EXISTS schema_version(version INTEGER NOT NULL)")?;
+
+    let mut tx = client.transaction()?;
+    tx.batch_execute("LOCK TABLE schema_version IN EXCLUSIVE MODE")?;
+    let version: i32 = tx
+        .query_one("SELECT COALESCE(MAX(version), 0) FROM schema_version", &[])?
+        .get(0);
+
+    for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        tx.batch_execute(migration)?;
+        tx.execute(
+            "INSERT INTO schema_version (version) VALUES ($1)",
+            &[&(applied as i32 + 1)],
+        )?;
+        info!(
+            "migrated postgres storage to schema version {}",
+            applied + 1
+        );
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// A date `window` (an SQLite modifier such as `-7 days`) before today.
+fn window_start(window: &str) -> String {
+    format!(
+        "(CURRENT_DATE - INTERVAL '{}')",
+        window.trim_start_matches('-')
+    )
+}
+
+fn insert_attempt(
+    client: &mut impl GenericClient,
+    site: &str,
+    outcome: ValidationOutcome,
+    size: Option<f64>,
+    detail: Option<&str>,
+) -> Result<(), postgres::Error> {
+    client.execute(
+        r#"INSERT INTO validation_attempts (site_id, outcome, size, detail, attempted)
+           VALUES ((SELECT id FROM site_ids WHERE url = $1), $2, $3, $4, LOCALTIMESTAMP(0))"#,
+        &[&site, &outcome.as_str(), &size, &detail],
+    )?;
+    Ok(())
+}
+
+fn set_outcome(
+    client: &mut impl GenericClient,
+    site: &str,
+    outcome: ValidationOutcome,
+) -> Result<(), postgres::Error> {
+    client.execute(
+        r#"UPDATE validation_queue SET outcome = $1
+           WHERE id = (SELECT id FROM site_ids WHERE url = $2)"#,
+        &[&outcome.as_str(), &site],
+    )?;
+    Ok(())
+}
+
+fn replace_related(
+    client: &mut impl GenericClient,
+    id: i32,
+    related: &[RelatedLink],
+) -> Result<(), postgres::Error> {
+    client.execute(r#"DELETE FROM related WHERE id = $1"#, &[&id])?;
+
+    let statement = client.prepare(r#"INSERT INTO related VALUES ($1, $2, $3, $4, $5, $6, $7)"#)?;
+    for link in related {
+        client.execute(
+            &statement,
+            &[
+                &id,
+                &link.url,
+                &link.discussion_url,
+                &link.date,
+                &link.description,
+                &(link.upvotes as i32),
+                &(link.comments as i32),
+            ],
+        )?;
+    }
+
+    client.execute(
+        r#"INSERT INTO related_refreshes (site_id, refreshed, requested)
+           VALUES ($1, LOCALTIMESTAMP(0), NULL)
+           ON CONFLICT (site_id) DO UPDATE SET refreshed = LOCALTIMESTAMP(0), requested = NULL"#,
+        &[&id],
+    )?;
+    Ok(())
+}
+
+fn site_id(client: &mut impl GenericClient, site: &str) -> Result<i32, postgres::Error> {
+    Ok(client
+        .query_one(r#"SELECT id FROM site_ids WHERE url = $1"#, &[&site])?
+        .get(0))
+}
+
+impl Storage for PgStorage {
+    fn get_sites(
+        &self,
+        sortby: SortOptions,
+        filter: &SiteFilter,
+        skip: usize,
+        paginate: usize,
+    ) -> Result<Vec<Site>, TenKbError> {
+        // The same columns and orders as `database::get_sites`.  Ties are broken by id, so
+        // that pages don't overlap.
+        let order = match sortby {
+            SortOptions::Votes => "upvotes DESC, size ASC",
+            SortOptions::Size => "size",
+            SortOptions::New => "date_added",
+            SortOptions::Discussed => "points DESC, comments DESC, size ASC",
+            SortOptions::Week | SortOptions::Month => "recent DESC, upvotes DESC, size ASC",
+        };
+
+        let recent = match sortby.window().map(window_start) {
+            Some(since) => format!(
+                r#"GREATEST((SELECT COUNT(*) FROM votes
+                    WHERE votes.id = site_ids.id AND NOT votes.shadowed) - COALESCE(
+                       (SELECT votes FROM vote_snapshots
+                        WHERE site_id = site_ids.id AND day <= {since}
+                        ORDER BY day DESC LIMIT 1),
+                       CASE WHEN sites.date_added >= {since} THEN 0 ELSE
+                           (SELECT votes FROM vote_snapshots WHERE site_id = site_ids.id
+                            ORDER BY day LIMIT 1) END,
+                       0), 0)"#
+            ),
+            None => String::from("NULL::BIGINT"),
+        };
+
+        // Favicons and screenshots are kept in SQLite, so none are served.
+        let query = format!(
+            r#"SELECT site_ids.id, site_ids.url, sites.size,
+                      (SELECT COUNT(*) FROM related WHERE related.id = site_ids.id) AS related,
+                      FLOOR(EXTRACT(EPOCH FROM LOCALTIMESTAMP - sites.date_added)
+                            / (365.25 * 86400))::INTEGER,
+                      (SELECT COUNT(*) FROM votes
+                       WHERE votes.id = site_ids.id AND NOT votes.shadowed) AS upvotes,
+                      sites.date_added::TEXT AS date_added,
+                      (SELECT COALESCE(SUM(score), 0) FROM related
+                       WHERE related.id = site_ids.id) AS points,
+                      (SELECT COALESCE(SUM(comments), 0) FROM related
+                       WHERE related.id = site_ids.id) AS comments,
+                      sites.note,
+                      {recent} AS recent,
+                      {SITE_SLUG}
+               FROM site_ids JOIN sites ON site_ids.id = sites.id
+               WHERE valid = true{}
+               ORDER BY {order}, site_ids.id LIMIT $1 OFFSET $2"#,
+            filter.sql()
+        );
+
+        let rows = self.with_client(|client| {
+            Ok(client.query(&query, &[&(paginate as i64), &(skip as i64)])?)
+        })?;
+
+        Ok(rows
+            .iter()
+            .zip(skip + 1..)
+            .map(|(row, offset)| Site {
+                offset,
+                id: row.get::<_, i32>(0) as u32,
+                url: row.get(1),
+                size_bytes: row.get::<_, f64>(2) as u64,
+                related: row.get::<_, i64>(3) as u32,
+                member_years: row.get::<_, i32>(4) as u32,
+                votes: row.get::<_, i64>(5) as u32,
+                date_added: row.get(6),
+                discussion_points: row.get::<_, i64>(7) as u64,
+                discussion_comments: row.get::<_, i64>(8) as u64,
+                note: row.get(9),
+                favicon: false,
+                recent_votes: row.get::<_, Option<i64>>(10).map(|votes| votes as u32),
+                thumbnail: false,
+                slug: row.get(11),
+            })
+            .collect())
+    }
+
+    fn get_site_count(&self, filter: &SiteFilter) -> Result<usize, TenKbError> {
+        let query = format!(
+            r#"SELECT COUNT(site_ids.id) FROM site_ids JOIN sites ON site_ids.id = sites.id
+               WHERE valid = true{}"#,
+            filter.sql()
+        );
+        let count: i64 = self.with_client(|client| Ok(client.query_one(&query, &[])?.get(0)))?;
+        Ok(count as usize)
+    }
+
+    fn get_site_url(&self, id: u32) -> Result<String, TenKbError> {
+        self.with_client(|client| {
+            client
+                .query_opt(r#"SELECT url FROM site_ids WHERE id = $1"#, &[&(id as i32)])?
+                .map(|row| row.get(0))
+                .ok_or_else(|| TenKbError::Msg("Query returned no rows".into()))
+        })
+    }
+
+    fn find_site(&self, key: &str) -> Result<Option<(u32, String)>, TenKbError> {
+        let row = if key.bytes().all(|b| b.is_ascii_digit()) {
+            let Ok(id) = key.parse::<i32>() else {
+                return Ok(None);
+            };
+            let query =
+                format!(r#"SELECT site_ids.id, {SITE_SLUG} FROM site_ids WHERE site_ids.id = $1"#);
+            self.with_client(|client| Ok(client.query_opt(&query, &[&id])?))?
+        } else {
+            let query = format!(
+                r#"SELECT site_ids.id, {SITE_SLUG}
+                   FROM site_slugs JOIN site_ids ON site_ids.id = site_slugs.site_id
+                   WHERE site_slugs.slug = $1"#
+            );
+            self.with_client(|client| Ok(client.query_opt(&query, &[&key])?))?
+        };
+
+        Ok(row.map(|row| (row.get::<_, i32>(0) as u32, row.get(1))))
+    }
+
+    fn check_site_active(&self, site: &str) -> Result<bool, TenKbError> {
+        self.with_client(|client| {
+            Ok(client
+                .query_one(
+                    r#"SELECT EXISTS (SELECT 1 FROM site_ids JOIN sites ON site_ids.id = sites.id
+                                      WHERE site_ids.url = $1 AND sites.valid = true)"#,
+                    &[&site],
+                )?
+                .get(0))
+        })
+    }
+
+    fn get_alias_target(&self, url: &str) -> Result<Option<u32>, TenKbError> {
+        let row = self.with_client(|client| {
+            Ok(client.query_opt(
+                r#"SELECT site_id FROM site_aliases WHERE url = $1"#,
+                &[&url],
+            )?)
+        })?;
+        Ok(row.map(|row| row.get::<_, i32>(0) as u32))
+    }
+
+    fn get_blocked_patterns(&self) -> Result<Vec<String>, TenKbError> {
+        let rows = self.with_client(|client| {
+            Ok(client.query(r#"SELECT pattern FROM blocked_site_patterns"#, &[])?)
+        })?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    fn check_site_queued(&self, site: &str) -> Result<bool, TenKbError> {
+        self.with_client(|client| {
+            Ok(client
+                .query_one(
+                    r#"SELECT EXISTS (SELECT 1 FROM site_ids
+                                      JOIN validation_queue ON validation_queue.id = site_ids.id
+                                      WHERE site_ids.url = $1)"#,
+                    &[&site],
+                )?
+                .get(0))
+        })
+    }
+
+    fn count_domain_pages(&self, host: &str) -> Result<usize, TenKbError> {
+        let count: i64 = self.with_client(|client| {
+            Ok(client
+                .query_one(
+                    r#"SELECT COUNT(*) FROM site_ids
+                       LEFT JOIN sites ON sites.id = site_ids.id
+                       LEFT JOIN validation_queue ON validation_queue.id = site_ids.id
+                       WHERE site_ids.host = $1
+                         AND (sites.valid = true OR validation_queue.outcome = 'pending')"#,
+                    &[&host],
+                )?
+                .get(0))
+        })?;
+        Ok(count as usize)
+    }
+
+    fn submit_site(&self, site: String, pages_per_domain: usize) -> Result<(), TenKbError> {
+        self.check_submission(&site, pages_per_domain)?;
+
+        let host = site_domain(&site);
+        self.with_client(|client| {
+            let mut tx = client.transaction()?;
+            let id: i32 = tx
+                .query_one(
+                    r#"INSERT INTO site_ids (url, host) VALUES ($1, $2) RETURNING id"#,
+                    &[&site, &host],
+                )?
+                .get(0);
+
+            let mut rng = rand::thread_rng();
+            while tx.execute(
+                r#"INSERT INTO site_slugs (slug, site_id, is_primary, added)
+                   VALUES ($1, $2, true, LOCALTIMESTAMP(0)) ON CONFLICT DO NOTHING"#,
+                &[&slugs::generate(&mut rng), &id],
+            )? == 0
+            {}
+
+            tx.execute(
+                r#"INSERT INTO validation_queue (id, date_added, outcome, priority)
+                   VALUES ($1, LOCALTIMESTAMP(0), 'pending', $2)"#,
+                &[&id, &(QueuePriority::Submission as i32)],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+    }
+
+    fn generate_id(&self, id: String) -> Result<(), TenKbError> {
+        self.with_client(|client| {
+            client.execute(
+                r#"INSERT INTO voter_ids (uuid, created) VALUES ($1, LOCALTIMESTAMP(0))"#,
+                &[&id],
+            )?;
+            Ok(())
+        })
+    }
+
+    fn voter_exists(&self, voter_id: &str) -> Result<bool, TenKbError> {
+        self.with_client(|client| {
+            Ok(client
+                .query_one(
+                    r#"SELECT EXISTS (SELECT 1 FROM voter_ids WHERE uuid = $1)"#,
+                    &[&voter_id],
+                )?
+                .get(0))
+        })
+    }
+
+    fn cast_vote(
+        &self,
+        voter_id: String,
+        site_id: u32,
+        vote: isize,
+        shadowed: bool,
+    ) -> Result<(bool, u32), TenKbError> {
+        // As in `database::cast_vote`, an unknown voter inserts nothing; so does an unknown
+        // site, which the foreign key would otherwise refuse.
+        let upsert_query = r#"INSERT INTO votes (id, voter_id, shadowed, voted)
+                              SELECT $1::INTEGER, id, $2::BOOL, LOCALTIMESTAMP(0)
+                              FROM voter_ids WHERE uuid = $3
+                                AND EXISTS (SELECT 1 FROM site_ids WHERE site_ids.id = $1)
+                              ON CONFLICT (id, voter_id) DO NOTHING"#;
+        let unvote_query = r#"DELETE FROM votes
+                              WHERE id = $1
+                                AND voter_id = (SELECT id FROM voter_ids WHERE uuid = $2)"#;
+        let count_query = r#"SELECT COUNT(*) FROM votes WHERE id = $1
+                             AND (NOT shadowed
+                                  OR ($2 AND voter_id = (SELECT id FROM voter_ids
+                                                         WHERE uuid = $3)))"#;
+
+        let site_id = site_id as i32;
+        self.with_client(|client| {
+            let mut tx = client.transaction()?;
+            let changed = if vote == 0 {
+                tx.execute(unvote_query, &[&site_id, &voter_id])?
+            } else {
+                tx.execute(upsert_query, &[&site_id, &shadowed, &voter_id])?
+            };
+            let total: i64 = tx
+                .query_one(count_query, &[&site_id, &shadowed, &voter_id])?
+                .get(0);
+            tx.commit()?;
+            Ok((changed > 0, total as u32))
+        })
+    }
+
+    fn get_votes(&self, voter_id: String) -> Result<Vec<u32>, TenKbError> {
+        let rows = self.with_client(|client| {
+            Ok(client.query(
+                r#"SELECT id FROM votes
+                   WHERE voter_id = (SELECT id FROM voter_ids WHERE uuid = $1)"#,
+                &[&voter_id],
+            )?)
+        })?;
+        Ok(rows.iter().map(|row| row.get::<_, i32>(0) as u32).collect())
+    }
+
+    fn snapshot_vote_counts(&self) -> Result<usize, TenKbError> {
+        let recorded = self.with_client(|client| {
+            Ok(client.execute(
+                r#"INSERT INTO vote_snapshots (site_id, day, votes)
+                   SELECT DISTINCT sites.id, CURRENT_DATE,
+                          (SELECT COUNT(*) FROM votes
+                           WHERE votes.id = sites.id AND NOT votes.shadowed)
+                   FROM sites WHERE valid = true
+                   ON CONFLICT (site_id, day) DO UPDATE SET votes = excluded.votes"#,
+                &[],
+            )?)
+        })?;
+        Ok(recorded as usize)
+    }
+
+    fn get_validation_queue(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let rows = self
+            .with_client(|client| {
+                Ok(client.query(
+                    r#"SELECT site_ids.url FROM site_ids
+                       JOIN validation_queue ON site_ids.id = validation_queue.id
+                       WHERE validation_queue.outcome = 'pending'
+                       ORDER BY validation_queue.priority DESC, validation_queue.date_added ASC"#,
+                    &[],
+                )?)
+            })
+            .map_err(|TenKbError::Msg(msg)| msg)?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    fn mark_good(
+        &self,
+        site: &str,
+        size: f64,
+        mobile_size: Option<f64>,
+        related: &[RelatedLink],
+    ) -> Result<u32, Box<dyn Error>> {
+        let id = self
+            .with_client(|client| {
+                let mut tx = client.transaction()?;
+                let id = site_id(&mut tx, site)?;
+
+                insert_attempt(&mut tx, site, ValidationOutcome::Passed, Some(size), None)?;
+                tx.execute(r#"DELETE FROM validation_queue WHERE id = $1"#, &[&id])?;
+                tx.execute(
+                    r#"INSERT INTO sites (id, date_added, size, mobile_size, valid)
+                       VALUES ($1, LOCALTIMESTAMP(0), $2, $3, true)"#,
+                    &[&id, &size, &mobile_size],
+                )?;
+                tx.execute(
+                    r#"INSERT INTO size_history (site_id, size, measured, kind, passed)
+                       VALUES ($1, $2, LOCALTIMESTAMP(0), $3, true)"#,
+                    &[&id, &size, &SizeCheck::Listing.as_str()],
+                )?;
+                replace_related(&mut tx, id, related)?;
+
+                tx.commit()?;
+                Ok(id)
+            })
+            .map_err(|TenKbError::Msg(msg)| msg)?;
+        Ok(id as u32)
+    }
+
+    fn mark_bad(
+        &self,
+        site: &str,
+        outcome: ValidationOutcome,
+        detail: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.with_client(|client| {
+            let mut tx = client.transaction()?;
+            insert_attempt(&mut tx, site, outcome, None, Some(detail))?;
+            set_outcome(&mut tx, site, outcome)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .map_err(|TenKbError::Msg(msg)| msg.into())
+    }
+
+    fn mark_bad_size(&self, site: &str, size: f64) -> Result<(), Box<dyn Error>> {
+        self.with_client(|client| {
+            let mut tx = client.transaction()?;
+            tx.execute(
+                r#"INSERT INTO validation_log
+                   VALUES ((SELECT id FROM site_ids WHERE url = $1), LOCALTIMESTAMP(0), $2)"#,
+                &[
+                    &site,
+                    &format!("size validation failed: site is {size} bytes"),
+                ],
+            )?;
+            insert_attempt(
+                &mut tx,
+                site,
+                ValidationOutcome::FailedSize,
+                Some(size),
+                None,
+            )?;
+            set_outcome(&mut tx, site, ValidationOutcome::FailedSize)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .map_err(|TenKbError::Msg(msg)| msg.into())
+    }
+
+    fn get_related(&self, site: u32) -> Result<Vec<RelatedLink>, TenKbError> {
+        let rows = self.with_client(|client| {
+            Ok(client.query(
+                r#"SELECT url, discussion_url, date, title, score, comments
+                   FROM related WHERE id = $1"#,
+                &[&(site as i32)],
+            )?)
+        })?;
+
+        Ok(rows
+            .iter()
+            .map(|row| RelatedLink {
+                url: row.get(0),
+                discussion_url: row.get(1),
+                date: row.get(2),
+                description: row.get(3),
+                upvotes: row.get::<_, i32>(4) as usize,
+                comments: row.get::<_, i32>(5) as usize,
+            })
+            .collect())
+    }
+
+    fn update_related(&self, site: &str, related: &[RelatedLink]) -> Result<(), Box<dyn Error>> {
+        self.with_client(|client| {
+            let mut tx = client.transaction()?;
+            let id = site_id(&mut tx, site)?;
+            replace_related(&mut tx, id, related)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .map_err(|TenKbError::Msg(msg)| msg.into())
+    }
+
+    fn get_related_refresh(
+        &self,
+        site: u32,
+    ) -> Result<(Option<String>, Option<String>), TenKbError> {
+        let row = self.with_client(|client| {
+            Ok(client.query_opt(
+                r#"SELECT refreshed::TEXT, requested::TEXT FROM related_refreshes
+                   WHERE site_id = $1"#,
+                &[&(site as i32)],
+            )?)
+        })?;
+        Ok(row.map_or((None, None), |row| (row.get(0), row.get(1))))
+    }
+
+    fn request_related_refresh(&self, site: u32) -> Result<bool, TenKbError> {
+        let requested = self.with_client(|client| {
+            Ok(client.execute(
+                r#"INSERT INTO related_refreshes (site_id, refreshed, requested)
+                   VALUES ($1, NULL, LOCALTIMESTAMP(0))
+                   ON CONFLICT (site_id) DO UPDATE SET requested = LOCALTIMESTAMP(0)
+                   WHERE related_refreshes.requested IS NULL"#,
+                &[&(site as i32)],
+            )?)
+        })?;
+        Ok(requested > 0)
+    }
+
+    fn get_requested_refreshes(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let rows = self
+            .with_client(|client| {
+                Ok(client.query(
+                    r#"SELECT site_ids.url FROM related_refreshes
+                       JOIN site_ids ON site_ids.id = related_refreshes.site_id
+                       WHERE requested IS NOT NULL ORDER BY requested"#,
+                    &[],
+                )?)
+            })
+            .map_err(|TenKbError::Msg(msg)| msg)?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+}
@@ -0,0 +1,229 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Display preferences (theme, default sort, page size, and view) kept in a signed cookie.
+
+use actix_web::{
+    body::MessageBody,
+    cookie::{time::Duration, Cookie, CookieJar, Key, SameSite},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web, Error, HttpMessage, HttpRequest,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::form_urlencoded;
+
+use crate::{
+    config::{Config, IndexConfig},
+    SortOptions,
+};
+
+const PREFS_COOKIE: &str = "10kb_prefs";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+/// How the index lists sites; each has its own `index_<view>.html` partial.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum View {
+    /// Every detail, one row per site.
+    #[default]
+    Table,
+    /// Just the links, for scanning (or scraping) the whole directory.
+    Compact,
+    /// Screenshot thumbnails.
+    Gallery,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Prefs {
+    pub theme: Option<Theme>,
+    pub sortby: Option<SortOptions>,
+    pub paginate: Option<usize>,
+    pub view: Option<View>,
+    /// The operator's front-page defaults, used where the visitor has made no choice.
+    #[serde(skip)]
+    pub site: IndexConfig,
+}
+
+impl Theme {
+    pub fn parse(theme: &str) -> Option<Self> {
+        match theme {
+            "light" => Some(Theme::Light),
+            "dark" => Some(Theme::Dark),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+}
+
+impl View {
+    pub fn parse(view: &str) -> Option<Self> {
+        match view {
+            "table" => Some(View::Table),
+            "compact" => Some(View::Compact),
+            "gallery" => Some(View::Gallery),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            View::Table => "table",
+            View::Compact => "compact",
+            View::Gallery => "gallery",
+        }
+    }
+}
+
+impl Prefs {
+    /// The visitor's preferences, or the site defaults if they have none.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        req.extensions().get::<Prefs>().cloned().unwrap_or_default()
+    }
+
+    /// No visitor choices, only the site's defaults.
+    pub fn site(config: &Config) -> Self {
+        Self {
+            site: config.index,
+            ..Self::default()
+        }
+    }
+
+    pub fn default_sort(&self) -> SortOptions {
+        self.sortby.unwrap_or(self.site.sortby)
+    }
+
+    pub fn default_paginate(&self) -> usize {
+        self.site
+            .clamp_paginate(self.paginate.unwrap_or(self.site.paginate))
+    }
+
+    pub fn default_view(&self) -> View {
+        self.view.unwrap_or(self.site.view)
+    }
+
+    /// A signed cookie holding these preferences for a year.
+    pub fn cookie(&self, key: &Key) -> Cookie<'static> {
+        let mut value = form_urlencoded::Serializer::new(String::new());
+        if let Some(theme) = self.theme {
+            value.append_pair("theme", theme.as_str());
+        }
+        if let Some(sortby) = self.sortby {
+            value.append_pair("sortby", &sortby.to_string());
+        }
+        if let Some(paginate) = self.paginate {
+            value.append_pair("paginate", &paginate.to_string());
+        }
+        if let Some(view) = self.view {
+            value.append_pair("view", view.as_str());
+        }
+
+        let cookie = Cookie::build(PREFS_COOKIE, value.finish())
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .max_age(Duration::days(365))
+            .finish();
+
+        let mut jar = CookieJar::new();
+        jar.signed_mut(key).add(cookie);
+        jar.get(PREFS_COOKIE)
+            .cloned()
+            .expect("signed cookie was just added")
+    }
+
+    fn decode(value: &str, site: IndexConfig) -> Self {
+        let mut prefs = Self {
+            site,
+            ..Self::default()
+        };
+
+        for (key, val) in form_urlencoded::parse(value.as_bytes()) {
+            match &key[..] {
+                "theme" => prefs.theme = Theme::parse(&val),
+                "sortby" => prefs.sortby = val.parse().ok(),
+                "paginate" => prefs.paginate = val.parse().ok().filter(|p| *p > 0),
+                "view" => prefs.view = View::parse(&val),
+                _ => {}
+            }
+        }
+
+        prefs
+    }
+}
+
+/// The key used to sign preference cookies.  Without a configured `cookie_secret` a random
+/// key is generated, so preferences only last until the server restarts.
+pub fn cookie_key(config: &Config) -> Result<Key, String> {
+    match &config.cookie_secret {
+        Some(secret) if secret.len() < 32 => {
+            Err(String::from("cookie_secret must be at least 32 bytes long"))
+        }
+        Some(secret) => Ok(Key::derive_from(secret.as_bytes())),
+        None => {
+            warn!("no cookie_secret configured; preferences will not survive a restart");
+            Ok(Key::generate())
+        }
+    }
+}
+
+/// Middleware that verifies an incoming preferences cookie and makes it available to
+/// handlers.  Cookies with a bad signature are ignored.
+pub async fn prefs_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let site = req
+        .app_data::<web::Data<Config>>()
+        .map(|config| config.index)
+        .unwrap_or_default();
+    let mut prefs = Prefs {
+        site,
+        ..Prefs::default()
+    };
+
+    if let (Some(key), Some(cookie)) = (req.app_data::<web::Data<Key>>(), req.cookie(PREFS_COOKIE))
+    {
+        let mut jar = CookieJar::new();
+        jar.add_original(cookie);
+
+        if let Some(cookie) = jar.signed(key).get(PREFS_COOKIE) {
+            prefs = Prefs::decode(cookie.value(), site);
+        }
+    }
+    req.extensions_mut().insert(prefs);
+
+    next.call(req).await
+}
@@ -0,0 +1,152 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The first step of a submission: before a site is queued for the (rate-limited, paid)
+//! Cloudflare scan, we fetch its front page ourselves and show the submitter what we found,
+//! along with a signed token they send back to confirm.  Sites whose page alone is over the
+//! limit never reach the queue.
+
+use std::{
+    error::Error,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::cookie::{Cookie, CookieJar, Key};
+use reqwest::header;
+use url::Url;
+
+use crate::{cloudflare::SIZE_LIMIT, favicon::read_limited, resolver};
+
+/// How long a submitter has to confirm a preview.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+/// How long we wait for a submitted site's front page.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Name the token is signed under; it is never sent as a cookie.
+const TOKEN_NAME: &str = "submission";
+
+/// The compressed size of the front page of `url` as it comes over the wire, without the
+/// images, styles, and scripts the full scan also counts.  Pages that can't be fetched, or
+/// that are already over [`SIZE_LIMIT`] by themselves, are an error.
+pub async fn estimate_size(url: &Url) -> Result<usize, Box<dyn Error>> {
+    let res = resolver::client()
+        .get(url.clone())
+        .header(header::ACCEPT_ENCODING, "gzip, deflate, br")
+        .timeout(FETCH_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| format!("unable to fetch {url}: {e}"))?;
+    if !res.status().is_success() {
+        return Err(format!("{url} answered with {}", res.status()).into());
+    }
+
+    let (_, body) = read_limited(res, SIZE_LIMIT)
+        .await
+        .map_err(|_| format!("{url} is over {SIZE_LIMIT} bytes before anything it loads"))?;
+    Ok(body.len())
+}
+
+/// A token confirming that `site` was previewed, good for [`TOKEN_LIFETIME`].
+pub fn token(key: &Key, site: &str) -> String {
+    sign(key, TOKEN_NAME, site, TOKEN_LIFETIME)
+}
+
+/// Whether `token` came from [`token`] for `site` and hasn't expired.
+pub fn check_token(key: &Key, site: &str, token: &str) -> bool {
+    verify(key, TOKEN_NAME, token).is_some_and(|signed| signed == site)
+}
+
+/// `value`, signed with the cookie key under `name` (so tokens for one purpose can't be
+/// passed off as another's) and good for `lifetime`.  The signature doesn't cover a cookie's
+/// name, so `name` goes in the signed value too.
+pub(crate) fn sign(key: &Key, name: &'static str, value: &str, lifetime: Duration) -> String {
+    let expires = now() + lifetime.as_secs();
+    let mut jar = CookieJar::new();
+    jar.signed_mut(key)
+        .add(Cookie::new(name, format!("{name}:{expires}:{value}")));
+    jar.get(name)
+        .expect("token was just signed")
+        .value()
+        .to_owned()
+}
+
+/// The value `token` was signed with by [`sign`], if it was signed under `name` and hasn't
+/// expired.
+pub(crate) fn verify(key: &Key, name: &'static str, token: &str) -> Option<String> {
+    let mut jar = CookieJar::new();
+    jar.add_original(Cookie::new(name, token.to_owned()));
+    let cookie = jar.signed(key).get(name)?;
+
+    let (signed_name, rest) = cookie.value().split_once(':')?;
+    let (expires, value) = rest.split_once(':')?;
+    if signed_name != name {
+        return None;
+    }
+
+    expires
+        .parse::<u64>()
+        .is_ok_and(|expires| expires > now())
+        .then(|| value.to_owned())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_confirms_only_its_own_site() {
+        let key = Key::generate();
+        let token = token(&key, "https://small.example/");
+
+        assert!(check_token(&key, "https://small.example/", &token));
+        assert!(!check_token(&key, "https://other.example/", &token));
+        assert!(!check_token(
+            &Key::generate(),
+            "https://small.example/",
+            &token
+        ));
+    }
+
+    #[test]
+    fn expired_token_is_refused() {
+        let key = Key::generate();
+        let token = sign(&key, TOKEN_NAME, "https://small.example/", Duration::ZERO);
+
+        assert!(!check_token(&key, "https://small.example/", &token));
+    }
+
+    #[test]
+    fn token_signed_for_another_purpose_is_refused() {
+        let key = Key::generate();
+        let token = sign(&key, "owner", "https://small.example/", TOKEN_LIFETIME);
+
+        assert!(!check_token(&key, "https://small.example/", &token));
+    }
+}
@@ -0,0 +1,97 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! "Top this week/month" rankings: the sites that gained the most votes over a period,
+//! computed from the daily vote snapshots.  The snapshots change once a day, so rankings are
+//! kept for a while rather than recomputed for every request.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{error::TenKbError, storage::Storage, Site, SiteFilter, SortOptions};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    #[default]
+    Week,
+    Month,
+}
+
+impl Period {
+    pub fn sort(&self) -> SortOptions {
+        match self {
+            Period::Week => SortOptions::Week,
+            Period::Month => SortOptions::Month,
+        }
+    }
+}
+
+struct Ranking {
+    computed: Instant,
+    sites: Vec<Site>,
+}
+
+pub struct TopCache {
+    ttl: Duration,
+    limit: usize,
+    rankings: Mutex<HashMap<Period, Ranking>>,
+}
+
+impl TopCache {
+    pub fn new(ttl: Duration, limit: usize) -> Self {
+        Self {
+            ttl,
+            limit,
+            rankings: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The sites that gained votes over `period`, most first, recomputed once the cached
+    /// ranking is older than the TTL.
+    pub fn get(&self, storage: &dyn Storage, period: Period) -> Result<Vec<Site>, TenKbError> {
+        if let Some(ranking) = self.rankings.lock().unwrap().get(&period) {
+            if ranking.computed.elapsed() < self.ttl {
+                return Ok(ranking.sites.clone());
+            }
+        }
+
+        let sites = storage
+            .get_sites(period.sort(), &SiteFilter::default(), 0, self.limit)?
+            .into_iter()
+            .filter(|site| site.recent_votes.is_some_and(|votes| votes > 0))
+            .collect::<Vec<_>>();
+
+        self.rankings.lock().unwrap().insert(
+            period,
+            Ranking {
+                computed: Instant::now(),
+                sites: sites.clone(),
+            },
+        );
+        Ok(sites)
+    }
+}
@@ -0,0 +1,87 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Read-only mode: submissions and voting are refused while browsing keeps working.  It
+//! starts from the `read_only` config flag and can be toggled from the admin dashboard.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    web, Error, ResponseError,
+};
+
+use crate::error::{wants_html, HtmlError, JsonError};
+
+/// Endpoints that write to the database on behalf of visitors.
+const WRITE_PATHS: [&str; 4] = ["/dosubmit/", "/id/", "/vote/", "/webmention"];
+
+fn is_write(path: &str) -> bool {
+    WRITE_PATHS.contains(&path)
+        || (path.starts_with("/related/") && path.ends_with("/refresh/"))
+        || (path.starts_with("/site/")
+            && (path.ends_with("/rescan/")
+                || path.ends_with("/comments/")
+                || path.ends_with("/discussions/")))
+}
+
+const READ_ONLY_MESSAGE: &str =
+    "The site is read-only for the moment; submissions and voting will be back shortly.";
+
+pub type ReadOnlyHandle = Arc<AtomicBool>;
+
+pub fn is_read_only(handle: &ReadOnlyHandle) -> bool {
+    handle.load(Ordering::Relaxed)
+}
+
+pub fn set_read_only(handle: &ReadOnlyHandle, enabled: bool) {
+    handle.store(enabled, Ordering::Relaxed);
+}
+
+/// Middleware that answers visitor writes with a 403 while read-only mode is on.
+pub async fn read_only_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let blocked = req.method() == Method::POST
+        && is_write(req.path())
+        && req
+            .app_data::<web::Data<ReadOnlyHandle>>()
+            .is_some_and(|handle| is_read_only(handle));
+
+    if !blocked {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let res = if wants_html(req.headers()) {
+        HtmlError::new(403, READ_ONLY_MESSAGE).error_response()
+    } else {
+        JsonError::new(403, READ_ONLY_MESSAGE).error_response()
+    };
+    Ok(req.into_response(res).map_into_right_body())
+}
@@ -20,14 +20,16 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::{config::RelatedSourceConfig, resolver};
+use chrono::{DateTime, Utc};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::{error::Error, future::Future};
 use tokio::runtime::Handle;
 use tracing::debug;
 use url::Url;
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct RelatedLink {
     pub url: String,
     pub discussion_url: String,
@@ -37,7 +39,82 @@ pub struct RelatedLink {
     pub date: String,
 }
 
-type RelatedLinkResult = Result<Vec<RelatedLink>, Box<dyn Error>>;
+pub type RelatedLinkResult = Result<Vec<RelatedLink>, Box<dyn Error>>;
+
+/// Where discussions of a site are looked up.  `LiveSources` asks Hacker News and
+/// Lobsters; `testing::MockSources` answers from canned links.
+pub trait RelatedSources: Send + Sync {
+    fn hackernews(&self, site: &str) -> impl Future<Output = RelatedLinkResult> + Send;
+
+    fn lobsters(&self, site: &str) -> impl Future<Output = RelatedLinkResult> + Send;
+}
+
+pub struct LiveSources;
+
+impl RelatedSources for LiveSources {
+    async fn hackernews(&self, site: &str) -> RelatedLinkResult {
+        hackernews(site, Handle::current()).await
+    }
+
+    async fn lobsters(&self, site: &str) -> RelatedLinkResult {
+        lobsters(site, Handle::current()).await
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub enum BreakerState {
+    #[default]
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// Request/failure counts for one related-link source, plus a circuit breaker that stops
+/// calling a source that keeps failing.  Once the cooldown has elapsed, a single probe
+/// request is allowed through (half-open); success closes the breaker, failure re-opens it.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SourceHealth {
+    pub requests: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    pub state: BreakerState,
+    pub opened_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl SourceHealth {
+    pub fn allow(&mut self, cooldown_secs: u64) -> bool {
+        match (self.state, self.opened_at) {
+            (BreakerState::Open, Some(opened_at))
+                if (Utc::now() - opened_at).num_seconds() >= cooldown_secs as i64 =>
+            {
+                self.state = BreakerState::HalfOpen;
+                true
+            }
+            (BreakerState::Open, _) => false,
+            _ => true,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.requests += 1;
+        self.consecutive_failures = 0;
+        self.state = BreakerState::Closed;
+        self.opened_at = None;
+    }
+
+    pub fn record_failure(&mut self, err: String, threshold: u32) {
+        self.requests += 1;
+        self.failures += 1;
+        self.consecutive_failures += 1;
+        self.last_error = Some(err);
+
+        if self.state == BreakerState::HalfOpen || self.consecutive_failures >= threshold {
+            self.state = BreakerState::Open;
+            self.opened_at = Some(Utc::now());
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
 pub struct HnRelatedLinkSearch {
@@ -82,10 +159,6 @@ pub async fn hackernews(site: &str, _handle: Handle) -> RelatedLinkResult {
 
         let discussion_url = format!("https://news.ycombinator.com/item?id={}", link.object_id);
 
-        if link.num_comments == 0 {
-            debug!("no comments for {discussion_url}; skipping");
-        }
-
         if check_link(&link.url).await {
             related.push(RelatedLink {
                 url: link.url,
@@ -100,8 +173,6 @@ pub async fn hackernews(site: &str, _handle: Handle) -> RelatedLinkResult {
         tokio::time::sleep(std::time::Duration::from_secs(2)).await;
     }
 
-    related.sort_by(|x, y| y.upvotes.cmp(&x.upvotes));
-
     Ok(related)
 }
 
@@ -164,11 +235,6 @@ pub async fn lobsters(site: &str, _handle: Handle) -> RelatedLinkResult {
             let score = score.parse().unwrap_or(0);
             let comments = comments.parse().unwrap_or(0);
 
-            if comments == 0 {
-                debug!("no comments for {discussion}; skipping");
-                continue;
-            }
-
             related.push(RelatedLink {
                 url,
                 upvotes: score,
@@ -182,13 +248,37 @@ pub async fn lobsters(site: &str, _handle: Handle) -> RelatedLinkResult {
         tokio::time::sleep(std::time::Duration::from_secs(2)).await;
     }
 
-    related.sort_by(|x, y| y.upvotes.cmp(&x.upvotes));
-
     Ok(related)
 }
 
+/// Apply each source's score/comment thresholds and limit, and combine the survivors,
+/// highest scoring first within each source.
+pub fn merge_related(sources: Vec<(&RelatedSourceConfig, Vec<RelatedLink>)>) -> Vec<RelatedLink> {
+    let mut merged = vec![];
+
+    for (limits, mut links) in sources {
+        links.retain(|link| {
+            if link.upvotes < limits.min_score || link.comments < limits.min_comments {
+                debug!(
+                    "{} has {} points and {} comments; skipping",
+                    link.discussion_url, link.upvotes, link.comments
+                );
+                false
+            } else {
+                true
+            }
+        });
+
+        links.sort_by_key(|link| std::cmp::Reverse(link.upvotes));
+        links.truncate(limits.limit);
+        merged.extend(links);
+    }
+
+    merged
+}
+
 pub async fn check_link(url: &String) -> bool {
-    let client = reqwest::Client::new();
+    let client = resolver::client();
 
     match client.get(url).send().await {
         Ok(res) => {
@@ -201,3 +291,53 @@ pub async fn check_link(url: &String) -> bool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn breaker_opens_after_threshold_failures() {
+        let mut health = SourceHealth::default();
+        health.record_failure(String::from("timeout"), 3);
+        health.record_failure(String::from("timeout"), 3);
+        assert_eq!(health.state, BreakerState::Closed);
+        assert!(health.allow(60));
+
+        health.record_failure(String::from("timeout"), 3);
+        assert_eq!(health.state, BreakerState::Open);
+        assert_eq!(health.requests, 3);
+        assert_eq!(health.failures, 3);
+        assert_eq!(health.last_error.as_deref(), Some("timeout"));
+        assert!(!health.allow(60));
+    }
+
+    #[test]
+    fn breaker_probes_once_the_cooldown_has_elapsed() {
+        let mut health = SourceHealth::default();
+        health.record_failure(String::from("500"), 1);
+        health.opened_at = Some(Utc::now() - Duration::seconds(61));
+
+        assert!(health.allow(60));
+        assert_eq!(health.state, BreakerState::HalfOpen);
+
+        health.record_success();
+        assert_eq!(health.state, BreakerState::Closed);
+        assert_eq!(health.consecutive_failures, 0);
+        assert_eq!(health.opened_at, None);
+    }
+
+    #[test]
+    fn failed_probe_reopens_the_breaker() {
+        let mut health = SourceHealth::default();
+        health.record_failure(String::from("500"), 1);
+        health.opened_at = Some(Utc::now() - Duration::seconds(61));
+        assert!(health.allow(60));
+
+        // While half-open one failure re-opens the breaker, whatever the threshold.
+        health.record_failure(String::from("500"), 10);
+        assert_eq!(health.state, BreakerState::Open);
+        assert!(!health.allow(60));
+    }
+}
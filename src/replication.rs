@@ -0,0 +1,186 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Continuous off-site replication of the database by an external WAL-shipping tool such
+//! as Litestream.  The database is switched to WAL mode, restored from the replica if the
+//! file is missing at startup, and the replicator is run alongside the server: restarted
+//! if it exits, and stopped only once the server has made its last write.
+
+use std::{io, path::Path, process::Stdio, sync::Arc, time::Duration};
+
+use tokio::{
+    process::{Child, Command},
+    sync::Notify,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tracing::{error, info, warn};
+
+use crate::{config::ReplicationConfig, database::Pool, error::TenKbError};
+
+/// Restore the database with the configured restore command if the file doesn't exist.
+pub fn restore(config: &ReplicationConfig, database: &Path) -> io::Result<()> {
+    if database.exists() {
+        return Ok(());
+    }
+    let Some((program, args)) = config.restore.split_first() else {
+        return Ok(());
+    };
+
+    info!("database {database:?} is missing; restoring it from the replica");
+    let status = std::process::Command::new(program)
+        .args(args)
+        .env("TENKB_DATABASE", database)
+        .stdin(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "restore command failed ({status})"
+        )));
+    }
+    if !database.exists() {
+        return Err(io::Error::other(format!(
+            "restore command did not create {database:?}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// WAL shipping needs the database in WAL mode; the setting is persistent, so this only
+/// changes anything the first time.
+pub fn enable_wal(pool: &Pool) -> Result<(), TenKbError> {
+    let conn = pool.get()?;
+    let mode: String = conn.query_row("PRAGMA journal_mode = WAL;", [], |row| row.get(0))?;
+    if !mode.eq_ignore_ascii_case("wal") {
+        return Err(TenKbError::Msg(format!(
+            "unable to switch the database to WAL mode (journal mode is {mode})"
+        )));
+    }
+
+    Ok(())
+}
+
+pub struct Replicator {
+    stop: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl Replicator {
+    /// Start the replicator.  Failing to start it at all is an error; once it's running,
+    /// it's restarted whenever it exits.
+    pub fn spawn(config: &ReplicationConfig, database: &Path) -> io::Result<Self> {
+        let config = config.clone();
+        let database = database.to_path_buf();
+        let mut child = start(&config, &database)?;
+        let stop = Arc::new(Notify::new());
+
+        let stopped = stop.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    status = child.wait() => {
+                        match status {
+                            Ok(status) => warn!("replicator exited ({status}); restarting in {}s", config.restart_secs),
+                            Err(e) => error!("unable to wait for the replicator: {e}"),
+                        }
+                    }
+                    _ = stopped.notified() => {
+                        terminate(child, Duration::from_secs(config.shutdown_secs)).await;
+                        return;
+                    }
+                }
+
+                tokio::select! {
+                    _ = sleep(Duration::from_secs(config.restart_secs)) => {}
+                    _ = stopped.notified() => return,
+                }
+
+                loop {
+                    match start(&config, &database) {
+                        Ok(restarted) => {
+                            child = restarted;
+                            break;
+                        }
+                        Err(e) => error!("unable to restart the replicator: {e}"),
+                    }
+                    tokio::select! {
+                        _ = sleep(Duration::from_secs(config.restart_secs)) => {}
+                        _ = stopped.notified() => return,
+                    }
+                }
+            }
+        });
+
+        Ok(Self { stop, task })
+    }
+
+    /// Ask the replicator to ship what's left of the WAL and exit.
+    pub async fn stop(self) {
+        self.stop.notify_one();
+        if let Err(e) = self.task.await {
+            error!("replicator supervisor failed: {e}");
+        }
+    }
+}
+
+fn start(config: &ReplicationConfig, database: &Path) -> io::Result<Child> {
+    let (program, args) = config
+        .command
+        .split_first()
+        .ok_or_else(|| io::Error::other("replication.command is empty"))?;
+
+    let child = Command::new(program)
+        .args(args)
+        .env("TENKB_DATABASE", database)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(pid) = child.id() {
+        info!("started replicator {program} (pid {pid})");
+    }
+
+    Ok(child)
+}
+
+/// SIGTERM gives the replicator the chance to sync before exiting; it's killed if it
+/// takes longer than `grace`.
+async fn terminate(mut child: Child, grace: Duration) {
+    if let Some(pid) = child.id() {
+        // SAFETY: kill(2) has no memory-safety requirements; the pid belongs to a child
+        // that hasn't been reaped, so it can't have been reused.
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+
+    match timeout(grace, child.wait()).await {
+        Ok(Ok(status)) => info!("replicator stopped ({status})"),
+        Ok(Err(e)) => error!("unable to wait for the replicator: {e}"),
+        Err(_) => {
+            warn!("replicator did not stop within {grace:?}; killing it");
+            if let Err(e) = child.kill().await {
+                error!("unable to kill the replicator: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The resolver behind the analyzer's outbound checks (`site_live`, `check_link` and the
+//! redirect check): the system's, a list of name servers, or DNS-over-HTTPS, with a timeout on
+//! every lookup so a flaky resolver fails a check instead of stalling it.
+
+use std::{
+    error::Error,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use hickory_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig as NameServers, ResolverOpts},
+    TokioAsyncResolver,
+};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+use crate::config::{ResolverConfig, ResolverMode};
+
+static RESOLVER: OnceLock<Arc<Resolver>> = OnceLock::new();
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+struct Resolver {
+    /// `None` means the system resolver.
+    lookup: Option<TokioAsyncResolver>,
+    timeout: Duration,
+}
+
+/// Set up the resolver described by `config`.  Call once at startup, before the analyzer
+/// runs; checks made before then use the system resolver.
+pub fn configure(config: &ResolverConfig) -> Result<(), Box<dyn Error>> {
+    let resolver = Arc::new(Resolver::new(config)?);
+    if RESOLVER.set(resolver).is_err() {
+        return Err("the outbound resolver is already configured".into());
+    }
+    Ok(())
+}
+
+/// A client builder whose lookups go through the configured resolver.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let resolver = RESOLVER
+        .get_or_init(|| Arc::new(Resolver::system(&ResolverConfig::default())))
+        .clone();
+    reqwest::Client::builder().dns_resolver(resolver)
+}
+
+/// A shared client whose lookups go through the configured resolver.
+pub fn client() -> reqwest::Client {
+    CLIENT
+        .get_or_init(|| {
+            client_builder()
+                .build()
+                .expect("unable to build the outbound HTTP client")
+        })
+        .clone()
+}
+
+impl Resolver {
+    fn new(config: &ResolverConfig) -> Result<Self, Box<dyn Error>> {
+        let mut servers = NameServers::new();
+        match config.mode {
+            ResolverMode::System => return Ok(Self::system(config)),
+            ResolverMode::Servers => {
+                for addr in name_servers(config, 53)? {
+                    servers.add_name_server(NameServerConfig::new(addr, Protocol::Udp));
+                    servers.add_name_server(NameServerConfig::new(addr, Protocol::Tcp));
+                }
+            }
+            ResolverMode::Doh => {
+                let Some(tls_name) = &config.tls_name else {
+                    return Err("resolver.tls_name is required for DNS-over-HTTPS".into());
+                };
+                for addr in name_servers(config, 443)? {
+                    servers.add_name_server(NameServerConfig {
+                        tls_dns_name: Some(tls_name.clone()),
+                        ..NameServerConfig::new(addr, Protocol::Https)
+                    });
+                }
+            }
+        }
+
+        Ok(Self {
+            lookup: Some(TokioAsyncResolver::tokio(servers, ResolverOpts::default())),
+            timeout: Duration::from_millis(config.timeout_ms),
+        })
+    }
+
+    fn system(config: &ResolverConfig) -> Self {
+        Self {
+            lookup: None,
+            timeout: Duration::from_millis(config.timeout_ms),
+        }
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let lookup = self.lookup.clone();
+        let timeout = self.timeout;
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::time::timeout(timeout, async {
+                Ok::<_, Box<dyn Error + Send + Sync>>(match &lookup {
+                    // reqwest fills in the port.
+                    Some(resolver) => resolver
+                        .lookup_ip(host)
+                        .await?
+                        .iter()
+                        .map(|ip| SocketAddr::new(ip, 0))
+                        .collect(),
+                    None => tokio::net::lookup_host((host, 0)).await?.collect(),
+                })
+            })
+            .await
+            .map_err(|_| format!("timed out resolving {host}"))??;
+
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// `resolver.servers`, each an IP with an optional port; `port` is used where none is given.
+fn name_servers(config: &ResolverConfig, port: u16) -> Result<Vec<SocketAddr>, Box<dyn Error>> {
+    if config.servers.is_empty() {
+        return Err("resolver.servers must list at least one name server".into());
+    }
+
+    config
+        .servers
+        .iter()
+        .map(|server| {
+            server
+                .parse::<SocketAddr>()
+                .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, port)))
+                .map_err(|_| format!("invalid name server {server:?} in resolver.servers").into())
+        })
+        .collect()
+}
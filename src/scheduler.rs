@@ -0,0 +1,87 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::{future::Future, str::FromStr, sync::Arc};
+
+use chrono::Utc;
+use cron::Schedule;
+use tokio::sync::Notify;
+use tracing::{debug, error, info};
+
+/// A background job run on a cron schedule.  If a wakeup handle is supplied, the job
+/// will also run as soon as the handle is notified.
+pub struct Job {
+    name: String,
+    schedule: Schedule,
+    wakeup: Option<Arc<Notify>>,
+}
+
+impl Job {
+    pub fn new(name: &str, expression: &str) -> Result<Self, String> {
+        let schedule = Schedule::from_str(expression)
+            .map_err(|e| format!("invalid schedule '{expression}' for job '{name}': {e}"))?;
+
+        Ok(Self {
+            name: String::from(name),
+            schedule,
+            wakeup: None,
+        })
+    }
+
+    pub fn with_wakeup(mut self, wakeup: Arc<Notify>) -> Self {
+        self.wakeup = Some(wakeup);
+        self
+    }
+
+    /// Spawn the job onto the tokio runtime.  Runs of the same job never overlap; the
+    /// next run is scheduled after the current one finishes.
+    pub fn spawn<F, Fut>(self, run: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        info!("scheduling job '{}' ({})", self.name, self.schedule);
+
+        tokio::task::spawn(async move {
+            loop {
+                let Some(next) = self.schedule.upcoming(Utc).next() else {
+                    error!("job '{}' has no upcoming runs; stopping", self.name);
+                    return;
+                };
+
+                let delay = (next - Utc::now()).to_std().unwrap_or_default();
+                debug!("job '{}' next run at {next}", self.name);
+
+                match &self.wakeup {
+                    Some(wakeup) => tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = wakeup.notified() => info!("job '{}' woken up", self.name),
+                    },
+                    None => tokio::time::sleep(delay).await,
+                }
+
+                debug!("running job '{}'", self.name);
+                run().await;
+            }
+        });
+    }
+}
@@ -0,0 +1,108 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Screenshots of newly listed sites, taken from the Cloudflare scan or a local headless
+//! browser, and the WebP thumbnails served from `/thumb/{slug}.webp`.
+
+use image::{codecs::webp::WebPEncoder, imageops::FilterType, ImageReader};
+use std::{error::Error, io::Cursor, path::PathBuf};
+use tokio::process::Command;
+use tracing::info;
+
+use crate::{
+    cloudflare::{self, UrlScan},
+    config::{Config, ScreenshotConfig, ScreenshotSource},
+};
+
+/// Thumbnails keep the top of the page at this height-to-width ratio.
+const THUMBNAIL_ASPECT: f32 = 0.625;
+
+/// Capture `site` and write `{id}.png` and `{id}.webp` to the screenshot directory,
+/// returning both paths.
+pub async fn capture(
+    config: &Config,
+    shots: &ScreenshotConfig,
+    site: &str,
+    site_id: u32,
+    scan: &UrlScan,
+) -> Result<(PathBuf, PathBuf), Box<dyn Error + Send + Sync>> {
+    tokio::fs::create_dir_all(&shots.directory).await?;
+    let path = shots.directory.join(format!("{site_id}.png"));
+    let thumbnail_path = shots.directory.join(format!("{site_id}.webp"));
+
+    match shots.source {
+        ScreenshotSource::Cloudflare => {
+            let Some(scan_id) = &scan.scan_id else {
+                return Err(format!("no Cloudflare scan id for {site}").into());
+            };
+            let png = cloudflare::screenshot(scan_id, config)
+                .await
+                .map_err(|e| e.to_string())?;
+            tokio::fs::write(&path, png).await?;
+        }
+        ScreenshotSource::Browser => {
+            let status = Command::new(&shots.browser)
+                .arg("--headless")
+                .arg("--disable-gpu")
+                .arg("--hide-scrollbars")
+                .arg("--window-size=1280,800")
+                .arg(format!("--screenshot={}", path.display()))
+                .arg(site)
+                .status()
+                .await?;
+            if !status.success() {
+                return Err(format!("{} exited with {status}", shots.browser).into());
+            }
+        }
+    }
+    info!("captured screenshot of {site} to {}", path.display());
+
+    let png = tokio::fs::read(&path).await?;
+    let width = shots.thumbnail_width;
+    let webp = tokio::task::spawn_blocking(move || thumbnail(&png, width)).await??;
+    tokio::fs::write(&thumbnail_path, webp).await?;
+
+    Ok((path, thumbnail_path))
+}
+
+/// Scale a screenshot to `width` pixels wide and crop it to [`THUMBNAIL_ASPECT`].
+fn thumbnail(png: &[u8], width: u32) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let image = ImageReader::new(Cursor::new(png))
+        .with_guessed_format()?
+        .decode()?;
+
+    let height = (image.height() as f32 * width as f32 / image.width().max(1) as f32) as u32;
+    let resized = image.resize_exact(width, height.max(1), FilterType::Triangle);
+    let cropped = resized.crop_imm(
+        0,
+        0,
+        width,
+        height.min((width as f32 * THUMBNAIL_ASPECT) as u32).max(1),
+    );
+
+    let mut webp = vec![];
+    cropped
+        .to_rgba8()
+        .write_with_encoder(WebPEncoder::new_lossless(&mut webp))?;
+
+    Ok(webp)
+}
@@ -0,0 +1,179 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Fake but plausible data for performance work on an empty database: sites with a spread
+//! of sizes and ages, votes concentrated on a few popular sites as they are in practice,
+//! discussions, and the vote snapshots the weekly and monthly rankings need.  The same
+//! seed always produces the same data.
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rusqlite::params;
+
+use crate::{
+    cloudflare::SIZE_LIMIT,
+    database::{assign_slug, Pool},
+    error::TenKbError,
+};
+
+const WORDS: &[&str] = &[
+    "amber", "brook", "cedar", "delta", "ember", "fern", "grove", "harbor", "iris", "juniper",
+    "kestrel", "lumen", "maple", "north", "onyx", "pine", "quill", "river", "slate", "thistle",
+    "umber", "vale", "willow", "yarrow", "zephyr",
+];
+const TLDS: &[&str] = &["com", "net", "org", "dev", "io", "blog", "xyz", "me"];
+const TOPICS: &[&str] = &[
+    "Show HN: My website is under 10KB",
+    "The case for plain HTML",
+    "Why I removed all JavaScript from my blog",
+    "A minimal personal site",
+    "Web pages are too big",
+    "Writing a static site generator",
+];
+
+pub struct SeedOptions {
+    pub sites: u32,
+    pub votes: u32,
+    pub seed: u64,
+}
+
+pub struct SeedStats {
+    pub sites: u32,
+    pub voters: u32,
+    pub votes: u32,
+    pub related: u32,
+}
+
+/// Fill an empty database.  A database that already has sites is left alone, so this
+/// can't be pointed at a real instance by mistake.
+pub fn seed(pool: &Pool, options: &SeedOptions) -> Result<SeedStats, TenKbError> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    let existing: u32 = tx.query_row("SELECT COUNT(*) FROM site_ids", [], |row| row.get(0))?;
+    if existing > 0 {
+        return Err(TenKbError::Msg(format!(
+            "the database already has {existing} sites; seed data only goes in an empty one"
+        )));
+    }
+
+    let mut stats = SeedStats {
+        sites: 0,
+        voters: 0,
+        votes: 0,
+        related: 0,
+    };
+
+    for id in 1..=options.sites {
+        // Two words and a number keep hosts unique however many sites are asked for.
+        let host = format!(
+            "{}{}{id}.{}",
+            WORDS.choose(&mut rng).unwrap(),
+            WORDS.choose(&mut rng).unwrap(),
+            TLDS.choose(&mut rng).unwrap()
+        );
+        let url = format!("https://{host}/");
+        // Most members are comfortably under the limit; a few are right up against it.
+        let size = (SIZE_LIMIT as f64 * rng.gen::<f64>().powf(1.5))
+            .max(300.0)
+            .round();
+        let age_days = rng.gen_range(0..4 * 365);
+
+        tx.execute(
+            r#"INSERT INTO site_ids (id, url, host) VALUES (?, ?, ?)"#,
+            params![id, url, host],
+        )?;
+        assign_slug(&tx, id, &mut rng)?;
+        tx.execute(
+            r#"INSERT INTO sites (id, size, date_added, valid)
+               VALUES (?, ?, DATETIME('now', ?), true)"#,
+            params![id, size, format!("-{age_days} days")],
+        )?;
+        tx.execute(
+            r#"INSERT INTO size_history (site_id, size, measured, kind, passed)
+               VALUES (?, ?, DATETIME('now', ?), 'listing', true)"#,
+            params![id, size, format!("-{age_days} days")],
+        )?;
+        stats.sites += 1;
+
+        for n in 0..rng.gen_range(0..4) {
+            let posted = rng.gen_range(0..=age_days);
+            tx.execute(
+                r#"INSERT INTO related VALUES (?, ?, ?, DATE('now', ?), ?, ?, ?)"#,
+                params![
+                    id,
+                    format!("{url}posts/{n}"),
+                    format!("https://news.ycombinator.com/item?id={}", id * 10 + n),
+                    format!("-{posted} days"),
+                    TOPICS.choose(&mut rng).unwrap(),
+                    rng.gen_range(1..500),
+                    rng.gen_range(0..300),
+                ],
+            )?;
+            stats.related += 1;
+        }
+    }
+
+    if options.sites == 0 {
+        tx.commit()?;
+        return Ok(stats);
+    }
+
+    // Each voter votes for about ten sites, so there are enough distinct pairs.
+    let voters = (options.votes / 10).max(1);
+    for voter in 1..=voters {
+        tx.execute(
+            r#"INSERT INTO voter_ids (id, uuid, created) VALUES (?, ?, DATETIME('now', '-90 days'))"#,
+            params![voter, format!("{:064x}", rng.gen::<u128>())],
+        )?;
+    }
+    stats.voters = voters;
+
+    let wanted = options.votes.min(voters.saturating_mul(options.sites));
+    while stats.votes < wanted {
+        // Squaring skews the choice toward low ids, giving a long tail of popularity.
+        let site = 1 + (rng.gen::<f64>().powi(2) * options.sites as f64) as u32;
+        let site = site.min(options.sites);
+        let voter = rng.gen_range(1..=voters);
+        // Spread over two months, too thinly to look like a burst.
+        let voted = format!("-{} minutes", rng.gen_range(0..60 * 24 * 60));
+        stats.votes += tx.execute(
+            r#"INSERT OR IGNORE INTO votes (id, voter_id, voted)
+               VALUES (?, ?, DATETIME('now', ?))"#,
+            params![site, voter, voted],
+        )? as u32;
+    }
+
+    // Earlier counts, so the weekly and monthly rankings have something to compare with.
+    for (days, fraction) in [(40, 0.6), (8, 0.85)] {
+        tx.execute(
+            r#"INSERT INTO vote_snapshots (site_id, day, votes)
+               SELECT sites.id, DATE('now', ?),
+                      CAST((SELECT COUNT(*) FROM votes WHERE votes.id = sites.id) * ? AS INTEGER)
+               FROM sites"#,
+            params![format!("-{days} days"), fraction],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(stats)
+}
@@ -0,0 +1,58 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Public names for sites, like `quiet-forest-7`, used in `/site/{slug}/` and
+//! `/related/{slug}/` in place of the database ids, which are sequential and so give away
+//! how to walk the whole directory.
+
+use rand::{seq::SliceRandom, Rng};
+
+const ADJECTIVES: [&str; 64] = [
+    "amber", "ancient", "autumn", "bold", "brave", "bright", "brisk", "calm", "clever", "cool",
+    "crisp", "curious", "dawn", "deep", "eager", "early", "faint", "fair", "fancy", "gentle",
+    "glad", "golden", "grand", "green", "hidden", "humble", "icy", "jolly", "keen", "kind", "late",
+    "light", "little", "lively", "lucky", "mellow", "merry", "misty", "modest", "neat", "nimble",
+    "noble", "odd", "pale", "patient", "plain", "proud", "quick", "quiet", "rapid", "rare",
+    "rustic", "shy", "silent", "silver", "simple", "sleek", "small", "snowy", "steady", "swift",
+    "tidy", "tiny", "wise",
+];
+
+const NOUNS: [&str; 64] = [
+    "acorn", "anchor", "arrow", "aspen", "badger", "beacon", "birch", "brook", "canyon", "cedar",
+    "cloud", "comet", "coral", "creek", "delta", "dune", "ember", "falcon", "fern", "field",
+    "finch", "fjord", "forest", "fox", "garden", "glade", "grove", "harbor", "hazel", "heron",
+    "hill", "island", "lake", "lantern", "leaf", "maple", "meadow", "moon", "moss", "night", "oak",
+    "otter", "owl", "pebble", "pine", "planet", "pond", "prairie", "rain", "raven", "reef",
+    "river", "robin", "shore", "sparrow", "spring", "star", "stone", "summit", "thistle", "tide",
+    "valley", "willow", "wren",
+];
+
+/// A random slug: an adjective, a noun, and a number from 1 to 99.  There are about 400,000,
+/// so callers must still check a new one isn't taken.
+pub fn generate(rng: &mut impl Rng) -> String {
+    format!(
+        "{}-{}-{}",
+        ADJECTIVES.choose(rng).unwrap(),
+        NOUNS.choose(rng).unwrap(),
+        rng.gen_range(1..100)
+    )
+}
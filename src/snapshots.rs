@@ -0,0 +1,217 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Archived copies of member sites' front pages as they were when they passed validation,
+//! optionally with their stylesheets and images inlined so the copy stands on its own.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use regex::{Captures, Regex};
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use std::sync::OnceLock;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::{config::SnapshotConfig, favicon::fetch_limited, html::rel_tags};
+
+/// The page to archive for `site`: `body` as fetched, with assets inlined if configured.
+pub async fn snapshot_html(site: &str, body: &[u8], config: &SnapshotConfig) -> String {
+    let html = String::from_utf8_lossy(body).into_owned();
+    if !config.inline_assets {
+        return html;
+    }
+
+    let Ok(base) = Url::parse(site) else {
+        return html;
+    };
+
+    let mut budget = config.max_asset_bytes;
+    let html = inline_stylesheets(&base, html, &mut budget).await;
+    inline_images(&base, html, &mut budget).await
+}
+
+/// How much a page changed between two snapshots, counted over added and removed lines.
+#[derive(Debug)]
+pub struct ChangeStats {
+    pub bytes_added: usize,
+    pub bytes_removed: usize,
+}
+
+impl ChangeStats {
+    /// Net growth in bytes; negative if the page shrank.
+    pub fn growth(&self) -> i64 {
+        self.bytes_added as i64 - self.bytes_removed as i64
+    }
+}
+
+pub fn change_stats(old: &str, new: &str) -> ChangeStats {
+    let mut stats = ChangeStats {
+        bytes_added: 0,
+        bytes_removed: 0,
+    };
+
+    for change in TextDiff::from_lines(old, new).iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => stats.bytes_added += change.value().len(),
+            ChangeTag::Delete => stats.bytes_removed += change.value().len(),
+            ChangeTag::Equal => {}
+        }
+    }
+
+    stats
+}
+
+/// One line of a diff for `snapshot_diff.html`; `kind` is "added", "removed", "unchanged",
+/// or "gap" between hunks.
+#[derive(Debug, Serialize)]
+pub struct DiffLine {
+    pub kind: &'static str,
+    pub text: String,
+}
+
+/// A line diff from `old` to `new` with three lines of context around each change.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = vec![];
+
+    for (i, group) in diff.grouped_ops(3).iter().enumerate() {
+        if i > 0 {
+            lines.push(DiffLine {
+                kind: "gap",
+                text: String::new(),
+            });
+        }
+
+        for op in group {
+            for change in diff.iter_changes(op) {
+                lines.push(DiffLine {
+                    kind: match change.tag() {
+                        ChangeTag::Insert => "added",
+                        ChangeTag::Delete => "removed",
+                        ChangeTag::Equal => "unchanged",
+                    },
+                    text: change.value().trim_end_matches(['\r', '\n']).to_owned(),
+                });
+            }
+        }
+    }
+
+    lines
+}
+
+fn img_pattern() -> &'static Regex {
+    static IMG: OnceLock<Regex> = OnceLock::new();
+    IMG.get_or_init(|| {
+        Regex::new(r#"(?is)(<img\b[^>]*\bsrc\s*=\s*)(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap()
+    })
+}
+
+/// The first of a pattern's alternative quoted/unquoted value groups that matched.
+fn value<'a>(caps: &'a Captures, groups: &[usize]) -> Option<&'a str> {
+    groups
+        .iter()
+        .find_map(|group| caps.get(*group))
+        .map(|m| m.as_str().trim())
+}
+
+/// Fetch an asset referenced from the page, charging it against `budget`.
+async fn fetch_asset(base: &Url, href: &str, budget: &mut usize) -> Option<(String, Vec<u8>)> {
+    if href.starts_with("data:") {
+        return None;
+    }
+
+    let url = base.join(href).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+
+    match fetch_limited(&url, *budget).await {
+        Ok((content_type, data)) => {
+            *budget -= data.len();
+            Some((content_type, data))
+        }
+        Err(e) => {
+            warn!("snapshot: not inlining {url}: {e:?}");
+            None
+        }
+    }
+}
+
+async fn inline_stylesheets(base: &Url, html: String, budget: &mut usize) -> String {
+    // Collected up front: the regex iterators can't be held across an await.
+    let stylesheets: Vec<_> = rel_tags(&html, "stylesheet", false)
+        .map(|link| (link.tag.range(), link.href.to_owned()))
+        .collect();
+
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    for (range, target) in stylesheets {
+        let Some((_, css)) = fetch_asset(base, &target, budget).await else {
+            continue;
+        };
+
+        debug!("snapshot: inlining stylesheet {target}");
+        out.push_str(&html[last..range.start]);
+        out.push_str("<style>");
+        out.push_str(&String::from_utf8_lossy(&css).replace("</style", "<\\/style"));
+        out.push_str("</style>");
+        last = range.end;
+    }
+    out.push_str(&html[last..]);
+
+    out
+}
+
+async fn inline_images(base: &Url, html: String, budget: &mut usize) -> String {
+    let images: Vec<_> = img_pattern()
+        .captures_iter(&html)
+        .filter_map(|caps| {
+            Some((
+                caps.get(0)?.range(),
+                caps.get(1)?.end(),
+                value(&caps, &[2, 3, 4])?.to_owned(),
+            ))
+        })
+        .collect();
+
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    for (range, prefix_end, target) in images {
+        let Some((content_type, data)) = fetch_asset(base, &target, budget).await else {
+            continue;
+        };
+        if !content_type.starts_with("image/") {
+            continue;
+        }
+
+        debug!("snapshot: inlining image {target}");
+        out.push_str(&html[last..prefix_end]);
+        out.push_str(&format!(
+            "\"data:{content_type};base64,{}\"",
+            STANDARD.encode(&data)
+        ));
+        last = range.end;
+    }
+    out.push_str(&html[last..]);
+
+    out
+}
@@ -0,0 +1,193 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Optional spam scoring for submissions and comments, consulted before either is queued.
+//! Scores run from 0 (ham) to 1 (spam).  With an Akismet key configured the verdict comes
+//! from Akismet; otherwise a small naive Bayes filter scores the content, trained on what the
+//! analyzer lists or rejects and on the comments admins approve or reject.
+
+use actix_web::{http::header, HttpRequest};
+use std::collections::BTreeSet;
+
+use crate::{
+    config::{AkismetConfig, Config, SpamConfig},
+    database::{get_spam_counts, train_spam_tokens, Pool},
+    error::TenKbError,
+    raw_client_ip,
+};
+
+/// Tokens with the most extreme probabilities combined into a score.
+const INTERESTING_TOKENS: usize = 15;
+
+/// Times a token must have been seen before it counts toward a score.
+const MIN_TOKEN_COUNT: u32 = 2;
+
+#[derive(Copy, Clone, Debug)]
+pub enum SpamKind {
+    /// The content is the submitted URL.
+    Submission,
+    Comment,
+}
+
+impl SpamKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpamKind::Submission => "submission",
+            SpamKind::Comment => "comment",
+        }
+    }
+}
+
+/// Something to score, with the request details Akismet wants.
+pub struct Sample {
+    pub kind: SpamKind,
+    pub content: String,
+    client_ip: String,
+    user_agent: String,
+}
+
+impl Sample {
+    /// The client's full address is kept only for the Akismet request; it is never stored.
+    pub fn new(kind: SpamKind, content: impl Into<String>, req: &HttpRequest) -> Self {
+        Self {
+            kind,
+            content: content.into(),
+            client_ip: raw_client_ip(req).unwrap_or_default(),
+            user_agent: req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+}
+
+/// Score `sample`.  Errors (e.g. Akismet being unreachable) are left for the caller to
+/// decide on.
+pub async fn score(pool: &Pool, config: &Config, sample: &Sample) -> Result<f64, String> {
+    let Some(spam) = &config.spam else {
+        return Ok(0.0);
+    };
+
+    match &spam.akismet {
+        Some(akismet) => akismet_check(akismet, &config.base_url, sample).await,
+        None => {
+            let (pool, tokens) = (pool.clone(), tokens(&sample.content));
+            tokio::task::spawn_blocking(move || bayes_score(&pool, &tokens))
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(|TenKbError::Msg(msg)| msg)
+        }
+    }
+}
+
+/// Whether a score should be treated as spam.
+pub fn is_spam(spam: &SpamConfig, score: f64) -> bool {
+    score >= spam.threshold
+}
+
+/// Teach the built-in filter that `text` is (or isn't) spam.
+pub fn train(pool: &Pool, text: &str, spam: bool) -> Result<(), TenKbError> {
+    train_spam_tokens(pool, &tokens(text), spam)
+}
+
+/// Lowercased runs of letters and digits, each counted once.
+fn tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| (2..=32).contains(&token.chars().count()))
+        .map(str::to_lowercase)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Graham-style combination of the most telling tokens' spam probabilities.  Content with
+/// no tokens the filter knows scores a neutral 0.5.
+fn bayes_score(pool: &Pool, tokens: &[String]) -> Result<f64, TenKbError> {
+    let counts = get_spam_counts(pool, tokens)?;
+    let spam_docs = counts.spam_documents.max(1) as f64;
+    let ham_docs = counts.ham_documents.max(1) as f64;
+
+    let mut probabilities: Vec<f64> = counts
+        .tokens
+        .into_iter()
+        .filter(|(spam, ham)| spam + ham >= MIN_TOKEN_COUNT)
+        .map(|(spam, ham)| {
+            let spam = spam as f64 / spam_docs;
+            let ham = ham as f64 / ham_docs;
+            (spam / (spam + ham)).clamp(0.01, 0.99)
+        })
+        .collect();
+
+    if probabilities.is_empty() {
+        return Ok(0.5);
+    }
+
+    probabilities.sort_by(|a, b| (b - 0.5).abs().total_cmp(&(a - 0.5).abs()));
+    probabilities.truncate(INTERESTING_TOKENS);
+
+    let spam: f64 = probabilities.iter().product();
+    let ham: f64 = probabilities.iter().map(|p| 1.0 - p).product();
+    Ok(spam / (spam + ham))
+}
+
+/// Akismet gives a verdict rather than a score, so spam scores 1 and ham 0.
+async fn akismet_check(
+    akismet: &AkismetConfig,
+    base_url: &str,
+    sample: &Sample,
+) -> Result<f64, String> {
+    let (comment_type, content_field) = match sample.kind {
+        SpamKind::Submission => ("contact-form", "comment_author_url"),
+        SpamKind::Comment => ("comment", "comment_content"),
+    };
+
+    let res = reqwest::Client::new()
+        .post(&akismet.endpoint)
+        .form(&[
+            ("api_key", akismet.api_key.as_str()),
+            ("blog", base_url),
+            ("user_ip", sample.client_ip.as_str()),
+            ("user_agent", sample.user_agent.as_str()),
+            ("comment_type", comment_type),
+            (content_field, sample.content.as_str()),
+        ])
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let debug_help = res
+        .headers()
+        .get("x-akismet-debug-help")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+
+    match res.text().await.map_err(|e| e.to_string())?.trim() {
+        "true" => Ok(1.0),
+        "false" => Ok(0.0),
+        other => Err(format!(
+            "unexpected akismet response '{other}': {}",
+            debug_help.unwrap_or_default()
+        )),
+    }
+}
@@ -0,0 +1,372 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The storage behind listing, submission, voting, validation, and related links, as a
+//! trait with a backend for each database it can live in: the SQLite pool, and with the
+//! `postgres` feature, [`crate::postgres::PgStorage`].  `storage.backend` in the config
+//! picks one.  Everything outside these operations (comments, favicons, snapshots, the
+//! admin pages and so on) stays in SQLite whichever it is; see [`Storage::sqlite`].
+
+use std::{error::Error, sync::Arc};
+
+use regex::Regex;
+use tracing::info;
+
+use crate::{
+    config::{Config, StorageBackend},
+    database::{self, Pool, ValidationOutcome},
+    error::TenKbError,
+    relatedlinks::RelatedLink,
+    site_domain, Site, SiteFilter, SortOptions,
+};
+
+pub trait Storage: Send + Sync {
+    /// Listed sites matching `filter` in `sortby` order, skipping the first `skip`.
+    fn get_sites(
+        &self,
+        sortby: SortOptions,
+        filter: &SiteFilter,
+        skip: usize,
+        paginate: usize,
+    ) -> Result<Vec<Site>, TenKbError>;
+
+    /// How many listed sites match `filter`.
+    fn get_site_count(&self, filter: &SiteFilter) -> Result<usize, TenKbError>;
+
+    fn get_site_url(&self, id: u32) -> Result<String, TenKbError>;
+
+    /// The site a `/site/{key}/` path names, as its id and primary slug.  `key` is a slug,
+    /// primary or not, or a legacy numeric id.
+    fn find_site(&self, key: &str) -> Result<Option<(u32, String)>, TenKbError>;
+
+    /// Whether `site` is listed.
+    fn check_site_active(&self, site: &str) -> Result<bool, TenKbError>;
+
+    /// The site `url` is an alias of, if any.
+    fn get_alias_target(&self, url: &str) -> Result<Option<u32>, TenKbError>;
+
+    /// Regular expressions matching the URLs that may not be submitted.
+    fn get_blocked_patterns(&self) -> Result<Vec<String>, TenKbError>;
+
+    /// Whether `site` is in the validation queue, whatever its outcome.
+    fn check_site_queued(&self, site: &str) -> Result<bool, TenKbError>;
+
+    /// Pages of a domain that are listed or waiting for validation.
+    fn count_domain_pages(&self, host: &str) -> Result<usize, TenKbError>;
+
+    /// Why `site` can't be submitted, if it can't: it is already listed, an alias of a
+    /// listed site, blocked, already queued, or on a domain with its fill of pages.
+    fn check_submission(&self, site: &str, pages_per_domain: usize) -> Result<(), TenKbError> {
+        if self.check_site_active(site)? {
+            info!("site '{site}' is already active");
+            return Err(TenKbError::Msg(format!(
+                "site '{site}' is already in the database"
+            )));
+        }
+
+        if let Some(id) = self.get_alias_target(site)? {
+            info!("site '{site}' is an alias of site {id}");
+            return Err(TenKbError::Msg(format!(
+                "site '{site}' is already in the database"
+            )));
+        }
+
+        if is_blocked(site, &self.get_blocked_patterns()?) {
+            return Err(TenKbError::Msg(format!(
+                "sorry! site '{site}' is blocked from submission"
+            )));
+        }
+
+        if self.check_site_queued(site)? {
+            info!("site '{site}' is already queued for validation");
+            return Err(TenKbError::Msg(format!(
+                "site '{site}' is already pending validation"
+            )));
+        }
+
+        if let (Some(host), true) = (site_domain(site), pages_per_domain > 0) {
+            let pages = self.count_domain_pages(&host)?;
+            if pages >= pages_per_domain {
+                info!("domain '{host}' already has {pages} pages listed or pending");
+                return Err(TenKbError::Msg(format!(
+                    "sorry! {host} already has as many pages in the club as it may have"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queue a submitted site for validation, or explain why it can't be.
+    fn submit_site(&self, site: String, pages_per_domain: usize) -> Result<(), TenKbError>;
+
+    /// Register a new voter id.
+    fn generate_id(&self, id: String) -> Result<(), TenKbError>;
+
+    fn voter_exists(&self, voter_id: &str) -> Result<bool, TenKbError>;
+
+    /// Set the voter's vote on a site, returning whether anything changed and the site's
+    /// new count.
+    fn cast_vote(
+        &self,
+        voter_id: String,
+        site_id: u32,
+        vote: isize,
+        shadowed: bool,
+    ) -> Result<(bool, u32), TenKbError>;
+
+    /// The ids of the sites the voter has voted for.
+    fn get_votes(&self, voter_id: String) -> Result<Vec<u32>, TenKbError>;
+
+    /// Record today's public vote count for every listed site, for the "top this
+    /// week/month" rankings.  Returns how many sites were recorded.
+    fn snapshot_vote_counts(&self) -> Result<usize, TenKbError>;
+
+    /// URLs of the sites waiting to be scanned, in the order to scan them.
+    fn get_validation_queue(&self) -> Result<Vec<String>, Box<dyn Error>>;
+
+    /// List a site that passed validation, returning its id.
+    fn mark_good(
+        &self,
+        site: &str,
+        size: f64,
+        mobile_size: Option<f64>,
+        related: &[RelatedLink],
+    ) -> Result<u32, Box<dyn Error>>;
+
+    /// Take a site off the queue with a failing `outcome`.
+    fn mark_bad(
+        &self,
+        site: &str,
+        outcome: ValidationOutcome,
+        detail: &str,
+    ) -> Result<(), Box<dyn Error>>;
+
+    /// Take a site off the queue for being over the size limit.
+    fn mark_bad_size(&self, site: &str, size: f64) -> Result<(), Box<dyn Error>>;
+
+    /// The site's related links.
+    fn get_related(&self, site: u32) -> Result<Vec<RelatedLink>, TenKbError>;
+
+    /// Replace a site's related links.
+    fn update_related(&self, site: &str, related: &[RelatedLink]) -> Result<(), Box<dyn Error>>;
+
+    /// When a site's related links were last harvested, and when a refresh was asked for
+    /// if one is still waiting.
+    fn get_related_refresh(
+        &self,
+        site: u32,
+    ) -> Result<(Option<String>, Option<String>), TenKbError>;
+
+    /// Ask the analyzer to harvest a site's related links again.  Returns false if a
+    /// refresh is already waiting.
+    fn request_related_refresh(&self, site: u32) -> Result<bool, TenKbError>;
+
+    /// Sites waiting for their related links to be refreshed, in the order they were asked
+    /// for.
+    fn get_requested_refreshes(&self) -> Result<Vec<String>, Box<dyn Error>>;
+
+    /// The SQLite pool, if this is it.  The data kept only in SQLite is keyed by SQLite's
+    /// site ids, so features built on it are left out when the sites live elsewhere.
+    fn sqlite(&self) -> Option<&Pool> {
+        None
+    }
+}
+
+/// The storage listings are served from: the SQLite read replica if one is configured,
+/// otherwise the same storage as everything else.
+#[derive(Clone)]
+pub struct ReadStorage(pub Arc<dyn Storage>);
+
+/// The configured storage, and the storage to serve listings from.  `pool` and `replica`
+/// are the SQLite primary and read pools, used when SQLite is the backend.
+pub fn open(
+    config: &Config,
+    pool: &Pool,
+    replica: &Pool,
+) -> Result<(Arc<dyn Storage>, ReadStorage), std::io::Error> {
+    match config.storage.backend {
+        StorageBackend::Sqlite => Ok((
+            Arc::new(pool.clone()),
+            ReadStorage(Arc::new(replica.clone())),
+        )),
+        #[cfg(feature = "postgres")]
+        StorageBackend::Postgres => {
+            let url = config.storage.url.as_deref().unwrap_or_default();
+            let storage: Arc<dyn Storage> = Arc::new(
+                crate::postgres::PgStorage::connect(url, config.storage.pool_size)
+                    .map_err(|TenKbError::Msg(msg)| std::io::Error::other(msg))?,
+            );
+            info!("storing sites in postgres");
+            Ok((storage.clone(), ReadStorage(storage)))
+        }
+        #[cfg(not(feature = "postgres"))]
+        StorageBackend::Postgres => Err(std::io::Error::other(
+            "storage.backend is postgres, but this build lacks the postgres feature",
+        )),
+    }
+}
+
+/// Whether `site` matches one of the block `patterns`.  Patterns that aren't valid regular
+/// expressions are skipped.
+fn is_blocked(site: &str, patterns: &[String]) -> bool {
+    for pattern in patterns {
+        let Ok(re) = Regex::new(pattern) else {
+            continue;
+        };
+
+        if re.is_match(site) {
+            info!("site '{site}' matched block pattern '{pattern}'");
+            return true;
+        }
+    }
+
+    false
+}
+
+impl Storage for Pool {
+    fn get_sites(
+        &self,
+        sortby: SortOptions,
+        filter: &SiteFilter,
+        skip: usize,
+        paginate: usize,
+    ) -> Result<Vec<Site>, TenKbError> {
+        database::get_sites(self, sortby, filter, skip, paginate)
+    }
+
+    fn get_site_count(&self, filter: &SiteFilter) -> Result<usize, TenKbError> {
+        database::get_site_count(self, filter)
+    }
+
+    fn get_site_url(&self, id: u32) -> Result<String, TenKbError> {
+        database::get_site_url(self, id)
+    }
+
+    fn find_site(&self, key: &str) -> Result<Option<(u32, String)>, TenKbError> {
+        database::find_site(self, key)
+    }
+
+    fn check_site_active(&self, site: &str) -> Result<bool, TenKbError> {
+        database::check_site_active(self, site)
+    }
+
+    fn get_alias_target(&self, url: &str) -> Result<Option<u32>, TenKbError> {
+        database::get_alias_target(self, url)
+    }
+
+    fn get_blocked_patterns(&self) -> Result<Vec<String>, TenKbError> {
+        database::get_blocked_patterns(self)
+    }
+
+    fn check_site_queued(&self, site: &str) -> Result<bool, TenKbError> {
+        database::check_site_queued(self, site)
+    }
+
+    fn count_domain_pages(&self, host: &str) -> Result<usize, TenKbError> {
+        database::count_domain_pages(self, host)
+    }
+
+    fn submit_site(&self, site: String, pages_per_domain: usize) -> Result<(), TenKbError> {
+        database::submit_site(self, site, pages_per_domain)
+    }
+
+    fn generate_id(&self, id: String) -> Result<(), TenKbError> {
+        database::generate_id(self, id)
+    }
+
+    fn voter_exists(&self, voter_id: &str) -> Result<bool, TenKbError> {
+        database::voter_exists(self, voter_id)
+    }
+
+    fn cast_vote(
+        &self,
+        voter_id: String,
+        site_id: u32,
+        vote: isize,
+        shadowed: bool,
+    ) -> Result<(bool, u32), TenKbError> {
+        database::cast_vote(self, voter_id, site_id, vote, shadowed)
+    }
+
+    fn get_votes(&self, voter_id: String) -> Result<Vec<u32>, TenKbError> {
+        database::get_votes(self, voter_id)
+    }
+
+    fn snapshot_vote_counts(&self) -> Result<usize, TenKbError> {
+        database::snapshot_vote_counts(self)
+    }
+
+    fn get_validation_queue(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        database::get_validation_queue(self)
+    }
+
+    fn mark_good(
+        &self,
+        site: &str,
+        size: f64,
+        mobile_size: Option<f64>,
+        related: &[RelatedLink],
+    ) -> Result<u32, Box<dyn Error>> {
+        database::mark_good(self, site, size, mobile_size, related)
+    }
+
+    fn mark_bad(
+        &self,
+        site: &str,
+        outcome: ValidationOutcome,
+        detail: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        database::mark_bad(self, site, outcome, detail)
+    }
+
+    fn mark_bad_size(&self, site: &str, size: f64) -> Result<(), Box<dyn Error>> {
+        database::mark_bad_size(self, site, size)
+    }
+
+    fn get_related(&self, site: u32) -> Result<Vec<RelatedLink>, TenKbError> {
+        database::get_related(self, site)
+    }
+
+    fn update_related(&self, site: &str, related: &[RelatedLink]) -> Result<(), Box<dyn Error>> {
+        database::update_related(self, site, related)
+    }
+
+    fn get_related_refresh(
+        &self,
+        site: u32,
+    ) -> Result<(Option<String>, Option<String>), TenKbError> {
+        database::get_related_refresh(self, site)
+    }
+
+    fn request_related_refresh(&self, site: u32) -> Result<bool, TenKbError> {
+        database::request_related_refresh(self, site)
+    }
+
+    fn get_requested_refreshes(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        database::get_requested_refreshes(self)
+    }
+
+    fn sqlite(&self) -> Option<&Pool> {
+        Some(self)
+    }
+}
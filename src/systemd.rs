@@ -0,0 +1,82 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! systemd integration: readiness and watchdog notifications (`Type=notify`,
+//! `WatchdogSec=`) and socket activation (`LISTEN_FDS`).  Everything here is a no-op when
+//! the server isn't run by systemd.
+
+use std::{
+    net::TcpListener,
+    os::fd::{FromRawFd, RawFd},
+};
+
+use sd_notify::NotifyState;
+use tracing::{info, warn};
+
+/// The first socket passed by systemd, if the server was socket activated.
+pub fn activated_listener() -> std::io::Result<Option<TcpListener>> {
+    let mut fds = sd_notify::listen_fds()?;
+    let Some(fd) = fds.next() else {
+        return Ok(None);
+    };
+
+    if fds.len() > 0 {
+        warn!(
+            "systemd passed {} sockets; only the first is used",
+            fds.len() + 1
+        );
+    }
+
+    info!("using socket passed by systemd (fd {fd})");
+    // SAFETY: systemd passes ownership of the listening sockets to this process, and
+    // listen_fds() yields each descriptor once.
+    Ok(Some(unsafe { TcpListener::from_raw_fd(fd as RawFd) }))
+}
+
+pub fn notify_ready() {
+    if let Err(e) = sd_notify::notify(&[NotifyState::Ready]) {
+        warn!("unable to notify systemd of readiness: {e}");
+    }
+}
+
+pub fn notify_stopping() {
+    let _ = sd_notify::notify(&[NotifyState::Stopping]);
+}
+
+/// If systemd expects watchdog pings, send them at half the configured interval from the
+/// current runtime, so a wedged runtime gets the service restarted.
+pub fn spawn_watchdog() {
+    let Some(timeout) = sd_notify::watchdog_enabled() else {
+        return;
+    };
+
+    info!("systemd watchdog enabled ({timeout:?})");
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(timeout / 2);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = sd_notify::notify(&[NotifyState::Watchdog]) {
+                warn!("unable to ping systemd watchdog: {e}");
+            }
+        }
+    });
+}
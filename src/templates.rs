@@ -0,0 +1,92 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use std::{path::PathBuf, sync::Arc};
+
+use minijinja::{Environment, State, Value};
+
+use crate::{config::Config, filters, i18n::Catalogs, minify::minify_html};
+
+/// The template environment.  In reload mode, templates are read from disk on every
+/// render, so edits show up without restarting; otherwise a single environment is shared
+/// by every worker and each template is loaded and compiled once.  Output can optionally
+/// be minified.
+pub struct Templates {
+    path: PathBuf,
+    cached: Option<Environment<'static>>,
+    minify: bool,
+    catalogs: Arc<Catalogs>,
+}
+
+impl Templates {
+    /// Reload mode defaults to on in debug builds; `template_reload` in the config
+    /// overrides it.
+    pub fn new(config: &Config, catalogs: Arc<Catalogs>) -> Self {
+        let reload = config.template_reload.unwrap_or(cfg!(debug_assertions));
+
+        let path = config.template_path.clone();
+        let cached = if reload {
+            None
+        } else {
+            Some(environment(&path, catalogs.clone()))
+        };
+
+        Self {
+            path,
+            cached,
+            minify: config.minify_html,
+            catalogs,
+        }
+    }
+
+    pub fn render(&self, name: &str, ctx: Value) -> Result<String, minijinja::Error> {
+        let html = match &self.cached {
+            Some(env) => env.get_template(name)?.render(ctx)?,
+            None => environment(&self.path, self.catalogs.clone())
+                .get_template(name)?
+                .render(ctx)?,
+        };
+
+        if self.minify && name.ends_with(".html") {
+            Ok(minify_html(&html))
+        } else {
+            Ok(html)
+        }
+    }
+}
+
+fn environment(path: &PathBuf, catalogs: Arc<Catalogs>) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_loader(minijinja::path_loader(path));
+    filters::register(&mut env);
+
+    // t("message") translates into the page's `lang`.
+    env.add_function("t", move |state: &State, msgid: String| -> String {
+        let lang = state
+            .lookup("lang")
+            .and_then(|lang| lang.as_str().map(String::from))
+            .unwrap_or_else(|| String::from(catalogs.default_locale()));
+        catalogs.translate(&lang, &msgid)
+    });
+
+    env
+}
@@ -0,0 +1,109 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Stand-ins for the network, so the analyzer can be run end to end in tests: open a
+//! database with `database::init_db_in_memory`, submit a site, and run `validation_sweep`
+//! with a `MockScanner` and `MockSources` that know about it.
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use crate::{
+    analyzer::Scanner,
+    cloudflare::{ScanVantage, UrlScan, UrlScanResult, SIZE_LIMIT},
+    config::Config,
+    relatedlinks::{RelatedLink, RelatedLinkResult, RelatedSources},
+};
+
+/// Serves canned pages.  A scan measures the page's length, so a page over the size limit
+/// fails validation; any URL without a page is unreachable.
+#[derive(Default)]
+pub struct MockScanner {
+    pages: HashMap<String, Vec<u8>>,
+    malicious: HashSet<String>,
+}
+
+impl MockScanner {
+    pub fn page(mut self, url: &str, body: &str) -> Self {
+        self.pages
+            .insert(String::from(url), body.as_bytes().to_vec());
+        self
+    }
+
+    /// Have scans flag `url` as malicious.
+    pub fn malicious(mut self, url: &str) -> Self {
+        self.malicious.insert(String::from(url));
+        self
+    }
+}
+
+impl Scanner for MockScanner {
+    async fn fetch(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.pages
+            .get(url)
+            .cloned()
+            .ok_or_else(|| format!("no page for {url}").into())
+    }
+
+    async fn scan(&self, site: &str, _vantage: ScanVantage<'_>, _config: &Config) -> UrlScanResult {
+        let size = self.fetch(site).await?.len() as f64;
+        let malicious = self.malicious.contains(site);
+
+        Ok(UrlScan {
+            size,
+            acceptable: size <= SIZE_LIMIT as f64 && !malicious,
+            malicious,
+            scan_id: None,
+        })
+    }
+}
+
+/// Canned discussions; sites without any have none.
+#[derive(Default)]
+pub struct MockSources {
+    hackernews: HashMap<String, Vec<RelatedLink>>,
+    lobsters: HashMap<String, Vec<RelatedLink>>,
+}
+
+impl MockSources {
+    pub fn hackernews(mut self, site: &str, links: Vec<RelatedLink>) -> Self {
+        self.hackernews.insert(String::from(site), links);
+        self
+    }
+
+    pub fn lobsters(mut self, site: &str, links: Vec<RelatedLink>) -> Self {
+        self.lobsters.insert(String::from(site), links);
+        self
+    }
+}
+
+impl RelatedSources for MockSources {
+    async fn hackernews(&self, site: &str) -> RelatedLinkResult {
+        Ok(self.hackernews.get(site).cloned().unwrap_or_default())
+    }
+
+    async fn lobsters(&self, site: &str) -> RelatedLinkResult {
+        Ok(self.lobsters.get(site).cloned().unwrap_or_default())
+    }
+}
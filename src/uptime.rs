@@ -0,0 +1,91 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Periodic liveness checks of listed sites.  Each check's outcome is kept for
+//! [`UPTIME_DAYS`] days, long enough for the rolling uptime shown on a site's detail page,
+//! so a member that has quietly gone dead stands out before anyone clicks through to it.
+
+use std::{error::Error, time::Duration};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::{
+    analyzer::{instance_id, Scanner},
+    database::{
+        acquire_lease, get_listed_sites, prune_uptime_checks, record_uptime_check, release_lease,
+        Pool,
+    },
+    error::TenKbError,
+};
+
+const UPTIME_LEASE: &str = "uptime";
+const UPTIME_LEASE_TTL: u32 = 600;
+
+/// How far back uptime is computed, and how long checks are kept.
+pub const UPTIME_DAYS: u32 = 90;
+
+/// How long a site has to answer before it counts as down.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A site's uptime over the last [`UPTIME_DAYS`] days.
+#[derive(Debug, Serialize)]
+pub struct UptimeSummary {
+    pub checks: u32,
+    pub up: u32,
+    pub percent: f64,
+}
+
+/// Check that every listed site still answers with a 200, and record the outcome.
+pub async fn uptime_sweep(pool: &Pool, scanner: &impl Scanner) -> Result<(), Box<dyn Error>> {
+    if !acquire_lease(pool, UPTIME_LEASE, instance_id(), UPTIME_LEASE_TTL)? {
+        info!("another instance holds the uptime lease; skipping sweep");
+        return Ok(());
+    }
+
+    let sites = get_listed_sites(pool).map_err(|TenKbError::Msg(msg)| msg)?;
+    info!("checking that {} listed sites are up", sites.len());
+
+    let mut down = 0;
+    for (site_id, site) in sites {
+        if !acquire_lease(pool, UPTIME_LEASE, instance_id(), UPTIME_LEASE_TTL)? {
+            warn!("lost the uptime lease; abandoning sweep");
+            return Ok(());
+        }
+
+        let detail = match tokio::time::timeout(CHECK_TIMEOUT, scanner.fetch(&site)).await {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => Some(format!("no answer in {} seconds", CHECK_TIMEOUT.as_secs())),
+        };
+        if let Some(detail) = &detail {
+            warn!("uptime: {site} is down: {detail}");
+            down += 1;
+        }
+        record_uptime_check(pool, site_id, detail.as_deref())?;
+    }
+
+    let pruned = prune_uptime_checks(pool, UPTIME_DAYS)?;
+    info!("uptime sweep done: {down} sites down, {pruned} old checks pruned");
+
+    release_lease(pool, UPTIME_LEASE, instance_id())
+}
@@ -0,0 +1,272 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! [Webmentions](https://www.w3.org/TR/webmention/) in both directions.  A page that links
+//! to a member's club page (`/site/{slug}/` or `/related/{slug}/`) can tell us so, and once we
+//! have confirmed the link the mention is shown alongside the site's related discussions.
+//! When a site is listed or featured, we send one from its club page to its homepage.
+
+use regex::Regex;
+use reqwest::{header, StatusCode};
+use serde::Serialize;
+use std::{fmt, fmt::Display, fmt::Formatter, sync::OnceLock};
+use tracing::info;
+use url::Url;
+
+use crate::{
+    config::Config,
+    database::{get_site_slug, record_webmention_sent, webmention_sent_recently, Pool},
+    error::TenKbError,
+    favicon::read_limited,
+    html::rel_tags,
+};
+
+/// A confirmed mention of a member site.
+#[derive(Debug, Serialize)]
+pub struct Webmention {
+    pub source: String,
+    pub title: Option<String>,
+    pub verified: String,
+}
+
+/// A webmention we sent, or tried to send, to a member's homepage.
+#[derive(Debug, Serialize)]
+pub struct SentWebmention {
+    pub site_id: u32,
+    pub target: String,
+    pub reason: String,
+    pub endpoint: Option<String>,
+    /// `no endpoint`, the endpoint's response status, or why the attempt failed.
+    pub result: String,
+    pub sent: String,
+}
+
+/// Why a member is being told about its club page.
+#[derive(Clone, Copy, Debug)]
+pub enum Announcement {
+    Listed,
+    Featured,
+}
+
+impl Display for Announcement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Announcement::Listed => write!(f, "listed"),
+            Announcement::Featured => write!(f, "featured"),
+        }
+    }
+}
+
+/// The outcome of fetching a mention's source.
+#[derive(Debug)]
+pub enum Verification {
+    /// The source links to the target; carries the source's `<title>`, if any.
+    Links(Option<String>),
+    /// The source no longer links to the target (or is gone), so any stored mention should
+    /// be removed.
+    Gone,
+}
+
+/// How a webmention target names a member site, if it is one of our club pages: the slug
+/// or legacy id from its path, for `database::find_site`.
+pub fn target_site(base_url: &str, target: &Url) -> Option<String> {
+    static PATH: OnceLock<Regex> = OnceLock::new();
+    let path = PATH.get_or_init(|| Regex::new(r"^/(?:site|related)/([a-z0-9-]+)/?$").unwrap());
+
+    let base = Url::parse(base_url).ok()?;
+    if base.origin() != target.origin() {
+        return None;
+    }
+
+    Some(path.captures(target.path())?.get(1)?.as_str().into())
+}
+
+/// Fetch `source` and check that it links to `target`.
+pub async fn verify(source: &Url, target: &Url, max_bytes: usize) -> Result<Verification, String> {
+    let res = reqwest::get(source.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    match res.status().as_u16() {
+        200 => {}
+        410 => return Ok(Verification::Gone),
+        status => return Err(format!("source returned status code {status}")),
+    }
+
+    let (content_type, body) = read_limited(res, max_bytes)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !content_type.is_empty() && !content_type.starts_with("text/") {
+        return Err(format!("unsupported source content type '{content_type}'"));
+    }
+
+    let html = String::from_utf8_lossy(&body);
+    if !links_to(&html, source, target) {
+        return Ok(Verification::Gone);
+    }
+
+    Ok(Verification::Links(title(&html)))
+}
+
+fn links_to(html: &str, source: &Url, target: &Url) -> bool {
+    links(html, source).any(|url| url == *target)
+}
+
+/// Every `href` in `html`, resolved against `base` and without fragments.
+pub(crate) fn links<'a>(html: &'a str, base: &'a Url) -> impl Iterator<Item = Url> + 'a {
+    static HREF: OnceLock<Regex> = OnceLock::new();
+    let href = HREF.get_or_init(|| {
+        Regex::new(r#"(?i)\bhref\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap()
+    });
+
+    href.captures_iter(html)
+        .filter_map(|caps| caps.get(1).or(caps.get(2)).or(caps.get(3)))
+        .filter_map(|m| base.join(m.as_str().trim()).ok())
+        .map(|mut url| {
+            url.set_fragment(None);
+            url
+        })
+}
+
+pub(crate) fn title(html: &str) -> Option<String> {
+    static TITLE: OnceLock<Regex> = OnceLock::new();
+    let title = TITLE.get_or_init(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+
+    let text = title.captures(html)?.get(1)?.as_str().trim();
+    let text: String = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then(|| text.chars().take(200).collect())
+}
+
+/// Send a webmention from a site's club page to its homepage, if announcements are enabled
+/// and the homepage hasn't been sent one within `min_interval_hours`.  Every attempt is
+/// recorded, including those to sites without an endpoint.
+pub async fn announce(
+    pool: &Pool,
+    config: &Config,
+    site_id: u32,
+    site: &str,
+    reason: Announcement,
+) -> Result<(), String> {
+    let Some(announce) = &config.announcements else {
+        return Ok(());
+    };
+
+    let hours = announce.min_interval_hours;
+    let (tmp, target) = (pool.clone(), String::from(site));
+    if tokio::task::spawn_blocking(move || webmention_sent_recently(&tmp, &target, hours))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|TenKbError::Msg(msg)| msg)?
+    {
+        info!("not announcing {site}: it was sent a webmention in the last {hours} hours");
+        return Ok(());
+    }
+
+    let tmp = pool.clone();
+    let slug = tokio::task::spawn_blocking(move || get_site_slug(&tmp, site_id))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|TenKbError::Msg(msg)| msg)?;
+    let source = format!("{}/site/{slug}/", config.base_url);
+    let target = Url::parse(site).map_err(|e| e.to_string())?;
+
+    let (endpoint, result) = match discover_endpoint(&target, announce.max_bytes).await {
+        Ok(Some(endpoint)) => {
+            let result = match send(&endpoint, &source, &target).await {
+                Ok(status) if status.is_success() => format!("delivered ({status})"),
+                Ok(status) => format!("rejected ({status})"),
+                Err(e) => e,
+            };
+            (Some(endpoint.to_string()), result)
+        }
+        Ok(None) => (None, String::from("no endpoint")),
+        Err(e) => (None, e),
+    };
+    info!("webmention to {site} ({reason}): {result}");
+
+    let (tmp, target, reason) = (pool.clone(), String::from(site), reason.to_string());
+    tokio::task::spawn_blocking(move || {
+        record_webmention_sent(
+            &tmp,
+            site_id,
+            &target,
+            &reason,
+            endpoint.as_deref(),
+            &result,
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|TenKbError::Msg(msg)| msg)
+}
+
+/// The target's webmention endpoint, from a `Link` header or else the first `<link>` or `<a>`
+/// with `rel="webmention"`.
+async fn discover_endpoint(target: &Url, max_bytes: usize) -> Result<Option<Url>, String> {
+    static LINK_HEADER: OnceLock<Regex> = OnceLock::new();
+    let link_header = LINK_HEADER.get_or_init(|| {
+        Regex::new(r#"(?i)<([^>]*)>\s*;[^,]*?\brel\s*=\s*"?[^",]*\bwebmention\b"#).unwrap()
+    });
+
+    let res = reqwest::get(target.clone())
+        .await
+        .map_err(|e| e.to_string())?;
+    if res.status() != StatusCode::OK {
+        return Err(format!("homepage returned status code {}", res.status()));
+    }
+
+    // Relative endpoints resolve against wherever the homepage ended up after redirects.
+    let base = res.url().clone();
+    let from_header = res
+        .headers()
+        .get_all(header::LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| Some(String::from(link_header.captures(value)?.get(1)?.as_str())));
+    if let Some(endpoint) = from_header {
+        return base.join(&endpoint).map(Some).map_err(|e| e.to_string());
+    }
+
+    let (_, body) = read_limited(res, max_bytes)
+        .await
+        .map_err(|e| e.to_string())?;
+    let html = String::from_utf8_lossy(&body);
+
+    let endpoint = rel_tags(&html, "webmention", true)
+        .next()
+        .map(|tag| tag.href);
+    match endpoint {
+        Some(endpoint) => base.join(endpoint).map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+async fn send(endpoint: &Url, source: &str, target: &Url) -> Result<StatusCode, String> {
+    let res = reqwest::Client::new()
+        .post(endpoint.clone())
+        .form(&[("source", source), ("target", target.as_str())])
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(res.status())
+}
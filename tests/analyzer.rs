@@ -0,0 +1,124 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The analyzer end to end against an in-memory database, with the network replaced by the
+//! stand-ins in `testing`.
+
+use std::sync::{Arc, Mutex};
+
+use tenkbclub::{
+    analyzer::validation_sweep,
+    cloudflare::SIZE_LIMIT,
+    config::Config,
+    database::{check_site_active, find_site, init_db_in_memory, Pool},
+    events,
+    relatedlinks::RelatedLink,
+    storage::Storage,
+    testing::{MockScanner, MockSources},
+    SiteFilter,
+};
+
+const SITE: &str = "https://small.example/";
+
+fn config() -> Config {
+    serde_json::from_value(serde_json::json!({
+        "database_path": ":memory:",
+        "template_path": "templates",
+        "cloudflare_account": "test",
+        "cloudflare_api_token": "test",
+    }))
+    .expect("unable to build config")
+}
+
+/// One validation sweep over everything in the queue.
+async fn sweep(pool: &Pool, scanner: &MockScanner, sources: &MockSources) {
+    let status = Arc::new(Mutex::new(Default::default()));
+    validation_sweep(
+        pool,
+        pool,
+        &config(),
+        &status,
+        &events::channel(),
+        scanner,
+        sources,
+    )
+    .await
+    .expect("sweep failed");
+}
+
+#[tokio::test]
+async fn submitted_site_is_listed_after_a_sweep() {
+    let pool = init_db_in_memory();
+    pool.submit_site(String::from(SITE), 1)
+        .expect("unable to submit site");
+
+    let scanner = MockScanner::default().page(SITE, "<html><p>Small.</p></html>");
+    sweep(&pool, &scanner, &MockSources::default()).await;
+
+    assert!(check_site_active(&pool, &String::from(SITE)).expect("unable to check site"));
+    assert_eq!(pool.get_site_count(&SiteFilter::default()).unwrap(), 1);
+    assert!(pool.get_validation_queue().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn oversized_site_is_not_listed() {
+    let pool = init_db_in_memory();
+    pool.submit_site(String::from(SITE), 1)
+        .expect("unable to submit site");
+
+    let page = format!("<html><p>{}</p></html>", "x".repeat(SIZE_LIMIT));
+    let scanner = MockScanner::default().page(SITE, &page);
+    sweep(&pool, &scanner, &MockSources::default()).await;
+
+    assert!(!check_site_active(&pool, &String::from(SITE)).expect("unable to check site"));
+    assert_eq!(pool.get_site_count(&SiteFilter::default()).unwrap(), 0);
+    assert!(pool.get_validation_queue().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn listed_site_keeps_its_discussions() {
+    let pool = init_db_in_memory();
+    pool.submit_site(String::from(SITE), 1)
+        .expect("unable to submit site");
+
+    let scanner = MockScanner::default().page(SITE, "<html><p>Small.</p></html>");
+    let sources = MockSources::default().hackernews(
+        SITE,
+        vec![RelatedLink {
+            url: String::from(SITE),
+            discussion_url: String::from("https://news.ycombinator.com/item?id=1"),
+            description: String::from("A small site"),
+            upvotes: 12,
+            comments: 3,
+            date: String::from("2024-01-01 00:00:00"),
+        }],
+    );
+    sweep(&pool, &scanner, &sources).await;
+
+    let (id, _) = find_site(&pool, "1").unwrap().expect("site not found");
+    let related = pool.get_related(id).unwrap();
+    assert_eq!(related.len(), 1);
+    assert_eq!(
+        related[0].discussion_url,
+        "https://news.ycombinator.com/item?id=1"
+    );
+}
@@ -0,0 +1,291 @@
+// MIT License
+//
+// Copyright (c) 2024 Marcus Butler
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The Postgres storage backend against a real server.  Set `TENKB_TEST_POSTGRES` to a
+//! key-value connection string, e.g. `host=localhost user=postgres`, and run with
+//! `--features postgres`; each test works in a schema of its own, dropped afterwards.
+//! Without the variable the tests pass without doing anything.
+
+#![cfg(feature = "postgres")]
+
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use postgres::{Client, NoTls};
+use tenkbclub::{
+    analyzer::validation_sweep,
+    config::Config,
+    database::{init_db_in_memory, ValidationOutcome},
+    events,
+    postgres::PgStorage,
+    relatedlinks::RelatedLink,
+    storage::Storage,
+    testing::{MockScanner, MockSources},
+    SiteFilter, SortOptions,
+};
+
+const SITE: &str = "https://small.example/";
+
+/// A schema of its own on the test server, dropped with it.
+struct Schema {
+    url: String,
+    name: String,
+}
+
+impl Schema {
+    fn create() -> Option<Self> {
+        static SCHEMAS: AtomicUsize = AtomicUsize::new(0);
+
+        let Ok(url) = env::var("TENKB_TEST_POSTGRES") else {
+            eprintln!("TENKB_TEST_POSTGRES is not set; skipping");
+            return None;
+        };
+        let name = format!(
+            "tenkb_test_{}_{}",
+            std::process::id(),
+            SCHEMAS.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut client = Client::connect(&url, NoTls).expect("unable to connect");
+        client
+            .batch_execute(&format!("CREATE SCHEMA {name}"))
+            .expect("unable to create schema");
+        Some(Self { url, name })
+    }
+
+    /// Storage in the schema, which is created and migrated on the first connection.
+    fn storage(&self) -> PgStorage {
+        let url = format!("{} options='-c search_path={}'", self.url, self.name);
+        PgStorage::connect(&url, 2).expect("unable to connect storage")
+    }
+}
+
+impl Drop for Schema {
+    fn drop(&mut self) {
+        let mut client = Client::connect(&self.url, NoTls).expect("unable to connect");
+        client
+            .batch_execute(&format!("DROP SCHEMA {} CASCADE", self.name))
+            .expect("unable to drop schema");
+    }
+}
+
+fn config() -> Config {
+    serde_json::from_value(serde_json::json!({
+        "database_path": ":memory:",
+        "template_path": "templates",
+        "cloudflare_account": "test",
+        "cloudflare_api_token": "test",
+    }))
+    .expect("unable to build config")
+}
+
+fn link(discussion: &str) -> RelatedLink {
+    RelatedLink {
+        url: String::from(SITE),
+        discussion_url: format!("https://news.ycombinator.com/item?id={discussion}"),
+        description: String::from("A small site"),
+        upvotes: 12,
+        comments: 3,
+        date: String::from("2024-01-01 00:00:00"),
+    }
+}
+
+/// A site as the analyzer lists it, returning its id.
+fn listed(storage: &PgStorage, url: &str) -> u32 {
+    storage.submit_site(String::from(url), 0).unwrap();
+    storage.mark_good(url, 5000.0, None, &[]).unwrap()
+}
+
+#[test]
+fn migrations_are_applied_once() {
+    let Some(schema) = Schema::create() else {
+        return;
+    };
+    drop(schema.storage());
+    let storage = schema.storage();
+    assert_eq!(storage.get_site_count(&SiteFilter::default()).unwrap(), 0);
+}
+
+#[test]
+fn submitted_site_is_listed_after_a_sweep() {
+    let Some(schema) = Schema::create() else {
+        return;
+    };
+    let storage = schema.storage();
+    storage.submit_site(String::from(SITE), 1).unwrap();
+    assert_eq!(storage.get_validation_queue().unwrap(), vec![SITE]);
+
+    let scanner = MockScanner::default().page(SITE, "<html><p>Small.</p></html>");
+    let sources = MockSources::default().hackernews(SITE, vec![link("1")]);
+    let status = Arc::new(Mutex::new(Default::default()));
+
+    // The sweep uses the storage from within a runtime, as the server's analyzer does.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime
+        .block_on(validation_sweep(
+            &storage,
+            &init_db_in_memory(),
+            &config(),
+            &status,
+            &events::channel(),
+            &scanner,
+            &sources,
+        ))
+        .expect("sweep failed");
+
+    assert!(storage.check_site_active(SITE).unwrap());
+    assert!(storage.get_validation_queue().unwrap().is_empty());
+    let (id, slug) = storage
+        .find_site("1")
+        .unwrap()
+        .expect("site not found by id");
+    assert_eq!(storage.find_site(&slug).unwrap(), Some((id, slug.clone())));
+    assert_eq!(storage.get_site_url(id).unwrap(), SITE);
+    assert_eq!(storage.get_related(id).unwrap().len(), 1);
+
+    let sites = storage
+        .get_sites(SortOptions::Votes, &SiteFilter { has_related: true }, 0, 10)
+        .unwrap();
+    let site = &serde_json::to_value(&sites).unwrap()[0];
+    assert_eq!(site["url"], SITE);
+    assert_eq!(site["slug"], slug.as_str());
+    assert_eq!(site["related"], 1);
+    assert_eq!(site["discussion_points"], 12);
+    assert_eq!(site["recent_votes"], serde_json::Value::Null);
+}
+
+#[test]
+fn submissions_are_checked() {
+    let Some(schema) = Schema::create() else {
+        return;
+    };
+    let storage = schema.storage();
+    storage.submit_site(String::from(SITE), 0).unwrap();
+
+    let queued = storage.submit_site(String::from(SITE), 0).unwrap_err();
+    assert!(format!("{queued:?}").contains("already pending validation"));
+
+    storage.mark_good(SITE, 5000.0, None, &[]).unwrap();
+    let active = storage.check_submission(SITE, 0).unwrap_err();
+    assert!(format!("{active:?}").contains("already in the database"));
+
+    let other = "https://small.example/about/";
+    let capped = storage.check_submission(other, 1).unwrap_err();
+    assert!(format!("{capped:?}").contains("already has as many pages"));
+    storage.check_submission(other, 2).unwrap();
+}
+
+#[test]
+fn failed_sites_leave_the_queue() {
+    let Some(schema) = Schema::create() else {
+        return;
+    };
+    let storage = schema.storage();
+    storage.submit_site(String::from(SITE), 0).unwrap();
+    storage.mark_bad_size(SITE, 20000.0).unwrap();
+    assert!(storage.get_validation_queue().unwrap().is_empty());
+    assert!(!storage.check_site_active(SITE).unwrap());
+
+    let other = "https://other.example/";
+    storage.submit_site(String::from(other), 0).unwrap();
+    storage
+        .mark_bad(other, ValidationOutcome::FailedUnreachable, "no answer")
+        .unwrap();
+    assert!(storage.get_validation_queue().unwrap().is_empty());
+}
+
+#[test]
+fn votes_count_once_per_voter() {
+    let Some(schema) = Schema::create() else {
+        return;
+    };
+    let storage = schema.storage();
+    let site = listed(&storage, SITE);
+    let voter = String::from("voter");
+    storage.generate_id(voter.clone()).unwrap();
+    assert!(storage.voter_exists(&voter).unwrap());
+    assert!(!storage.voter_exists("nobody").unwrap());
+
+    assert_eq!(
+        storage.cast_vote(voter.clone(), site, 1, false).unwrap(),
+        (true, 1)
+    );
+    assert_eq!(
+        storage.cast_vote(voter.clone(), site, 1, false).unwrap(),
+        (false, 1)
+    );
+    assert_eq!(
+        storage.cast_vote(voter.clone(), 999, 1, false).unwrap(),
+        (false, 0)
+    );
+    assert_eq!(storage.get_votes(voter.clone()).unwrap(), vec![site]);
+    assert_eq!(storage.snapshot_vote_counts().unwrap(), 1);
+
+    let sites = storage
+        .get_sites(SortOptions::Week, &SiteFilter::default(), 0, 10)
+        .unwrap();
+    let site_json = &serde_json::to_value(&sites).unwrap()[0];
+    assert_eq!(site_json["votes"], 1);
+    assert_eq!(site_json["recent_votes"], 1);
+
+    // A shadowed vote counts only for its voter.
+    let shadowed = String::from("shadowed");
+    storage.generate_id(shadowed.clone()).unwrap();
+    assert_eq!(
+        storage.cast_vote(shadowed.clone(), site, 1, true).unwrap(),
+        (true, 2)
+    );
+    assert_eq!(
+        storage.cast_vote(voter.clone(), site, 0, false).unwrap(),
+        (true, 0)
+    );
+}
+
+#[test]
+fn related_refreshes_wait_for_the_analyzer() {
+    let Some(schema) = Schema::create() else {
+        return;
+    };
+    let storage = schema.storage();
+    let site = listed(&storage, SITE);
+    let (refreshed, requested) = storage.get_related_refresh(site).unwrap();
+    assert!(refreshed.is_some());
+    assert_eq!(requested, None);
+
+    assert!(storage.request_related_refresh(site).unwrap());
+    assert!(!storage.request_related_refresh(site).unwrap());
+    assert_eq!(storage.get_requested_refreshes().unwrap(), vec![SITE]);
+
+    storage
+        .update_related(SITE, &[link("1"), link("2")])
+        .unwrap();
+    assert!(storage.get_requested_refreshes().unwrap().is_empty());
+    assert_eq!(storage.get_related(site).unwrap().len(), 2);
+    assert_eq!(storage.get_related_refresh(site).unwrap().1, None);
+}